@@ -7,15 +7,21 @@ use alloc::vec;
 use alloc::vec::Vec;
 
 use crate::kernel::drivers::filesystem::{self, FileOpenMode};
+use crate::kernel::drivers::power;
+use crate::runtime;
 use alloc::format;
 use bincode;
 use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
 use bincode::{Decode, Encode};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use spin::Mutex;
+
+mod json;
+
 /// Main system configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct SystemConfig {
     /// Display settings
@@ -53,7 +59,7 @@ pub struct SystemConfig {
 }
 
 /// Display configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct DisplayConfig {
     /// Resolution (width, height)
@@ -71,12 +77,39 @@ pub struct DisplayConfig {
     /// Whether to use VSync
     pub vsync: bool,
 
+    /// Let the panel refresh in step with the frame rate (FreeSync, G-Sync)
+    /// when the GPU supports it
+    #[serde(default)]
+    pub variable_refresh: bool,
+
+    /// Variable refresh range in Hz (min, max); (0, 0) uses 48 Hz up to `refresh_rate`
+    #[serde(default)]
+    pub variable_refresh_range: (u32, u32),
+
     /// Scaling factor for UI elements
     pub ui_scale: f32,
 
+    /// Fraction of the resolution frames are rendered at before upscaling,
+    /// 0.5 to 1.0; 0 renders at native resolution
+    #[serde(default)]
+    pub render_scale: f32,
+
     /// Gamma correction
     pub gamma: f32,
 
+    /// Blend in linear light for correct anti-aliased edges and overlays,
+    /// at some cost in software rendering speed
+    #[serde(default)]
+    pub linear_blending: bool,
+
+    /// How content of another aspect ratio is scaled: "fit" adds bars, "fill" crops
+    #[serde(default)]
+    pub scale_mode: String,
+
+    /// Background and letterbox bar color (r, g, b)
+    #[serde(default)]
+    pub clear_color: (u8, u8, u8),
+
     /// Maximum framerate (0 for unlimited)
     pub max_framerate: u32,
 
@@ -88,7 +121,7 @@ pub struct DisplayConfig {
 }
 
 /// Audio configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct AudioConfig {
     /// Whether audio is enabled
@@ -123,7 +156,7 @@ pub struct AudioConfig {
 }
 
 /// Network configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct NetworkConfig {
     /// Whether networking is enabled
@@ -158,7 +191,7 @@ pub struct NetworkConfig {
 }
 
 /// Input configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct InputConfig {
     /// Keyboard layout
@@ -182,6 +215,14 @@ pub struct InputConfig {
     /// Controller deadzone (0.0-1.0)
     pub controller_deadzone: f32,
 
+    /// Deadzone shape for the left stick ("axial", "radial" or "scaled_radial")
+    #[serde(default)]
+    pub left_stick_deadzone_shape: String,
+
+    /// Deadzone shape for the right stick ("axial", "radial" or "scaled_radial")
+    #[serde(default)]
+    pub right_stick_deadzone_shape: String,
+
     /// Controller vibration strength (0-100)
     pub controller_vibration: u8,
 
@@ -190,10 +231,14 @@ pub struct InputConfig {
 
     /// Input device priority
     pub device_priority: Vec<String>,
+
+    /// Requested pointer polling rate in Hz (0 keeps the hardware default)
+    #[serde(default)]
+    pub poll_rate: u32,
 }
 
 /// GPU configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct GpuConfig {
     /// Preferred GPU (useful for multi-GPU systems)
@@ -228,10 +273,19 @@ pub struct GpuConfig {
 
     /// Whether to use asynchronous compute
     pub async_compute: bool,
+
+    /// Lower texture, shadow and anti-aliasing quality at runtime when frames
+    /// miss the target frame time, and restore it when there is headroom
+    #[serde(default)]
+    pub adaptive_quality: bool,
+
+    /// Lowest level adaptive quality may drop any of those settings to
+    #[serde(default)]
+    pub adaptive_quality_floor: u8,
 }
 
 /// Performance configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct PerformanceConfig {
     /// Process priority (0=Low, 1=Normal, 2=High)
@@ -266,7 +320,7 @@ pub struct PerformanceConfig {
 }
 
 /// Power management configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct PowerConfig {
     /// Power profile (0=Balanced, 1=Performance, 2=Power Saving)
@@ -278,6 +332,11 @@ pub struct PowerConfig {
     /// Screen timeout in seconds (0 for never)
     pub screen_timeout: u16,
 
+    /// Seconds the screen stays dimmed after the timeout before blanking
+    /// (0 to blank without dimming first)
+    #[serde(default)]
+    pub screen_dim_duration: u16,
+
     /// System sleep timeout in minutes (0 for never)
     pub sleep_timeout: u16,
 
@@ -285,6 +344,19 @@ pub struct PowerConfig {
     #[serde(default)]
     pub cpu_governor: String,
 
+    /// What to do on a kernel panic ("halt", "reboot" or "console").
+    /// A `panic=` command line option takes precedence.
+    #[serde(default)]
+    pub panic_policy: String,
+
+    /// Power button action ("shutdown", "suspend", "hibernate" or "ignore")
+    #[serde(default)]
+    pub power_button_action: String,
+
+    /// Lid close action ("suspend", "hibernate", "shutdown" or "ignore")
+    #[serde(default)]
+    pub lid_close_action: String,
+
     /// GPU power state (0=Auto, 1=Full, 2=Reduced, 3=Minimum)
     pub gpu_power_state: u8,
 
@@ -303,7 +375,7 @@ pub struct PowerConfig {
 
 
 /// Storage configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct StorageConfig {
     /// Cache size in MB
@@ -338,7 +410,7 @@ pub struct StorageConfig {
 }
 
 /// Window layout configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct WindowLayoutConfig {
     /// Stored window positions and sizes
@@ -367,7 +439,7 @@ pub struct WindowLayoutConfig {
 }
 
 /// Individual window position
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct WindowPosition {
     /// Window ID or name
@@ -390,7 +462,7 @@ pub struct WindowPosition {
 }
 
 /// User-specific settings
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct UserSettings {
     /// Username
@@ -416,7 +488,7 @@ pub struct UserSettings {
 }
 
 /// Accessibility configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct AccessibilityConfig {
     /// Whether high contrast mode is enabled
@@ -439,7 +511,7 @@ pub struct AccessibilityConfig {
 }
 
 /// Custom key binding
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct KeyBinding {
     /// Action name
@@ -453,7 +525,7 @@ pub struct KeyBinding {
 }
 
 /// Notification settings
-#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
 #[serde(crate = "serde")]
 pub struct NotificationSettings {
     /// Whether notifications are enabled
@@ -470,6 +542,19 @@ pub struct NotificationSettings {
 
     /// Maximum number of notifications to show at once
     pub max_visible: u8,
+
+    /// Sound for info notifications: empty for the built-in tone, "none"
+    /// for silence, or the path of a PCM WAV file
+    #[serde(default)]
+    pub info_sound: String,
+
+    /// Sound for warnings, as `info_sound`
+    #[serde(default)]
+    pub warning_sound: String,
+
+    /// Sound for errors, as `info_sound`
+    #[serde(default)]
+    pub error_sound: String,
 }
 
 impl Default for SystemConfig {
@@ -498,8 +583,14 @@ impl Default for DisplayConfig {
             color_depth: 32,
             hardware_acceleration: true,
             vsync: true,
+            variable_refresh: false,
+            variable_refresh_range: (0, 0),
             ui_scale: 1.0,
+            render_scale: 1.0,
             gamma: 1.0,
+            linear_blending: false,
+            scale_mode: "fit".into(),
+            clear_color: (0, 0, 0),
             max_framerate: 144,
             allow_tearing: false,
             fullscreen: false,
@@ -551,9 +642,12 @@ impl Default for InputConfig {
             key_repeat_delay: 500,
             key_repeat_rate: 30,
             controller_deadzone: 0.1,
+            left_stick_deadzone_shape: "radial".into(),
+            right_stick_deadzone_shape: "radial".into(),
             controller_vibration: 80,
             swap_ab_buttons: false,
             device_priority: vec!["keyboard".into(), "controller".into(), "mouse".into()],
+            poll_rate: 0,
         }
     }
 }
@@ -572,6 +666,8 @@ impl Default for GpuConfig {
             shader_quality: 2,
             compute_shaders: true,
             async_compute: true,
+            adaptive_quality: false,
+            adaptive_quality_floor: 0,
         }
     }
 }
@@ -599,8 +695,12 @@ impl Default for PowerConfig {
             power_profile: 0,
             reduce_on_battery: true,
             screen_timeout: 300,
+            screen_dim_duration: 15,
             sleep_timeout: 30,
             cpu_governor: "ondemand".into(),
+            panic_policy: "halt".into(),
+            power_button_action: "shutdown".into(),
+            lid_close_action: "suspend".into(),
             gpu_power_state: 0,
             dynamic_frequency: true,
             low_battery_threshold: 20,
@@ -692,6 +792,9 @@ impl Default for NotificationSettings {
             duration: 5,
             play_sound: true,
             max_visible: 3,
+            info_sound: String::new(),
+            warning_sound: String::new(),
+            error_sound: String::new(),
         }
     }
 }
@@ -707,9 +810,46 @@ impl SystemConfig {
         load_system_config()
     }
 
-    /// Save configuration to file
+    /// Check every setting is within its documented range, naming the first
+    /// one that isn't. Catches corrupted and mistyped hand-edited files.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        fn check(ok: bool, field: &'static str) -> Result<(), ConfigError> {
+            if ok { Ok(()) } else { Err(ConfigError::InvalidValue(field)) }
+        }
+
+        let audio = &self.audio;
+        check(audio.master_volume <= 100, "audio.master_volume must be 0-100")?;
+        check(audio.sfx_volume <= 100, "audio.sfx_volume must be 0-100")?;
+        check(audio.music_volume <= 100, "audio.music_volume must be 0-100")?;
+        check(audio.voice_volume <= 100, "audio.voice_volume must be 0-100")?;
+
+        let gpu = &self.gpu;
+        check(gpu.texture_quality <= 3, "gpu.texture_quality must be 0-3")?;
+        check(gpu.shadow_quality <= 3, "gpu.shadow_quality must be 0-3")?;
+        check(gpu.antialiasing <= 4, "gpu.antialiasing must be 0-4")?;
+        check(
+            matches!(gpu.anisotropic_filtering, 0 | 2 | 4 | 8 | 16),
+            "gpu.anisotropic_filtering must be 0, 2, 4, 8 or 16",
+        )?;
+        check(gpu.shader_quality <= 2, "gpu.shader_quality must be 0-2")?;
+
+        // Written so NaN fails too
+        let deadzone = self.input.controller_deadzone;
+        check((0.0..=1.0).contains(&deadzone), "input.controller_deadzone must be 0.0-1.0")?;
+
+        let display = &self.display;
+        check(display.ui_scale > 0.0, "display.ui_scale must be positive")?;
+        check(display.gamma > 0.0, "display.gamma must be positive")?;
+        check(
+            display.render_scale == 0.0 || (0.5..=1.0).contains(&display.render_scale),
+            "display.render_scale must be 0 or 0.5-1.0",
+        )?;
+        Ok(())
+    }
+
+    /// Save configuration to file, in the format it was loaded from
     pub fn save(&self) -> Result<(), ConfigError> {
-        save_system_config(self)
+        save_system_config(self, ConfigFormat::active())
     }
 
     /// Apply a profile
@@ -722,6 +862,7 @@ impl SystemConfig {
 
         // Update the active profile
         self.active_profile = profile_name.clone();
+        mark_config_dirty();
 
         log::info!("Applied profile: {}", profile_name);
     }
@@ -752,6 +893,7 @@ impl SystemConfig {
         self.power.cpu_governor = "performance".into();
         self.power.gpu_power_state = 1; // Full power
         self.power.dynamic_frequency = false;
+        mark_config_dirty();
 
         log::info!("Applied performance optimizations");
     }
@@ -782,6 +924,7 @@ impl SystemConfig {
         self.power.gpu_power_state = 2; // Reduced
         self.power.screen_timeout = 60; // 1 minute
         self.power.sleep_timeout = 5; // 5 minutes
+        mark_config_dirty();
 
         log::info!("Applied power-saving optimizations");
     }
@@ -809,16 +952,95 @@ impl SystemConfig {
         config
     }
 
-    /// Get all available profiles
+    /// The profile shipped under `name`, if there is one
+    fn builtin_profile(name: &str) -> Option<Self> {
+        match name {
+            "Balanced" => Some(Self::create_balanced_profile()),
+            "Performance" => Some(Self::create_performance_profile()),
+            "Power Saving" => Some(Self::create_power_saving_profile()),
+            _ => None,
+        }
+    }
+
+    /// Store these settings as profile `name`, replacing any profile of that name
+    pub fn save_profile(&self, name: &str) -> Result<(), ConfigError> {
+        let mut profile = self.clone();
+        profile.active_profile = name.into();
+        let bytes = ConfigFormat::Binary.encode(&profile)?;
+        replace_file(&profile_path(name)?, &bytes)?;
+        log::info!("Saved profile: {}", name);
+        Ok(())
+    }
+
+    /// Read profile `name` from disk. Built-in profiles that were never saved
+    /// (or were deleted) come back with their shipped settings.
+    pub fn load_profile(name: &str) -> Result<Self, ConfigError> {
+        let path = profile_path(name)?;
+        let buffer = {
+            let fs_manager = filesystem::get_fs_manager().lock();
+            read_config_file(&fs_manager, &path)
+        };
+        let buffer = match buffer {
+            Some(buffer) => buffer?,
+            None => return Self::builtin_profile(name).ok_or(ConfigError::IoError("Profile not found")),
+        };
+
+        let mut profile = ConfigFormat::Binary.decode(&buffer)?;
+        profile.validate()?;
+        profile.active_profile = name.into();
+        Ok(profile)
+    }
+
+    /// Remove the file of profile `name`
+    pub fn delete_profile(name: &str) -> Result<(), ConfigError> {
+        let path = profile_path(name)?;
+        let mut fs_manager = filesystem::get_fs_manager().lock();
+        fs_manager.delete_entry(&path).map_err(|e| {
+            log::warn!("Failed to delete profile {}: {}", name, e);
+            ConfigError::IoError("Failed to delete profile")
+        })?;
+        if let Err(e) = fs_manager.sync_all() {
+            log::warn!("Failed to sync after deleting profile {}: {}", name, e);
+        }
+        log::info!("Deleted profile: {}", name);
+        Ok(())
+    }
+
+    /// Get all available profiles: the built-in ones, then every profile saved
+    /// in the profile directory
     pub fn get_available_profiles() -> Vec<String> {
-        vec![
+        let mut profiles: Vec<String> = vec![
             "Balanced".into(),
             "Performance".into(),
             "Power Saving".into(),
-        ]
+        ];
+
+        let fs_manager = filesystem::get_fs_manager().lock();
+        // No directory yet means nothing has been saved
+        if let Ok(dir) = fs_manager.open_directory(PROFILE_DIR) {
+            for entry in dir.read_entries() {
+                if entry.is_directory() {
+                    continue;
+                }
+                if let Some(name) = entry.name.strip_suffix(".bin") {
+                    if !profiles.iter().any(|profile| profile == name) {
+                        profiles.push(name.into());
+                    }
+                }
+            }
+        }
+        profiles
     }
 }
 
+/// File of profile `name`, rejecting names that would escape the profile directory
+fn profile_path(name: &str) -> Result<String, ConfigError> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(ConfigError::InvalidValue("Invalid profile name"));
+    }
+    Ok(format!("{}/{}.bin", PROFILE_DIR, name))
+}
+
 /// Config error types
 #[derive(Debug, Clone)]
 pub enum ConfigError {
@@ -837,108 +1059,274 @@ impl fmt::Display for ConfigError {
     }
 }
 
-/// Load system configuration from file
-pub fn load_system_config() -> Result<SystemConfig, ConfigError> {
-    // Try to open the config file (using the binary extension)
-    let config_path = "/etc/fluxGridOs/config.bin"; // Use matching path from save function
+/// On-disk encoding of the system config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// Compact bincode, what a fresh install writes
+    Binary,
+    /// Hand-editable JSON, preferred when both files exist
+    Json,
+}
+
+impl ConfigFormat {
+    pub fn path(self) -> &'static str {
+        match self {
+            ConfigFormat::Binary => "/etc/fluxGridOs/config.bin",
+            ConfigFormat::Json => "/etc/fluxGridOs/config.json",
+        }
+    }
 
-    // Create a new filesystem manager
-    let fs_manager = filesystem::FilesystemManager::new();
+    /// The format the config was last loaded from, which `SystemConfig::save` keeps using
+    pub fn active() -> Self {
+        if LOADED_JSON.load(Ordering::Acquire) {
+            ConfigFormat::Json
+        } else {
+            ConfigFormat::Binary
+        }
+    }
 
-    match fs_manager.open_file(config_path, true) {
-        // Use enum for clarity if available
-        Ok(mut file) => {
-            // Create a buffer to hold file content
-            let mut buffer = Vec::new();
-            let mut read_buf = [0u8; 4096]; // Temporary buffer for each read call
-
-            // Read the file in chunks until we've got it all
-            loop {
-                // Assuming file.read takes a mutable slice and returns Result<usize, _>
-                match file.read(&mut read_buf, &fs_manager, 0) {
-                    Ok(bytes_read) => {
-                        if bytes_read == 0 {
-                            // End of file
-                            break;
-                        }
-                        // Append the read bytes to our main buffer
-                        buffer.extend_from_slice(&read_buf[..bytes_read]);
-                    }
-                    Err(e) => {
-                        // Log the specific error if possible, e.g., log::error!("Error reading config file {}: {:?}", config_path, e);
-                        return Err(ConfigError::IoError("Failed to read config file"));
-                    }
-                }
+    fn encode(self, config: &SystemConfig) -> Result<Vec<u8>, ConfigError> {
+        match self {
+            ConfigFormat::Binary => {
+                let body = bincode::encode_to_vec(config, bincode::config::standard())
+                    .map_err(|_| ConfigError::ParseError("Failed to serialize config using bincode"))?;
+                let mut bytes = Vec::with_capacity(VERSION_TAG_LEN + body.len() + CHECKSUM_LEN);
+                bytes.extend_from_slice(&CONFIG_VERSION.to_le_bytes());
+                bytes.extend_from_slice(&body);
+                let checksum = simd_adler32::adler32(&bytes.as_slice());
+                bytes.extend_from_slice(&checksum.to_le_bytes());
+                Ok(bytes)
+            }
+            ConfigFormat::Json => json::to_string(config).map(String::into_bytes).map_err(|e| {
+                log::error!("Failed to serialize config as JSON: {}", e);
+                ConfigError::ParseError("Failed to serialize config as JSON")
+            }),
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> Result<SystemConfig, ConfigError> {
+        match self {
+            ConfigFormat::Binary => {
+                let (version, body) = split_version_tag(verify_checksum(bytes)?)?;
+                migrate(version, body)
             }
+            ConfigFormat::Json => json::from_slice(bytes).map_err(|e| {
+                log::error!("{}: {}", self.path(), e);
+                ConfigError::ParseError("Invalid config file format (JSON)")
+            }),
+        }
+    }
+}
 
-            let config: Result<SystemConfig, _> = bincode::decode_from_slice(
-                &buffer,
-                bincode::config::standard()
-            ).map(|(decoded, _)| decoded);
+/// Layout version of `config.bin`, written as a little-endian `u32` before
+/// the bincode body. An Adler-32 of both follows them, also little-endian. Bump it whenever `SystemConfig` gains, loses or reorders
+/// a field, and teach `migrate` to read the previous layout.
+pub const CONFIG_VERSION: u32 = 1;
 
+const VERSION_TAG_LEN: usize = core::mem::size_of::<u32>();
+const CHECKSUM_LEN: usize = core::mem::size_of::<u32>();
 
-            match config {
-                Ok(decoded_config) => {
-                    log::info!(
-            "System configuration loaded successfully from {}",
-            config_path
-        );
-                    Ok(decoded_config)
-                }
-                Err(e) => {
-                    // Log the specific error if possible
-                    Err(ConfigError::ParseError(
-                        "Invalid config file format (bincode)"
-                    ))
+/// Directory holding the config files
+const CONFIG_DIR: &str = "/etc/fluxGridOs";
+
+/// One `<name>.bin` per saved profile, in the `config.bin` format
+const PROFILE_DIR: &str = "/etc/fluxGridOs/profiles";
+
+/// Where a `config.bin` of an unsupported version is moved before defaults are written
+const BINARY_BACKUP_PATH: &str = "/etc/fluxGridOs/config.bin.bak";
+
+/// Check the trailing Adler-32 of a `config.bin` and return what it covers.
+/// Catches bit-rot and partial writes bincode would decode into nonsense.
+fn verify_checksum(bytes: &[u8]) -> Result<&[u8], ConfigError> {
+    if bytes.len() < CHECKSUM_LEN {
+        return Err(ConfigError::ParseError("Config file too short for a checksum"));
+    }
+    let (payload, stored) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    let stored = u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]);
+    if simd_adler32::adler32(&payload) != stored {
+        return Err(ConfigError::ParseError("checksum mismatch"));
+    }
+    Ok(payload)
+}
+
+fn split_version_tag(bytes: &[u8]) -> Result<(u32, &[u8]), ConfigError> {
+    if bytes.len() < VERSION_TAG_LEN {
+        return Err(ConfigError::ParseError("Config file too short for a version tag"));
+    }
+    let (tag, body) = bytes.split_at(VERSION_TAG_LEN);
+    Ok((u32::from_le_bytes([tag[0], tag[1], tag[2], tag[3]]), body))
+}
+
+/// Decode a bincode body written with layout `version` into the current struct.
+///
+/// Older layouts are decoded into their own struct and converted here; there
+/// is only one layout so far.
+pub fn migrate(version: u32, bytes: &[u8]) -> Result<SystemConfig, ConfigError> {
+    match version {
+        CONFIG_VERSION => bincode::decode_from_slice(bytes, bincode::config::standard())
+            .map(|(decoded, _)| decoded)
+            .map_err(|_| ConfigError::ParseError("Invalid config file format (bincode)")),
+        _ => Err(ConfigError::InvalidValue("Unsupported config version")),
+    }
+}
+
+/// Whether the config came from the JSON file
+static LOADED_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Load system configuration from file
+///
+/// Reads `config.json` if it exists, else `config.bin`. Without either a
+/// default config is created and saved in the binary format.
+pub fn load_system_config() -> Result<SystemConfig, ConfigError> {
+    let unsupported = 'search: {
+        // Use the mounted filesystems
+        let fs_manager = filesystem::get_fs_manager().lock();
+
+        for format in [ConfigFormat::Json, ConfigFormat::Binary] {
+            let buffer = match read_config_file(&fs_manager, format.path()) {
+                Some(buffer) => buffer?,
+                None => continue,
+            };
+            if format == ConfigFormat::Binary {
+                let (version, _) = split_version_tag(verify_checksum(&buffer)?)?;
+                if version == 0 || version > CONFIG_VERSION {
+                    log::warn!("{} has unsupported version {}", format.path(), version);
+                    break 'search Some(buffer);
                 }
             }
+            let config = format.decode(&buffer)?;
+            LOADED_JSON.store(format == ConfigFormat::Json, Ordering::Release);
+            // The file is left alone so the user can fix it
+            if let Err(e) = config.validate() {
+                log::error!("Ignoring {}: {}, using defaults", format.path(), e);
+                return Ok(SystemConfig::default());
+            }
+            log::info!("System configuration loaded successfully from {}", format.path());
+            return Ok(config);
         }
-        Err(e) => {
-            // File doesn't exist or couldn't be opened, create default config
-            // Log the specific error if possible, e.g., log::info!("Config file {} not found or error opening: {:?}. Creating default.", config_path, e);
-            log::info!("Config file not found, creating default configuration");
-            let config = SystemConfig::default();
-            // Try to save it using the bincode save function
-            if let Err(save_err) = save_system_config(&config) {
-                // Log the specific save error if possible, e.g., log::warn!("Failed to save default configuration: {:?}", save_err);
-                log::warn!("Failed to save default configuration");
+        None
+    };
+
+    match unsupported {
+        // Keep the original around; a newer build may still be able to read it
+        Some(original) => {
+            let mut fs_manager = filesystem::get_fs_manager().lock();
+            match write_file(&mut fs_manager, BINARY_BACKUP_PATH, &original) {
+                Ok(()) => log::info!("Old configuration backed up to {}", BINARY_BACKUP_PATH),
+                Err(e) => log::warn!("Failed to back up configuration: {}", e),
             }
-            Ok(config)
+            log::info!("Replacing configuration with defaults");
         }
+        None => log::info!("Config file not found, creating default configuration"),
     }
+    let config = SystemConfig::default();
+    if let Err(e) = save_system_config(&config, ConfigFormat::Binary) {
+        log::warn!("Failed to save default configuration: {}", e);
+    }
+    Ok(config)
 }
 
-/// Save system configuration to file
-pub fn save_system_config(config: &SystemConfig) -> Result<(), ConfigError> {
-    // Serialize config using bincode
-    let bytes = match bincode::encode_to_vec(config, bincode::config::standard()) {
-        Ok(bytes) => bytes,
-        Err(_) => return Err(ConfigError::ParseError("Failed to serialize config using bincode")),
-    };
+/// Whole contents of `path`, or None if it can't be opened
+fn read_config_file(
+    fs_manager: &filesystem::FilesystemManager,
+    path: &str,
+) -> Option<Result<Vec<u8>, ConfigError>> {
+    let mut file = fs_manager.open_file(path, true).ok()?;
+    let mut buffer = Vec::new();
+    let mut read_buf = [0u8; 4096]; // Temporary buffer for each read call
+
+    // Read the file in chunks until we've got it all
+    loop {
+        match file.read(&mut read_buf, fs_manager, buffer.len() as u64) {
+            Ok(0) => return Some(Ok(buffer)),
+            Ok(bytes_read) => buffer.extend_from_slice(&read_buf[..bytes_read]),
+            Err(e) => {
+                log::error!("Error reading config file {}: {}", path, e);
+                return Some(Err(ConfigError::IoError("Failed to read config file")));
+            }
+        }
+    }
+}
+
+/// Save system configuration to file in `format`
+pub fn save_system_config(config: &SystemConfig, format: ConfigFormat) -> Result<(), ConfigError> {
+    // Cleared up front so a change made while writing is not lost
+    let was_dirty = CONFIG_DIRTY.swap(false, Ordering::AcqRel);
+    let result = write_system_config(config, format);
+    if result.is_err() && was_dirty {
+        CONFIG_DIRTY.store(true, Ordering::Release);
+    }
+    result
+}
+
+fn write_system_config(config: &SystemConfig, format: ConfigFormat) -> Result<(), ConfigError> {
+    let bytes = format.encode(config)?;
+    replace_file(format.path(), &bytes)?;
+    log::info!("System configuration saved to {}", format.path());
+    Ok(())
+}
 
+/// Durably replace `config_path` (under `CONFIG_DIR`) with `bytes`
+fn replace_file(config_path: &str, bytes: &[u8]) -> Result<(), ConfigError> {
     // Create directory if it doesn't exist
-    let mut fs_manager = filesystem::FilesystemManager::new();
+    let mut fs_manager = filesystem::get_fs_manager().lock();
 
-    // Create directory
-    let dir_path = "/etc/fluxGridOs";
-    // Use if let Err(_) = ... to avoid unused result warning if create_directory returns Result<(), _>
-    if let Err(e) = fs_manager.create_directory(dir_path) {
-        // Log the specific error if possible, e.g., log::warn!("Failed to create config directory {}: {:?}", dir_path, e);
-        log::warn!("Failed to create config directory {}", dir_path);
-        // Decide if this should be a hard error or just a warning
-        // return Err(ConfigError::IoError("Failed to create config directory"));
+    // Create the config directory and any subdirectory the file sits in
+    let parent = config_path.rsplit_once('/').map_or(CONFIG_DIR, |(parent, _)| parent);
+    for dir_path in [CONFIG_DIR, parent] {
+        if fs_manager.open_directory(dir_path).is_ok() {
+            continue;
+        }
+        if let Err(e) = fs_manager.create_directory(dir_path) {
+            log::warn!("Failed to create config directory {}: {}", dir_path, e);
+        }
+    }
+
+    // Written beside the real file and renamed over it once durable, so a
+    // power cut leaves either the old config or the new one, never half of each
+    let temp_path = format!("{}.tmp", config_path);
+    write_file(&mut fs_manager, &temp_path, bytes)?;
+    if let Err(e) = fs_manager.rename(&temp_path, config_path) {
+        log::error!("Failed to move {} into place: {}", temp_path, e);
+        return Err(ConfigError::IoError("Failed to replace config file"));
     }
+    // The rename itself has to reach the device too
+    if let Err(e) = fs_manager.sync_all() {
+        log::error!("Failed to sync after saving config: {}", e);
+        return Err(ConfigError::IoError("Failed to sync config file"));
+    }
+
+    Ok(())
+}
 
-    // Write to file (consider changing extension from .toml to .bin or .cfg)
-    let config_path = "/etc/fluxGridOs/config.bin"; // Changed extension
-    match fs_manager.open_file(config_path, true) {
+/// Replace the contents of `config_path` with `bytes` and sync it
+fn write_file(
+    fs_manager: &mut filesystem::FilesystemManager,
+    config_path: &str,
+    bytes: &[u8],
+) -> Result<(), ConfigError> {
+    // Start from an empty file: writes don't truncate, and a stale tail after a
+    // shorter config, or a temp file left by an interrupted save, would break
+    // the parser
+    if fs_manager.open_file(config_path, true).is_ok() {
+        if let Err(e) = fs_manager.delete_entry(config_path) {
+            log::warn!("Failed to replace config file {}: {}", config_path, e);
+        }
+    }
+    if fs_manager.open_file(config_path, true).is_err() {
+        if let Err(e) = fs_manager.create_file(config_path) {
+            log::warn!("Failed to create config file {}: {}", config_path, e);
+        }
+    }
+
+    match fs_manager.open_file(config_path, false) {
         // Use enum for clarity if available
         Ok(mut file) => {
             let mut position = 0;
 
             while position < bytes.len() {
                 // Pass the slice directly
-                match file.write(&bytes[position..], &fs_manager) {
+                match file.write(&bytes[position..], fs_manager) {
                     Ok(bytes_written) => {
                         if bytes_written == 0 {
                             // Avoid infinite loop if write returns 0 without error
@@ -956,7 +1344,12 @@ pub fn save_system_config(config: &SystemConfig) -> Result<(), ConfigError> {
                 }
             }
 
-            log::info!("System configuration saved to {}", config_path);
+            // Settings have to survive a power cut right after saving
+            if let Err(e) = file.sync(fs_manager) {
+                log::error!("Failed to sync config file: {}", e);
+                return Err(ConfigError::IoError("Failed to sync config file"));
+            }
+
             Ok(())
         }
         Err(e) => {
@@ -968,6 +1361,7 @@ pub fn save_system_config(config: &SystemConfig) -> Result<(), ConfigError> {
     }
 }
 
+
 /// Global configuration
 lazy_static! {
     static ref CONFIG: Mutex<SystemConfig> = Mutex::new(load_system_config().unwrap_or_else(|e| {
@@ -980,3 +1374,350 @@ lazy_static! {
 pub fn get_config() -> &'static Mutex<SystemConfig> {
     &CONFIG
 }
+
+/// Tag of the global config's record in a hibernation image
+const HIBERNATION_TAG: u32 = u32::from_le_bytes(*b"CONF");
+
+/// Carry the global config through hibernation. Must be registered before a
+/// resume so the image's config record is recognized.
+pub fn register_hibernation_state() -> Result<(), &'static str> {
+    power::register_hibernation_state(
+        HIBERNATION_TAG,
+        save_for_hibernation,
+        validate_for_hibernation,
+        restore_from_hibernation,
+    )
+}
+
+fn save_for_hibernation() -> Result<Vec<u8>, &'static str> {
+    ConfigFormat::Binary
+        .encode(&CONFIG.lock())
+        .map_err(|_| "Failed to encode the config for hibernation")
+}
+
+fn decode_for_hibernation(bytes: &[u8]) -> Result<SystemConfig, &'static str> {
+    ConfigFormat::Binary
+        .decode(bytes)
+        .map_err(|_| "Hibernation image holds an invalid config")
+}
+
+fn validate_for_hibernation(bytes: &[u8]) -> Result<(), &'static str> {
+    decode_for_hibernation(bytes).map(drop)
+}
+
+fn restore_from_hibernation(bytes: &[u8]) -> Result<(), &'static str> {
+    *CONFIG.lock() = decode_for_hibernation(bytes)?;
+    Ok(())
+}
+
+/// Set by any config mutation, cleared by a successful save
+static CONFIG_DIRTY: AtomicBool = AtomicBool::new(false);
+/// Set when a window is opened, closed, moved or resized
+static LAYOUT_DIRTY: AtomicBool = AtomicBool::new(false);
+/// Raised by the autosave task, consumed by the main loop
+static AUTOSAVE_DUE: AtomicBool = AtomicBool::new(false);
+static AUTOSAVE_TASK: Mutex<Option<runtime::TaskId>> = Mutex::new(None);
+
+/// Record that the in-memory config differs from the saved one
+pub fn mark_config_dirty() {
+    CONFIG_DIRTY.store(true, Ordering::Release);
+}
+
+/// Record that the window layout changed since it was last saved
+pub fn mark_layout_dirty() {
+    LAYOUT_DIRTY.store(true, Ordering::Release);
+}
+
+/// Take the layout dirty flag; the caller is expected to copy the layout into the config
+pub fn take_layout_dirty() -> bool {
+    LAYOUT_DIRTY.swap(false, Ordering::AcqRel)
+}
+
+/// Whether the config or window layout has unsaved changes
+pub fn has_unsaved_changes() -> bool {
+    CONFIG_DIRTY.load(Ordering::Acquire) || LAYOUT_DIRTY.load(Ordering::Acquire)
+}
+
+/// Apply `StorageConfig::autosave_interval` (minutes, 0 disables).
+/// Called at boot and again on every config reload.
+pub fn configure_autosave(interval_minutes: u16) {
+    let interval_ms = interval_minutes as u64 * 60_000;
+    let mut task = AUTOSAVE_TASK.lock();
+    if let Some(previous) = task.take() {
+        runtime::cancel(previous);
+    }
+
+    if interval_ms == 0 {
+        AUTOSAVE_DUE.store(false, Ordering::Release);
+        log::info!("Config autosave disabled");
+        return;
+    }
+
+    *task = Some(runtime::spawn_periodic("config_autosave", interval_ms, autosave_tick));
+    log::info!("Config autosave every {} min", interval_minutes);
+}
+
+/// Only raises a request; the save itself needs the system manager, which
+/// the main loop holds.
+fn autosave_tick() -> runtime::TaskStatus {
+    // Nothing changed, spare the flash a write
+    if has_unsaved_changes() {
+        AUTOSAVE_DUE.store(true, Ordering::Release);
+    }
+    runtime::TaskStatus::Continue
+}
+
+/// Take a pending autosave request raised by the autosave task
+pub fn take_autosave_request() -> bool {
+    AUTOSAVE_DUE.swap(false, Ordering::AcqRel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    /// Every optional field set, every list non-empty, most values off their defaults
+    fn populated_config() -> SystemConfig {
+        let mut config = SystemConfig::default();
+        config.display.resolution = Some((2560, 1440));
+        config.display.refresh_rate = 144;
+        config.display.ui_scale = 1.25;
+        config.display.variable_refresh_range = (48, 144);
+        config.display.scale_mode = "integer".to_string();
+        config.display.clear_color = (12, 34, 56);
+        config.audio.master_volume = 42;
+        config.audio.backend = "hda".to_string();
+        config.network.use_dhcp = false;
+        config.network.static_ip = Some("192.168.1.20".to_string());
+        config.network.subnet_mask = Some("255.255.255.0".to_string());
+        config.network.gateway = Some("192.168.1.1".to_string());
+        config.network.dns_servers = vec!["1.1.1.1".to_string(), "9.9.9.9".to_string()];
+        config.input.mouse_acceleration = 0.75;
+        config.input.device_priority = vec!["gamepad0".to_string(), "keyboard".to_string()];
+        config.gpu.preferred_gpu = "amd".to_string();
+        config.gpu.vram_limit = 3072;
+        config.performance.thread_pool_size = 12;
+        config.power.cpu_governor = "ondemand".to_string();
+        config.storage.autosave_interval = 7;
+        config.window_layout = Some(WindowLayoutConfig {
+            windows: vec![
+                WindowPosition {
+                    id: "console".to_string(),
+                    position: (-40, 25),
+                    size: (640, 480),
+                    minimized: false,
+                    maximized: true,
+                    z_order: 0,
+                },
+                WindowPosition {
+                    id: "settings".to_string(),
+                    position: (300, 200),
+                    size: (400, 300),
+                    minimized: true,
+                    maximized: false,
+                    z_order: 1,
+                },
+            ],
+            default_theme: "dark".to_string(),
+            remember_positions: true,
+            use_animations: false,
+            border_thickness: 2,
+            corner_radius: 6,
+            allow_transparency: true,
+            default_opacity: 200,
+        });
+        config.active_profile = "competitive".to_string();
+        config.user_settings.username = "player".to_string();
+        config.user_settings.accessibility.text_scale = 1.5;
+        config.user_settings.key_bindings = vec![
+            KeyBinding { action: "screenshot".to_string(), key_code: 0x58, modifiers: 0b011 },
+            KeyBinding { action: "overlay".to_string(), key_code: 0x57, modifiers: 0 },
+        ];
+        config.user_settings.recent_apps = vec!["doom".to_string(), "quake".to_string()];
+        config.user_settings.notifications.max_visible = 5;
+        config.user_settings.notifications.warning_sound = "/usr/share/sounds/warn.wav".to_string();
+        config
+    }
+
+    #[test]
+    fn binary_round_trip_keeps_every_field() {
+        let config = populated_config();
+        let bytes = ConfigFormat::Binary.encode(&config).unwrap();
+        assert_eq!(ConfigFormat::Binary.decode(&bytes).unwrap(), config);
+    }
+
+    #[test]
+    fn json_round_trip_keeps_every_field() {
+        let config = populated_config();
+        let bytes = ConfigFormat::Json.encode(&config).unwrap();
+        assert_eq!(ConfigFormat::Json.decode(&bytes).unwrap(), config);
+    }
+
+    #[test]
+    fn round_trip_keeps_absent_layout() {
+        let mut config = populated_config();
+        config.window_layout = None;
+        config.network.static_ip = None;
+        let bytes = ConfigFormat::Binary.encode(&config).unwrap();
+        assert_eq!(ConfigFormat::Binary.decode(&bytes).unwrap(), config);
+    }
+
+    /// A `config.bin` assembled by hand: version tag, bincode body, Adler-32
+    fn binary_blob(version: u32, config: &SystemConfig) -> Vec<u8> {
+        let mut bytes = version.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&bincode::encode_to_vec(config, bincode::config::standard()).unwrap());
+        let checksum = simd_adler32::adler32(&bytes.as_slice());
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn version_1_blob_loads() {
+        let config = populated_config();
+        assert_eq!(ConfigFormat::Binary.decode(&binary_blob(1, &config)).unwrap(), config);
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        for version in [0, CONFIG_VERSION + 1] {
+            let bytes = binary_blob(version, &SystemConfig::default());
+            assert!(matches!(
+                ConfigFormat::Binary.decode(&bytes),
+                Err(ConfigError::InvalidValue("Unsupported config version"))
+            ));
+        }
+    }
+
+    #[test]
+    fn corrupted_blob_fails_the_checksum() {
+        let mut bytes = binary_blob(1, &SystemConfig::default());
+        bytes[VERSION_TAG_LEN] ^= 0xFF;
+        assert!(matches!(
+            ConfigFormat::Binary.decode(&bytes),
+            Err(ConfigError::ParseError("checksum mismatch"))
+        ));
+    }
+
+    /// Field message `validate` rejects the default config with after `edit`, if any
+    fn rejected_field(edit: impl FnOnce(&mut SystemConfig)) -> Option<&'static str> {
+        let mut config = SystemConfig::default();
+        edit(&mut config);
+        match config.validate() {
+            Ok(()) => None,
+            Err(ConfigError::InvalidValue(field)) => Some(field),
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(SystemConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn volumes_stop_at_100() {
+        assert_eq!(rejected_field(|c| c.audio.master_volume = 100), None);
+        assert_eq!(rejected_field(|c| c.audio.master_volume = 101), Some("audio.master_volume must be 0-100"));
+        assert_eq!(rejected_field(|c| c.audio.sfx_volume = 100), None);
+        assert_eq!(rejected_field(|c| c.audio.sfx_volume = 101), Some("audio.sfx_volume must be 0-100"));
+        assert_eq!(rejected_field(|c| c.audio.music_volume = 100), None);
+        assert_eq!(rejected_field(|c| c.audio.music_volume = 101), Some("audio.music_volume must be 0-100"));
+        assert_eq!(rejected_field(|c| c.audio.voice_volume = 100), None);
+        assert_eq!(rejected_field(|c| c.audio.voice_volume = 101), Some("audio.voice_volume must be 0-100"));
+    }
+
+    #[test]
+    fn gpu_quality_levels_stop_at_their_maximum() {
+        assert_eq!(rejected_field(|c| c.gpu.texture_quality = 3), None);
+        assert_eq!(rejected_field(|c| c.gpu.texture_quality = 4), Some("gpu.texture_quality must be 0-3"));
+        assert_eq!(rejected_field(|c| c.gpu.shadow_quality = 3), None);
+        assert_eq!(rejected_field(|c| c.gpu.shadow_quality = 4), Some("gpu.shadow_quality must be 0-3"));
+        assert_eq!(rejected_field(|c| c.gpu.antialiasing = 4), None);
+        assert_eq!(rejected_field(|c| c.gpu.antialiasing = 5), Some("gpu.antialiasing must be 0-4"));
+        assert_eq!(rejected_field(|c| c.gpu.shader_quality = 2), None);
+        assert_eq!(rejected_field(|c| c.gpu.shader_quality = 3), Some("gpu.shader_quality must be 0-2"));
+    }
+
+    #[test]
+    fn anisotropic_filtering_takes_powers_of_two_up_to_16() {
+        for level in [0, 2, 4, 8, 16] {
+            assert_eq!(rejected_field(|c| c.gpu.anisotropic_filtering = level), None);
+        }
+        for level in [1, 3, 32] {
+            assert_eq!(
+                rejected_field(|c| c.gpu.anisotropic_filtering = level),
+                Some("gpu.anisotropic_filtering must be 0, 2, 4, 8 or 16")
+            );
+        }
+    }
+
+    #[test]
+    fn controller_deadzone_stays_within_0_and_1() {
+        assert_eq!(rejected_field(|c| c.input.controller_deadzone = 0.0), None);
+        assert_eq!(rejected_field(|c| c.input.controller_deadzone = 1.0), None);
+        for deadzone in [-0.01, 1.01, f32::NAN] {
+            assert_eq!(
+                rejected_field(|c| c.input.controller_deadzone = deadzone),
+                Some("input.controller_deadzone must be 0.0-1.0")
+            );
+        }
+    }
+
+    #[test]
+    fn ui_scale_and_gamma_must_be_positive() {
+        assert_eq!(rejected_field(|c| c.display.ui_scale = 0.01), None);
+        assert_eq!(rejected_field(|c| c.display.ui_scale = 0.0), Some("display.ui_scale must be positive"));
+        assert_eq!(rejected_field(|c| c.display.gamma = 0.01), None);
+        assert_eq!(rejected_field(|c| c.display.gamma = 0.0), Some("display.gamma must be positive"));
+    }
+
+    #[test]
+    fn render_scale_is_off_or_between_half_and_native() {
+        for scale in [0.0, 0.5, 1.0] {
+            assert_eq!(rejected_field(|c| c.display.render_scale = scale), None);
+        }
+        for scale in [0.49, 1.01, -0.5] {
+            assert_eq!(
+                rejected_field(|c| c.display.render_scale = scale),
+                Some("display.render_scale must be 0 or 0.5-1.0")
+            );
+        }
+    }
+
+    // One test: profiles live on the global filesystem manager
+    #[test]
+    fn saved_profile_times_listed_until_deleted() {
+        {
+            let mut fs_manager = filesystem::get_fs_manager().lock();
+            let ram = filesystem::Filesystem::new("ram".to_string(), filesystem::FilesystemType::RamFs, String::new(), false);
+            fs_manager.add_filesystem(ram).unwrap();
+            // Part of the standard directory layout created at boot
+            fs_manager.create_directory("/etc").unwrap();
+        }
+        let times_listed = |name: &str| {
+            SystemConfig::get_available_profiles()
+                .iter()
+                .filter(|profile| profile.as_str() == name)
+                .count()
+        };
+
+        let mut custom = SystemConfig::default();
+        custom.audio.master_volume = 42;
+        custom.save_profile("Tournament").unwrap();
+        assert_eq!(times_listed("Tournament"), 1);
+        let loaded = SystemConfig::load_profile("Tournament").unwrap();
+        assert_eq!(loaded.audio.master_volume, 42);
+        assert_eq!(loaded.active_profile, "Tournament");
+
+        // Saving again replaces the profile rather than adding a second one
+        custom.save_profile("Tournament").unwrap();
+        assert_eq!(times_listed("Tournament"), 1);
+
+        SystemConfig::delete_profile("Tournament").unwrap();
+        assert_eq!(times_listed("Tournament"), 0);
+        assert!(SystemConfig::load_profile("Tournament").is_err());
+        // The built-in profiles are always offered
+        assert_eq!(times_listed("Balanced"), 1);
+    }
+}
@@ -0,0 +1,234 @@
+//! System-wide event bus
+//!
+//! Subsystems subscribe to an `EventKind` and get called synchronously when a
+//! matching `Event` is published. Events published with interrupts disabled
+//! (i.e. from an interrupt handler) are queued instead and delivered by the
+//! next `dispatch_pending` call from the main loop, so handlers never run in
+//! interrupt context.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::gui::input::{InputDevice, NavAction};
+use crate::kernel::drivers::display_hotplug::Connector;
+
+/// Maximum number of live subscriptions
+const MAX_SUBSCRIBERS: usize = 64;
+/// Maximum events waiting for delivery from interrupt context
+const MAX_PENDING_EVENTS: usize = 256;
+/// How deeply handlers may publish from inside other handlers
+const MAX_DISPATCH_DEPTH: usize = 4;
+
+/// Categories subscribers can listen for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    KeyPress,
+    KeyRelease,
+    MouseMove,
+    MouseButton,
+    MouseScroll,
+    WindowResize,
+    WindowClose,
+    WindowFocus,
+    WindowBlur,
+    NavAction,
+    FullscreenEntered,
+    FullscreenExited,
+    ControllerConnected,
+    ControllerDisconnected,
+    GameLaunched,
+    PowerButton,
+    SleepButton,
+    LidSwitch,
+    DisplayConnected,
+    DisplayDisconnected,
+}
+
+/// Something that happened which other subsystems may react to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    KeyPress { key: u16 },
+    KeyRelease { key: u16 },
+    MouseMove { x: f32, y: f32 },
+    MouseButton { button: u8, pressed: bool },
+    MouseScroll { delta: i32 },
+    WindowResize { width: u32, height: u32 },
+    WindowClose,
+    WindowFocus,
+    WindowBlur,
+    /// Navigation action after device arbitration, for games reading menus and pads alike
+    NavAction { action: NavAction, device: InputDevice },
+    FullscreenEntered { window_id: u32 },
+    FullscreenExited { window_id: u32 },
+    ControllerConnected { id: usize },
+    ControllerDisconnected { id: usize },
+    GameLaunched { window_id: u32 },
+    PowerButton,
+    SleepButton,
+    LidSwitch { closed: bool },
+    /// A monitor was plugged in; the mode is its preferred one
+    DisplayConnected { connector: Connector, width: u32, height: u32, refresh_rate: u32 },
+    DisplayDisconnected { connector: Connector },
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::KeyPress { .. } => EventKind::KeyPress,
+            Event::KeyRelease { .. } => EventKind::KeyRelease,
+            Event::MouseMove { .. } => EventKind::MouseMove,
+            Event::MouseButton { .. } => EventKind::MouseButton,
+            Event::MouseScroll { .. } => EventKind::MouseScroll,
+            Event::WindowResize { .. } => EventKind::WindowResize,
+            Event::WindowClose => EventKind::WindowClose,
+            Event::WindowFocus => EventKind::WindowFocus,
+            Event::WindowBlur => EventKind::WindowBlur,
+            Event::NavAction { .. } => EventKind::NavAction,
+            Event::FullscreenEntered { .. } => EventKind::FullscreenEntered,
+            Event::FullscreenExited { .. } => EventKind::FullscreenExited,
+            Event::ControllerConnected { .. } => EventKind::ControllerConnected,
+            Event::ControllerDisconnected { .. } => EventKind::ControllerDisconnected,
+            Event::GameLaunched { .. } => EventKind::GameLaunched,
+            Event::PowerButton => EventKind::PowerButton,
+            Event::SleepButton => EventKind::SleepButton,
+            Event::LidSwitch { .. } => EventKind::LidSwitch,
+            Event::DisplayConnected { .. } => EventKind::DisplayConnected,
+            Event::DisplayDisconnected { .. } => EventKind::DisplayDisconnected,
+        }
+    }
+}
+
+/// Event handler; runs on the publisher's stack, outside interrupt context
+pub type Handler = fn(&Event);
+
+/// Handle returned by `subscribe`, used to unsubscribe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriptionId(u32);
+
+struct Subscription {
+    id: SubscriptionId,
+    kind: EventKind,
+    handler: Handler,
+}
+
+/// A set of subscriptions and the events waiting to be delivered to them
+pub struct Bus {
+    subscriptions: Vec<Subscription>,
+    pending: VecDeque<Event>,
+    next_id: u32,
+}
+
+impl Bus {
+    pub const fn new() -> Self {
+        Self {
+            subscriptions: Vec::new(),
+            pending: VecDeque::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn subscribe(&mut self, kind: EventKind, handler: Handler) -> Result<SubscriptionId, &'static str> {
+        if self.subscriptions.len() >= MAX_SUBSCRIBERS {
+            return Err("Too many event subscribers");
+        }
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscriptions.push(Subscription { id, kind, handler });
+        Ok(id)
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let before = self.subscriptions.len();
+        self.subscriptions.retain(|s| s.id != id);
+        self.subscriptions.len() != before
+    }
+
+    /// Handlers registered for `kind`, in subscription order
+    pub fn handlers_for(&self, kind: EventKind) -> Vec<Handler> {
+        self.subscriptions
+            .iter()
+            .filter(|s| s.kind == kind)
+            .map(|s| s.handler)
+            .collect()
+    }
+
+    /// Queue an event for later delivery; drops it if the queue is full
+    pub fn defer(&mut self, event: Event) -> bool {
+        if self.pending.len() >= MAX_PENDING_EVENTS {
+            return false;
+        }
+        self.pending.push_back(event);
+        true
+    }
+
+    pub fn take_pending(&mut self) -> Option<Event> {
+        self.pending.pop_front()
+    }
+}
+
+lazy_static! {
+    static ref BUS: Mutex<Bus> = Mutex::new(Bus::new());
+}
+
+static DISPATCH_DEPTH: AtomicUsize = AtomicUsize::new(0);
+static DROPPED_EVENTS: AtomicU32 = AtomicU32::new(0);
+
+/// Register `handler` for every published event of `kind`
+pub fn subscribe(kind: EventKind, handler: Handler) -> Result<SubscriptionId, &'static str> {
+    BUS.lock().subscribe(kind, handler)
+}
+
+pub fn unsubscribe(id: SubscriptionId) -> bool {
+    BUS.lock().unsubscribe(id)
+}
+
+/// Deliver `event` to its subscribers. From interrupt context the event is
+/// queued for `dispatch_pending` instead.
+pub fn publish(event: Event) {
+    if !interrupts::are_enabled() {
+        // Never spin on the bus lock with interrupts off; the holder may be the code we interrupted
+        let queued = BUS.try_lock().map_or(false, |mut bus| bus.defer(event));
+        if !queued {
+            DROPPED_EVENTS.fetch_add(1, Ordering::Relaxed);
+        }
+        return;
+    }
+    dispatch(&event);
+}
+
+/// Deliver events queued from interrupt context. Call from the main loop.
+pub fn dispatch_pending() {
+    loop {
+        let event = match BUS.lock().take_pending() {
+            Some(event) => event,
+            None => break,
+        };
+        dispatch(&event);
+    }
+
+    let dropped = DROPPED_EVENTS.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        log::warn!("Event bus dropped {} events published from interrupt context", dropped);
+    }
+}
+
+fn dispatch(event: &Event) {
+    // Handlers may publish in turn; bound the recursion
+    if DISPATCH_DEPTH.fetch_add(1, Ordering::SeqCst) >= MAX_DISPATCH_DEPTH {
+        DISPATCH_DEPTH.fetch_sub(1, Ordering::SeqCst);
+        log::warn!("Event {:?} dropped: dispatch nested too deeply", event.kind());
+        return;
+    }
+
+    // Copy the handlers out so they can subscribe or publish without deadlocking
+    let handlers = BUS.lock().handlers_for(event.kind());
+    for handler in handlers {
+        handler(event);
+    }
+
+    DISPATCH_DEPTH.fetch_sub(1, Ordering::SeqCst);
+}
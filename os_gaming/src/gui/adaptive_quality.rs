@@ -0,0 +1,188 @@
+//! Adaptive quality
+//!
+//! Watches frame times and steps the GPU quality settings down one level at a
+//! time while the 1% low misses the target frame time, then back up once
+//! there is clear headroom. Settings never go above what the config asks for
+//! nor below the configured floor.
+//!
+//! To avoid oscillating between two levels, stepping up needs several
+//! consecutive evaluations with headroom, and a step up that has to be undone
+//! shortly after doubles that requirement.
+
+use alloc::vec::Vec;
+
+use super::frame_stats::FrameStats;
+use crate::config::SystemConfig;
+use crate::kernel::drivers::gpu::QualitySettings;
+
+/// Frames measured at one quality level before judging it
+const MIN_FRAMES: usize = 120;
+const WINDOW_FRAMES: usize = 240;
+/// Minimum time between evaluations
+const EVAL_INTERVAL_MS: u64 = 1000;
+/// Step down when the 1% low is this much slower than the target
+const SLOW_FACTOR: f32 = 1.15;
+/// Headroom means the 1% low is at most this fraction of the target
+const HEADROOM_FACTOR: f32 = 0.8;
+/// Consecutive evaluations with headroom needed to step up
+const BASE_HEADROOM_EVALS: u32 = 3;
+const MAX_HEADROOM_EVALS: u32 = 48;
+/// A step down this soon after a step up counts as a failed upgrade
+const PROBATION_MS: u64 = 10_000;
+
+/// One of the settings the controller adjusts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Setting {
+    Antialiasing,
+    Shadow,
+    Texture,
+}
+
+/// Lowered in this order, cheapest visual loss first
+const STEP_ORDER: [Setting; 3] = [Setting::Antialiasing, Setting::Shadow, Setting::Texture];
+
+fn level_mut(settings: &mut QualitySettings, setting: Setting) -> &mut u8 {
+    match setting {
+        Setting::Antialiasing => &mut settings.antialiasing,
+        Setting::Shadow => &mut settings.shadow,
+        Setting::Texture => &mut settings.texture,
+    }
+}
+
+fn level(mut settings: QualitySettings, setting: Setting) -> u8 {
+    *level_mut(&mut settings, setting)
+}
+
+pub struct AdaptiveQuality {
+    target_frame_ms: f32,
+    /// The configured settings, never exceeded
+    ceiling: QualitySettings,
+    floor: u8,
+    current: QualitySettings,
+    /// Settings lowered so far, most recent last; stepping up undoes them in reverse
+    lowered: Vec<Setting>,
+    /// Where in `STEP_ORDER` the next step down starts looking
+    next_step: usize,
+    /// Frames measured since the last change
+    stats: FrameStats,
+    last_eval_ms: u64,
+    headroom_evals: u32,
+    required_headroom_evals: u32,
+    /// When the last step up happened, while it is still on probation
+    upgraded_at_ms: Option<u64>,
+}
+
+impl AdaptiveQuality {
+    pub fn new(target_frame_ms: f32, ceiling: QualitySettings, floor: u8) -> Self {
+        Self {
+            target_frame_ms: target_frame_ms.max(1.0),
+            ceiling,
+            floor,
+            current: ceiling,
+            lowered: Vec::new(),
+            next_step: 0,
+            stats: FrameStats::new(WINDOW_FRAMES),
+            last_eval_ms: 0,
+            headroom_evals: 0,
+            required_headroom_evals: BASE_HEADROOM_EVALS,
+            upgraded_at_ms: None,
+        }
+    }
+
+    /// Controller for the config, or None when adaptive quality is off
+    pub fn from_config(config: &SystemConfig) -> Option<Self> {
+        if !config.gpu.adaptive_quality {
+            return None;
+        }
+        let fps = match (config.display.max_framerate, config.display.refresh_rate) {
+            (0, 0) => 60,
+            (0, refresh) => refresh,
+            (max, _) => max,
+        };
+        Some(Self::new(
+            1000.0 / fps as f32,
+            QualitySettings::from_config(&config.gpu),
+            config.gpu.adaptive_quality_floor,
+        ))
+    }
+
+    pub fn current(&self) -> QualitySettings {
+        self.current
+    }
+
+    pub fn target_frame_ms(&self) -> f32 {
+        self.target_frame_ms
+    }
+
+    /// Start over from new configured settings
+    pub fn reset(&mut self, ceiling: QualitySettings) {
+        *self = Self::new(self.target_frame_ms, ceiling, self.floor);
+    }
+
+    fn floor_for(&self, setting: Setting) -> u8 {
+        self.floor.min(level(self.ceiling, setting))
+    }
+
+    /// Record a frame time. Returns the new settings when they change.
+    pub fn record(&mut self, frame_ms: f32, now_ms: u64) -> Option<QualitySettings> {
+        self.stats.record(frame_ms);
+        if self.stats.len() < MIN_FRAMES || now_ms.saturating_sub(self.last_eval_ms) < EVAL_INTERVAL_MS {
+            return None;
+        }
+        self.last_eval_ms = now_ms;
+
+        if let Some(upgraded_at) = self.upgraded_at_ms {
+            if now_ms.saturating_sub(upgraded_at) >= PROBATION_MS {
+                // The last step up held, so trust headroom readings again
+                self.upgraded_at_ms = None;
+                self.required_headroom_evals = BASE_HEADROOM_EVALS;
+            }
+        }
+
+        let low_ms = self.stats.summary().low_1_ms;
+        if low_ms > self.target_frame_ms * SLOW_FACTOR {
+            self.headroom_evals = 0;
+            return self.step_down(now_ms);
+        }
+        if low_ms <= self.target_frame_ms * HEADROOM_FACTOR && !self.lowered.is_empty() {
+            self.headroom_evals += 1;
+            if self.headroom_evals >= self.required_headroom_evals {
+                return self.step_up(now_ms);
+            }
+        } else {
+            self.headroom_evals = 0;
+        }
+        None
+    }
+
+    fn step_down(&mut self, now_ms: u64) -> Option<QualitySettings> {
+        let setting = (0..STEP_ORDER.len())
+            .map(|i| STEP_ORDER[(self.next_step + i) % STEP_ORDER.len()])
+            .find(|&setting| level(self.current, setting) > self.floor_for(setting))?;
+        *level_mut(&mut self.current, setting) -= 1;
+        self.lowered.push(setting);
+        self.next_step = (STEP_ORDER.iter().position(|&s| s == setting).unwrap_or(0) + 1) % STEP_ORDER.len();
+
+        if self.upgraded_at_ms.take().is_some() {
+            self.required_headroom_evals = (self.required_headroom_evals * 2).min(MAX_HEADROOM_EVALS);
+        }
+        log::info!("Adaptive quality: lowering {:?} ({:?})", setting, self.current);
+        self.changed(now_ms)
+    }
+
+    fn step_up(&mut self, now_ms: u64) -> Option<QualitySettings> {
+        let setting = self.lowered.pop()?;
+        *level_mut(&mut self.current, setting) += 1;
+        self.upgraded_at_ms = Some(now_ms);
+        log::info!("Adaptive quality: raising {:?} ({:?})", setting, self.current);
+        self.changed(now_ms)
+    }
+
+    /// Measure the new level from scratch
+    fn changed(&mut self, now_ms: u64) -> Option<QualitySettings> {
+        self.stats.clear();
+        self.headroom_evals = 0;
+        self.last_eval_ms = now_ms;
+        Some(self.current)
+    }
+}
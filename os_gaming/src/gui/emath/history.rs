@@ -0,0 +1,110 @@
+//! Timestamped samples over a sliding window
+
+use alloc::collections::VecDeque;
+
+/// The most recent samples of a value, such as frame times. Samples older
+/// than `max_age` seconds behind the newest one, or beyond `max_len`, are
+/// dropped as new ones arrive.
+#[derive(Debug, Clone)]
+pub struct History {
+    /// (time in seconds, value), oldest first
+    samples: VecDeque<(f64, f32)>,
+    max_len: usize,
+    max_age: f64,
+}
+
+impl History {
+    pub fn new(max_len: usize, max_age: f64) -> Self {
+        Self { samples: VecDeque::with_capacity(max_len), max_len: max_len.max(1), max_age }
+    }
+
+    /// Record `value` at `time` seconds. Times should not go backwards.
+    pub fn add(&mut self, time: f64, value: f32) {
+        self.samples.push_back((time, value));
+        while self.samples.len() > self.max_len {
+            self.samples.pop_front();
+        }
+        while self.samples.front().map_or(false, |&(t, _)| time - t > self.max_age) {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// The newest value
+    pub fn latest(&self) -> Option<f32> {
+        self.samples.back().map(|&(_, value)| value)
+    }
+
+    /// Average of the kept values
+    pub fn mean(&self) -> Option<f32> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let sum: f32 = self.samples.iter().map(|&(_, value)| value).sum();
+        Some(sum / self.samples.len() as f32)
+    }
+
+    /// Seconds between the oldest and newest sample
+    pub fn duration(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some(&(first, _)), Some(&(last, _))) => last - first,
+            _ => 0.0,
+        }
+    }
+
+    /// Samples oldest first, as (time, value)
+    pub fn iter(&self) -> impl Iterator<Item = (f64, f32)> + '_ {
+        self.samples.iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::emath::ApproxEq;
+
+    #[test]
+    fn mean_of_kept_samples() {
+        let mut history = History::new(8, 10.0);
+        assert_eq!(history.mean(), None);
+        for (i, value) in [16.0, 17.0, 15.0, 20.0].into_iter().enumerate() {
+            history.add(i as f64 * 0.1, value);
+        }
+        assert!(history.mean().unwrap().approx_eq(&17.0, 1e-6));
+    }
+
+    #[test]
+    fn mean_forgets_samples_beyond_max_len() {
+        let mut history = History::new(2, 10.0);
+        history.add(0.0, 100.0);
+        history.add(0.1, 10.0);
+        history.add(0.2, 20.0);
+        assert_eq!(history.len(), 2);
+        assert!(history.mean().unwrap().approx_eq(&15.0, 1e-6));
+    }
+
+    #[test]
+    fn mean_forgets_samples_older_than_max_age() {
+        let mut history = History::new(8, 1.0);
+        history.add(0.0, 100.0);
+        history.add(0.5, 10.0);
+        history.add(1.5, 20.0);
+        assert_eq!(history.len(), 2);
+        assert!(history.mean().unwrap().approx_eq(&15.0, 1e-6));
+
+        history.clear();
+        assert_eq!(history.mean(), None);
+    }
+}
+
@@ -0,0 +1,28 @@
+//! Small 2D math for GUI geometry
+//!
+//! Float vectors for drag, resize and layout calculations, where the integer
+//! pixel `Rect` of the renderer loses precision, and a sample history for
+//! smoothing measurements such as frame times. Square roots and angles use
+//! micromath's approximations, so comparisons should go through `ApproxEq`.
+
+pub mod history;
+pub mod pos2;
+pub mod rect;
+pub mod vec2;
+
+pub use history::History;
+pub use pos2::Pos2;
+pub use rect::Rect;
+pub use vec2::Vec2;
+
+/// Equality within a tolerance, for values computed with approximations
+pub trait ApproxEq {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool;
+}
+
+impl ApproxEq for f32 {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        let diff = *self - *other;
+        diff <= epsilon && diff >= -epsilon
+    }
+}
@@ -0,0 +1,84 @@
+//! A 2D position
+
+use core::fmt;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+use super::{ApproxEq, Vec2};
+
+/// A point, as opposed to the offset a `Vec2` describes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pos2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Pos2 {
+    pub const ZERO: Pos2 = Pos2 { x: 0.0, y: 0.0 };
+
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    /// Offset of this point from the origin
+    pub fn to_vec2(self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+
+    pub fn distance(self, other: Pos2) -> f32 {
+        (self - other).length()
+    }
+
+    pub fn min(self, other: Pos2) -> Pos2 {
+        Pos2::new(self.x.min(other.x), self.y.min(other.y))
+    }
+
+    pub fn max(self, other: Pos2) -> Pos2 {
+        Pos2::new(self.x.max(other.x), self.y.max(other.y))
+    }
+}
+
+impl ApproxEq for Pos2 {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        self.x.approx_eq(&other.x, epsilon) && self.y.approx_eq(&other.y, epsilon)
+    }
+}
+
+impl Add<Vec2> for Pos2 {
+    type Output = Pos2;
+    fn add(self, rhs: Vec2) -> Pos2 {
+        Pos2::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl AddAssign<Vec2> for Pos2 {
+    fn add_assign(&mut self, rhs: Vec2) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Vec2> for Pos2 {
+    type Output = Pos2;
+    fn sub(self, rhs: Vec2) -> Pos2 {
+        Pos2::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl SubAssign<Vec2> for Pos2 {
+    fn sub_assign(&mut self, rhs: Vec2) {
+        *self = *self - rhs;
+    }
+}
+
+/// The offset from `rhs` to `self`
+impl Sub for Pos2 {
+    type Output = Vec2;
+    fn sub(self, rhs: Pos2) -> Vec2 {
+        Vec2::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl fmt::Display for Pos2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:.1} {:.1}]", self.x, self.y)
+    }
+}
@@ -0,0 +1,93 @@
+//! An axis-aligned rectangle in float coordinates
+
+use micromath::F32Ext;
+
+use super::{ApproxEq, Pos2, Vec2};
+use crate::gui::renderer;
+
+/// The area from `min` (inclusive) to `max` (exclusive), y down like the screen
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rect {
+    pub min: Pos2,
+    pub max: Pos2,
+}
+
+impl Rect {
+    pub const fn from_min_max(min: Pos2, max: Pos2) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_min_size(min: Pos2, size: Vec2) -> Self {
+        Self { min, max: min + size }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.max.x - self.min.x
+    }
+
+    pub fn height(&self) -> f32 {
+        self.max.y - self.min.y
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    pub fn center(&self) -> Pos2 {
+        self.min + self.size() / 2.0
+    }
+
+    /// Whether the rect covers no area
+    pub fn is_empty(&self) -> bool {
+        self.width() <= 0.0 || self.height() <= 0.0
+    }
+
+    /// Whether `p` lies inside; the right and bottom edges are outside, so
+    /// rects that share an edge never both contain a point
+    pub fn contains(&self, p: Pos2) -> bool {
+        p.x >= self.min.x && p.x < self.max.x && p.y >= self.min.y && p.y < self.max.y
+    }
+
+    /// The overlap of both rects, None when they only touch or are apart
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let overlap = Rect::from_min_max(self.min.max(other.min), self.max.min(other.max));
+        if overlap.is_empty() {
+            None
+        } else {
+            Some(overlap)
+        }
+    }
+
+    /// The smallest rect covering both
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect::from_min_max(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// Grow by `amount` on every side; a negative amount shrinks
+    pub fn expand(&self, amount: f32) -> Rect {
+        let margin = Vec2::new(amount, amount);
+        Rect::from_min_max(self.min - margin, self.max + margin)
+    }
+
+    /// The pixels this rect touches, rounding outward
+    pub fn to_pixels(&self) -> renderer::Rect {
+        let x = self.min.x.floor() as i32;
+        let y = self.min.y.floor() as i32;
+        let right = self.max.x.ceil() as i32;
+        let bottom = self.max.y.ceil() as i32;
+        renderer::Rect::new(x, y, (right - x).max(0) as u32, (bottom - y).max(0) as u32)
+    }
+}
+
+impl From<renderer::Rect> for Rect {
+    fn from(rect: renderer::Rect) -> Self {
+        let min = Pos2::new(rect.x as f32, rect.y as f32);
+        Rect::from_min_size(min, Vec2::new(rect.width as f32, rect.height as f32))
+    }
+}
+
+impl ApproxEq for Rect {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        self.min.approx_eq(&other.min, epsilon) && self.max.approx_eq(&other.max, epsilon)
+    }
+}
@@ -0,0 +1,152 @@
+//! A 2D vector
+
+use core::fmt;
+use core::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+use micromath::F32Ext;
+
+use super::ApproxEq;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub const ZERO: Vec2 = Vec2 { x: 0.0, y: 0.0 };
+
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn dot(self, other: Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// Z component of the 3D cross product; positive when `other` is
+    /// counter-clockwise from `self` in y-up coordinates
+    pub fn cross(self, other: Vec2) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+
+    /// Squared length, exact and cheaper than `length` for comparisons
+    pub fn length_sq(self) -> f32 {
+        self.dot(self)
+    }
+
+    pub fn length(self) -> f32 {
+        let length_sq = self.length_sq();
+        // micromath's estimate can be a few percent off; two Newton steps
+        // bring it to f32 precision. Called through the trait so host builds
+        // run the same math instead of std's inherent sqrt.
+        let mut length = F32Ext::sqrt(length_sq);
+        if length > 0.0 {
+            for _ in 0..2 {
+                length = 0.5 * (length + length_sq / length);
+            }
+        }
+        length
+    }
+
+    /// This vector scaled to length 1; the zero vector stays zero
+    pub fn normalized(self) -> Vec2 {
+        let length = self.length();
+        if length > 0.0 {
+            self / length
+        } else {
+            Vec2::ZERO
+        }
+    }
+
+    pub fn distance(self, other: Vec2) -> f32 {
+        (self - other).length()
+    }
+
+    /// Angle from the positive x axis in radians, in -PI..=PI
+    pub fn angle(self) -> f32 {
+        self.y.atan2(self.x)
+    }
+}
+
+impl ApproxEq for Vec2 {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        self.x.approx_eq(&other.x, epsilon) && self.y.approx_eq(&other.y, epsilon)
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Vec2;
+    fn add(self, rhs: Vec2) -> Vec2 {
+        Vec2::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl AddAssign for Vec2 {
+    fn add_assign(&mut self, rhs: Vec2) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Vec2;
+    fn sub(self, rhs: Vec2) -> Vec2 {
+        Vec2::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl SubAssign for Vec2 {
+    fn sub_assign(&mut self, rhs: Vec2) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul<f32> for Vec2 {
+    type Output = Vec2;
+    fn mul(self, factor: f32) -> Vec2 {
+        Vec2::new(self.x * factor, self.y * factor)
+    }
+}
+
+impl Div<f32> for Vec2 {
+    type Output = Vec2;
+    fn div(self, divisor: f32) -> Vec2 {
+        Vec2::new(self.x / divisor, self.y / divisor)
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Vec2;
+    fn neg(self) -> Vec2 {
+        Vec2::new(-self.x, -self.y)
+    }
+}
+
+impl fmt::Display for Vec2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:.1} {:.1}]", self.x, self.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_vectors_have_unit_length() {
+        for v in [
+            Vec2::new(3.0, 4.0),
+            Vec2::new(-0.001, 0.002),
+            Vec2::new(1920.0, -1080.0),
+            Vec2::new(0.0, -7.5),
+        ] {
+            let length = v.normalized().length();
+            assert!(length.approx_eq(&1.0, 1e-3), "{} normalized to length {}", v, length);
+        }
+    }
+
+    #[test]
+    fn zero_vector_stays_zero() {
+        assert_eq!(Vec2::ZERO.normalized(), Vec2::ZERO);
+    }
+}
+
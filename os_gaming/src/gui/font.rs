@@ -8,6 +8,23 @@ use alloc::sync::Arc;
 use alloc::{format, vec};
 use hashbrown::HashMap;
 use core::convert::AsRef;
+use lazy_static::lazy_static;
+use micromath::F32Ext;
+use spin::Mutex;
+
+use super::bitmap_font;
+use super::glyph_raster::{GlyphBitmap, Rasterizer};
+use super::ttf_parser::{self, Face};
+
+lazy_static! {
+    /// Fonts set up by `gui::init_fonts`
+    static ref FONT_MANAGER: Mutex<FontManager> = Mutex::new(FontManager::new());
+}
+
+/// The font manager shared by the GUI
+pub fn font_manager() -> &'static Mutex<FontManager> {
+    &FONT_MANAGER
+}
 
 #[derive(Default)]
 pub struct FontDefinitions {
@@ -46,10 +63,124 @@ impl FontId {
     }
 }
 
+/// Rasterized glyphs kept before the cache starts over; bounds its share of the heap
+const MAX_CACHED_GLYPHS: usize = 512;
+
+/// Line height as a multiple of the font size
+pub const LINE_SPACING: f32 = 1.2;
+/// Tab stops are this many spaces apart
+pub const TAB_WIDTH_SPACES: f32 = 4.0;
+
+/// One line of wrapped text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineLayout {
+    /// Byte range of the line in the source text, without the line break or trailing whitespace
+    pub start: usize,
+    pub end: usize,
+    pub width: f32,
+    /// Top of the line, relative to the top of the first line
+    pub y: f32,
+    pub height: f32,
+}
+
+/// Total height of laid out lines
+pub fn layout_height(lines: &[LineLayout]) -> f32 {
+    lines.last().map_or(0.0, |line| line.y + line.height)
+}
+
+/// Double-width characters (CJK, Hangul, fullwidth forms, most emoji).
+/// Lines may break before or after any of them.
+pub fn is_wide_char(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x115F
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x2FFFD
+        | 0x30000..=0x3FFFD)
+}
+
+/// Advance of `c` as a fraction of the font size.
+/// Proportional metrics approximating a typical sans-serif face.
+fn advance_em(c: char) -> f32 {
+    match c {
+        '\u{0300}'..='\u{036F}' | '\u{200B}'..='\u{200D}' => 0.0,
+        c if c.is_control() => 0.0,
+        ' ' | 'i' | 'l' | 'j' | '!' | '.' | ',' | ':' | ';' | '\'' | '|' => 0.28,
+        'f' | 't' | 'r' | 'I' | '(' | ')' | '[' | ']' | '-' => 0.33,
+        'm' | 'w' | 'M' | 'W' | '@' => 0.83,
+        'A'..='Z' => 0.67,
+        '0'..='9' | 'a'..='z' => 0.55,
+        c if c.is_ascii() => 0.45,
+        c if is_wide_char(c) => 1.0,
+        _ => 0.6,
+    }
+}
+
+/// Pair adjustments for the built-in metrics, in ems (negative tightens)
+const BUILTIN_KERNING: &[(char, char, f32)] = &[
+    ('A', 'V', -0.07), ('V', 'A', -0.07),
+    ('A', 'W', -0.05), ('W', 'A', -0.05),
+    ('A', 'Y', -0.08), ('Y', 'A', -0.08),
+    ('A', 'T', -0.07), ('T', 'A', -0.07),
+    ('L', 'T', -0.08), ('L', 'V', -0.07), ('L', 'Y', -0.09), ('L', 'W', -0.05),
+    ('T', 'o', -0.07), ('T', 'e', -0.07), ('T', 'a', -0.07), ('T', 'y', -0.05), ('T', 'r', -0.04),
+    ('V', 'o', -0.04), ('V', 'a', -0.05), ('V', 'e', -0.04),
+    ('W', 'o', -0.03), ('W', 'a', -0.03), ('W', 'e', -0.03),
+    ('Y', 'o', -0.07), ('Y', 'a', -0.07), ('Y', 'e', -0.07),
+    ('F', 'a', -0.04), ('F', '.', -0.1), ('F', ',', -0.1),
+    ('P', '.', -0.1), ('P', ',', -0.1),
+    ('T', '.', -0.1), ('T', ',', -0.1),
+    ('V', '.', -0.08), ('V', ',', -0.08),
+    ('Y', '.', -0.1), ('Y', ',', -0.1),
+    ('r', '.', -0.05), ('r', ',', -0.05),
+];
+
+/// Kerning pair adjustments for one font, in ems
+#[derive(Debug, Clone, Default)]
+pub struct KerningTable {
+    pairs: HashMap<(char, char), f32>,
+}
+
+impl KerningTable {
+    pub fn from_pairs(pairs: &[(char, char, f32)]) -> Self {
+        Self {
+            pairs: pairs.iter().map(|&(left, right, em)| ((left, right), em)).collect(),
+        }
+    }
+
+    /// Table matching the built-in metrics
+    pub fn builtin() -> Self {
+        Self::from_pairs(BUILTIN_KERNING)
+    }
+
+    /// Adjustment between `left` and `right` in ems; 0 for pairs not in the table
+    pub fn adjustment(&self, left: char, right: char) -> f32 {
+        self.pairs.get(&(left, right)).copied().unwrap_or(0.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
 pub struct FontManager {
     fonts: HashMap<String, usize>,
     font_definitions: FontDefinitions,
     sizes: HashMap<String, f32>,
+    /// Kerning tables by font name
+    kerning: HashMap<String, KerningTable>,
+    builtin_kerning: KerningTable,
+    kerning_enabled: bool,
+    /// No TrueType font could be loaded; text uses the built-in bitmap font
+    bitmap_fallback: bool,
+    /// Rasterized glyphs by (font id, character, size bits)
+    glyph_cache: Mutex<HashMap<(usize, char, u32), GlyphBitmap>>,
 }
 
 impl FontManager {
@@ -58,10 +189,172 @@ impl FontManager {
             fonts: HashMap::new(),
             font_definitions: FontDefinitions::default(),
             sizes: HashMap::new(),
+            kerning: HashMap::new(),
+            builtin_kerning: KerningTable::builtin(),
+            kerning_enabled: true,
+            bitmap_fallback: false,
+            glyph_cache: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Check that `data` is a font the GUI can draw with: it must parse and
+    /// map all of basic Latin to glyphs
+    pub fn validate_font(data: &[u8]) -> Result<(), String> {
+        let face = Face::from_slice(data, 0).map_err(|e| format!("Invalid font: {}", e))?;
+        if !face.covers(ttf_parser::BASIC_LATIN_REQUIRED) {
+            return Err("Font does not cover basic Latin".to_string());
+        }
+        Ok(())
+    }
+
+    /// Render text with the built-in 8x8 bitmap font. Used when no TrueType
+    /// font loads, so the system is never without text.
+    pub fn use_bitmap_fallback(&mut self) {
+        self.bitmap_fallback = true;
+    }
+
+    pub fn is_bitmap_fallback(&self) -> bool {
+        self.bitmap_fallback
+    }
+
+    /// Coverage of `c` from the bitmap font at the integer scale closest to
+    /// `size`, with the bitmap's width and height
+    pub fn bitmap_glyph(&self, c: char, size: f32) -> (Vec<u8>, u32, u32) {
+        let scale = ((size / bitmap_font::GLYPH_HEIGHT as f32).round() as u32).max(1);
+        (
+            bitmap_font::coverage(c, scale),
+            bitmap_font::GLYPH_WIDTH * scale,
+            bitmap_font::GLYPH_HEIGHT * scale,
+        )
+    }
+
+    /// Id of the loaded font `name`, for `rasterize_glyph`
+    pub fn font_id(&self, name: &str) -> Option<usize> {
+        self.fonts.get(name).copied()
+    }
+
+    /// The first loaded font of the proportional family
+    pub fn default_font_id(&self) -> Option<usize> {
+        self.font_definitions
+            .families
+            .get(&FontFamily::Proportional)?
+            .iter()
+            .find_map(|name| self.font_id(name))
+    }
+
+    fn font_data_by_id(&self, font_id: usize) -> Option<&Arc<FontData>> {
+        let name = self.fonts.iter().find(|(_, &id)| id == font_id)?.0;
+        self.font_definitions.font_data.get(name)
+    }
+
+    /// Scan-convert `c` from font `font_id` at `size` pixels per em.
+    /// None when the font is not loaded or doesn't cover `c`.
+    pub fn rasterize_glyph(&self, font_id: usize, c: char, size: f32) -> Option<GlyphBitmap> {
+        let key = (font_id, c, size.to_bits());
+        if let Some(glyph) = self.glyph_cache.lock().get(&key) {
+            return Some(glyph.clone());
+        }
+
+        let data = self.font_data_by_id(font_id)?;
+        let face = Face::from_slice(&data.data, 0).ok()?;
+        let id = face.glyph_index(c)?;
+        let scale = size / face.units_per_em().max(1) as f32;
+        let advance = face.glyph_hor_advance(id).map_or(advance_em(c) * size, |units| units as f32 * scale);
+
+        let outlined = face.glyph_bounding_box(id).and_then(|bbox| {
+            let mut rasterizer = Rasterizer::new(bbox, scale);
+            face.outline_glyph(id, &mut rasterizer)?;
+            Some(rasterizer)
+        });
+        let glyph = match outlined {
+            Some(rasterizer) => {
+                let (width, height) = rasterizer.size();
+                let (left, top) = rasterizer.offset();
+                GlyphBitmap { width, height, left, top, advance, coverage: rasterizer.fill() }
+            }
+            // Spaces, and composite glyphs until they are supported
+            None => GlyphBitmap::empty(advance),
+        };
+
+        let mut cache = self.glyph_cache.lock();
+        if cache.len() >= MAX_CACHED_GLYPHS {
+            cache.clear();
+        }
+        cache.insert(key, glyph.clone());
+        Some(glyph)
+    }
+
+    /// `c` at `size` from the default font, or from the bitmap font when no
+    /// TrueType font is usable or the default one lacks `c`
+    pub fn glyph(&self, c: char, size: f32) -> GlyphBitmap {
+        if !self.bitmap_fallback {
+            if let Some(glyph) = self.default_font_id().and_then(|id| self.rasterize_glyph(id, c, size)) {
+                return glyph;
+            }
+        }
+        let (coverage, width, height) = self.bitmap_glyph(c, size);
+        GlyphBitmap {
+            width,
+            height,
+            left: 0,
+            // Bitmap glyphs hang from the top of the line
+            top: self.ascent(size).round() as i32,
+            advance: width as f32,
+            coverage,
+        }
+    }
+
+    /// Distance from the top of a line to its baseline at `size`
+    pub fn ascent(&self, size: f32) -> f32 {
+        let face = self
+            .default_font_id()
+            .and_then(|id| self.font_data_by_id(id))
+            .and_then(|data| Face::from_slice(&data.data, 0).ok());
+        match face {
+            Some(face) if !self.bitmap_fallback => face.ascender() as f32 * size / face.units_per_em().max(1) as f32,
+            _ => size,
+        }
+    }
+
+    /// Use `table` for pair adjustments whenever `font_name` is the active proportional font
+    pub fn set_kerning_table(&mut self, font_name: &str, table: KerningTable) {
+        self.kerning.insert(font_name.to_string(), table);
+    }
+
+    /// Turn kerning off for contexts that need a fixed grid, such as the console
+    pub fn set_kerning_enabled(&mut self, enabled: bool) {
+        self.kerning_enabled = enabled;
+    }
+
+    pub fn kerning_enabled(&self) -> bool {
+        self.kerning_enabled
+    }
+
+    /// Kerning for the active proportional font. Loaded fonts without a table
+    /// get plain advances; with no font loaded the built-in metrics' table applies.
+    fn active_kerning(&self) -> Option<&KerningTable> {
+        if !self.kerning_enabled {
+            return None;
+        }
+        let loaded = self
+            .font_definitions
+            .families
+            .get(&FontFamily::Proportional)
+            .and_then(|names| names.iter().find(|name| self.font_definitions.font_data.contains_key(*name)));
+        match loaded {
+            Some(name) => self.kerning.get(name),
+            None => Some(&self.builtin_kerning),
+        }
+    }
+
+    /// Pair adjustment between `left` and `right` at `size`; 0 when kerning is off
+    pub fn kerning(&self, left: char, right: char, size: f32) -> f32 {
+        self.active_kerning().map_or(0.0, |table| table.adjustment(left, right) * size)
+    }
+
+    /// Add a TrueType/OpenType font, rejecting data `validate_font` refuses
     pub fn load_font(&mut self, name: &str, font_data: &[u8]) -> Result<(), String> {
+        Self::validate_font(font_data)?;
         let font_index = self.font_definitions.font_data.len();
 
         self.font_definitions.font_data.insert(
@@ -71,6 +364,8 @@ impl FontManager {
             }),
         );
         self.fonts.insert(name.to_string(), font_index);
+        // Ids can be reused when a font is replaced
+        self.glyph_cache.lock().clear();
 
         Ok(())
     }
@@ -107,17 +402,126 @@ impl FontManager {
         self.sizes.insert(element.to_string(), size);
     }
 
-    pub fn load_font_from_memory(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
-        let font_index = self.font_definitions.font_data.len();
-        self.font_definitions.font_data.insert(
-            name.to_string(),
-            Arc::new(FontData {
-                data: data.to_vec(),
-            }),
-        );
-        self.fonts.insert(name.to_string(), font_index);
+    /// Horizontal advance of `c` at `size`, for a pen at `pen_x` (tabs jump to the next stop)
+    pub fn glyph_advance(&self, c: char, pen_x: f32, size: f32) -> f32 {
+        if c == '\t' {
+            let stop = advance_em(' ') * size * TAB_WIDTH_SPACES;
+            return ((pen_x / stop).floor() + 1.0) * stop - pen_x;
+        }
+        advance_em(c) * size
+    }
 
-        Ok(())
+    pub fn line_height(&self, size: f32) -> f32 {
+        size * LINE_SPACING
+    }
+
+    /// Advance of `c` after `prev`, including the pair's kerning.
+    /// Whitespace is never kerned.
+    fn kerned_advance(&self, prev: Option<char>, c: char, pen_x: f32, size: f32) -> f32 {
+        let kern = match prev {
+            Some(left) if !c.is_whitespace() => self.kerning(left, c, size),
+            _ => 0.0,
+        };
+        self.glyph_advance(c, pen_x, size) + kern
+    }
+
+    /// Width of a single line, ignoring newlines
+    fn line_width(&self, line: &str, size: f32) -> f32 {
+        let mut prev = None;
+        line.chars().fold(0.0, |x, c| {
+            let advance = self.kerned_advance(prev, c, x, size);
+            prev = if c.is_whitespace() { None } else { Some(c) };
+            x + advance
+        })
+    }
+
+    /// Size of `text` at `size`: the widest line and the height of all lines.
+    /// Explicit newlines start new lines; nothing is wrapped.
+    pub fn measure_text(&self, text: &str, size: f32) -> (f32, f32) {
+        let mut width: f32 = 0.0;
+        let mut lines = 0;
+        for line in text.split('\n') {
+            width = width.max(self.line_width(line.trim_end_matches('\r'), size));
+            lines += 1;
+        }
+        (width, lines as f32 * self.line_height(size))
+    }
+
+    /// Break `text` into lines no wider than `max_width`.
+    ///
+    /// Lines break at spaces and tabs, around wide characters and at explicit
+    /// newlines. A word longer than `max_width` is split where it overflows.
+    /// Trailing whitespace is not counted towards a line's width. Widths
+    /// include kerning, so a renderer drawing the lines should advance with
+    /// `kerning` between characters as well.
+    pub fn layout_wrapped(&self, text: &str, max_width: f32, size: f32) -> Vec<LineLayout> {
+        let height = self.line_height(size);
+        let mut lines = Vec::new();
+        let push_line = |lines: &mut Vec<LineLayout>, start: usize, end: usize| {
+            let trimmed = text[start..end].trim_end_matches(|c| c == ' ' || c == '\t' || c == '\r');
+            let y = lines.len() as f32 * height;
+            lines.push(LineLayout {
+                start,
+                end: start + trimmed.len(),
+                width: self.line_width(trimmed, size),
+                y,
+                height,
+            });
+        };
+
+        let mut paragraph_start = 0;
+        for paragraph in text.split('\n') {
+            let paragraph_end = paragraph_start + paragraph.len();
+            let mut line_start = paragraph_start;
+            // Where the current line may be broken, i.e. the start of the next word
+            let mut break_at: Option<usize> = None;
+            let mut x = 0.0;
+            let mut i = line_start;
+            let mut prev: Option<char> = None;
+
+            while i < paragraph_end {
+                let c = text[i..].chars().next().unwrap_or(' ');
+                let advance = self.kerned_advance(prev, c, x, size);
+
+                // Whitespace may hang past the edge; it is trimmed from the line
+                if c == ' ' || c == '\t' {
+                    x += advance;
+                    i += c.len_utf8();
+                    break_at = Some(i);
+                    prev = None;
+                    continue;
+                }
+                if is_wide_char(c) && i > line_start {
+                    break_at = Some(i);
+                }
+                if x + advance > max_width && i > line_start {
+                    let next = break_at.filter(|&b| b > line_start).unwrap_or(i);
+                    push_line(&mut lines, line_start, next);
+                    line_start = next;
+                    i = next;
+                    x = 0.0;
+                    break_at = None;
+                    prev = None;
+                    continue;
+                }
+
+                x += advance;
+                i += c.len_utf8();
+                prev = Some(c);
+                if is_wide_char(c) {
+                    break_at = Some(i);
+                }
+            }
+
+            push_line(&mut lines, line_start, paragraph_end);
+            paragraph_start = paragraph_end + 1;
+        }
+
+        lines
+    }
+
+    pub fn load_font_from_memory(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        self.load_font(name, data)
     }
 }
 
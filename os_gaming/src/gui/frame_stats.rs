@@ -0,0 +1,187 @@
+//! Frame time statistics
+//!
+//! `FrameStats` keeps a window of recent frame times and derives FPS figures
+//! from it: current, average, min/max and the "1% low" and "0.1% low", the
+//! average of the slowest 1% (0.1%) of frames. The GUI loop feeds it once per
+//! frame; the performance overlay and adaptive quality read it.
+
+use alloc::{vec, vec::Vec};
+
+/// Frames kept by default, about 8 seconds at 120 FPS
+pub const DEFAULT_WINDOW: usize = 1000;
+
+/// Derived figures, all frame times in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameSummary {
+    pub frames: usize,
+    pub last_ms: f32,
+    pub average_ms: f32,
+    pub min_ms: f32,
+    pub max_ms: f32,
+    /// Mean of the slowest 1% of frames
+    pub low_1_ms: f32,
+    /// Mean of the slowest 0.1% of frames
+    pub low_01_ms: f32,
+}
+
+impl FrameSummary {
+    pub fn current_fps(&self) -> f32 {
+        fps_from_ms(self.last_ms)
+    }
+
+    pub fn average_fps(&self) -> f32 {
+        fps_from_ms(self.average_ms)
+    }
+
+    pub fn one_percent_low_fps(&self) -> f32 {
+        fps_from_ms(self.low_1_ms)
+    }
+
+    pub fn point_one_percent_low_fps(&self) -> f32 {
+        fps_from_ms(self.low_01_ms)
+    }
+}
+
+/// Ring buffer of frame times with a running total
+pub struct FrameStats {
+    /// Frame times in milliseconds, oldest sample at `head` once full
+    samples: Vec<f32>,
+    head: usize,
+    len: usize,
+    /// Sum of the held samples, kept up to date on every push
+    total_ms: f64,
+    /// Summary of the current window, invalidated by `record`
+    cached: Option<FrameSummary>,
+    /// Scratch space for the percentile selection
+    scratch: Vec<f32>,
+}
+
+impl FrameStats {
+    /// Keep the last `capacity` frame times
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            samples: vec![0.0; capacity],
+            head: 0,
+            len: 0,
+            total_ms: 0.0,
+            cached: None,
+            scratch: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Add one frame time, evicting the oldest once the window is full
+    pub fn record(&mut self, frame_ms: f32) {
+        if frame_ms.is_nan() || frame_ms <= 0.0 {
+            return;
+        }
+        let capacity = self.samples.len();
+        let slot = (self.head + self.len) % capacity;
+        if self.len < capacity {
+            self.len += 1;
+        } else {
+            self.total_ms -= self.samples[slot] as f64;
+            self.head = (self.head + 1) % capacity;
+        }
+        self.samples[slot] = frame_ms;
+        self.total_ms += frame_ms as f64;
+        self.cached = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.total_ms = 0.0;
+        self.cached = None;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Samples from oldest to newest
+    pub fn samples(&self) -> impl Iterator<Item = f32> + '_ {
+        let capacity = self.samples.len();
+        (0..self.len).map(move |i| self.samples[(self.head + i) % capacity])
+    }
+
+    /// The newest `count` samples, oldest first
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = f32> + '_ {
+        self.samples().skip(self.len.saturating_sub(count))
+    }
+
+    pub fn last_ms(&self) -> f32 {
+        match self.len {
+            0 => 0.0,
+            len => self.samples[(self.head + len - 1) % self.samples.len()],
+        }
+    }
+
+    pub fn average_ms(&self) -> f32 {
+        match self.len {
+            0 => 0.0,
+            len => (self.total_ms / len as f64) as f32,
+        }
+    }
+
+    /// Statistics for the current window. Computed at most once per recorded frame.
+    pub fn summary(&mut self) -> FrameSummary {
+        if let Some(summary) = self.cached {
+            return summary;
+        }
+        let summary = self.compute();
+        self.cached = Some(summary);
+        summary
+    }
+
+    fn compute(&mut self) -> FrameSummary {
+        if self.len == 0 {
+            return FrameSummary::default();
+        }
+        let mut scratch = core::mem::take(&mut self.scratch);
+        scratch.clear();
+        scratch.extend(self.samples());
+
+        let (min_ms, max_ms) = scratch
+            .iter()
+            .fold((f32::MAX, 0.0f32), |(lo, hi), &ms| (lo.min(ms), hi.max(ms)));
+        let low_1_ms = mean_of_slowest(&mut scratch, 100);
+        let low_01_ms = mean_of_slowest(&mut scratch, 1000);
+
+        let summary = FrameSummary {
+            frames: self.len,
+            last_ms: self.last_ms(),
+            average_ms: self.average_ms(),
+            min_ms,
+            max_ms,
+            low_1_ms,
+            low_01_ms,
+        };
+        self.scratch = scratch;
+        summary
+    }
+}
+
+/// Mean of the slowest `1/divisor` of `samples` (at least one frame).
+/// Reorders `samples`.
+fn mean_of_slowest(samples: &mut [f32], divisor: usize) -> f32 {
+    let worst = ((samples.len() + divisor - 1) / divisor).max(1);
+    // Partition so the `worst` largest values come first
+    let by_slowest = |a: &f32, b: &f32| b.partial_cmp(a).unwrap_or(core::cmp::Ordering::Equal);
+    if worst < samples.len() {
+        samples.select_nth_unstable_by(worst - 1, by_slowest);
+    }
+    samples[..worst].iter().sum::<f32>() / worst as f32
+}
+
+pub fn fps_from_ms(frame_ms: f32) -> f32 {
+    if frame_ms > 0.0 { 1000.0 / frame_ms } else { 0.0 }
+}
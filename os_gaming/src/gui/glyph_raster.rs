@@ -0,0 +1,184 @@
+//! Scan conversion of TrueType outlines into coverage bitmaps
+//!
+//! Curves are flattened to line segments in pixel space, then each pixel row
+//! is sampled on a few sub-scanlines with the non-zero winding rule. Span ends
+//! get fractional coverage, which anti-aliases edges in both directions.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use micromath::F32Ext;
+
+use super::ttf_parser::{OutlineBuilder, Rect};
+
+/// Sub-scanlines per pixel row
+const SUBSAMPLES: usize = 4;
+/// Line segments per quadratic curve
+const CURVE_STEPS: usize = 8;
+
+/// A glyph drawn at one size: 8-bit alpha coverage plus where to put it
+#[derive(Debug, Clone)]
+pub struct GlyphBitmap {
+    pub width: u32,
+    pub height: u32,
+    /// Offset of the bitmap's left edge from the pen position
+    pub left: i32,
+    /// Rows of the bitmap above the baseline
+    pub top: i32,
+    /// How far the pen moves after this glyph, in pixels
+    pub advance: f32,
+    /// `width * height` coverage values, row by row, 255 fully inside
+    pub coverage: Vec<u8>,
+}
+
+impl GlyphBitmap {
+    /// A glyph with nothing to draw, such as a space
+    pub fn empty(advance: f32) -> Self {
+        Self { width: 0, height: 0, left: 0, top: 0, advance, coverage: Vec::new() }
+    }
+}
+
+/// Collects a glyph outline as line segments in bitmap pixel coordinates
+pub struct Rasterizer {
+    scale: f32,
+    /// Bitmap origin in scaled font units
+    origin_x: f32,
+    origin_y: f32,
+    width: u32,
+    height: u32,
+    /// (x0, y0, x1, y1), y down
+    edges: Vec<(f32, f32, f32, f32)>,
+    current: (f32, f32),
+    start: (f32, f32),
+}
+
+impl Rasterizer {
+    /// Size a bitmap for `bbox` (font units) drawn at `scale` pixels per unit
+    pub fn new(bbox: Rect, scale: f32) -> Self {
+        let left = (bbox.x_min as f32 * scale).floor();
+        let right = (bbox.x_max as f32 * scale).ceil();
+        let bottom = (bbox.y_min as f32 * scale).floor();
+        let top = (bbox.y_max as f32 * scale).ceil();
+        Self {
+            scale,
+            origin_x: left,
+            origin_y: top,
+            width: (right - left).max(0.0) as u32,
+            height: (top - bottom).max(0.0) as u32,
+            edges: Vec::new(),
+            current: (0.0, 0.0),
+            start: (0.0, 0.0),
+        }
+    }
+
+    /// Left edge and top row relative to the pen and baseline
+    pub fn offset(&self) -> (i32, i32) {
+        (self.origin_x as i32, self.origin_y as i32)
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn to_pixels(&self, x: f32, y: f32) -> (f32, f32) {
+        (x * self.scale - self.origin_x, self.origin_y - y * self.scale)
+    }
+
+    fn edge_to(&mut self, to: (f32, f32)) {
+        let from = self.current;
+        // Horizontal edges never cross a sample row
+        if from.1 != to.1 {
+            self.edges.push((from.0, from.1, to.0, to.1));
+        }
+        self.current = to;
+    }
+
+    /// Scan-convert the collected outline into `width * height` coverage values
+    pub fn fill(&self) -> Vec<u8> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut accumulated = vec![0.0f32; width * height];
+        let mut crossings: Vec<(f32, i32)> = Vec::new();
+        let weight = 1.0 / SUBSAMPLES as f32;
+
+        for row in 0..height {
+            let line = &mut accumulated[row * width..(row + 1) * width];
+            for sample in 0..SUBSAMPLES {
+                let y = row as f32 + (sample as f32 + 0.5) * weight;
+                crossings.clear();
+                for &(x0, y0, x1, y1) in &self.edges {
+                    let (low, high) = if y0 < y1 { (y0, y1) } else { (y1, y0) };
+                    if y < low || y >= high {
+                        continue;
+                    }
+                    let x = x0 + (y - y0) / (y1 - y0) * (x1 - x0);
+                    crossings.push((x, if y1 > y0 { 1 } else { -1 }));
+                }
+                crossings.sort_unstable_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(core::cmp::Ordering::Equal));
+
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    if winding != 0 {
+                        add_span(line, pair[0].0, pair[1].0, weight);
+                    }
+                }
+            }
+        }
+
+        accumulated.iter().map(|&c| (c.min(1.0) * 255.0 + 0.5) as u8).collect()
+    }
+}
+
+/// Add `weight` to the pixels between `x0` and `x1`, partially at the ends
+fn add_span(line: &mut [f32], x0: f32, x1: f32, weight: f32) {
+    let x0 = x0.max(0.0);
+    let x1 = x1.min(line.len() as f32);
+    if x1 <= x0 {
+        return;
+    }
+    let first = x0 as usize;
+    let last = x1 as usize;
+    if first == last {
+        line[first] += (x1 - x0) * weight;
+        return;
+    }
+    line[first] += (first as f32 + 1.0 - x0) * weight;
+    for pixel in &mut line[first + 1..last] {
+        *pixel += weight;
+    }
+    if last < line.len() {
+        line[last] += (x1 - last as f32) * weight;
+    }
+}
+
+impl OutlineBuilder for Rasterizer {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let point = self.to_pixels(x, y);
+        self.current = point;
+        self.start = point;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let point = self.to_pixels(x, y);
+        self.edge_to(point);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (cx, cy) = self.to_pixels(x1, y1);
+        let (ex, ey) = self.to_pixels(x, y);
+        let (sx, sy) = self.current;
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let u = 1.0 - t;
+            let px = u * u * sx + 2.0 * u * t * cx + t * t * ex;
+            let py = u * u * sy + 2.0 * u * t * cy + t * t * ey;
+            self.edge_to((px, py));
+        }
+    }
+
+    fn close(&mut self) {
+        let start = self.start;
+        if self.current != start {
+            self.edge_to(start);
+        }
+    }
+}
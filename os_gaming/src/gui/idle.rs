@@ -0,0 +1,99 @@
+//! Screen idle timeout
+//!
+//! After `screen_timeout` seconds without input the screen dims, and after a
+//! further `screen_dim_duration` seconds it blanks. Any input wakes it at
+//! once. Every frame is redrawn from the windows, so waking only needs
+//! rendering to resume for the previous content to come back.
+
+use crate::config::PowerConfig;
+
+/// Darkest the dim overlay gets
+const DIM_ALPHA: u8 = 170;
+/// How long dimming fades in
+const DIM_FADE_MS: u64 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleState {
+    Active,
+    Dimmed,
+    Blanked,
+}
+
+/// A change the display has to act on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleTransition {
+    Dim,
+    Blank,
+    Wake,
+}
+
+pub struct IdleMonitor {
+    /// Idle time before dimming; 0 disables the timeout
+    timeout_ms: u64,
+    dim_ms: u64,
+    state: IdleState,
+    /// When the current dim started, for the fade
+    dimmed_at_ms: u64,
+}
+
+impl IdleMonitor {
+    pub fn new(timeout_ms: u64, dim_ms: u64) -> Self {
+        Self {
+            timeout_ms,
+            dim_ms,
+            state: IdleState::Active,
+            dimmed_at_ms: 0,
+        }
+    }
+
+    pub fn from_config(config: &PowerConfig) -> Self {
+        Self::new(config.screen_timeout as u64 * 1000, config.screen_dim_duration as u64 * 1000)
+    }
+
+    pub fn state(&self) -> IdleState {
+        self.state
+    }
+
+    pub fn is_blanked(&self) -> bool {
+        self.state == IdleState::Blanked
+    }
+
+    /// Advance with the current time and the time of the latest input
+    pub fn update(&mut self, now_ms: u64, last_input_ms: u64) -> Option<IdleTransition> {
+        let idle_ms = now_ms.saturating_sub(last_input_ms);
+        if self.timeout_ms == 0 || idle_ms < self.timeout_ms {
+            if self.state == IdleState::Active {
+                return None;
+            }
+            self.state = IdleState::Active;
+            return Some(IdleTransition::Wake);
+        }
+
+        if idle_ms >= self.timeout_ms + self.dim_ms {
+            if self.state == IdleState::Blanked {
+                return None;
+            }
+            self.state = IdleState::Blanked;
+            return Some(IdleTransition::Blank);
+        }
+
+        if self.state == IdleState::Active {
+            self.state = IdleState::Dimmed;
+            self.dimmed_at_ms = now_ms;
+            return Some(IdleTransition::Dim);
+        }
+        None
+    }
+
+    /// Opacity of the black overlay to draw while dimmed, fading in
+    pub fn dim_alpha(&self, now_ms: u64) -> u8 {
+        match self.state {
+            IdleState::Active => 0,
+            IdleState::Blanked => 255,
+            IdleState::Dimmed => {
+                let elapsed = now_ms.saturating_sub(self.dimmed_at_ms).min(DIM_FADE_MS);
+                (DIM_ALPHA as u64 * elapsed / DIM_FADE_MS) as u8
+            }
+        }
+    }
+}
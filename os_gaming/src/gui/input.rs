@@ -1,6 +1,58 @@
 use alloc::collections::VecDeque;
+use alloc::string::String;
 use alloc::vec::Vec;
-use hashbrown::HashSet;
+use hashbrown::{HashMap, HashSet};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use crate::events;
+use crate::kernel::drivers::{gamepad, keyboard, mouse, timer, touch};
+use crate::kernel::drivers::touch::{TouchPhase, TouchPoint};
+
+/// Input is sampled on the timer tick at this interval, independent of frame rate
+const INPUT_SAMPLE_INTERVAL_MS: u64 = 1;
+/// Sampled events kept for the render loop; the oldest are dropped beyond this
+const MAX_SAMPLED_EVENTS: usize = 1024;
+
+/// Deliver unprocessed mouse counts (no sensitivity, acceleration or inversion)
+/// while enabled; disabling restores the configured pointer curve.
+pub fn set_raw_mouse(enabled: bool) {
+    mouse::set_raw_mode(enabled);
+}
+
+/// Pointer reports per second achieved by the last `set_poll_rate`, 0 before any
+static POLL_RATE: AtomicU32 = AtomicU32::new(0);
+
+/// Ask pointing devices to report `hz` times per second. The PS/2 mouse and
+/// each USB HID device clamp to the nearest rate they support without going
+/// over; the slowest achieved rate is returned and kept for `poll_rate`.
+/// Faster rates lower input latency at a small CPU cost.
+pub fn set_poll_rate(hz: u32) -> u32 {
+    let mut achieved = mouse::set_sample_rate(hz);
+    if let Some(manager) = crate::kernel::drivers::get_driver_manager().lock().as_mut() {
+        if let Some(usb) = manager.usb_manager.as_mut().and_then(|usb| usb.set_hid_poll_rate(hz)) {
+            achieved = achieved.min(usb);
+        }
+    }
+    log::info!("Pointer poll rate: {} Hz requested, {} Hz achieved", hz, achieved);
+    POLL_RATE.store(achieved, Ordering::Relaxed);
+    achieved
+}
+
+/// Pointer poll rate in effect, for the settings UI
+pub fn poll_rate() -> u32 {
+    match POLL_RATE.load(Ordering::Relaxed) {
+        0 => mouse::sample_rate(),
+        rate => rate,
+    }
+}
+
+/// Apply `input.poll_rate` from the config; 0 keeps the hardware defaults
+pub fn configure_poll_rate(config: &crate::config::InputConfig) {
+    if config.poll_rate != 0 && config.poll_rate != POLL_RATE.load(Ordering::Relaxed) {
+        set_poll_rate(config.poll_rate);
+    }
+}
 
 /// Represents different input states.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -57,6 +109,22 @@ pub enum Key {
     Number7,
     Number8,
     Number9,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Tab,
+    Shift,
+    Ctrl,
+    Alt,
 }
 
 /// Represents mouse buttons.
@@ -67,6 +135,90 @@ pub enum MouseButton {
     Right,
 }
 
+/// Modifier keys currently held, tracked from key presses and releases
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    /// Record a press or release of `key`. Returns false for keys that
+    /// aren't modifiers, which leave the state alone.
+    pub fn update(&mut self, key: Key, pressed: bool) -> bool {
+        match key {
+            Key::Shift => self.shift = pressed,
+            Key::Ctrl => self.ctrl = pressed,
+            Key::Alt => self.alt = pressed,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Input device classes, as named in `InputConfig.device_priority`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputDevice {
+    Keyboard,
+    Controller,
+    Mouse,
+}
+
+impl InputDevice {
+    const ALL: [InputDevice; 3] = [InputDevice::Keyboard, InputDevice::Controller, InputDevice::Mouse];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "keyboard" => Some(InputDevice::Keyboard),
+            "controller" | "gamepad" => Some(InputDevice::Controller),
+            "mouse" => Some(InputDevice::Mouse),
+            _ => None,
+        }
+    }
+}
+
+/// Semantic navigation actions that several devices can produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NavAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Back,
+}
+
+impl NavAction {
+    fn from_key(key: Key) -> Option<Self> {
+        match key {
+            Key::Up => Some(NavAction::Up),
+            Key::Down => Some(NavAction::Down),
+            Key::Left => Some(NavAction::Left),
+            Key::Right => Some(NavAction::Right),
+            Key::Enter => Some(NavAction::Confirm),
+            Key::Escape => Some(NavAction::Back),
+            _ => None,
+        }
+    }
+
+    fn from_gamepad_buttons(buttons: u32) -> Vec<Self> {
+        [
+            (gamepad::BTN_DPAD_UP, NavAction::Up),
+            (gamepad::BTN_DPAD_DOWN, NavAction::Down),
+            (gamepad::BTN_DPAD_LEFT, NavAction::Left),
+            (gamepad::BTN_DPAD_RIGHT, NavAction::Right),
+            (gamepad::BTN_A, NavAction::Confirm),
+            (gamepad::BTN_B, NavAction::Back),
+        ]
+        .iter()
+        .filter(|(mask, _)| buttons & mask != 0)
+        .map(|(_, action)| *action)
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Event {
     KeyPress(Key),
     KeyRelease(Key),
@@ -78,9 +230,254 @@ pub enum Event {
     WindowClose,
     WindowFocus,
     WindowBlur,
+    /// Navigation action, resolved to a single source device per frame
+    Action(NavAction, InputDevice),
+    /// Touch contact ID and screen position
+    TouchDown(u32, f32, f32),
+    TouchMove(u32, f32, f32),
+    TouchUp(u32, f32, f32),
     Quit
 }
 
+impl Event {
+    /// Whether the event comes from the user touching an input device
+    pub fn is_user_input(&self) -> bool {
+        matches!(
+            self,
+            Event::KeyPress(_)
+                | Event::KeyRelease(_)
+                | Event::MouseMove(..)
+                | Event::MousePress(_)
+                | Event::MouseRelease(_)
+                | Event::MouseScroll(_)
+                | Event::Action(..)
+                | Event::TouchDown(..)
+                | Event::TouchMove(..)
+                | Event::TouchUp(..)
+        )
+    }
+
+    /// The system event bus equivalent, if other subsystems care about this event
+    pub fn to_bus_event(&self) -> Option<events::Event> {
+        Some(match *self {
+            Event::KeyPress(key) => events::Event::KeyPress { key: key as u16 },
+            Event::KeyRelease(key) => events::Event::KeyRelease { key: key as u16 },
+            Event::MouseMove(x, y) => events::Event::MouseMove { x, y },
+            Event::MousePress(button) => events::Event::MouseButton { button: button as u8, pressed: true },
+            Event::MouseRelease(button) => events::Event::MouseButton { button: button as u8, pressed: false },
+            Event::MouseScroll(delta) => events::Event::MouseScroll { delta },
+            Event::WindowResize(width, height) => events::Event::WindowResize { width, height },
+            Event::WindowClose => events::Event::WindowClose,
+            Event::WindowFocus => events::Event::WindowFocus,
+            Event::WindowBlur => events::Event::WindowBlur,
+            Event::Action(action, device) => events::Event::NavAction { action, device },
+            // Touches reach the bus through their emulated mouse events
+            Event::TouchDown(..)
+            | Event::TouchMove(..)
+            | Event::TouchUp(..)
+            | Event::Quit => return None,
+        })
+    }
+}
+
+/// An input event with the time it was captured
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedEvent {
+    pub event: Event,
+    /// Capture time in microseconds since boot
+    pub timestamp_us: u64,
+}
+
+/// State owned by the timer-driven sampler
+struct Sampler {
+    /// Mouse position and buttons at the previous sample
+    last_mouse: Option<(i32, i32, u8)>,
+    /// Events captured while the shared queue was busy
+    backlog: Vec<TimedEvent>,
+}
+
+lazy_static! {
+    static ref SAMPLER: Mutex<Sampler> = Mutex::new(Sampler { last_mouse: None, backlog: Vec::new() });
+    /// Timestamped events waiting for the render loop, oldest first
+    static ref SAMPLED_EVENTS: Mutex<VecDeque<TimedEvent>> = Mutex::new(VecDeque::new());
+}
+
+static SAMPLING_STARTED: AtomicBool = AtomicBool::new(false);
+static DROPPED_SAMPLES: AtomicU32 = AtomicU32::new(0);
+
+/// Recorded events, present while a recording runs
+static RECORDING: Mutex<Option<Vec<TimedEvent>>> = Mutex::new(None);
+
+/// A recorded session being replayed
+struct Playback {
+    /// Events still to inject, with their original timestamps, oldest first
+    events: VecDeque<TimedEvent>,
+    /// Original timestamp of the first event
+    recorded_start_us: u64,
+    /// When playback started; events are injected at the same offsets from it
+    started_us: u64,
+}
+
+static PLAYBACK: Mutex<Option<Playback>> = Mutex::new(None);
+
+/// Start recording the keyboard and mouse events the GUI receives,
+/// discarding any recording already in progress
+pub fn start_recording() {
+    *RECORDING.lock() = Some(Vec::new());
+}
+
+/// Stop recording and return what was captured, oldest first
+pub fn stop_recording() -> Vec<TimedEvent> {
+    RECORDING.lock().take().unwrap_or_default()
+}
+
+pub fn is_recording() -> bool {
+    RECORDING.lock().is_some()
+}
+
+/// Replay recorded events through the input sampler, keeping their relative
+/// timing. They reach the GUI exactly like live input. Replaces any playback
+/// in progress.
+pub fn play(events: Vec<TimedEvent>) -> Result<(), &'static str> {
+    let recorded_start_us = match events.first() {
+        Some(first) => first.timestamp_us,
+        None => return Ok(()),
+    };
+    start_input_sampling()?;
+    *PLAYBACK.lock() = Some(Playback {
+        events: events.into(),
+        recorded_start_us,
+        started_us: timer::timestamp_us_lockless(),
+    });
+    Ok(())
+}
+
+/// Whether recorded events are still waiting to be replayed
+pub fn is_playing() -> bool {
+    PLAYBACK.lock().is_some()
+}
+
+pub fn stop_playback() {
+    *PLAYBACK.lock() = None;
+}
+
+/// Move playback events that are due into `backlog`, restamped to now's timeline.
+/// Called from the sampler in interrupt context.
+fn inject_playback(backlog: &mut Vec<TimedEvent>, now_us: u64) {
+    let mut playback = match PLAYBACK.try_lock() {
+        Some(playback) => playback,
+        None => return,
+    };
+    let state = match playback.as_mut() {
+        Some(state) => state,
+        None => return,
+    };
+    while let Some(next) = state.events.front() {
+        let due_us = state.started_us + next.timestamp_us.saturating_sub(state.recorded_start_us);
+        if due_us > now_us {
+            break;
+        }
+        let event = next.event;
+        state.events.pop_front();
+        sampler_push(backlog, TimedEvent { event, timestamp_us: due_us });
+    }
+    if state.events.is_empty() {
+        *playback = None;
+    }
+}
+
+/// Start sampling keyboard and mouse on the timer tick. Safe to call more than once.
+pub fn start_input_sampling() -> Result<(), &'static str> {
+    if SAMPLING_STARTED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    timer::schedule_periodic_task("input-sample", INPUT_SAMPLE_INTERVAL_MS, sample_input).map_err(|e| {
+        SAMPLING_STARTED.store(false, Ordering::SeqCst);
+        e
+    })
+}
+
+/// Timer callback: capture every pending keyboard/mouse change with its time.
+/// Runs in interrupt context, so it only ever try-locks.
+fn sample_input() {
+    let mut sampler = match SAMPLER.try_lock() {
+        Some(sampler) => sampler,
+        None => return,
+    };
+    let timestamp_us = timer::timestamp_us_lockless();
+    let mut capture = |event: Event| sampler_push(&mut sampler.backlog, TimedEvent { event, timestamp_us });
+
+    while let Some(scancode) = keyboard::pop_scancode() {
+        if let Some(key) = InputManager::scancode_to_key(scancode & 0x7F) {
+            capture(if scancode & 0x80 == 0 { Event::KeyPress(key) } else { Event::KeyRelease(key) });
+        }
+    }
+
+    if let Some(state) = mouse::try_get_state() {
+        let (last_x, last_y, last_buttons) = sampler.last_mouse.unwrap_or((state.x, state.y, 0));
+        if (state.x, state.y) != (last_x, last_y) {
+            sampler_push(&mut sampler.backlog, TimedEvent { event: Event::MouseMove(state.x as f32, state.y as f32), timestamp_us });
+        }
+        for (bit, button) in [(0x1, MouseButton::Left), (0x2, MouseButton::Right), (0x4, MouseButton::Middle)] {
+            let event = match (last_buttons & bit != 0, state.buttons & bit != 0) {
+                (false, true) => Event::MousePress(button),
+                (true, false) => Event::MouseRelease(button),
+                _ => continue,
+            };
+            sampler_push(&mut sampler.backlog, TimedEvent { event, timestamp_us });
+        }
+        if state.scroll_wheel != 0 {
+            sampler_push(&mut sampler.backlog, TimedEvent { event: Event::MouseScroll(state.scroll_wheel as i32), timestamp_us });
+        }
+        sampler.last_mouse = Some((state.x, state.y, state.buttons));
+    }
+
+    // The primary finger also drives the pointer with the left button held
+    for touch_event in touch::try_drain_events() {
+        let TouchPoint { id, x, y } = touch_event.point;
+        let (event, button) = match touch_event.phase {
+            TouchPhase::Down => (Event::TouchDown(id, x, y), Some(Event::MousePress(MouseButton::Left))),
+            TouchPhase::Move => (Event::TouchMove(id, x, y), None),
+            TouchPhase::Up => (Event::TouchUp(id, x, y), Some(Event::MouseRelease(MouseButton::Left))),
+        };
+        sampler_push(&mut sampler.backlog, TimedEvent { event, timestamp_us });
+        if touch_event.primary {
+            sampler_push(&mut sampler.backlog, TimedEvent { event: Event::MouseMove(x, y), timestamp_us });
+            if let Some(event) = button {
+                sampler_push(&mut sampler.backlog, TimedEvent { event, timestamp_us });
+            }
+        }
+    }
+
+    inject_playback(&mut sampler.backlog, timestamp_us);
+    hand_over(&mut sampler.backlog);
+}
+
+/// Move the backlog to the render loop's queue, unless the render loop is
+/// draining it right now; then it stays in the backlog for the next tick.
+fn hand_over(backlog: &mut Vec<TimedEvent>) {
+    if backlog.is_empty() {
+        return;
+    }
+    if let Some(mut queue) = SAMPLED_EVENTS.try_lock() {
+        for timed in backlog.drain(..) {
+            if queue.len() >= MAX_SAMPLED_EVENTS {
+                queue.pop_front();
+                DROPPED_SAMPLES.fetch_add(1, Ordering::Relaxed);
+            }
+            queue.push_back(timed);
+        }
+    }
+}
+
+fn sampler_push(backlog: &mut Vec<TimedEvent>, timed: TimedEvent) {
+    if backlog.len() >= MAX_SAMPLED_EVENTS {
+        DROPPED_SAMPLES.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    backlog.push(timed);
+}
+
 /// Manages input state for the GUI.
 pub struct InputManager {
     pressed_keys: HashSet<Key>,
@@ -91,8 +488,16 @@ pub struct InputManager {
     pressed_mouse_buttons: HashSet<MouseButton>,
     held_mouse_buttons: HashSet<MouseButton>,
     released_mouse_buttons: HashSet<MouseButton>,
-    event_queue: VecDeque<Event>,
+    event_queue: VecDeque<TimedEvent>,
     static_instance: Option<&'static mut InputManager>,
+    /// Device classes in preference order; earlier entries win action conflicts
+    device_priority: Vec<InputDevice>,
+    /// Actions raised this frame, with the device that raised them
+    frame_actions: Vec<(NavAction, InputDevice)>,
+    /// Buttons last seen held on each gamepad, to only raise actions on press
+    gamepad_buttons: HashMap<u8, u32>,
+    /// Capture time of the latest user input (µs since boot), for idle detection
+    last_input_us: u64,
 }
 
 impl InputManager {
@@ -109,6 +514,60 @@ impl InputManager {
             released_mouse_buttons: HashSet::new(),
             event_queue: VecDeque::new(),
             static_instance: None,
+            device_priority: InputDevice::ALL.to_vec(),
+            frame_actions: Vec::new(),
+            gamepad_buttons: HashMap::new(),
+            last_input_us: timer::timestamp_us_lockless(),
+        }
+    }
+
+    /// Set device preference from config names. Unknown names are ignored
+    /// and devices missing from the list are appended in default order.
+    pub fn set_device_priority(&mut self, names: &[String]) {
+        let mut priority = Vec::with_capacity(InputDevice::ALL.len());
+        for name in names {
+            match InputDevice::from_name(name.as_str()) {
+                Some(device) if !priority.contains(&device) => priority.push(device),
+                Some(_) => {}
+                None => log::warn!("Unknown input device '{}' in device_priority", name),
+            }
+        }
+        for device in InputDevice::ALL {
+            if !priority.contains(&device) {
+                priority.push(device);
+            }
+        }
+        self.device_priority = priority;
+    }
+
+    pub fn device_priority(&self) -> &[InputDevice] {
+        &self.device_priority
+    }
+
+    fn priority_rank(&self, device: InputDevice) -> usize {
+        self.device_priority
+            .iter()
+            .position(|d| *d == device)
+            .unwrap_or(self.device_priority.len())
+    }
+
+    /// Record a navigation action raised by a device during this frame
+    pub fn submit_action(&mut self, action: NavAction, device: InputDevice) {
+        self.frame_actions.push((action, device));
+    }
+
+    /// Emit one event per action, from the highest-priority device that raised it
+    fn resolve_frame_actions(&mut self) {
+        let mut actions = core::mem::take(&mut self.frame_actions);
+        // Stable sort keeps the arrival order among devices of equal rank
+        actions.sort_by_key(|(_, device)| self.priority_rank(*device));
+
+        let mut dispatched: Vec<NavAction> = Vec::new();
+        for (action, device) in actions {
+            if !dispatched.contains(&action) {
+                dispatched.push(action);
+                self.push_event(Event::Action(action, device));
+            }
         }
     }
 
@@ -131,13 +590,15 @@ impl InputManager {
         self.last_mouse_position = self.mouse_position;
 
         // Check for hardware events (in a real system, this would poll hardware)
+        self.drain_sampled_events();
         self.poll_hardware_events();
+        self.resolve_frame_actions();
 
         // Process key states
         // Move pressed keys to held keys
         let mut pressed_keys_copy = self.pressed_keys.clone();
         for key in pressed_keys_copy.drain() {
-            self.event_queue.push_back(Event::KeyPress(key));
+            self.push_event(Event::KeyPress(key));
             self.held_keys.insert(key);
         }
         self.pressed_keys.clear();
@@ -145,14 +606,14 @@ impl InputManager {
         // Process released keys
         let mut released_keys_copy = self.released_keys.clone();
         for key in released_keys_copy.drain() {
-            self.event_queue.push_back(Event::KeyRelease(key));
+            self.push_event(Event::KeyRelease(key));
         }
         self.released_keys.clear();
 
         // Process mouse buttons
         let mut pressed_buttons_copy = self.pressed_mouse_buttons.clone();
         for button in pressed_buttons_copy.drain() {
-            self.event_queue.push_back(Event::MousePress(button));
+            self.push_event(Event::MousePress(button));
             self.held_mouse_buttons.insert(button);
         }
         self.pressed_mouse_buttons.clear();
@@ -160,21 +621,104 @@ impl InputManager {
         // Process released mouse buttons
         let mut released_buttons_copy = self.released_mouse_buttons.clone();
         for button in released_buttons_copy.drain() {
-            self.event_queue.push_back(Event::MouseRelease(button));
+            self.push_event(Event::MouseRelease(button));
         }
         self.released_mouse_buttons.clear();
 
         // Check if mouse has moved
         if self.mouse_position != self.last_mouse_position {
-            self.event_queue.push_back(Event::MouseMove(
+            self.push_event(Event::MouseMove(
                 self.mouse_position.0,
                 self.mouse_position.1,
             ));
         }
+
+        let latest_input = self
+            .event_queue
+            .iter()
+            .filter(|timed| timed.event.is_user_input())
+            .map(|timed| timed.timestamp_us)
+            .max();
+        if let Some(timestamp_us) = latest_input {
+            self.last_input_us = self.last_input_us.max(timestamp_us);
+        }
     }
+
+    /// When the user last pressed, moved or scrolled anything (µs since boot)
+    pub fn last_input_us(&self) -> u64 {
+        self.last_input_us
+    }
+    /// Move events captured by the timer-driven sampler into this frame's queue.
+    /// Each event is delivered individually, in capture order, with its timestamp,
+    /// so a press and release within one frame are both seen.
+    fn drain_sampled_events(&mut self) {
+        let sampled = core::mem::take(&mut *SAMPLED_EVENTS.lock());
+        if let Some(recording) = RECORDING.lock().as_mut() {
+            recording.extend(sampled.iter().copied());
+        }
+        for timed in sampled {
+            match timed.event {
+                Event::KeyPress(key) => {
+                    self.held_keys.insert(key);
+                    if let Some(action) = NavAction::from_key(key) {
+                        self.submit_action(action, InputDevice::Keyboard);
+                    }
+                }
+                Event::KeyRelease(key) => {
+                    self.held_keys.remove(&key);
+                }
+                Event::MouseMove(x, y) => {
+                    // Keep the end-of-update movement check from reporting it a second time
+                    self.mouse_position = (x, y);
+                    self.last_mouse_position = (x, y);
+                }
+                Event::MousePress(button) => {
+                    self.held_mouse_buttons.insert(button);
+                }
+                Event::MouseRelease(button) => {
+                    self.held_mouse_buttons.remove(&button);
+                }
+                _ => {}
+            }
+            self.event_queue.push_back(timed);
+        }
+
+        let dropped = DROPPED_SAMPLES.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            log::warn!("Input sampler dropped {} events", dropped);
+        }
+    }
+
+    /// Poll devices in priority order
     fn poll_hardware_events(&mut self) {
+        for device in self.device_priority.clone() {
+            match device {
+                InputDevice::Controller => self.poll_gamepads(),
+                InputDevice::Keyboard | InputDevice::Mouse => self.poll_device_buffer(device),
+            }
+        }
+    }
+
+    fn poll_gamepads(&mut self) {
+        let events = match gamepad::get_pending_events() {
+            Ok(events) => events,
+            Err(_) => return,
+        };
+        for event in events {
+            if event.event_type != gamepad::InputEventType::Button {
+                continue;
+            }
+            let previous = self.gamepad_buttons.insert(event.id, event.buttons).unwrap_or(0);
+            let newly_pressed = event.buttons & !previous;
+            for action in NavAction::from_gamepad_buttons(newly_pressed) {
+                self.submit_action(action, InputDevice::Controller);
+            }
+        }
+    }
+
+    fn poll_device_buffer(&mut self, device: InputDevice) {
         // For now, we'll simulate with stub code that would be replaced
-        if let Some(raw_events) = self.read_hardware_input_buffer() {
+        if let Some(raw_events) = self.read_hardware_input_buffer(device) {
             for event in raw_events {
                 match event {
                     Event::KeyPress(scancode) => {
@@ -207,9 +751,15 @@ impl InputManager {
                     Event::WindowBlur => {
                         self.process_window_blur();
                     }
+                    Event::Action(action, device) => {
+                        self.submit_action(action, device);
+                    }
+                    Event::TouchDown(..) | Event::TouchMove(..) | Event::TouchUp(..) => {
+                        self.push_event(event);
+                    }
                     Event::Quit => {
                         // Handle quit event
-                        self.event_queue.push_back(Event::Quit);
+                        self.push_event(Event::Quit);
                     }
                 }
             }
@@ -218,16 +768,23 @@ impl InputManager {
     /// Processes a keyboard key press.
     pub fn process_key_press(&mut self, key: Key) {
         self.pressed_keys.insert(key);
+        if let Some(action) = NavAction::from_key(key) {
+            self.submit_action(action, InputDevice::Keyboard);
+        }
     }
 
-    fn read_hardware_input_buffer(&self) -> Option<Vec<Event>> {
+    fn read_hardware_input_buffer(&self, _device: InputDevice) -> Option<Vec<Event>> {
         // In real implementation: read from hardware/driver
         None // No events for now
     }
-    fn scancode_to_key(&self, scancode: u8) -> Option<Key> {
+    fn scancode_to_key(scancode: u8) -> Option<Key> {
         // Map hardware scancode to Key enum
         match scancode {
             0x01 => Some(Key::Escape),
+            0x0F => Some(Key::Tab),
+            0x2A | 0x36 => Some(Key::Shift), // Left and right shift
+            0x1D => Some(Key::Ctrl),
+            0x38 => Some(Key::Alt),
             0x1E => Some(Key::A),
             0x30 => Some(Key::B),
             0x2E => Some(Key::C),
@@ -275,6 +832,12 @@ impl InputManager {
             0x47 => Some(Key::Number7),
             0x49 => Some(Key::Number8),
             0x4A => Some(Key::Number9),
+            0x3B..=0x44 => Some([
+                Key::F1, Key::F2, Key::F3, Key::F4, Key::F5,
+                Key::F6, Key::F7, Key::F8, Key::F9, Key::F10,
+            ][(scancode - 0x3B) as usize]),
+            0x57 => Some(Key::F11),
+            0x58 => Some(Key::F12),
             _ => None,
         }
     }
@@ -288,23 +851,23 @@ impl InputManager {
 
     pub fn process_mouse_scroll(&mut self, delta: i32) {
         // Handle mouse scroll event
-        self.event_queue.push_back(Event::MouseScroll(delta));
+        self.push_event(Event::MouseScroll(delta));
     }
     pub fn process_window_resize(&mut self, width: u32, height: u32) {
         // Handle window resize event
-        self.event_queue.push_back(Event::WindowResize(width, height));
+        self.push_event(Event::WindowResize(width, height));
     }
     pub fn process_window_close(&mut self) {
         // Handle window close event
-        self.event_queue.push_back(Event::WindowClose);
+        self.push_event(Event::WindowClose);
     }
     pub fn process_window_focus(&mut self) {
         // Handle window focus event
-        self.event_queue.push_back(Event::WindowFocus);
+        self.push_event(Event::WindowFocus);
     }
     pub fn process_window_blur(&mut self) {
         // Handle window blur event
-        self.event_queue.push_back(Event::WindowBlur);
+        self.push_event(Event::WindowBlur);
     }
 
     fn button_id_to_mouse_button(&self, button_id: u8) -> Option<MouseButton> {
@@ -369,6 +932,11 @@ impl InputManager {
     }
 
     pub fn next_event(&mut self) -> Option<Event> {
+        self.next_timed_event().map(|timed| timed.event)
+    }
+
+    /// Next event along with the time it was captured
+    pub fn next_timed_event(&mut self) -> Option<TimedEvent> {
         self.event_queue.pop_front()
     }
 
@@ -377,9 +945,9 @@ impl InputManager {
         !self.event_queue.is_empty()
     }
 
-    /// Add a custom event to the queue
+    /// Add a custom event to the queue, stamped with the current time
     pub fn push_event(&mut self, event: Event) {
-        self.event_queue.push_back(event);
+        self.event_queue.push_back(TimedEvent { event, timestamp_us: timer::timestamp_us_lockless() });
     }
 
     /// Clear the event queue
@@ -387,3 +955,67 @@ impl InputManager {
         self.event_queue.clear();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A press and release per 1 ms tick, drained every 16 ticks like a
+    /// 60 Hz render loop, which is sometimes busy when the sampler hands over
+    #[test]
+    fn slower_render_loop_gets_every_sampled_event_in_order() {
+        let mut manager = InputManager::new();
+        let mut backlog = Vec::new();
+        let mut sent = Vec::new();
+        let mut received = Vec::new();
+
+        for tick in 0..500u64 {
+            let timestamp_us = tick * 1000;
+            for event in [Event::KeyPress(Key::A), Event::KeyRelease(Key::A)] {
+                let timed = TimedEvent { event, timestamp_us };
+                sampler_push(&mut backlog, timed);
+                sent.push(timed);
+            }
+
+            if tick % 7 == 0 {
+                // The render loop holds the queue; the tick keeps its backlog
+                let _draining = SAMPLED_EVENTS.lock();
+                hand_over(&mut backlog);
+                assert!(!backlog.is_empty());
+            } else {
+                hand_over(&mut backlog);
+            }
+
+            if tick % 16 == 15 {
+                manager.drain_sampled_events();
+                while let Some(timed) = manager.next_timed_event() {
+                    received.push(timed);
+                }
+            }
+        }
+        hand_over(&mut backlog);
+        manager.drain_sampled_events();
+        while let Some(timed) = manager.next_timed_event() {
+            received.push(timed);
+        }
+
+        assert_eq!(received, sent);
+        assert_eq!(DROPPED_SAMPLES.load(Ordering::Relaxed), 0);
+    }
+    #[test]
+    fn arbitrated_action_reaches_the_bus() {
+        let mut manager = InputManager::new();
+        manager.set_device_priority(&["controller".to_string()]);
+        manager.submit_action(NavAction::Confirm, InputDevice::Keyboard);
+        manager.submit_action(NavAction::Confirm, InputDevice::Controller);
+        manager.resolve_frame_actions();
+
+        let event = manager.next_event().unwrap();
+        assert_eq!(event, Event::Action(NavAction::Confirm, InputDevice::Controller));
+        assert_eq!(
+            event.to_bus_event(),
+            Some(events::Event::NavAction { action: NavAction::Confirm, device: InputDevice::Controller })
+        );
+        assert_eq!(manager.next_event(), None);
+    }
+}
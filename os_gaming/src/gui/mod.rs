@@ -6,23 +6,57 @@ pub mod renderer;
 pub mod window_manager;
 pub mod theme;
 pub mod input;
+pub mod emath;
 pub mod font;
+pub mod ttf_parser;
+pub mod bitmap_font;
+pub mod glyph_raster;
 pub mod windows_layout;
+pub mod overlay;
+pub mod frame_stats;
+pub mod adaptive_quality;
+pub mod idle;
+pub mod screenshot;
+pub mod toast;
 
 use core::arch::asm;
+use core::sync::atomic::{AtomicU32, Ordering};
 use crate::Config;
+use crate::events;
 
 // These will be implemented later
-mod widgets;
+pub mod widgets;
 
 // Re-export main types
-pub use renderer::{Renderer, Color, Rect, BlendMode, RendererError};
+pub use renderer::{Renderer, Color, Rect, BlendMode, RendererError, Letterbox, ScaleMode};
 pub use window_manager::{WindowManager, Window};
 pub use font::FontManager;
 pub use theme::Theme;
 pub use windows_layout::WindowLayoutConfig;
+pub use screenshot::export_screenshot_to_usb;
 use crate::kernel::cpu;
 use crate::kernel::cpu::get_cpu_info;
+use crate::kernel::drivers::{filesystem, gpu, timer, touch};
+use alloc::{string::String, vec::Vec};
+
+/// Frame times averaged for the FPS log, by count and by age in seconds
+const FPS_HISTORY_LEN: usize = 240;
+const FPS_HISTORY_SECONDS: f64 = 2.0;
+
+/// While more than this many milliseconds of a frame remain, the loop halts
+/// until the next timer tick instead of spinning
+const FRAME_WAIT_HLT_MS: f32 = 1.5;
+
+/// Frame rate of the main loop, updated once a second
+static CURRENT_FPS: AtomicU32 = AtomicU32::new(0);
+
+/// Smoothed frames per second of `run_app`, 0 before the first second
+pub fn current_fps() -> u32 {
+    CURRENT_FPS.load(Ordering::Relaxed)
+}
+
+/// With tearing allowed, flips wait until scanout is this far down the screen
+const TEAR_LINE_PERCENT: u32 = 75;
 
 pub struct Instant {
     timestamp: u64,
@@ -34,6 +68,15 @@ impl Instant {
             timestamp: read_tsc()
         }
     }
+
+    /// Milliseconds between `earlier` and this instant, 0 if the TSC rate is unknown
+    pub fn duration_since_ms(&self, earlier: &Instant) -> f32 {
+        let mhz = timer::get_cpu_mhz();
+        if mhz == 0 {
+            return 0.0;
+        }
+        self.timestamp.saturating_sub(earlier.timestamp) as f32 / (mhz as f32 * 1000.0)
+    }
 }
 
 // Lire le Time Stamp Counter
@@ -94,10 +137,65 @@ pub fn init_window_manager(renderer: Renderer) -> Result<WindowManager, &'static
     }
 }
 
+/// Change the display resolution at runtime
+///
+/// Mode-sets the GPU to the closest supported mode, then reallocates the renderer
+/// and rescales the windows. If the renderer cannot follow, the previous mode is restored.
+/// Returns the resolution actually applied.
+pub fn set_resolution(
+    window_manager: &mut WindowManager,
+    width: u32,
+    height: u32,
+    refresh_rate: u32,
+) -> Result<(u32, u32), &'static str> {
+    let previous = gpu::current_mode().ok();
+    let target = gpu::best_mode_for(width, height, refresh_rate).ok();
+
+    let mut mode_changed = false;
+    if let Some(mode) = target {
+        match gpu::set_display_mode(mode) {
+            Ok(()) => mode_changed = true,
+            // Without mode-set support only the renderer's surfaces change
+            Err(gpu::GpuError::NotSupported) => {}
+            Err(e) => {
+                log::error!("Failed to set display mode {}x{}: {:?}", mode.width, mode.height, e);
+                return Err("Display mode set failed");
+            }
+        }
+    }
+    let (width, height) = target.map(|mode| (mode.width, mode.height)).unwrap_or((width, height));
+
+    if let Err(e) = window_manager.set_resolution(width, height) {
+        log::error!("Failed to apply {}x{}: {}, reverting", width, height, e);
+        if let (true, Some(previous)) = (mode_changed, previous) {
+            if let Err(e) = gpu::set_display_mode(previous) {
+                log::error!("Failed to restore previous display mode: {:?}", e);
+            }
+        }
+        return Err(e);
+    }
+
+    touch::set_screen_size(width, height);
+    log::info!("Display resolution changed to {}x{}", width, height);
+    Ok((width, height))
+}
+
+/// Render below the display resolution for frame rate
+///
+/// Frames are drawn at `factor` of the resolution (clamped to 0.5..=1.0, 0 meaning
+/// native) and upscaled bilinearly when presented; pointer input is mapped back.
+/// Returns the factor actually applied.
+pub fn set_render_scale(window_manager: &mut WindowManager, factor: f32) -> Result<f32, &'static str> {
+    let factor = if factor == 0.0 { 1.0 } else { factor };
+    window_manager.set_render_scale(factor)
+}
+
 /// Initialize font system
+///
+/// Tries the system fonts, then the embedded fallback font, and finally the
+/// built-in bitmap font, so there is always something to draw text with.
 pub fn init_fonts() -> Result<(), &'static str> {
-    // Get the font manager singleton
-    let mut font_manager = FontManager::new();
+    let mut font_manager = font::font_manager().lock();
 
     // Define font paths - adjust these to actual paths in your filesystem
     let system_font_paths = [
@@ -111,11 +209,15 @@ pub fn init_fonts() -> Result<(), &'static str> {
     // Try to load the system fonts
     let mut default_font_loaded = false;
     for font_path in &system_font_paths {
-        match font_manager.load_font("AssetFont", font_path.as_ref()) {
-            Ok(font_id) => {
+        let loaded = read_font_file(font_path)
+            .map_err(String::from)
+            .and_then(|data| font_manager.load_font(font_path, &data));
+        match loaded {
+            Ok(()) => {
                 // Set the first successfully loaded font as default
                 if !default_font_loaded {
-                    font_manager.setup_default_fonts();
+                    let _ = font_manager.setup_default_fonts();
+                    let _ = font_manager.set_font_family(font::FontFamily::Proportional, font_path);
                     default_font_loaded = true;
                 }
             },
@@ -134,13 +236,13 @@ pub fn init_fonts() -> Result<(), &'static str> {
         static FALLBACK_FONT: &[u8] = include_bytes!("assets/arial-font/arial.ttf");
         
         match font_manager.load_font_from_memory("FallbackFont", FALLBACK_FONT) {
-            Ok(font_id) => {
-                font_manager.setup_default_fonts();
-                default_font_loaded = true;
+            Ok(()) => {
+                let _ = font_manager.setup_default_fonts();
+                let _ = font_manager.set_font_family(font::FontFamily::Proportional, "FallbackFont");
             },
             Err(err) => {
-                log::error!("Failed to load fallback font: {}", err);
-                return Err("No usable fonts could be loaded");
+                log::error!("Failed to load fallback font: {}; using the built-in bitmap font", err);
+                font_manager.use_bitmap_fallback();
             }
         }
     }
@@ -157,6 +259,21 @@ pub fn init_fonts() -> Result<(), &'static str> {
     Ok(())
 }
 
+/// Read a whole font file from the mounted filesystems
+fn read_font_file(path: &str) -> Result<Vec<u8>, &'static str> {
+    let fs_manager = filesystem::get_fs_manager().lock();
+    let mut file = fs_manager.open_file(path, true)?;
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        match file.read(&mut chunk, &fs_manager, data.len() as u64)? {
+            0 => break,
+            n => data.extend_from_slice(&chunk[..n]),
+        }
+    }
+    Ok(data)
+}
+
 /// Run the main application loop
 pub fn run_app(config: Config) {
     // Get required components
@@ -175,8 +292,49 @@ pub fn run_app(config: Config) {
             return;
         }
     };
+    let (screen_width, screen_height) = window_manager.screen_size();
+    touch::set_screen_size(screen_width, screen_height);
+
+    // Without vsync, tearing is allowed but kept below this line
+    let mut tear_line = None;
+    // Lowers GPU quality when frames run late, if enabled
+    let mut adaptive_quality = None;
+    // Frames per second the loop is held to; 0 runs unlimited
+    let mut frame_cap = config.refresh_rate;
+    // Dims, then blanks, the screen after a while without input
+    let mut idle_monitor = idle::IdleMonitor::new(0, 0);
 
     let mut input_handler = input::InputManager::new();
+    if let Err(e) = input::start_input_sampling() {
+        log::warn!("Input sampling unavailable, events limited to frame rate: {}", e);
+    }
+    {
+        // The config the system loaded and keeps current, not a fresh read from disk
+        let system_config = crate::config::get_config().lock().clone();
+        input_handler.set_device_priority(&system_config.input.device_priority);
+        input::configure_poll_rate(&system_config.input);
+        window_manager.set_accessibility(&system_config.user_settings.accessibility);
+        window_manager.set_notification_settings(&system_config.user_settings.notifications);
+        let display = &system_config.display;
+        window_manager.set_display_options(display);
+        if let Some(layout) = &system_config.window_layout {
+            window_manager.set_window_layout_options(layout);
+        }
+        if let Err(e) = set_render_scale(&mut window_manager, display.render_scale) {
+            log::warn!("Rendering at native resolution: {}", e);
+        }
+        if display.allow_tearing && !display.vsync {
+            tear_line = Some(config.height * TEAR_LINE_PERCENT / 100);
+        }
+        gpu::configure(&system_config.gpu);
+        gpu::configure_refresh(display);
+        adaptive_quality = adaptive_quality::AdaptiveQuality::from_config(&system_config);
+        idle_monitor = idle::IdleMonitor::from_config(&system_config.power);
+        frame_cap = display.max_framerate;
+    }
+    if crate::kernel::crash_log::recovered_from_crash() {
+        window_manager.notify("Recovered from a crash", "Details of the last session were saved to the crash log");
+    }
 
     // Create main system window if it doesn't exist yet
     // Using a window ID (u32) instead of a string
@@ -192,14 +350,20 @@ pub fn run_app(config: Config) {
     let theme = Theme::load(&config.theme);
     window_manager.set_theme(theme);
 
+    // Fullscreen games draw their own pointer
+    window_manager.set_cursor_visible(!config.fullscreen);
+
     // Target frame rate
-    let target_frame_time = 1.0 / config.refresh_rate as f64;
+    let target_frame_ms = (frame_cap > 0).then(|| 1000.0 / frame_cap as f32);
     let mut last_frame_time = Instant::now();
     
-    // FPS counter
-    let mut frames = 0;
-    let mut fps_timer = Instant::now();
-    let mut current_fps = 0;
+    // Smoothed frame rate, logged once a second
+    let mut frame_times = emath::History::new(FPS_HISTORY_LEN, FPS_HISTORY_SECONDS);
+    let mut last_fps_log = 0.0;
+
+    // Frame-time statistics, shown by the graph toggled with F3
+    let mut frame_stats = frame_stats::FrameStats::new(frame_stats::DEFAULT_WINDOW);
+    let mut perf_graph = overlay::PerfGraph::new(overlay::DEFAULT_SAMPLE_COUNT, config.refresh_rate);
 
     // Main loop running flag
     let mut running = true;
@@ -211,6 +375,9 @@ pub fn run_app(config: Config) {
         // Process input events
         input_handler.update();
         while let Some(event) = input_handler.next_event() {
+            if let Some(bus_event) = event.to_bus_event() {
+                events::publish(bus_event);
+            }
             match event {
                 input::Event::Quit => {
                     log::info!("Quit event received, exiting application loop");
@@ -218,6 +385,10 @@ pub fn run_app(config: Config) {
                     break;
                 },
                 input::Event::KeyPress(key) => {
+                    if key == input::Key::F3 {
+                        perf_graph.toggle();
+                        continue;
+                    }
                     if key == input::Key::Escape {
                         if config.exit_on_escape {
                             log::info!("Escape key pressed, exiting application loop");
@@ -226,10 +397,10 @@ pub fn run_app(config: Config) {
                         }
                     }
                     // Pass event to window manager
-                    window_manager.handle_key_press(key as u16);
+                    window_manager.handle_key_press(key);
                 },
                 input::Event::KeyRelease(key) => {
-                    window_manager.handle_key_release(key as u16);
+                    window_manager.handle_key_release(key);
                 },
                 input::Event::MouseMove(x, y) => {
                     window_manager.handle_mouse_move(x as i32, y as i32);
@@ -262,18 +433,105 @@ pub fn run_app(config: Config) {
                     log::info!("Window blur event received");
                     window_manager.handle_window_blur();
                 }
+                // Games get the action from the event bus, published above
+                input::Event::Action(action, _) => {
+                    window_manager.handle_action(action);
+                }
+                // The primary contact also arrives as mouse events, which the windows handle
+                input::Event::TouchDown(..) | input::Event::TouchMove(..) | input::Event::TouchUp(..) => {}
             }
         }
 
+        // Deliver bus events raised from interrupt context
+        events::dispatch_pending();
+        crate::runtime::poll();
+
         // Update window states
         window_manager.update();
-        
-        // Render all windows
-        window_manager.render();
+
+        let now_ms = timer::timestamp_us_lockless() / 1000;
+        match idle_monitor.update(now_ms, input_handler.last_input_us() / 1000) {
+            Some(idle::IdleTransition::Blank) => {
+                log::debug!("Screen blanked after inactivity");
+                window_manager.render_blank();
+                let _ = window_manager.present(None);
+            }
+            Some(idle::IdleTransition::Wake) => log::debug!("Screen woken by input"),
+            _ => {}
+        }
+        if idle_monitor.is_blanked() {
+            // Nothing to draw; sleep until the next interrupt
+            x86_64::instructions::hlt();
+            last_frame_time = Instant::now();
+            continue;
+        }
+
+        // Render changed windows, with the performance overlay and any idle dimming on top
+        let dim_alpha = idle_monitor.dim_alpha(now_ms);
+        let _ = window_manager.render_with_overlay(|renderer| {
+            let drawn = perf_graph.render(renderer, &mut frame_stats);
+            if dim_alpha > 0 {
+                let (width, height) = renderer.dimensions();
+                let screen = Rect::new(0, 0, width, height);
+                renderer.fill_rect(screen, Color::new(0, 0, 0, dim_alpha));
+                return Some(screen);
+            }
+            drawn
+        });
+        let _ = window_manager.present(tear_line);
+
+        if let Some(target_ms) = target_frame_ms {
+            wait_for_frame_slot(&last_frame_time, target_ms);
+        }
+        let now = Instant::now();
+        let frame_ms = now.duration_since_ms(&last_frame_time);
+        frame_stats.record(frame_ms);
+        last_frame_time = now;
+
+        let now_s = timer::uptime_ms() as f64 / 1000.0;
+        frame_times.add(now_s, frame_ms);
+        if now_s - last_fps_log >= 1.0 {
+            if let Some(mean_ms) = frame_times.mean().filter(|&ms| ms > 0.0) {
+                CURRENT_FPS.store((1000.0 / mean_ms + 0.5) as u32, Ordering::Relaxed);
+                log::debug!("{:.1} fps ({:.2} ms/frame)", 1000.0 / mean_ms, mean_ms);
+            }
+            last_fps_log = now_s;
+        }
+
+        if let Some(controller) = adaptive_quality.as_mut() {
+            // A config change re-applied the configured quality; adapt from there
+            let applied = gpu::quality();
+            if applied != controller.current() {
+                controller.reset(applied);
+            }
+            if let Some(settings) = controller.record(frame_ms, timer::uptime_ms()) {
+                gpu::set_quality(settings);
+            }
+        }
         
     }
     
     // Perform cleanup
     log::info!("Exiting application loop, performing cleanup");
     window_manager.shutdown();
+}
+
+/// Return once `target_ms` have passed since `frame_start`. Halts between
+/// timer ticks while most of the wait remains, then spins for precision.
+fn wait_for_frame_slot(frame_start: &Instant, target_ms: f32) {
+    // Without the TSC rate `Instant` measures nothing, so there is no cap
+    if timer::get_cpu_mhz() == 0 {
+        return;
+    }
+    loop {
+        let remaining = target_ms - Instant::now().duration_since_ms(frame_start);
+        if remaining <= 0.0 {
+            return;
+        }
+        if remaining > FRAME_WAIT_HLT_MS && x86_64::instructions::interrupts::are_enabled() {
+            x86_64::instructions::hlt();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
 }
\ No newline at end of file
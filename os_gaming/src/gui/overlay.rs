@@ -0,0 +1,186 @@
+//! On-screen debug overlays
+//!
+//! Overlays are drawn after all windows so they stay visible on top of
+//! whatever the game or desktop is showing.
+
+use alloc::format;
+
+use super::frame_stats::FrameStats;
+use super::renderer::{Color, Rect, Renderer};
+
+/// Default number of frame samples kept by the performance graph
+pub const DEFAULT_SAMPLE_COUNT: usize = 120;
+
+const BAR_WIDTH: u32 = 2;
+const GRAPH_HEIGHT: u32 = 60;
+const MARGIN: i32 = 8;
+const PADDING: i32 = 4;
+const GLYPH_SCALE: i32 = 2;
+const LINE_HEIGHT: i32 = 6 * GLYPH_SCALE;
+const TEXT_LINES: i32 = 4;
+
+const BACKGROUND: Color = Color::new(0, 0, 0, 160);
+const BAR_GOOD: Color = Color::rgb(80, 200, 120);
+const BAR_SLOW: Color = Color::rgb(230, 190, 60);
+const BAR_HITCH: Color = Color::rgb(230, 70, 60);
+const TARGET_LINE: Color = Color::new(255, 255, 255, 140);
+
+/// Screen corner the overlay is anchored to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Scrolling frame-time graph with FPS statistics
+///
+/// The graph holds no samples itself; it draws the newest frames of a
+/// `FrameStats` fed by the GUI loop.
+pub struct PerfGraph {
+    /// Number of bars drawn
+    bars: usize,
+    target_frame_ms: f32,
+    corner: Corner,
+    visible: bool,
+}
+
+impl PerfGraph {
+    /// Create a hidden graph showing the last `bars` frame times
+    pub fn new(bars: usize, target_fps: u32) -> Self {
+        Self {
+            bars: bars.max(1),
+            target_frame_ms: 1000.0 / target_fps.max(1) as f32,
+            corner: Corner::TopRight,
+            visible: false,
+        }
+    }
+
+    pub fn set_corner(&mut self, corner: Corner) {
+        self.corner = corner;
+    }
+
+    pub fn set_target_fps(&mut self, target_fps: u32) {
+        self.target_frame_ms = 1000.0 / target_fps.max(1) as f32;
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        log::info!("Performance overlay {}", if self.visible { "shown" } else { "hidden" });
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Draw the graph and statistics if the overlay is visible. Returns the
+    /// panel's area, which has to be repainted before it is drawn again.
+    pub fn render(&self, renderer: &mut Renderer, stats: &mut FrameStats) -> Option<Rect> {
+        if !self.visible {
+            return None;
+        }
+
+        let summary = stats.summary();
+        let graph_width = self.bars as u32 * BAR_WIDTH;
+        let text_height = TEXT_LINES * LINE_HEIGHT;
+        let panel_width = graph_width + 2 * PADDING as u32;
+        let panel_height = GRAPH_HEIGHT + (text_height + 3 * PADDING) as u32;
+
+        let (screen_width, screen_height) = renderer.dimensions();
+        let panel_x = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => MARGIN,
+            Corner::TopRight | Corner::BottomRight => screen_width as i32 - panel_width as i32 - MARGIN,
+        };
+        let panel_y = match self.corner {
+            Corner::TopLeft | Corner::TopRight => MARGIN,
+            Corner::BottomLeft | Corner::BottomRight => screen_height as i32 - panel_height as i32 - MARGIN,
+        };
+        let panel = Rect::new(panel_x, panel_y, panel_width, panel_height);
+        renderer.fill_rect(panel, BACKGROUND);
+
+        let text_x = panel_x + PADDING;
+        let mut text_y = panel_y + PADDING;
+        for (label, fps) in [
+            ("FPS", summary.current_fps()),
+            ("AVG", summary.average_fps()),
+            ("1%", summary.one_percent_low_fps()),
+            ("0.1%", summary.point_one_percent_low_fps()),
+        ] {
+            draw_text(renderer, text_x, text_y, &format!("{} {:.1}", label, fps), Color::WHITE);
+            text_y += LINE_HEIGHT;
+        }
+
+        // Scale so the target frame time sits at half height; longer frames clip at the top
+        let graph_x = panel_x + PADDING;
+        let graph_bottom = panel_y + panel_height as i32 - PADDING;
+        let ms_to_px = GRAPH_HEIGHT as f32 / (self.target_frame_ms * 2.0);
+
+        // Newest sample at the right edge, scrolling left
+        let shown = stats.len().min(self.bars);
+        let first_slot = self.bars - shown;
+        for (i, frame_ms) in stats.recent(shown).enumerate() {
+            let bar_height = ((frame_ms * ms_to_px) as u32).clamp(1, GRAPH_HEIGHT);
+            let color = if frame_ms <= self.target_frame_ms * 1.1 {
+                BAR_GOOD
+            } else if frame_ms <= self.target_frame_ms * 2.0 {
+                BAR_SLOW
+            } else {
+                BAR_HITCH
+            };
+            let x = graph_x + ((first_slot + i) as u32 * BAR_WIDTH) as i32;
+            renderer.fill_rect(Rect::new(x, graph_bottom - bar_height as i32, BAR_WIDTH, bar_height), color);
+        }
+
+        let target_y = (graph_bottom - (self.target_frame_ms * ms_to_px) as i32) as f32;
+        renderer.draw_line_aa(graph_x as f32, target_y, (graph_x + graph_width as i32) as f32, target_y, 1.0, TARGET_LINE);
+        Some(panel)
+    }
+}
+
+/// 3x5 glyphs for the characters the overlay prints, one row per byte (bit 2 = left column)
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b111, 0b100, 0b101, 0b101, 0b111],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        _ => [0; 5],
+    }
+}
+
+/// Draw text with the built-in glyphs; the overlay must not depend on the font system
+fn draw_text(renderer: &mut Renderer, x: i32, y: i32, text: &str, color: Color) {
+    let mut pen_x = x;
+    for c in text.chars() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) != 0 {
+                    renderer.fill_rect(
+                        Rect::new(pen_x + col * GLYPH_SCALE, y + row as i32 * GLYPH_SCALE, GLYPH_SCALE as u32, GLYPH_SCALE as u32),
+                        color,
+                    );
+                }
+            }
+        }
+        pen_x += 4 * GLYPH_SCALE;
+    }
+}
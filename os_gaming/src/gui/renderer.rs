@@ -10,6 +10,8 @@ use spin::Mutex;
 use serde::{Serialize, Deserialize};
 
 use crate::kernel::drivers::gpu;
+use super::font::{self, FontManager};
+use super::glyph_raster::GlyphBitmap;
 use crate::kernel::memory::{
     self,
     MemoryError as KernelMemoryError,
@@ -71,6 +73,115 @@ pub struct Texture { /* ... as before ... */
 pub enum TextureFormat { RGBA8, RGB8, BGRA8, A8 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlendMode { None, Alpha, Additive, Multiply }
+/// How content of a different aspect ratio is placed on the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// Show all of the content, with bars on the sides or top and bottom
+    Fit,
+    /// Cover the whole area, cropping the content's overflowing edges
+    Fill,
+}
+
+impl ScaleMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fit" => Some(ScaleMode::Fit),
+            "fill" => Some(ScaleMode::Fill),
+            _ => None,
+        }
+    }
+}
+
+/// Placement of content scaled into an area with its aspect ratio kept
+#[derive(Debug, Clone, Copy)]
+pub struct Letterbox {
+    /// Where the scaled content lands; larger than `area` in `Fill` mode
+    pub content: Rect,
+    pub area: Rect,
+    pub content_width: u32,
+    pub content_height: u32,
+    /// Display pixels per content pixel
+    pub scale: f32,
+}
+
+impl Letterbox {
+    pub fn compute(content_width: u32, content_height: u32, area: Rect, mode: ScaleMode) -> Self {
+        let scale_x = area.width as f32 / content_width.max(1) as f32;
+        let scale_y = area.height as f32 / content_height.max(1) as f32;
+        let scale = match mode {
+            ScaleMode::Fit => scale_x.min(scale_y),
+            ScaleMode::Fill => scale_x.max(scale_y),
+        };
+        let width = (content_width as f32 * scale).round() as u32;
+        let height = (content_height as f32 * scale).round() as u32;
+        Self {
+            content: Rect::new(
+                area.x + (area.width as i32 - width as i32) / 2,
+                area.y + (area.height as i32 - height as i32) / 2,
+                width,
+                height,
+            ),
+            area,
+            content_width,
+            content_height,
+            scale,
+        }
+    }
+
+    /// The bars around fitted content: left/right or top/bottom, empty when it fills the area
+    pub fn bars(&self) -> [Option<Rect>; 2] {
+        let (a, c) = (self.area, self.content);
+        let bar = |x: i32, y: i32, width: i32, height: i32| {
+            (width > 0 && height > 0).then(|| Rect::new(x, y, width as u32, height as u32))
+        };
+        let area_right = a.x + a.width as i32;
+        let area_bottom = a.y + a.height as i32;
+        if c.width < a.width {
+            [
+                bar(a.x, a.y, c.x - a.x, a.height as i32),
+                bar(c.x + c.width as i32, a.y, area_right - (c.x + c.width as i32), a.height as i32),
+            ]
+        } else {
+            [
+                bar(a.x, a.y, a.width as i32, c.y - a.y),
+                bar(a.x, c.y + c.height as i32, a.width as i32, area_bottom - (c.y + c.height as i32)),
+            ]
+        }
+    }
+
+    /// Content pixel under display point (x, y); None on a bar or outside the area
+    pub fn to_content(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        if !self.area.contains(x, y) || !self.content.contains(x, y) || self.scale <= 0.0 {
+            return None;
+        }
+        let cx = ((x - self.content.x) as f32 / self.scale) as i32;
+        let cy = ((y - self.content.y) as f32 / self.scale) as i32;
+        Some((cx.min(self.content_width as i32 - 1), cy.min(self.content_height as i32 - 1)))
+    }
+
+    /// Display position of the top-left corner of content pixel (x, y)
+    pub fn to_display(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            self.content.x + (x as f32 * self.scale) as i32,
+            self.content.y + (y as f32 * self.scale) as i32,
+        )
+    }
+}
+
+/// Lowest render scale; below it text stops being readable
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+
+/// The display framebuffer, set aside while drawing goes to a smaller back buffer
+struct ScaledOutput {
+    width: u32,
+    height: u32,
+    ptr: *mut u32,
+    size: usize,
+    pitch_pixels: u32,
+    accelerated: bool,
+    back_buffer: Vec<u32>,
+}
+
 #[derive(Debug)]
 pub struct RendererCapabilities { /* ... as before ... */
     pub max_texture_size: u32, pub supports_blend_modes: bool,
@@ -86,10 +197,19 @@ pub struct Renderer {
     framebuffer_is_gpu_provided: bool,
     framebuffer_pitch_pixels: u32,
     clip_rect: Option<Rect>,
+    /// Outer limit every clip rect is intersected with
+    clip_bounds: Option<Rect>,
     blend_mode: BlendMode,
+    /// Background and letterbox bar color
+    clear_color: Color,
+    scale_mode: ScaleMode,
     gpu_accelerated: AtomicBool,
     capabilities: RendererCapabilities,
     textures: Mutex<Vec<Texture>>,
+    /// Fraction of the display resolution frames are drawn at
+    render_scale: f32,
+    /// Set while rendering below the display resolution
+    output: Option<ScaledOutput>,
 }
 
 #[derive(Debug)]
@@ -190,10 +310,15 @@ impl Renderer {
             framebuffer_is_gpu_provided: framebuffer_is_gpu_provided_val,
             framebuffer_pitch_pixels: actual_pitch_bytes_val / 4,
             clip_rect: None,
+            clip_bounds: None,
             blend_mode: BlendMode::Alpha,
+            clear_color: Color::BLACK,
+            scale_mode: ScaleMode::Fit,
             gpu_accelerated: AtomicBool::new(gpu_hw_initialized && framebuffer_is_gpu_provided_val), // True acceleration if GPU provides FB
             capabilities,
             textures: Mutex::new(Vec::new()),
+            render_scale: 1.0,
+            output: None,
         })
     }
 
@@ -207,18 +332,62 @@ impl Renderer {
         }
     }
 
+    /// Clear the whole framebuffer with the configured clear color
+    pub fn clear_to_background(&mut self) {
+        self.clear(self.clear_color);
+    }
+
+    pub fn set_clear_color(&mut self, color: Color) {
+        self.clear_color = color;
+    }
+
+    pub fn clear_color(&self) -> Color {
+        self.clear_color
+    }
+
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.scale_mode = mode;
+    }
+
+    pub fn scale_mode(&self) -> ScaleMode {
+        self.scale_mode
+    }
+
+    /// Draw a `content_width` x `content_height` texture into `area` with its
+    /// aspect ratio kept, per the scale mode. Bars left in `Fit` mode get the
+    /// clear color. Returns the placement, for mapping input back to content.
+    pub fn draw_letterboxed(
+        &mut self,
+        texture_id: u32,
+        content_width: u32,
+        content_height: u32,
+        area: Rect,
+    ) -> Result<Letterbox, RendererError> {
+        if content_width == 0 || content_height == 0 { return Err(RendererError::InvalidParameters); }
+        let letterbox = Letterbox::compute(content_width, content_height, area, self.scale_mode);
+        for bar in letterbox.bars().iter().flatten() {
+            self.fill_rect(*bar, self.clear_color);
+        }
+
+        // Cropped content must not spill out of the area
+        let previous_clip = self.clip_rect;
+        let clip = previous_clip.map_or(Some(area), |clip| clip.intersection(&area));
+        if let Some(clip) = clip {
+            self.set_clip_rect(Some(clip));
+            let result = self.draw_texture(texture_id, letterbox.content);
+            self.set_clip_rect(previous_clip);
+            result?;
+        }
+        Ok(letterbox)
+    }
+
     fn clear_software(&self, color: Color) { /* ... as in previous corrected version ... */
         let color_value = color.to_argb();
-        let pixel_count = self.width * self.height;
-        unsafe {
-            for i in 0..pixel_count { // This simple loop is safer than assuming row-major for generic pitch
-                let y = i / self.width;
-                let x = i % self.width;
-                let offset = y as usize * self.framebuffer_pitch_pixels as usize + x as usize;
-                if offset < (self.framebuffer_size / 4) { // Bounds check
-                    *self.framebuffer_ptr.add(offset) = color_value;
-                }
-            }
+        let pitch = self.framebuffer_pitch_pixels as usize;
+        for y in 0..self.height as usize { // Row by row, since pitch may exceed width
+            let offset = y * pitch;
+            let len = (self.width as usize).min((self.framebuffer_size / 4).saturating_sub(offset)); // Bounds check
+            unsafe { gpu::fill_span(self.framebuffer_ptr.add(offset), len, color_value); }
         }
     }
     
@@ -264,6 +433,15 @@ impl Renderer {
         let end_x = (rect.x + rect.width as i32) as usize;
         let end_y = (rect.y + rect.height as i32) as usize;
 
+        if color.a == 255 { // Opaque fills take the wide-store path
+            for y_idx in start_y..end_y {
+                let offset = y_idx * self.framebuffer_pitch_pixels as usize + start_x;
+                let len = (end_x - start_x).min((self.framebuffer_size / 4).saturating_sub(offset)); // Bounds check
+                unsafe { gpu::fill_span(self.framebuffer_ptr.add(offset), len, color_value); }
+            }
+            return;
+        }
+
         unsafe {
             for y_idx in start_y..end_y {
                 for x_idx in start_x..end_x {
@@ -309,6 +487,16 @@ impl Renderer {
         }
     }
     
+    /// Anti-aliased line; falls back to a plain line without GPU acceleration
+    pub fn draw_line_aa(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, width: f32, color: Color) {
+        if self.gpu_accelerated.load(Ordering::Relaxed)
+            && gpu::draw_line_aa(x1, y1, x2, y2, width, color.to_argb()).is_ok()
+        {
+            return;
+        }
+        self.draw_line_software(x1.round() as i32, y1.round() as i32, x2.round() as i32, y2.round() as i32, color);
+    }
+
     fn draw_line_software(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: Color) { /* ... as in previous corrected version ... */
         let mut x = x1; let mut y = y1;
         let dx = (x2 - x1).abs(); let dy = (y2 - y1).abs();
@@ -339,7 +527,18 @@ impl Renderer {
         };
 
         if self.gpu_accelerated.load(Ordering::Relaxed) {
-            if gpu::draw_texture(texture_id, final_dst_rect.x, final_dst_rect.y, final_dst_rect.width, final_dst_rect.height).is_ok() {
+            // Draw the whole rect and let the device clip, so a partly hidden texture isn't squashed
+            let clipped = (final_dst_rect.x, final_dst_rect.y, final_dst_rect.width, final_dst_rect.height)
+                != (dst_rect.x, dst_rect.y, dst_rect.width, dst_rect.height);
+            if clipped {
+                let _ = gpu::set_clip_rect(final_dst_rect.x, final_dst_rect.y, final_dst_rect.width, final_dst_rect.height);
+            }
+            let drawn = gpu::draw_texture(texture_id, dst_rect.x, dst_rect.y, dst_rect.width, dst_rect.height).is_ok();
+            if clipped {
+                let clip = self.clip_rect;
+                self.set_clip_rect(clip);
+            }
+            if drawn {
                 return Ok(());
             }
         }
@@ -359,14 +558,15 @@ impl Renderer {
         // This assumes gpu::get_texture_data can retrieve data even if other ops failed.
         let tex_data = gpu::get_texture_data(tex_id_for_data).map_err(|_| RendererError::DrawingFailed)?;
 
+        // Sample relative to the unclipped rect so clipping crops instead of squashing
         for y_dst_rel in 0..final_dst_rect.height {
             let y_dst_abs = final_dst_rect.y + y_dst_rel as i32;
-            let y_src = (y_dst_rel as f32 / final_dst_rect.height as f32 * tex_h as f32) as u32;
+            let y_src = ((y_dst_abs - dst_rect.y) as f32 / dst_rect.height as f32 * tex_h as f32) as u32;
             if y_src >= tex_h { continue; }
 
             for x_dst_rel in 0..final_dst_rect.width {
                 let x_dst_abs = final_dst_rect.x + x_dst_rel as i32;
-                let x_src = (x_dst_rel as f32 / final_dst_rect.width as f32 * tex_w as f32) as u32;
+                let x_src = ((x_dst_abs - dst_rect.x) as f32 / dst_rect.width as f32 * tex_w as f32) as u32;
                 if x_src >= tex_w { continue; }
 
                 let pixel_index = (y_src * tex_w + x_src) as usize;
@@ -408,16 +608,22 @@ impl Renderer {
     
     // This method was missing from the impl block in the previous version based on errors
     pub fn set_clip_rect(&mut self, rect: Option<Rect>) {
-        self.clip_rect = rect.and_then(|r| { // Use and_then for cleaner chaining
-            let x = r.x.max(0).min(self.width as i32);
-            let y = r.y.max(0).min(self.height as i32);
-            let end_x = (r.x + r.width as i32).min(self.width as i32);
-            let end_y = (r.y + r.height as i32).min(self.height as i32);
-            let width = (end_x - x).max(0) as u32;
-            let height = (end_y - y).max(0) as u32;
-            if width == 0 || height == 0 { None }
-            else { Some(Rect::new(x, y, width, height)) }
-        });
+        self.clip_rect = match self.clip_bounds {
+            // A rect outside the bounds leaves an empty clip, which draws nothing
+            Some(bounds) => Some(rect.map_or(bounds, |r| {
+                r.intersection(&bounds).unwrap_or(Rect::new(bounds.x, bounds.y, 0, 0))
+            })),
+            None => rect.and_then(|r| { // Use and_then for cleaner chaining
+                let x = r.x.max(0).min(self.width as i32);
+                let y = r.y.max(0).min(self.height as i32);
+                let end_x = (r.x + r.width as i32).min(self.width as i32);
+                let end_y = (r.y + r.height as i32).min(self.height as i32);
+                let width = (end_x - x).max(0) as u32;
+                let height = (end_y - y).max(0) as u32;
+                if width == 0 || height == 0 { None }
+                else { Some(Rect::new(x, y, width, height)) }
+            }),
+        };
 
         if self.gpu_accelerated.load(Ordering::Relaxed) {
             if let Some(r) = self.clip_rect {
@@ -428,6 +634,19 @@ impl Renderer {
         }
     }
     
+    /// Confine all drawing to `bounds`, including rects later passed to
+    /// `set_clip_rect`, so a partial redraw can't spill out of its region
+    pub fn set_clip_bounds(&mut self, bounds: Option<Rect>) {
+        let screen = Rect::new(0, 0, self.width, self.height);
+        self.clip_bounds = bounds.map(|b| b.intersection(&screen).unwrap_or(Rect::new(0, 0, 0, 0)));
+        self.set_clip_rect(None);
+    }
+
+    /// Area drawing is currently limited to, if any
+    pub fn clip_rect(&self) -> Option<Rect> {
+        self.clip_rect
+    }
+
     pub fn set_blend_mode(&mut self, mode: BlendMode) { /* ... as in previous corrected version ... */
         self.blend_mode = mode;
         if self.gpu_accelerated.load(Ordering::Relaxed) && self.capabilities.supports_blend_modes {
@@ -436,6 +655,13 @@ impl Renderer {
     }
     
     pub fn present(&self) -> Result<(), RendererError> { /* ... as in previous corrected version ... */
+        if let Some(output) = &self.output {
+            self.upscale_to_output(output);
+            if output.accelerated {
+                gpu::present().map_err(|_| RendererError::DrawingFailed)?;
+            }
+            return Ok(());
+        }
         if self.gpu_accelerated.load(Ordering::Relaxed) {
             gpu::present().map_err(|_| RendererError::DrawingFailed)?;
         } else {
@@ -444,35 +670,332 @@ impl Renderer {
         Ok(())
     }
     
+    /// Present once scanout has passed line `y`, keeping tears out of the top of the screen
+    pub fn present_after_scanline(&self, y: u32) -> Result<(), RendererError> {
+        if let Some(output) = &self.output {
+            self.upscale_to_output(output);
+            if output.accelerated {
+                gpu::present_after_scanline(y).map_err(|_| RendererError::DrawingFailed)?;
+            }
+            return Ok(());
+        }
+        if self.gpu_accelerated.load(Ordering::Relaxed) {
+            gpu::present_after_scanline(y).map_err(|_| RendererError::DrawingFailed)?;
+        }
+        Ok(())
+    }
+
+    /// Blend `glyph` in `color` with its pen position at (x, baseline).
+    /// Coverage scales the color's alpha; the clip rect applies.
+    pub fn draw_glyph(&mut self, glyph: &GlyphBitmap, x: i32, baseline: i32, color: Color) {
+        let left = x + glyph.left;
+        let top = baseline - glyph.top;
+        for (i, &coverage) in glyph.coverage.iter().enumerate() {
+            if coverage == 0 {
+                continue;
+            }
+            let px = left + (i as u32 % glyph.width) as i32;
+            let py = top + (i as u32 / glyph.width) as i32;
+            let a = (color.a as u32 * coverage as u32 / 255) as u8;
+            self.draw_pixel(px, py, Color { a, ..color });
+        }
+    }
+
+    /// Draw `text` with the default font, its first line's top-left corner
+    /// at (x, y). Lines break at '\n'. Returns the width of the widest line,
+    /// so callers can center labels.
+    pub fn draw_text(&mut self, text: &str, x: i32, y: i32, size: f32, color: Color) -> u32 {
+        let fonts = font::font_manager().lock();
+        let ascent = fonts.ascent(size);
+        let (width, _) = layout_glyphs(&fonts, text, size, |glyph, pen_x, pen_y| {
+            self.draw_glyph(glyph, x + pen_x.round() as i32, y + (pen_y + ascent).round() as i32, color);
+        });
+        width.ceil() as u32
+    }
+
+    /// Width and height `draw_text` would cover
+    pub fn measure_text(&self, text: &str, size: f32) -> (u32, u32) {
+        let fonts = font::font_manager().lock();
+        let (width, height) = layout_glyphs(&fonts, text, size, |_, _, _| {});
+        (width.ceil() as u32, height.ceil() as u32)
+    }
+
+    /// Read the raw ARGB value of a framebuffer pixel, ignoring clipping
+    pub fn read_raw_pixel(&self, x: i32, y: i32) -> Option<u32> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 { return None; }
+        let offset = y as usize * self.framebuffer_pitch_pixels as usize + x as usize;
+        if offset >= (self.framebuffer_size / 4) { return None; }
+        unsafe { Some(*self.framebuffer_ptr.add(offset)) }
+    }
+
+    /// Write a raw ARGB value to a framebuffer pixel, ignoring clipping and blending
+    pub fn write_raw_pixel(&mut self, x: i32, y: i32, value: u32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 { return; }
+        let offset = y as usize * self.framebuffer_pitch_pixels as usize + x as usize;
+        if offset < (self.framebuffer_size / 4) {
+            unsafe { *self.framebuffer_ptr.add(offset) = value; }
+        }
+    }
+
+    /// Copy the raw pixels of `rect`, row by row. Off-screen pixels read as None.
+    pub fn copy_region(&self, rect: Rect) -> Vec<Option<u32>> {
+        let mut pixels = Vec::with_capacity(rect.width as usize * rect.height as usize);
+        for y in rect.y..rect.y + rect.height as i32 {
+            for x in rect.x..rect.x + rect.width as i32 {
+                pixels.push(self.read_raw_pixel(x, y));
+            }
+        }
+        pixels
+    }
+
+    /// Move the pixels of `src` to (dst_x, dst_y), overlapping or not. Lets
+    /// a scrolled console or list redraw only the strip that scrolled in.
+    /// The clip rect is not applied.
+    pub fn copy_rect(&mut self, src: Rect, dst_x: i32, dst_y: i32) {
+        if self.gpu_accelerated.load(Ordering::Relaxed)
+            && gpu::copy_rect(src.x, src.y, dst_x, dst_y, src.width, src.height).is_ok()
+        {
+            return;
+        }
+        // Safety: the framebuffer spans `framebuffer_pitch_pixels * height` pixels
+        let mut surface = unsafe {
+            gpu::Surface::from_raw(self.framebuffer_ptr, self.width, self.height, self.framebuffer_pitch_pixels * 4)
+        };
+        surface.copy_rect(src.x, src.y, dst_x, dst_y, src.width, src.height);
+    }
+
+    /// Blend what was drawn in `rect` at `opacity` over `below`, the pixels
+    /// `copy_region` returned for the same rect before drawing
+    pub fn composite_over(&mut self, rect: Rect, below: &[Option<u32>], opacity: u8) {
+        let mut pixels = below.iter();
+        for y in rect.y..rect.y + rect.height as i32 {
+            for x in rect.x..rect.x + rect.width as i32 {
+                let dst = match pixels.next() {
+                    Some(Some(dst)) => self.unpack_color(*dst),
+                    Some(None) => continue,
+                    None => return,
+                };
+                if let Some(src) = self.read_raw_pixel(x, y) {
+                    let src = Color { a: opacity, ..self.unpack_color(src) };
+                    let blended = Self::blend_alpha(src, dst);
+                    self.write_raw_pixel(x, y, blended.to_argb());
+                }
+            }
+        }
+    }
+
+    /// Reallocate the framebuffer for a new resolution. On failure the renderer is left untouched.
+    /// The render scale carries over to the new resolution.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), RendererError> {
+        if (width, height) == self.output_dimensions() { return Ok(()); }
+        let mut resized = Renderer::new(width, height)?;
+        // Textures survive the mode change; hand them over so the old renderer's Drop doesn't free them
+        core::mem::swap(&mut *resized.textures.lock(), &mut *self.textures.lock());
+        resized.blend_mode = self.blend_mode;
+        resized.clear_color = self.clear_color;
+        resized.scale_mode = self.scale_mode;
+        let render_scale = self.render_scale;
+        *self = resized;
+        log::info!("Renderer resized to {}x{}", width, height);
+        if render_scale < 1.0 {
+            if let Err(e) = self.set_render_scale(render_scale) {
+                log::warn!("Rendering at native resolution, render scale lost: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw frames at `factor` of the display resolution and upscale them
+    /// bilinearly on present. The factor is clamped to `MIN_RENDER_SCALE..=1.0`
+    /// and the render size rounded down to even numbers. Drawing is done in
+    /// software while scaled. Returns the factor applied.
+    pub fn set_render_scale(&mut self, factor: f32) -> Result<f32, RendererError> {
+        let factor = if factor.is_finite() { factor.clamp(MIN_RENDER_SCALE, 1.0) } else { 1.0 };
+        let (native_width, native_height) = self.output_dimensions();
+        let even = |extent: u32| (((extent as f32 * factor) as u32) & !1).max(2);
+        let (width, height) = (even(native_width), even(native_height));
+
+        if factor >= 1.0 || (width, height) == (native_width, native_height) {
+            self.restore_output();
+            self.render_scale = 1.0;
+            return Ok(1.0);
+        }
+        if self.output.is_some() && (width, height) == (self.width, self.height) {
+            self.render_scale = factor;
+            return Ok(factor);
+        }
+
+        let pixels = width as usize * height as usize;
+        let mut back_buffer = Vec::new();
+        back_buffer
+            .try_reserve_exact(pixels)
+            .map_err(|_| RendererError::InitializationFailed("Render scale back buffer allocation failed".into()))?;
+        back_buffer.resize(pixels, self.clear_color.to_argb());
+
+        self.restore_output();
+        self.set_clip_rect(None);
+        let mut output = ScaledOutput {
+            width: self.width,
+            height: self.height,
+            ptr: self.framebuffer_ptr,
+            size: self.framebuffer_size,
+            pitch_pixels: self.framebuffer_pitch_pixels,
+            accelerated: self.gpu_accelerated.load(Ordering::Relaxed),
+            back_buffer,
+        };
+        self.framebuffer_ptr = output.back_buffer.as_mut_ptr();
+        self.framebuffer_size = pixels * 4;
+        self.framebuffer_pitch_pixels = width;
+        self.width = width;
+        self.height = height;
+        // The GPU draws into the display framebuffer, not the back buffer
+        self.gpu_accelerated.store(false, Ordering::Relaxed);
+        self.output = Some(output);
+        self.render_scale = factor;
+        log::info!("Rendering at {}x{} ({:.2}x of {}x{})", width, height, factor, native_width, native_height);
+        Ok(factor)
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Draw straight into the display framebuffer again
+    fn restore_output(&mut self) {
+        if let Some(output) = self.output.take() {
+            self.width = output.width;
+            self.height = output.height;
+            self.framebuffer_ptr = output.ptr;
+            self.framebuffer_size = output.size;
+            self.framebuffer_pitch_pixels = output.pitch_pixels;
+            self.gpu_accelerated.store(output.accelerated, Ordering::Relaxed);
+            self.clip_rect = None;
+        }
+    }
+
+    /// Size of the display framebuffer; `dimensions` is the size drawn at
+    pub fn output_dimensions(&self) -> (u32, u32) {
+        self.output.as_ref().map_or((self.width, self.height), |output| (output.width, output.height))
+    }
+
+    /// Map a display position, e.g. from the mouse, into render space
+    pub fn to_render_space(&self, x: i32, y: i32) -> (i32, i32) {
+        match &self.output {
+            Some(output) => (
+                (x as i64 * self.width as i64 / output.width as i64) as i32,
+                (y as i64 * self.height as i64 / output.height as i64) as i32,
+            ),
+            None => (x, y),
+        }
+    }
+
+    /// Bilinearly stretch the back buffer over the display framebuffer
+    fn upscale_to_output(&self, output: &ScaledOutput) {
+        let (src_width, src_height) = (self.width as usize, self.height as usize);
+        let src = &output.back_buffer;
+        // 16.16 fixed-point source step per display pixel
+        let step_x = ((src_width as u64) << 16) / output.width as u64;
+        let step_y = ((src_height as u64) << 16) / output.height as u64;
+        // Source coordinate of a display pixel's center, minus half a source pixel
+        let source = |i: u32, step: u64, extent: usize| {
+            let pos = ((i as u64 * 2 + 1) * step / 2).saturating_sub(1 << 15);
+            let i0 = ((pos >> 16) as usize).min(extent - 1);
+            (i0, (i0 + 1).min(extent - 1), ((pos >> 8) & 0xFF) as u32)
+        };
+
+        for y in 0..output.height {
+            let row = y as usize * output.pitch_pixels as usize;
+            if row + output.width as usize > output.size / 4 { break; }
+            let (y0, y1, fy) = source(y, step_y, src_height);
+            let (top, bottom) = (&src[y0 * src_width..][..src_width], &src[y1 * src_width..][..src_width]);
+            for x in 0..output.width {
+                let (x0, x1, fx) = source(x, step_x, src_width);
+                let value = lerp_argb(lerp_argb(top[x0], top[x1], fx), lerp_argb(bottom[x0], bottom[x1], fx), fy);
+                unsafe { *output.ptr.add(row + x as usize) = value; }
+            }
+        }
+    }
+
     pub fn dimensions(&self) -> (u32, u32) { (self.width, self.height) }
     pub fn is_accelerated(&self) -> bool { self.gpu_accelerated.load(Ordering::Relaxed) }
+    /// Whether `present` goes through `gpu::present`, also while render-scaled
+    pub fn presents_through_gpu(&self) -> bool {
+        self.output.as_ref().map_or(self.is_accelerated(), |output| output.accelerated)
+    }
     pub fn capabilities(&self) -> &RendererCapabilities { &self.capabilities }
     
     fn unpack_color(&self, argb_val: u32) -> Color { /* ... as in previous corrected version (assuming ARGB) ... */
         Color {a: (argb_val >> 24) as u8, r: (argb_val >> 16) as u8, g: (argb_val >> 8) as u8, b: argb_val as u8 }
     }
+    /// Source-over blend of `src` onto `dst`
+    fn blend_alpha(src: Color, dst: Color) -> Color {
+        if src.a == 0 { return dst; } if src.a == 255 { return src; }
+        let sa = src.a as f32 / 255.0; let da = dst.a as f32 / 255.0;
+        let out_a = sa + da * (1.0 - sa);
+        if out_a < 0.001 { return Color::TRANSPARENT; }
+        let r = ((src.r as f32 * sa + dst.r as f32 * da * (1.0 - sa)) / out_a).round() as u8;
+        let g = ((src.g as f32 * sa + dst.g as f32 * da * (1.0 - sa)) / out_a).round() as u8;
+        let b = ((src.b as f32 * sa + dst.b as f32 * da * (1.0 - sa)) / out_a).round() as u8;
+        Color::new(r, g, b, (out_a * 255.0).round() as u8)
+    }
     fn blend_colors(&self, src: Color, dst: Color) -> Color { /* ... as in previous corrected version ... */
         match self.blend_mode {
             BlendMode::None => src,
-            BlendMode::Alpha => {
-                if src.a == 0 { return dst; } if src.a == 255 { return src; }
-                let sa = src.a as f32 / 255.0; let da = dst.a as f32 / 255.0;
-                let out_a = sa + da * (1.0 - sa);
-                if out_a < 0.001 { return Color::TRANSPARENT; }
-                let r = ((src.r as f32 * sa + dst.r as f32 * da * (1.0 - sa)) / out_a).round() as u8;
-                let g = ((src.g as f32 * sa + dst.g as f32 * da * (1.0 - sa)) / out_a).round() as u8;
-                let b = ((src.b as f32 * sa + dst.b as f32 * da * (1.0 - sa)) / out_a).round() as u8;
-                Color::new(r, g, b, (out_a * 255.0).round() as u8)
-            },
+            BlendMode::Alpha => Self::blend_alpha(src, dst),
             BlendMode::Additive => Color::rgb(src.r.saturating_add(dst.r), src.g.saturating_add(dst.g), src.b.saturating_add(dst.b)),
             BlendMode::Multiply => Color::rgb(((src.r as u16 * dst.r as u16) / 255) as u8, ((src.g as u16 * dst.g as u16) / 255) as u8, ((src.b as u16 * dst.b as u16) / 255) as u8),
         }
     }
 }
 
+/// Blend two ARGB values per channel, `t` of 256 towards `b`
+fn lerp_argb(a: u32, b: u32, t: u32) -> u32 {
+    let rb = (((a & 0x00FF00FF) * (256 - t) + (b & 0x00FF00FF) * t) >> 8) & 0x00FF00FF;
+    let ag = (((a >> 8) & 0x00FF00FF) * (256 - t) + ((b >> 8) & 0x00FF00FF) * t) & 0xFF00FF00;
+    rb | ag
+}
+
+/// Walk `text` glyph by glyph the way `draw_text` places them, calling
+/// `place` with each glyph and its pen position relative to the text origin
+/// (pen_y at the top of the glyph's line). Returns the widest line and the
+/// total height.
+fn layout_glyphs(fonts: &FontManager, text: &str, size: f32, mut place: impl FnMut(&GlyphBitmap, f32, f32)) -> (f32, f32) {
+    let line_height = fonts.line_height(size);
+    let (mut pen_x, mut pen_y, mut width) = (0.0f32, 0.0f32, 0.0f32);
+    let mut prev: Option<char> = None;
+    for c in text.chars() {
+        match c {
+            '\n' => {
+                width = width.max(pen_x);
+                pen_x = 0.0;
+                pen_y += line_height;
+                prev = None;
+                continue;
+            }
+            '\r' => continue,
+            '\t' => {
+                pen_x += fonts.glyph_advance(c, pen_x, size);
+                prev = None;
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(left) = prev.filter(|_| !c.is_whitespace()) {
+            pen_x += fonts.kerning(left, c, size);
+        }
+        let glyph = fonts.glyph(c, size);
+        place(&glyph, pen_x, pen_y);
+        pen_x += glyph.advance;
+        prev = if c.is_whitespace() { None } else { Some(c) };
+    }
+    (width.max(pen_x), pen_y + line_height)
+}
+
 impl Drop for Renderer { /* ... as in previous corrected version, ensure memory::free_virtual_backed_memory is used ... */
     fn drop(&mut self) {
         log::info!("Dropping Renderer resources.");
+        // Free the display framebuffer, not the back buffer
+        self.restore_output();
         let textures_guard = self.textures.lock();
         for texture in textures_guard.iter() {
             if self.gpu_accelerated.load(Ordering::Relaxed) {
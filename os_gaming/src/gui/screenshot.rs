@@ -0,0 +1,151 @@
+//! Screenshot capture and export
+//!
+//! Captures the visible framebuffer, encodes it as an uncompressed 24-bit BMP
+//! and writes it to removable storage.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::format;
+
+use crate::kernel::drivers::filesystem::{self, Filesystem, FilesystemManager};
+use crate::kernel::drivers::gpu;
+
+/// Give up picking a free name after this many numbered attempts
+const MAX_NAME_ATTEMPTS: u32 = 1000;
+
+const BMP_FILE_HEADER_SIZE: u32 = 14;
+const BMP_INFO_HEADER_SIZE: u32 = 40;
+
+/// A captured frame in ARGB8888, row-major, top row first
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u32>,
+}
+
+impl Screenshot {
+    /// Copy the currently displayed framebuffer
+    pub fn capture() -> Result<Self, &'static str> {
+        let mode = gpu::current_mode().map_err(|_| "No display mode to capture")?;
+        if mode.bpp != 32 {
+            return Err("Screenshots need a 32-bit framebuffer");
+        }
+        let pitch = gpu::get_framebuffer_pitch().map_err(|_| "Framebuffer pitch unavailable")? as usize;
+        let base = gpu::get_framebuffer(mode.width, mode.height).map_err(|_| "Framebuffer unavailable")?;
+        if base == 0 {
+            return Err("Framebuffer unavailable");
+        }
+
+        let mut pixels = Vec::with_capacity((mode.width * mode.height) as usize);
+        for y in 0..mode.height as usize {
+            let row = (base + y * pitch) as *const u32;
+            for x in 0..mode.width as usize {
+                // Safety: the GPU reported a framebuffer of this mode and pitch
+                pixels.push(unsafe { core::ptr::read_volatile(row.add(x)) });
+            }
+        }
+
+        Ok(Self { width: mode.width, height: mode.height, pixels })
+    }
+
+    /// Encode as a bottom-up 24-bit BMP
+    pub fn to_bmp(&self) -> Vec<u8> {
+        let row_size = (self.width * 3 + 3) & !3;
+        let image_size = row_size * self.height;
+        let data_offset = BMP_FILE_HEADER_SIZE + BMP_INFO_HEADER_SIZE;
+        let file_size = data_offset + image_size;
+
+        let mut bmp = Vec::with_capacity(file_size as usize);
+        // BITMAPFILEHEADER
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&file_size.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes()); // Reserved
+        bmp.extend_from_slice(&data_offset.to_le_bytes());
+        // BITMAPINFOHEADER
+        bmp.extend_from_slice(&BMP_INFO_HEADER_SIZE.to_le_bytes());
+        bmp.extend_from_slice(&(self.width as i32).to_le_bytes());
+        bmp.extend_from_slice(&(self.height as i32).to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes()); // Planes
+        bmp.extend_from_slice(&24u16.to_le_bytes()); // Bits per pixel
+        bmp.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB
+        bmp.extend_from_slice(&image_size.to_le_bytes());
+        bmp.extend_from_slice(&2835i32.to_le_bytes()); // 72 DPI
+        bmp.extend_from_slice(&2835i32.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes()); // Palette colors
+        bmp.extend_from_slice(&0u32.to_le_bytes()); // Important colors
+
+        let padding = (row_size - self.width * 3) as usize;
+        for y in (0..self.height as usize).rev() {
+            let row = &self.pixels[y * self.width as usize..(y + 1) * self.width as usize];
+            for &argb in row {
+                bmp.extend_from_slice(&[argb as u8, (argb >> 8) as u8, (argb >> 16) as u8]);
+            }
+            bmp.extend(core::iter::repeat(0u8).take(padding));
+        }
+        bmp
+    }
+}
+
+/// Capture the screen and save it as a BMP in the root of the first mounted
+/// USB mass-storage filesystem. If `filename` is taken, `-1`, `-2`, ... is
+/// appended before the extension. Returns the path written.
+pub fn export_screenshot_to_usb(filename: &str) -> Result<String, &'static str> {
+    let bmp = Screenshot::capture()?.to_bmp();
+
+    let mut fs_manager = filesystem::get_fs_manager().lock();
+    let fs_name = fs_manager
+        .first_usb_filesystem()
+        .map(|fs| String::from(fs.get_name()))
+        .ok_or("No USB storage mounted")?;
+
+    let path = write_unique(&mut fs_manager, &fs_name, filename, &bmp)?;
+    log::info!("Screenshot saved to {} on {}", path, fs_name);
+    Ok(path)
+}
+
+/// Write `data` to a new file named after `filename` in the root of `fs_name`
+pub fn write_unique(
+    fs_manager: &mut FilesystemManager,
+    fs_name: &str,
+    filename: &str,
+    data: &[u8],
+) -> Result<String, &'static str> {
+    let fs = fs_manager.get_filesystem_mut(fs_name).ok_or("Filesystem not found")?;
+    let path = unique_path(fs, filename)?;
+    fs.create_file(&path)?;
+
+    let mut file = fs_manager
+        .get_filesystem(fs_name)
+        .ok_or("Filesystem not found")?
+        .open_file(&path, false)?;
+    let mut written = 0;
+    while written < data.len() {
+        match file.write(&data[written..], fs_manager)? {
+            0 => return Err("Short write while saving screenshot"),
+            n => written += n,
+        }
+    }
+    file.sync(fs_manager)?;
+    file.close(fs_manager)?;
+    Ok(path)
+}
+
+/// First of `/name.ext`, `/name-1.ext`, `/name-2.ext`, ... not present in the root directory
+fn unique_path(fs: &Filesystem, filename: &str) -> Result<String, &'static str> {
+    let root = fs.open_directory("/")?;
+    let taken = |name: &str| root.entries.iter().any(|entry| entry.name == name);
+
+    let (stem, extension) = match filename.rfind('.') {
+        Some(dot) if dot > 0 => (&filename[..dot], &filename[dot..]),
+        _ => (filename, ""),
+    };
+
+    if !taken(filename) {
+        return Ok(format!("/{}", filename));
+    }
+    (1..MAX_NAME_ATTEMPTS)
+        .map(|n| format!("{}-{}{}", stem, n, extension))
+        .find(|candidate| !taken(candidate))
+        .map(|name| format!("/{}", name))
+        .ok_or("No free screenshot filename")
+}
@@ -4,6 +4,43 @@
 
 use super::renderer::Color;
 
+/// Mouse cursor sprite
+///
+/// One byte per pixel: 0 = transparent, 1 = outline, 2 = fill
+#[derive(Debug, Clone, Copy)]
+pub struct CursorSprite {
+    pub width: u32,
+    pub height: u32,
+    /// Pixel of the sprite that points at the mouse position
+    pub hotspot: (i32, i32),
+    pub pixels: &'static [u8],
+}
+
+/// Default arrow pointer
+pub const ARROW_CURSOR: CursorSprite = CursorSprite {
+    width: 11,
+    height: 16,
+    hotspot: (0, 0),
+    pixels: &[
+        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        1, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0,
+        1, 2, 2, 1, 0, 0, 0, 0, 0, 0, 0,
+        1, 2, 2, 2, 1, 0, 0, 0, 0, 0, 0,
+        1, 2, 2, 2, 2, 1, 0, 0, 0, 0, 0,
+        1, 2, 2, 2, 2, 2, 1, 0, 0, 0, 0,
+        1, 2, 2, 2, 2, 2, 2, 1, 0, 0, 0,
+        1, 2, 2, 2, 2, 2, 2, 2, 1, 0, 0,
+        1, 2, 2, 2, 2, 2, 2, 2, 2, 1, 0,
+        1, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1,
+        1, 2, 2, 1, 2, 2, 1, 0, 0, 0, 0,
+        1, 2, 1, 0, 1, 2, 2, 1, 0, 0, 0,
+        1, 1, 0, 0, 1, 2, 2, 1, 0, 0, 0,
+        1, 0, 0, 0, 0, 1, 2, 2, 1, 0, 0,
+        0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0,
+    ],
+};
+
 /// UI theme definition
 #[derive(Clone)]
 pub struct Theme {
@@ -42,6 +79,11 @@ pub struct Theme {
     pub scrollbar_background: Color,
     pub scrollbar_handle: Color,
     
+    // Mouse cursor
+    pub cursor: CursorSprite,
+    pub cursor_fill: Color,
+    pub cursor_outline: Color,
+    
     // Fonts
     pub font_family: &'static str,
     pub font_size: u16,
@@ -86,6 +128,11 @@ impl Default for Theme {
             scrollbar_background: Color::rgb(30, 30, 30),
             scrollbar_handle: Color::rgb(80, 80, 80),
             
+            // Mouse cursor
+            cursor: ARROW_CURSOR,
+            cursor_fill: Color::rgb(255, 255, 255),
+            cursor_outline: Color::rgb(0, 0, 0),
+            
             // Fonts
             font_family: "Roboto",
             font_size: 14,
@@ -132,6 +179,11 @@ impl Theme {
             scrollbar_background: Color::rgb(240, 240, 240),
             scrollbar_handle: Color::rgb(180, 180, 180),
             
+            // Mouse cursor
+            cursor: ARROW_CURSOR,
+            cursor_fill: Color::rgb(0, 0, 0),
+            cursor_outline: Color::rgb(255, 255, 255),
+            
             // Fonts
             font_family: "Roboto",
             font_size: 14,
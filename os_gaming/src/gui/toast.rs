@@ -0,0 +1,158 @@
+//! Toast notifications
+//!
+//! `WindowManager::notify` queues a toast. Up to `max_visible` are stacked in
+//! the bottom-right corner, newest at the bottom, and each is dismissed
+//! `duration` seconds after it first appears; toasts waiting for a free slot
+//! don't start their timer until they are shown. Code without access to the
+//! window manager, such as a driver reporting an error, can `post` one.
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::config::NotificationSettings;
+use crate::kernel::drivers::notification_sound::{self, NotificationLevel};
+use super::renderer::{Rect, Renderer};
+use super::theme::Theme;
+
+const TOAST_WIDTH: u32 = 300;
+/// Space between a toast's edge and its text
+const PADDING: i32 = 10;
+/// Distance of the stack from the screen edges
+const MARGIN: i32 = 16;
+/// Gap between stacked toasts, and between a toast's title and body
+const SPACING: i32 = 8;
+const TITLE_SIZE: f32 = 16.0;
+const BODY_SIZE: f32 = 14.0;
+/// Toasts queued beyond this many push out the oldest
+const MAX_QUEUED: usize = 32;
+
+/// Toasts posted from outside the GUI, picked up by `WindowManager::update`
+static POSTED: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Queue a toast without a window manager at hand. Not for interrupt handlers.
+pub fn post(title: &str, body: &str) {
+    POSTED.lock().push((String::from(title), String::from(body)));
+}
+
+/// Take the toasts `post`ed since the last call
+pub fn take_posted() -> Vec<(String, String)> {
+    core::mem::take(&mut *POSTED.lock())
+}
+
+struct Toast {
+    title: String,
+    body: String,
+    /// When it first appeared; None while waiting for a slot
+    shown_at_ms: Option<u64>,
+}
+
+pub struct Toasts {
+    /// Oldest first; the first `max_visible` are on screen
+    queue: VecDeque<Toast>,
+    enabled: bool,
+    show_in_fullscreen: bool,
+    duration_ms: u64,
+    max_visible: usize,
+    play_sound: bool,
+}
+
+impl Toasts {
+    pub fn from_config(settings: &NotificationSettings) -> Self {
+        let mut toasts = Self {
+            queue: VecDeque::new(),
+            enabled: true,
+            show_in_fullscreen: false,
+            duration_ms: 0,
+            max_visible: 0,
+            play_sound: false,
+        };
+        toasts.configure(settings);
+        toasts
+    }
+
+    /// Apply new settings; queued toasts are kept unless notifications are off
+    pub fn configure(&mut self, settings: &NotificationSettings) {
+        self.enabled = settings.enabled;
+        self.show_in_fullscreen = settings.show_in_fullscreen;
+        self.duration_ms = settings.duration as u64 * 1000;
+        self.max_visible = settings.max_visible as usize;
+        self.play_sound = settings.play_sound;
+        if !self.enabled {
+            self.queue.clear();
+        }
+    }
+
+    /// Queue a toast, playing the notification sound if configured. Returns
+    /// false when notifications are disabled.
+    pub fn push(&mut self, title: &str, body: &str) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if self.queue.len() >= MAX_QUEUED {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(Toast {
+            title: String::from(title),
+            body: String::from(body),
+            shown_at_ms: None,
+        });
+        if self.play_sound {
+            notification_sound::play(NotificationLevel::Info);
+        }
+        true
+    }
+
+    fn hidden(&self, fullscreen: bool) -> bool {
+        fullscreen && !self.show_in_fullscreen
+    }
+
+    /// Dismiss toasts whose time is up and start the timers of those that
+    /// just got a slot. While hidden by a fullscreen window nothing changes.
+    pub fn update(&mut self, now_ms: u64, fullscreen: bool) {
+        if self.hidden(fullscreen) {
+            return;
+        }
+        let duration_ms = self.duration_ms;
+        self.queue
+            .retain(|toast| toast.shown_at_ms.map_or(true, |shown| now_ms.saturating_sub(shown) < duration_ms));
+        for toast in self.queue.iter_mut().take(self.max_visible) {
+            toast.shown_at_ms.get_or_insert(now_ms);
+        }
+    }
+
+    /// Draw the toasts on screen. Returns the area they cover, which has to
+    /// be repainted before they are drawn again.
+    pub fn render(&self, renderer: &mut Renderer, theme: &Theme, fullscreen: bool) -> Option<Rect> {
+        if self.hidden(fullscreen) {
+            return None;
+        }
+        let shown = self.queue.iter().take_while(|toast| toast.shown_at_ms.is_some()).count();
+        if shown == 0 {
+            return None;
+        }
+
+        let (screen_width, screen_height) = renderer.dimensions();
+        let x = screen_width as i32 - TOAST_WIDTH as i32 - MARGIN;
+        let bottom = screen_height as i32 - MARGIN;
+        let mut top = bottom + SPACING;
+        // Newest at the bottom, older ones pushed up
+        for toast in self.queue.iter().take(shown).rev() {
+            let (_, title_height) = renderer.measure_text(&toast.title, TITLE_SIZE);
+            let (_, body_height) = renderer.measure_text(&toast.body, BODY_SIZE);
+            let height = (2 * PADDING + SPACING) as u32 + title_height + body_height;
+            let rect = Rect::new(x, top - SPACING - height as i32, TOAST_WIDTH, height);
+
+            renderer.fill_rect(rect, theme.window_background);
+            renderer.draw_rect(rect, theme.window_border_active);
+            renderer.set_clip_rect(Some(rect));
+            renderer.draw_text(&toast.title, x + PADDING, rect.y + PADDING, TITLE_SIZE, theme.title_text_active);
+            let body_y = rect.y + PADDING + title_height as i32 + SPACING;
+            renderer.draw_text(&toast.body, x + PADDING, body_y, BODY_SIZE, theme.text_normal);
+            renderer.set_clip_rect(None);
+            top = rect.y;
+        }
+        Some(Rect::new(x, top, TOAST_WIDTH, (bottom - top).max(0) as u32))
+    }
+}
@@ -0,0 +1,504 @@
+//! Minimal TrueType/OpenType parser
+//!
+//! Reads the table directory, maps characters to glyphs through the `cmap`
+//! table (formats 4 and 12), reads the family name from `name`, and walks
+//! simple TrueType outlines in `glyf`/`loca`. Composite glyphs and CFF
+//! outlines are not parsed yet.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Character-to-glyph coverage a usable UI font must have
+pub const BASIC_LATIN_REQUIRED: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+const SFNT_TRUETYPE: u32 = 0x0001_0000;
+const SFNT_APPLE_TRUE: u32 = 0x7472_7565; // 'true'
+const SFNT_OPENTYPE_CFF: u32 = 0x4F54_544F; // 'OTTO'
+const SFNT_COLLECTION: u32 = 0x7474_6366; // 'ttcf'
+
+const TABLE_RECORD_SIZE: usize = 16;
+
+/// `name` table IDs
+const NAME_FAMILY: u16 = 1;
+const NAME_TYPOGRAPHIC_FAMILY: u16 = 16;
+
+/// Simple glyph point flags
+const FLAG_ON_CURVE: u8 = 0x01;
+const FLAG_X_SHORT: u8 = 0x02;
+const FLAG_Y_SHORT: u8 = 0x04;
+const FLAG_REPEAT: u8 = 0x08;
+/// With the short flag: positive; without: same as the previous point
+const FLAG_X_SAME_OR_POSITIVE: u8 = 0x10;
+const FLAG_Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+/// Glyph index within a face
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GlyphId(pub u16);
+
+/// Glyph bounding box in font units, y up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x_min: i16,
+    pub y_min: i16,
+    pub x_max: i16,
+    pub y_max: i16,
+}
+
+/// Receives a glyph outline in font units, y up. Each contour starts with
+/// `move_to` and ends with `close`.
+pub trait OutlineBuilder {
+    fn move_to(&mut self, x: f32, y: f32);
+    fn line_to(&mut self, x: f32, y: f32);
+    /// Quadratic curve through control point (x1, y1) to (x, y)
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32);
+    fn close(&mut self);
+}
+
+/// Why a font could not be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaceParsingError {
+    /// The data ends before a structure it declares
+    MalformedFont,
+    /// Not a TrueType, OpenType or collection file
+    UnknownMagic,
+    /// `index` is past the number of faces in a collection
+    FaceIndexOutOfBounds,
+    /// A table every face needs is absent
+    NoTable(&'static str),
+    /// No cmap subtable in a supported format
+    UnsupportedCmap,
+}
+
+impl fmt::Display for FaceParsingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaceParsingError::MalformedFont => write!(f, "malformed font"),
+            FaceParsingError::UnknownMagic => write!(f, "not a TrueType or OpenType font"),
+            FaceParsingError::FaceIndexOutOfBounds => write!(f, "face index out of bounds"),
+            FaceParsingError::NoTable(tag) => write!(f, "missing '{}' table", tag),
+            FaceParsingError::UnsupportedCmap => write!(f, "no supported cmap subtable"),
+        }
+    }
+}
+
+/// Big-endian reads that fail instead of panicking on short data
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    read_u16(data, offset).map(|v| v as i16)
+}
+
+/// A parsed font face borrowing the font data
+#[derive(Clone, Copy)]
+pub struct Face<'a> {
+    data: &'a [u8],
+    /// Offset of the face's table directory
+    directory: usize,
+    num_tables: u16,
+    /// The selected cmap subtable
+    cmap: CmapSubtable<'a>,
+    number_of_glyphs: u16,
+}
+
+#[derive(Clone, Copy)]
+enum CmapSubtable<'a> {
+    Format4(&'a [u8]),
+    Format12(&'a [u8]),
+}
+
+impl<'a> Face<'a> {
+    /// Parse face `index` of `data` (0 unless `data` is a collection)
+    pub fn from_slice(data: &'a [u8], index: u32) -> Result<Self, FaceParsingError> {
+        let directory = match read_u32(data, 0).ok_or(FaceParsingError::MalformedFont)? {
+            SFNT_TRUETYPE | SFNT_APPLE_TRUE | SFNT_OPENTYPE_CFF => {
+                if index != 0 {
+                    return Err(FaceParsingError::FaceIndexOutOfBounds);
+                }
+                0
+            }
+            SFNT_COLLECTION => {
+                let faces = read_u32(data, 8).ok_or(FaceParsingError::MalformedFont)?;
+                if index >= faces {
+                    return Err(FaceParsingError::FaceIndexOutOfBounds);
+                }
+                read_u32(data, 12 + index as usize * 4).ok_or(FaceParsingError::MalformedFont)? as usize
+            }
+            _ => return Err(FaceParsingError::UnknownMagic),
+        };
+
+        let num_tables = read_u16(data, directory + 4).ok_or(FaceParsingError::MalformedFont)?;
+        let directory_end = directory + 12 + num_tables as usize * TABLE_RECORD_SIZE;
+        if directory_end > data.len() {
+            return Err(FaceParsingError::MalformedFont);
+        }
+
+        let mut face = Face {
+            data,
+            directory,
+            num_tables,
+            cmap: CmapSubtable::Format4(&[]),
+            number_of_glyphs: 0,
+        };
+        // Every record must point inside the file
+        for i in 0..num_tables as usize {
+            let record = directory + 12 + i * TABLE_RECORD_SIZE;
+            let offset = read_u32(data, record + 8).ok_or(FaceParsingError::MalformedFont)? as usize;
+            let length = read_u32(data, record + 12).ok_or(FaceParsingError::MalformedFont)? as usize;
+            if offset.checked_add(length).map_or(true, |end| end > data.len()) {
+                return Err(FaceParsingError::MalformedFont);
+            }
+        }
+
+        face.table(b"head").ok_or(FaceParsingError::NoTable("head"))?;
+        let maxp = face.table(b"maxp").ok_or(FaceParsingError::NoTable("maxp"))?;
+        face.number_of_glyphs = read_u16(maxp, 4).ok_or(FaceParsingError::MalformedFont)?;
+        let cmap = face.table(b"cmap").ok_or(FaceParsingError::NoTable("cmap"))?;
+        face.cmap = select_cmap_subtable(cmap).ok_or(FaceParsingError::UnsupportedCmap)?;
+        Ok(face)
+    }
+
+    /// Raw data of the table tagged `tag`
+    pub fn table(&self, tag: &[u8; 4]) -> Option<&'a [u8]> {
+        (0..self.num_tables as usize)
+            .map(|i| self.directory + 12 + i * TABLE_RECORD_SIZE)
+            .find(|&record| self.data.get(record..record + 4) == Some(&tag[..]))
+            .and_then(|record| {
+                let offset = read_u32(self.data, record + 8)? as usize;
+                let length = read_u32(self.data, record + 12)? as usize;
+                self.data.get(offset..offset + length)
+            })
+    }
+
+    pub fn number_of_glyphs(&self) -> u16 {
+        self.number_of_glyphs
+    }
+
+    /// Font units per em, the scale of every outline coordinate
+    pub fn units_per_em(&self) -> u16 {
+        self.table(b"head").and_then(|head| read_u16(head, 18)).unwrap_or(1000)
+    }
+
+    /// Distance from the baseline to the top of the tallest glyphs, in font units
+    pub fn ascender(&self) -> i16 {
+        self.table(b"hhea").and_then(|hhea| read_i16(hhea, 4)).unwrap_or(0)
+    }
+
+    /// Distance from the baseline to the bottom of descenders; negative
+    pub fn descender(&self) -> i16 {
+        self.table(b"hhea").and_then(|hhea| read_i16(hhea, 6)).unwrap_or(0)
+    }
+
+    /// Horizontal advance of `id` in font units, from `hmtx`
+    pub fn glyph_hor_advance(&self, id: GlyphId) -> Option<u16> {
+        if id.0 >= self.number_of_glyphs {
+            return None;
+        }
+        let metrics = read_u16(self.table(b"hhea")?, 34)?;
+        if metrics == 0 {
+            return None;
+        }
+        // Glyphs past the last full metric share its advance
+        let index = id.0.min(metrics - 1) as usize;
+        read_u16(self.table(b"hmtx")?, index * 4)
+    }
+
+    /// Family name from the `name` table, preferring the typographic family
+    pub fn family_name(&self) -> Option<String> {
+        let name = self.table(b"name")?;
+        let count = read_u16(name, 2)? as usize;
+        let strings = read_u16(name, 4)? as usize;
+
+        let mut best: Option<(u16, String)> = None;
+        for i in 0..count {
+            let record = 6 + i * 12;
+            let platform = read_u16(name, record)?;
+            let encoding = read_u16(name, record + 2)?;
+            let name_id = read_u16(name, record + 6)?;
+            if name_id != NAME_FAMILY && name_id != NAME_TYPOGRAPHIC_FAMILY {
+                continue;
+            }
+            let length = read_u16(name, record + 8)? as usize;
+            let offset = strings + read_u16(name, record + 10)? as usize;
+            let Some(bytes) = name.get(offset..offset + length) else { continue };
+            let text = match (platform, encoding) {
+                // Unicode and Windows strings are UTF-16BE
+                (0, _) | (3, 0) | (3, 1) | (3, 10) => decode_utf16_be(bytes),
+                // Mac Roman; the ASCII half is all a family name needs
+                (1, 0) => Some(bytes.iter().map(|&b| if b.is_ascii() { b as char } else { '?' }).collect()),
+                _ => None,
+            };
+            let Some(text) = text.filter(|t| !t.is_empty()) else { continue };
+            if best.as_ref().map_or(true, |(id, _)| name_id == NAME_TYPOGRAPHIC_FAMILY && *id != name_id) {
+                best = Some((name_id, text));
+            }
+        }
+        best.map(|(_, text)| text)
+    }
+
+    /// Glyph for `c`, or None when the font doesn't cover it
+    pub fn glyph_index(&self, c: char) -> Option<GlyphId> {
+        let code = c as u32;
+        let glyph = match self.cmap {
+            CmapSubtable::Format4(table) => cmap4_lookup(table, code)?,
+            CmapSubtable::Format12(table) => cmap12_lookup(table, code)?,
+        };
+        (glyph != 0 && glyph < self.number_of_glyphs).then_some(GlyphId(glyph))
+    }
+
+    /// Whether every character of `chars` has a glyph
+    pub fn covers(&self, chars: &str) -> bool {
+        chars.chars().all(|c| self.glyph_index(c).is_some())
+    }
+
+    /// Outline data of `id` in `glyf`; empty for glyphs without contours
+    fn glyph_data(&self, id: GlyphId) -> Option<&'a [u8]> {
+        if id.0 >= self.number_of_glyphs {
+            return None;
+        }
+        let long_offsets = read_i16(self.table(b"head")?, 50)? != 0;
+        let loca = self.table(b"loca")?;
+        let i = id.0 as usize;
+        let (start, end) = if long_offsets {
+            (read_u32(loca, i * 4)? as usize, read_u32(loca, i * 4 + 4)? as usize)
+        } else {
+            // Short offsets are stored halved
+            (read_u16(loca, i * 2)? as usize * 2, read_u16(loca, i * 2 + 2)? as usize * 2)
+        };
+        if start > end {
+            return None;
+        }
+        self.table(b"glyf")?.get(start..end)
+    }
+
+    /// Bounding box of `id` from its `glyf` header; None for empty glyphs
+    pub fn glyph_bounding_box(&self, id: GlyphId) -> Option<Rect> {
+        let glyph = self.glyph_data(id)?;
+        if read_i16(glyph, 0)? == 0 {
+            return None;
+        }
+        Some(Rect {
+            x_min: read_i16(glyph, 2)?,
+            y_min: read_i16(glyph, 4)?,
+            x_max: read_i16(glyph, 6)?,
+            y_max: read_i16(glyph, 8)?,
+        })
+    }
+
+    /// Feed the outline of `id` to `builder` and return its bounding box.
+    /// None for glyphs without contours (like space), composite glyphs and
+    /// malformed data.
+    pub fn outline_glyph(&self, id: GlyphId, builder: &mut dyn OutlineBuilder) -> Option<Rect> {
+        let glyph = self.glyph_data(id)?;
+        let contours = read_i16(glyph, 0)?;
+        if contours <= 0 {
+            return None;
+        }
+        let bbox = self.glyph_bounding_box(id)?;
+
+        let contours = contours as usize;
+        let mut end_points = Vec::with_capacity(contours);
+        for i in 0..contours {
+            end_points.push(read_u16(glyph, 10 + i * 2)? as usize);
+        }
+        let points = parse_simple_glyph(glyph, &end_points)?;
+
+        let mut start = 0;
+        for &end in &end_points {
+            let contour = points.get(start..=end)?;
+            emit_contour(contour, builder);
+            start = end + 1;
+        }
+        Some(bbox)
+    }
+}
+
+fn decode_utf16_be(bytes: &[u8]) -> Option<String> {
+    let units = bytes.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    char::decode_utf16(units).collect::<Result<String, _>>().ok()
+}
+
+/// A glyph point in font units
+#[derive(Clone, Copy)]
+struct Point {
+    x: f32,
+    y: f32,
+    on_curve: bool,
+}
+
+impl Point {
+    fn midpoint(self, other: Point) -> Point {
+        Point { x: (self.x + other.x) / 2.0, y: (self.y + other.y) / 2.0, on_curve: true }
+    }
+}
+
+/// Decode the flag, x and y arrays of a simple glyph
+fn parse_simple_glyph(glyph: &[u8], end_points: &[usize]) -> Option<Vec<Point>> {
+    let count = end_points.last()? + 1;
+    let instructions_at = 10 + end_points.len() * 2;
+    let instruction_length = read_u16(glyph, instructions_at)? as usize;
+    let mut offset = instructions_at + 2 + instruction_length;
+
+    let mut flags = Vec::with_capacity(count);
+    while flags.len() < count {
+        let flag = *glyph.get(offset)?;
+        offset += 1;
+        flags.push(flag);
+        if flag & FLAG_REPEAT != 0 {
+            let repeat = *glyph.get(offset)?;
+            offset += 1;
+            for _ in 0..repeat {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(count);
+
+    // Coordinates are deltas from the previous point
+    let mut read_axis = |short: u8, same_or_positive: u8| -> Option<Vec<i32>> {
+        let mut values = Vec::with_capacity(count);
+        let mut value = 0i32;
+        for &flag in &flags {
+            if flag & short != 0 {
+                let delta = *glyph.get(offset)? as i32;
+                offset += 1;
+                value += if flag & same_or_positive != 0 { delta } else { -delta };
+            } else if flag & same_or_positive == 0 {
+                value += read_i16(glyph, offset)? as i32;
+                offset += 2;
+            }
+            values.push(value);
+        }
+        Some(values)
+    };
+    let xs = read_axis(FLAG_X_SHORT, FLAG_X_SAME_OR_POSITIVE)?;
+    let ys = read_axis(FLAG_Y_SHORT, FLAG_Y_SAME_OR_POSITIVE)?;
+
+    Some(
+        flags
+            .iter()
+            .zip(xs.iter().zip(ys.iter()))
+            .map(|(&flag, (&x, &y))| Point { x: x as f32, y: y as f32, on_curve: flag & FLAG_ON_CURVE != 0 })
+            .collect(),
+    )
+}
+
+/// Turn one contour into builder calls. Two off-curve points in a row
+/// imply an on-curve point halfway between them.
+fn emit_contour(points: &[Point], builder: &mut dyn OutlineBuilder) {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return,
+    };
+    let (start, rest) = if first.on_curve {
+        (first, &points[1..])
+    } else if last.on_curve {
+        (last, &points[..points.len() - 1])
+    } else {
+        (last.midpoint(first), points)
+    };
+
+    builder.move_to(start.x, start.y);
+    let mut control: Option<Point> = None;
+    for &point in rest {
+        if point.on_curve {
+            match control.take() {
+                Some(c) => builder.quad_to(c.x, c.y, point.x, point.y),
+                None => builder.line_to(point.x, point.y),
+            }
+        } else {
+            if let Some(c) = control {
+                let mid = c.midpoint(point);
+                builder.quad_to(c.x, c.y, mid.x, mid.y);
+            }
+            control = Some(point);
+        }
+    }
+    match control {
+        Some(c) => builder.quad_to(c.x, c.y, start.x, start.y),
+        None => builder.line_to(start.x, start.y),
+    }
+    builder.close();
+}
+
+/// Prefer a full-Unicode subtable, then a BMP one
+fn select_cmap_subtable(cmap: &[u8]) -> Option<CmapSubtable<'_>> {
+    let count = read_u16(cmap, 2)? as usize;
+    let mut best: Option<(u8, CmapSubtable)> = None;
+    for i in 0..count {
+        let record = 4 + i * 8;
+        let platform = read_u16(cmap, record)?;
+        let encoding = read_u16(cmap, record + 2)?;
+        let offset = read_u32(cmap, record + 4)? as usize;
+        let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+        if !unicode {
+            continue;
+        }
+        let subtable = cmap.get(offset..)?;
+        let candidate = match read_u16(subtable, 0)? {
+            4 => (1, CmapSubtable::Format4(subtable)),
+            12 => (2, CmapSubtable::Format12(subtable)),
+            _ => continue,
+        };
+        if best.as_ref().map_or(true, |(rank, _)| candidate.0 > *rank) {
+            best = Some(candidate);
+        }
+    }
+    best.map(|(_, subtable)| subtable)
+}
+
+/// Segment mapping to delta values (BMP only)
+fn cmap4_lookup(table: &[u8], code: u32) -> Option<u16> {
+    if code > 0xFFFF {
+        return None;
+    }
+    let code = code as u16;
+    let segments = read_u16(table, 6)? as usize / 2;
+    let end_codes = 14;
+    let start_codes = end_codes + segments * 2 + 2;
+    let deltas = start_codes + segments * 2;
+    let range_offsets = deltas + segments * 2;
+
+    for i in 0..segments {
+        if code > read_u16(table, end_codes + i * 2)? {
+            continue;
+        }
+        let start = read_u16(table, start_codes + i * 2)?;
+        if code < start {
+            return None;
+        }
+        let delta = read_u16(table, deltas + i * 2)?;
+        let range_offset_at = range_offsets + i * 2;
+        let range_offset = read_u16(table, range_offset_at)? as usize;
+        if range_offset == 0 {
+            return Some(code.wrapping_add(delta));
+        }
+        let glyph_at = range_offset_at + range_offset + (code - start) as usize * 2;
+        return match read_u16(table, glyph_at)? {
+            0 => None,
+            glyph => Some(glyph.wrapping_add(delta)),
+        };
+    }
+    None
+}
+
+/// Segmented coverage (full Unicode)
+fn cmap12_lookup(table: &[u8], code: u32) -> Option<u16> {
+    let groups = read_u32(table, 12)? as usize;
+    for i in 0..groups {
+        let group = 16 + i * 12;
+        let start = read_u32(table, group)?;
+        let end = read_u32(table, group + 4)?;
+        if (start..=end).contains(&code) {
+            let glyph = read_u32(table, group + 8)? + (code - start);
+            return u16::try_from(glyph).ok();
+        }
+    }
+    None
+}
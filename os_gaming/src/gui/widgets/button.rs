@@ -0,0 +1,120 @@
+//! Push button
+//!
+//! Clicking means pressing inside the button and releasing inside it too;
+//! dragging off before letting go cancels the click.
+
+use alloc::string::String;
+
+use super::Widget;
+use crate::gui::renderer::{Rect, Renderer};
+use crate::gui::theme::Theme;
+use crate::gui::window_manager::{WidgetId, WindowEvent};
+
+pub struct Button {
+    id: WidgetId,
+    label: String,
+    /// Relative to the window content area
+    rect: Rect,
+    hovered: bool,
+    /// Pressed inside and not released yet
+    pressed: bool,
+    on_click: Option<fn(WidgetId)>,
+}
+
+impl Button {
+    pub fn new(id: WidgetId, label: &str, rect: Rect) -> Self {
+        Self {
+            id,
+            label: String::from(label),
+            rect,
+            hovered: false,
+            pressed: false,
+            on_click: None,
+        }
+    }
+
+    /// Call `callback` with the button's id whenever it is clicked
+    pub fn set_on_click(&mut self, callback: fn(WidgetId)) {
+        self.on_click = Some(callback);
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn set_label(&mut self, label: &str) {
+        self.label = String::from(label);
+    }
+
+    pub fn set_rect(&mut self, rect: Rect) {
+        self.rect = rect;
+    }
+
+    pub fn is_hovered(&self) -> bool {
+        self.hovered
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+impl Widget for Button {
+    fn id(&self) -> WidgetId {
+        self.id
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn render(&self, renderer: &mut Renderer, theme: &Theme, origin: (i32, i32)) {
+        let rect = Rect::new(origin.0 + self.rect.x, origin.1 + self.rect.y, self.rect.width, self.rect.height);
+        let background = if self.pressed && self.hovered {
+            theme.button_active
+        } else if self.hovered {
+            theme.button_hover
+        } else {
+            theme.button_normal
+        };
+        renderer.fill_rect(rect, background);
+        renderer.draw_rect(rect, theme.button_border);
+
+        // Label centered, cut off at the button's edges
+        let size = theme.font_size as f32;
+        let (text_width, text_height) = renderer.measure_text(&self.label, size);
+        let text_x = rect.x + (rect.width as i32 - text_width as i32) / 2;
+        let text_y = rect.y + (rect.height as i32 - text_height as i32) / 2;
+        let previous_clip = renderer.clip_rect();
+        let clip = previous_clip.map_or(Some(rect), |clip| clip.intersection(&rect));
+        if let Some(clip) = clip {
+            renderer.set_clip_rect(Some(clip));
+            renderer.draw_text(&self.label, text_x, text_y, size, theme.button_text);
+            renderer.set_clip_rect(previous_clip);
+        }
+    }
+
+    fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        let (hovered, pressed) = (self.hovered, self.pressed);
+        match *event {
+            WindowEvent::MouseMove { x, y } => {
+                self.hovered = self.rect.contains(x, y);
+            }
+            WindowEvent::MouseDown { x, y, .. } => {
+                self.hovered = self.rect.contains(x, y);
+                self.pressed = self.hovered;
+            }
+            WindowEvent::MouseUp { x, y, .. } => {
+                self.hovered = self.rect.contains(x, y);
+                if self.pressed && self.hovered {
+                    if let Some(callback) = self.on_click {
+                        callback(self.id);
+                    }
+                }
+                self.pressed = false;
+            }
+            _ => {}
+        }
+        (hovered, pressed) != (self.hovered, self.pressed)
+    }
+}
@@ -0,0 +1,67 @@
+//! Widgets placed in a window's content area
+//!
+//! Each window owns a `WidgetTree`. The window manager passes pointer events
+//! to the focused window's tree in content coordinates (scrolling included)
+//! and draws the tree over the window's render callback output. A widget
+//! that changes how it looks reports it, and the window is redrawn.
+
+pub mod button;
+
+pub use button::Button;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use super::renderer::{Rect, Renderer};
+use super::theme::Theme;
+use super::window_manager::{WidgetId, WindowEvent};
+
+pub trait Widget: Send {
+    fn id(&self) -> WidgetId;
+
+    /// Bounds relative to the window content area
+    fn rect(&self) -> Rect;
+
+    /// Draw with the content area's top-left corner at `origin`
+    fn render(&self, renderer: &mut Renderer, theme: &Theme, origin: (i32, i32));
+
+    /// React to a pointer event. Returns whether the widget needs redrawing.
+    fn handle_event(&mut self, event: &WindowEvent) -> bool;
+}
+
+/// The widgets of one window, drawn in the order they were added
+#[derive(Default)]
+pub struct WidgetTree {
+    widgets: Vec<Box<dyn Widget>>,
+}
+
+impl WidgetTree {
+    /// Add `widget`, replacing any with the same id
+    pub fn add(&mut self, widget: Box<dyn Widget>) {
+        self.remove(widget.id());
+        self.widgets.push(widget);
+    }
+
+    pub fn remove(&mut self, id: WidgetId) {
+        self.widgets.retain(|widget| widget.id() != id);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.widgets.is_empty()
+    }
+
+    pub fn render(&self, renderer: &mut Renderer, theme: &Theme, origin: (i32, i32)) {
+        for widget in &self.widgets {
+            widget.render(renderer, theme, origin);
+        }
+    }
+
+    /// Give every widget the event. Returns whether any needs redrawing.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        let mut changed = false;
+        for widget in self.widgets.iter_mut() {
+            changed |= widget.handle_event(event);
+        }
+        changed
+    }
+}
@@ -5,17 +5,64 @@
 
 extern crate alloc;
 use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use alloc::{string::String, vec::Vec};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
 use bincode::{Decode, Encode};
 use spin::Mutex;
-
-
-use super::renderer::{Color, Rect, Renderer, RendererError};
-use super::theme::Theme;
+use micromath::F32Ext;
+
+use crate::config;
+use crate::events;
+use crate::kernel::drivers::{gpu, keyboard, timer};
+use super::emath;
+use super::renderer::{Color, Letterbox, Rect, Renderer, RendererError, ScaleMode};
+use super::input;
+use super::theme::{CursorSprite, Theme};
+use super::toast::{self, Toasts};
+use super::widgets::{Widget, WidgetTree};
 
 /// Unique identifier for windows
 pub type WindowId = u32;
 
+/// Identifier of a widget, unique within its window
+pub type WidgetId = u32;
+
+/// Set-1 scancodes used for keyboard navigation
+const SCANCODE_ESCAPE: u16 = 0x01;
+const SCANCODE_ENTER: u16 = 0x1C;
+const SCANCODE_TAB: u16 = 0x0F;
+const SCANCODE_UP: u16 = 0x48;
+const SCANCODE_LEFT: u16 = 0x4B;
+const SCANCODE_RIGHT: u16 = 0x4D;
+const SCANCODE_DOWN: u16 = 0x50;
+
+/// Height of the title bar above a window's content
+const TITLE_BAR_HEIGHT: u32 = 25;
+/// Space between a window's left edge and its title text
+const TITLE_PADDING: i32 = 8;
+
+/// Focus ring thickness in pixels, normal and high contrast
+const FOCUS_RING_WIDTH: u32 = 2;
+const FOCUS_RING_WIDTH_HIGH_CONTRAST: u32 = 4;
+
+/// Pixels scrolled per wheel notch
+const SCROLL_STEP: i32 = 20;
+/// Time without scroll input after which momentum takes over (ms)
+const KINETIC_RELEASE_MS: u64 = 50;
+/// Length of one kinetic simulation step (ms)
+const KINETIC_STEP_MS: u64 = 16;
+/// Velocity a scroll burst must reach to keep coasting (pixels per step)
+const KINETIC_MOMENTUM_THRESHOLD: f32 = 30.0;
+/// Velocity under which coasting stops (pixels per step)
+const KINETIC_MIN_VELOCITY: f32 = 0.5;
+/// Velocity multiplier applied on every step while coasting
+const KINETIC_FRICTION: f32 = 0.92;
+
+/// Pixels of a dragged window's title bar kept on screen horizontally
+const DRAG_MIN_VISIBLE: i32 = 32;
+
+/// Damaged areas beyond this many are repainted as their bounding box
+const MAX_DAMAGE_RECTS: usize = 8;
+
 /// Window properties
 pub struct Window {
     id: WindowId,
@@ -29,6 +76,34 @@ pub struct Window {
     background_color: Color,
     border_color: Color,
     user_data: Option<*mut u8>, // Raw pointer to user-defined data
+    content_height: u32, // Height of the scrollable content (0 if not scrollable)
+    scroll_offset: i32,
+    /// Widgets in keyboard focus order
+    focus_order: Vec<FocusTarget>,
+    /// Index into `focus_order` of the focused widget
+    focused_widget: Option<usize>,
+    /// Fixed resolution the content is drawn at, letterboxed into the window
+    content_size: Option<(u32, u32)>,
+    /// How opaque the window is composited, 255 = fully opaque
+    opacity: u8,
+    /// Changed since it was last drawn
+    dirty: AtomicBool,
+    /// Where it was last drawn, repainted when it moves or disappears
+    drawn_rect: Option<Rect>,
+    /// Shared with the clones made for rendering
+    widgets: Arc<Mutex<WidgetTree>>,
+}
+
+/// A widget taking part in keyboard focus traversal
+#[derive(Debug, Clone, Copy)]
+pub struct FocusTarget {
+    pub widget: WidgetId,
+    /// Bounds relative to the window content area, used for the focus ring
+    pub rect: Rect,
+    /// False for widgets such as labels that Tab skips
+    pub focusable: bool,
+    /// Container the widget belongs to; arrow keys move between its members
+    pub container: Option<WidgetId>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -83,19 +158,209 @@ pub enum WindowEvent {
         x: i32,
         y: i32,
     },
+    Scroll {
+        offset: i32,
+    },
+    /// Keyboard focus moved to a widget of the window
+    WidgetFocus {
+        widget: WidgetId,
+    },
+}
+
+/// An Alt+Tab switch in progress
+struct WindowSwitcher {
+    /// Visible windows, most recently focused first
+    order: Vec<WindowId>,
+    /// Index into `order` of the window that gets focus when Alt is released
+    selected: usize,
+}
+
+/// Kinetic scrolling state for the window under the cursor
+#[derive(Debug, Clone, Copy)]
+struct KineticScroll {
+    /// Window receiving the scroll (0 if idle)
+    target: WindowId,
+    /// Current velocity in pixels per step
+    velocity: f32,
+    /// Timestamp of the last scroll input (ms)
+    last_input_ms: u64,
+    /// Timestamp of the last simulation step (ms)
+    last_step_ms: u64,
+    /// Whether the momentum phase has started
+    coasting: bool,
+}
+
+impl KineticScroll {
+    const fn idle() -> Self {
+        Self {
+            target: 0,
+            velocity: 0.0,
+            last_input_ms: 0,
+            last_step_ms: 0,
+            coasting: false,
+        }
+    }
+
+    /// Record a scroll of `delta_px` on `target`. Consecutive inputs in the
+    /// same direction add up to the burst's velocity.
+    fn input(&mut self, target: WindowId, delta_px: i32, now_ms: u64) {
+        let same_burst = self.target == target
+            && !self.coasting
+            && now_ms.saturating_sub(self.last_input_ms) <= KINETIC_RELEASE_MS * 4
+            && (self.velocity >= 0.0) == (delta_px >= 0);
+        if same_burst {
+            self.velocity += delta_px as f32;
+        } else {
+            self.velocity = delta_px as f32;
+        }
+        self.target = target;
+        self.last_input_ms = now_ms;
+        self.last_step_ms = now_ms;
+        self.coasting = false;
+    }
+
+    /// Advance to `now_ms`, scrolling the target in `windows` by the decaying
+    /// velocity once the input has stopped
+    fn tick(&mut self, now_ms: u64, windows: &Mutex<Vec<Window>>) {
+        if self.target == 0 {
+            return;
+        }
+
+        // Still receiving input, let the raw deltas drive the offset
+        if !self.coasting {
+            if now_ms.saturating_sub(self.last_input_ms) < KINETIC_RELEASE_MS {
+                return;
+            }
+
+            // Tiny flicks don't get momentum
+            if self.velocity.abs() < KINETIC_MOMENTUM_THRESHOLD {
+                *self = Self::idle();
+                return;
+            }
+
+            // Scale the burst down to a per-step velocity
+            self.velocity *= 0.25;
+            self.coasting = true;
+            self.last_step_ms = now_ms;
+            return;
+        }
+
+        let steps = now_ms.saturating_sub(self.last_step_ms) / KINETIC_STEP_MS;
+        if steps == 0 {
+            return;
+        }
+        self.last_step_ms += steps * KINETIC_STEP_MS;
+
+        let mut windows = windows.lock();
+        let window = match windows.iter_mut().find(|w| w.id() == self.target) {
+            Some(window) => window,
+            None => {
+                *self = Self::idle();
+                return;
+            }
+        };
+
+        let mut moved = false;
+        for _ in 0..steps {
+            self.velocity *= KINETIC_FRICTION;
+            if self.velocity.abs() < KINETIC_MIN_VELOCITY {
+                *self = Self::idle();
+                break;
+            }
+
+            // Stop at the content bounds
+            if window.scroll_by(self.velocity.round() as i32) == 0 {
+                *self = Self::idle();
+                break;
+            }
+            moved = true;
+        }
+
+        if moved {
+            window.dispatch(&WindowEvent::Scroll { offset: window.scroll_offset() });
+        }
+    }
+}
+
+/// Software cursor state, including the pixels hidden under the sprite
+struct CursorState {
+    x: i32,
+    y: i32,
+    visible: bool,
+    /// Top-left corner and width of the sprite last drawn
+    drawn_at: Option<(i32, i32, u32)>,
+    /// Framebuffer pixels saved before drawing the sprite
+    saved_pixels: Vec<Option<u32>>,
+    /// Shown by the gpu module (hardware plane or composited at present)
+    /// instead of drawn into each frame
+    on_gpu: bool,
+}
+
+/// Clamp a pointer position to the pixels of a `width` x `height` screen
+fn clamp_to_screen(x: i32, y: i32, (width, height): (u32, u32)) -> (i32, i32) {
+    (
+        x.clamp(0, width.saturating_sub(1) as i32),
+        y.clamp(0, height.saturating_sub(1) as i32),
+    )
+}
+
+/// Scale windows from an `old` screen size to a `new` one, keeping each
+/// one entirely on screen
+fn fit_windows(windows: &mut [Window], (old_width, old_height): (u32, u32), (width, height): (u32, u32)) {
+    let scale_x = width as f32 / old_width as f32;
+    let scale_y = height as f32 / old_height as f32;
+    for window in windows {
+        let rect = &mut window.rect;
+        rect.width = ((rect.width as f32 * scale_x).round() as u32).clamp(1, width);
+        rect.height = ((rect.height as f32 * scale_y).round() as u32).clamp(1, height);
+        rect.x = ((rect.x as f32 * scale_x).round() as i32).clamp(0, (width - rect.width) as i32);
+        rect.y = ((rect.y as f32 * scale_y).round() as i32).clamp(0, (height - rect.height) as i32);
+        window.scroll_offset = window.scroll_offset.min(window.max_scroll_offset());
+    }
 }
 
 /// Window manager that handles window creation, events, and rendering
 pub struct WindowManager {
     renderer: Renderer,
+    /// In z-order, back to front, which is also the order they are drawn in
     windows: Mutex<Vec<Window>>,
     next_window_id: AtomicU32,
     focused_window: AtomicU32,
     dragging_window: AtomicU32,
     drag_offset_x: i32,
     drag_offset_y: i32,
+    /// Mouse buttons held as of the last mouse event, bit 0 = left
+    mouse_buttons: u8,
+    /// Modifier keys held, from `handle_key_press`/`handle_key_release`
+    modifiers: input::Modifiers,
+    /// Windows in the order they last had focus, most recent first. Taken
+    /// after `windows` when both are locked.
+    focus_history: Mutex<Vec<WindowId>>,
+    /// Set while Alt is held after Alt+Tab
+    switcher: Option<WindowSwitcher>,
     theme: Theme,
     exit_requested: AtomicBool,
+    kinetic_scroll: KineticScroll,
+    cursor: CursorState,
+    /// Tab/arrow keys move focus between widgets
+    keyboard_navigation: bool,
+    /// Draw a thicker focus ring
+    high_contrast: bool,
+    /// Window holding the pointer grab (0 = none)
+    pointer_grab: AtomicU32,
+    /// Whether the grabbing window is fullscreen (raw mouse active)
+    pointer_grab_fullscreen: AtomicBool,
+    /// Honor per-window opacity; when off every window is drawn opaque
+    allow_transparency: bool,
+    /// Opacity new windows start with
+    default_opacity: u8,
+    /// Screen areas to repaint that no window tracks, e.g. of closed windows
+    damage: Mutex<Vec<Rect>>,
+    /// Repaint the whole screen on the next render
+    full_redraw: AtomicBool,
+    /// Areas drawn over the windows last frame, by toasts and the overlay
+    overlay_damage: Vec<Rect>,
+    toasts: Toasts,
 }
 
 impl Clone for Window {
@@ -112,6 +377,15 @@ impl Clone for Window {
             background_color: self.background_color,
             border_color: self.border_color,
             user_data: self.user_data,
+            content_height: self.content_height,
+            scroll_offset: self.scroll_offset,
+            focus_order: self.focus_order.clone(),
+            focused_widget: self.focused_widget,
+            content_size: self.content_size,
+            opacity: self.opacity,
+            dirty: AtomicBool::new(self.is_dirty()),
+            drawn_rect: self.drawn_rect,
+            widgets: self.widgets.clone(),
         }
     }
 }
@@ -134,6 +408,15 @@ impl Window {
             background_color: Color::UI_BACKGROUND,
             border_color: Color::UI_ACCENT,
             user_data: None,
+            content_height: 0,
+            scroll_offset: 0,
+            focus_order: Vec::new(),
+            focused_widget: None,
+            content_size: None,
+            opacity: 255,
+            dirty: AtomicBool::new(true),
+            drawn_rect: None,
+            widgets: Arc::new(Mutex::new(WidgetTree::default())),
         }
     }
 
@@ -149,6 +432,8 @@ impl Window {
     /// Set the window rectangle
     pub fn set_rect(&mut self, rect: Rect) {
         self.rect = rect;
+        self.mark_dirty();
+        config::mark_layout_dirty();
     }
 
     /// Check if the window is visible
@@ -158,7 +443,9 @@ impl Window {
 
     /// Set window visibility
     pub fn set_visible(&self, visible: bool) {
-        self.visible.store(visible, Ordering::Relaxed);
+        if self.visible.swap(visible, Ordering::Relaxed) != visible {
+            self.mark_dirty();
+        }
     }
 
     /// Check if the window is focused
@@ -168,12 +455,37 @@ impl Window {
 
     /// Set window focus state
     pub fn set_focused(&self, focused: bool) {
-        self.focused.store(focused, Ordering::Relaxed);
+        if self.focused.swap(focused, Ordering::Relaxed) != focused {
+            self.mark_dirty();
+        }
+    }
+
+    /// Redraw the window on the next frame. Content drawn by the render
+    /// callback is only redrawn after this, or after an event callback
+    /// returns true.
+    pub fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the window changed since it was last drawn
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Relaxed)
+    }
+
+    pub fn opacity(&self) -> u8 {
+        self.opacity
+    }
+
+    /// Composite the window at `opacity` (0-255) over what is beneath it
+    pub fn set_opacity(&mut self, opacity: u8) {
+        self.opacity = opacity;
+        self.mark_dirty();
     }
 
     /// Set window render callback
     pub fn set_render_callback(&mut self, callback: fn(&mut Renderer, &Window)) {
         self.render_callback = Some(callback);
+        self.mark_dirty();
     }
 
     /// Set window event callback
@@ -183,21 +495,162 @@ impl Window {
     /// Set window background color
     pub fn set_background_color(&mut self, color: Color) {
         self.background_color = color;
+        self.mark_dirty();
     }
     /// Set window border color
     pub fn set_border_color(&mut self, color: Color) {
         self.border_color = color;
+        self.mark_dirty();
     }
     /// Set user data pointer
     pub fn set_user_data(&mut self, data: *mut u8) {
         self.user_data = Some(data);
     }
+
+    /// Set the height of the scrollable content
+    /// Area below the title bar that the render callback draws into
+    pub fn content_rect(&self) -> Rect {
+        let rect = self.rect;
+        Rect::new(
+            rect.x,
+            rect.y + TITLE_BAR_HEIGHT as i32,
+            rect.width,
+            rect.height.saturating_sub(TITLE_BAR_HEIGHT),
+        )
+    }
+
+    /// Draw at a fixed resolution (e.g. a game's render size) scaled into the
+    /// content area with its aspect ratio kept. Pointer positions sent to the
+    /// window are then in content pixels.
+    pub fn set_content_size(&mut self, size: Option<(u32, u32)>) {
+        self.content_size = size;
+        self.mark_dirty();
+    }
+
+    pub fn content_size(&self) -> Option<(u32, u32)> {
+        self.content_size
+    }
+
+    /// Placement of fixed-size content for the given scale mode
+    pub fn letterbox(&self, mode: ScaleMode) -> Option<Letterbox> {
+        self.content_size
+            .map(|(width, height)| Letterbox::compute(width, height, self.content_rect(), mode))
+    }
+
+    pub fn set_content_height(&mut self, height: u32) {
+        self.content_height = height;
+        self.scroll_offset = self.scroll_offset.min(self.max_scroll_offset());
+        self.mark_dirty();
+    }
+
+    /// Get the current vertical scroll offset
+    pub fn scroll_offset(&self) -> i32 {
+        self.scroll_offset
+    }
+
+    /// Check if the window content is taller than its content area
+    pub fn is_scrollable(&self) -> bool {
+        self.max_scroll_offset() > 0
+    }
+
+    /// Largest scroll offset that still shows content
+    pub fn max_scroll_offset(&self) -> i32 {
+        let visible_height = self.rect.height.saturating_sub(TITLE_BAR_HEIGHT);
+        self.content_height.saturating_sub(visible_height) as i32
+    }
+
+    /// Scroll by the given amount, clamped to the content bounds.
+    /// Returns the distance actually scrolled.
+    pub fn scroll_by(&mut self, delta: i32) -> i32 {
+        let old_offset = self.scroll_offset;
+        self.scroll_offset = (old_offset + delta).max(0).min(self.max_scroll_offset());
+        if self.scroll_offset != old_offset {
+            self.mark_dirty();
+        }
+        self.scroll_offset - old_offset
+    }
+
+    /// Append a widget to the focus order
+    pub fn add_focus_target(&mut self, target: FocusTarget) {
+        self.focus_order.push(target);
+    }
+
+    /// Forget all widgets, e.g. before the window rebuilds its contents
+    pub fn clear_focus_targets(&mut self) {
+        self.focus_order.clear();
+        self.focused_widget = None;
+        self.mark_dirty();
+    }
+
+    /// Widget that currently has keyboard focus
+    pub fn focused_widget(&self) -> Option<WidgetId> {
+        self.focused_widget.map(|index| self.focus_order[index].widget)
+    }
+
+    /// Move focus to the next (or previous) focusable widget, wrapping at the ends.
+    /// Returns the newly focused widget.
+    pub fn focus_step(&mut self, forward: bool) -> Option<WidgetId> {
+        self.focus_first_match(forward, |_| true)
+    }
+
+    /// Move focus within the focused widget's container, wrapping at the ends.
+    /// Returns None when the focused widget isn't in a container.
+    pub fn focus_step_in_container(&mut self, forward: bool) -> Option<WidgetId> {
+        let container = self.focus_order[self.focused_widget?].container?;
+        self.focus_first_match(forward, |target| target.container == Some(container))
+    }
+
+    fn focus_first_match(&mut self, forward: bool, filter: impl Fn(&FocusTarget) -> bool) -> Option<WidgetId> {
+        let count = self.focus_order.len() as isize;
+        if count == 0 {
+            return None;
+        }
+
+        // Without a current focus, Tab starts at the first widget and Shift-Tab at the last
+        let start = match self.focused_widget {
+            Some(index) => index as isize,
+            None if forward => -1,
+            None => count,
+        };
+        for step in 1..=count {
+            let index = if forward { start + step } else { start - step }.rem_euclid(count) as usize;
+            let target = &self.focus_order[index];
+            if target.focusable && filter(target) {
+                self.focused_widget = Some(index);
+                let widget = target.widget;
+                self.mark_dirty();
+                return Some(widget);
+            }
+        }
+        None
+    }
+
+    /// Place a widget in the content area, replacing any with the same id
+    pub fn add_widget(&mut self, widget: Box<dyn Widget>) {
+        self.widgets.lock().add(widget);
+        self.mark_dirty();
+    }
+
+    pub fn remove_widget(&mut self, id: WidgetId) {
+        self.widgets.lock().remove(id);
+        self.mark_dirty();
+    }
+
+    /// Pass `event` to the event callback. One that reports the event
+    /// handled is assumed to have changed what the window shows.
+    fn dispatch(&self, event: &WindowEvent) {
+        if let Some(callback) = self.event_callback {
+            if callback(self, event) {
+                self.mark_dirty();
+            }
+        }
+    }
 }
 
 impl WindowManager {
     /// Create a new window manager
     pub fn new(renderer: Renderer) -> Result<Self, &'static str> {
-        Ok(Self {
+        let mut window_manager = Self {
             renderer,
             windows: Mutex::new(Vec::new()),
             next_window_id: AtomicU32::new(1),
@@ -205,9 +658,34 @@ impl WindowManager {
             dragging_window: AtomicU32::new(0),
             drag_offset_x: 0,
             drag_offset_y: 0,
+            mouse_buttons: 0,
+            modifiers: input::Modifiers::default(),
+            focus_history: Mutex::new(Vec::new()),
+            switcher: None,
             theme: Theme::default(),
             exit_requested: AtomicBool::new(false),
-        })
+            kinetic_scroll: KineticScroll::idle(),
+            cursor: CursorState {
+                x: 0,
+                y: 0,
+                visible: true,
+                drawn_at: None,
+                saved_pixels: Vec::new(),
+                on_gpu: false,
+            },
+            keyboard_navigation: true,
+            high_contrast: false,
+            pointer_grab: AtomicU32::new(0),
+            pointer_grab_fullscreen: AtomicBool::new(false),
+            allow_transparency: true,
+            default_opacity: 255,
+            damage: Mutex::new(Vec::new()),
+            full_redraw: AtomicBool::new(true),
+            overlay_damage: Vec::new(),
+            toasts: Toasts::from_config(&config::NotificationSettings::default()),
+        };
+        window_manager.upload_cursor();
+        Ok(window_manager)
     }
 
     /// Create a new window
@@ -234,21 +712,27 @@ impl WindowManager {
         };
 
         let rect = Rect::new(x, y, width, height);
-        let window = Window::new(id, title, rect);
+        let mut window = Window::new(id, title, rect);
+        window.set_opacity(self.default_opacity);
 
         // Add window to list
         let mut windows = self.windows.lock();
         windows.push(window);
+        config::mark_layout_dirty();
 
         Ok(id)
     }
 
     /// Show a window
     pub fn show_window(&self, id: WindowId) {
-        let mut windows = self.windows.lock();
-
-        if let Some(window) = windows.iter_mut().find(|w| w.id() == id) {
-            window.set_visible(true);
+        let shown = match self.windows.lock().iter().find(|w| w.id() == id) {
+            Some(window) => {
+                window.set_visible(true);
+                true
+            }
+            None => false,
+        };
+        if shown {
             self.focus_window(id);
         }
     }
@@ -264,8 +748,8 @@ impl WindowManager {
             if self.focused_window.load(Ordering::Relaxed) == id {
                 self.focused_window.store(0, Ordering::Relaxed);
 
-                // Find another visible window to focus
-                for w in windows.iter() {
+                // Focus the topmost remaining visible window
+                for w in windows.iter().rev() {
                     if w.is_visible() && w.id() != id {
                         self.focused_window.store(w.id(), Ordering::Relaxed);
                         w.set_focused(true);
@@ -281,18 +765,71 @@ impl WindowManager {
         // Hide the window first
         self.hide_window(id);
 
+        if self.pointer_grab.load(Ordering::Relaxed) == id {
+            self.release_pointer();
+        }
+
         // Then remove it from the list
         let mut windows = self.windows.lock();
         if let Some(index) = windows.iter().position(|w| w.id() == id) {
-            windows.remove(index);
+            let window = windows.remove(index);
+            self.damage.lock().extend(window.drawn_rect);
+            self.focus_history.lock().retain(|&w| w != id);
+            config::mark_layout_dirty();
+        }
+    }
+
+    /// Let a window capture the pointer. A window covering the whole screen
+    /// (a fullscreen game) also switches the mouse to raw, unaccelerated input.
+    pub fn grab_pointer(&self, id: WindowId) -> Result<(), &'static str> {
+        let window = self.get_window(id).ok_or("Window not found")?;
+        let rect = window.rect();
+        let (screen_width, screen_height) = self.renderer.dimensions();
+        let fullscreen = rect.x <= 0
+            && rect.y <= 0
+            && rect.x + rect.width as i32 >= screen_width as i32
+            && rect.y + rect.height as i32 >= screen_height as i32;
+
+        let previous = self.pointer_grab.swap(id, Ordering::Relaxed);
+        let was_fullscreen = self.pointer_grab_fullscreen.swap(fullscreen, Ordering::Relaxed);
+        if was_fullscreen && (previous != id || !fullscreen) {
+            events::publish(events::Event::FullscreenExited { window_id: previous });
+        }
+        input::set_raw_mouse(fullscreen);
+        if fullscreen && (previous != id || !was_fullscreen) {
+            events::publish(events::Event::FullscreenEntered { window_id: id });
+        }
+        Ok(())
+    }
+
+    /// Release the pointer grab and restore configured mouse processing
+    pub fn release_pointer(&self) {
+        let previous = self.pointer_grab.swap(0, Ordering::Relaxed);
+        if previous != 0 {
+            input::set_raw_mouse(false);
+            if self.pointer_grab_fullscreen.swap(false, Ordering::Relaxed) {
+                events::publish(events::Event::FullscreenExited { window_id: previous });
+            }
+        }
+    }
+
+    /// Window currently holding the pointer grab
+    pub fn pointer_grab(&self) -> Option<WindowId> {
+        match self.pointer_grab.load(Ordering::Relaxed) {
+            0 => None,
+            id => Some(id),
         }
     }
 
     /// Close all windows
     pub fn close_all_windows(&self) {
+        self.release_pointer();
         let mut windows = self.windows.lock();
         windows.clear();
+        self.focus_history.lock().clear();
         self.focused_window.store(0, Ordering::Relaxed);
+        self.invalidate();
+        config::mark_layout_dirty();
     }
 
     /// Focus a specific window
@@ -306,9 +843,7 @@ impl WindowManager {
                 window.set_focused(false);
 
                 // Send blur event
-                if let Some(callback) = window.event_callback {
-                    let _ = callback(window, &WindowEvent::Blur);
-                }
+                window.dispatch(&WindowEvent::Blur);
             }
         }
 
@@ -318,18 +853,80 @@ impl WindowManager {
             self.focused_window.store(id, Ordering::Relaxed);
 
             // Send focus event
-            if let Some(callback) = window.event_callback {
-                let _ = callback(window, &WindowEvent::Focus);
+            window.dispatch(&WindowEvent::Focus);
+
+            restack(&mut windows, id, true);
+
+            let mut history = self.focus_history.lock();
+            history.retain(|&w| w != id);
+            history.insert(0, id);
+        }
+    }
+
+    /// Alt+Tab: select the next window in most-recently-used order, or the
+    /// previous one with `forward` false, and raise it. Focus moves once Alt
+    /// is released.
+    fn cycle_windows(&mut self, forward: bool) {
+        if self.switcher.is_none() {
+            let order = {
+                let windows = self.windows.lock();
+                let history = self.focus_history.lock();
+                let visible = |id: &WindowId| windows.iter().any(|w| w.id() == *id && w.is_visible());
+                // Windows never focused follow, front to back
+                let mut order: Vec<WindowId> = history.iter().copied().filter(|id| visible(id)).collect();
+                for window in windows.iter().rev() {
+                    if window.is_visible() && !order.contains(&window.id()) {
+                        order.push(window.id());
+                    }
+                }
+                order
+            };
+            if order.len() < 2 {
+                return;
             }
+            self.switcher = Some(WindowSwitcher { order, selected: 0 });
+        }
 
-            // Move window to front (top of render order)
-            if let Some(index) = windows.iter().position(|w| w.id() == id) {
-                let window = windows.remove(index);
-                windows.push(window);
+        let switcher = match self.switcher.as_mut() {
+            Some(switcher) => switcher,
+            None => return,
+        };
+        let count = switcher.order.len();
+        switcher.selected = if forward {
+            (switcher.selected + 1) % count
+        } else {
+            (switcher.selected + count - 1) % count
+        };
+        let selected = switcher.order[switcher.selected];
+        self.raise(selected);
+    }
+
+    /// Focus the window Alt+Tab selected
+    fn commit_switch(&mut self) {
+        if let Some(switcher) = self.switcher.take() {
+            let id = switcher.order[switcher.selected];
+            if self.has_window(id) {
+                self.focus_window(id);
             }
         }
     }
 
+    /// Bring a window in front of all others, without changing focus
+    pub fn raise(&self, id: WindowId) {
+        restack(&mut self.windows.lock(), id, true);
+    }
+
+    /// Send a window behind all others, without changing focus
+    pub fn lower(&self, id: WindowId) {
+        restack(&mut self.windows.lock(), id, false);
+    }
+
+    /// Position of a window in the stack, 0 = frontmost
+    pub fn z_order(&self, id: WindowId) -> Option<usize> {
+        let windows = self.windows.lock();
+        windows.iter().rev().position(|w| w.id() == id)
+    }
+
     pub fn has_window(&self, id: WindowId) -> bool {
         let windows = self.windows.lock();
         windows.iter().any(|w| w.id() == id)
@@ -337,101 +934,341 @@ impl WindowManager {
 
     pub fn set_theme(&mut self, theme: Theme) {
         self.theme = theme;
+        self.upload_cursor();
+        self.invalidate();
+    }
+
+    /// Apply the accessibility settings that affect window management
+    pub fn set_accessibility(&mut self, accessibility: &config::AccessibilityConfig) {
+        self.keyboard_navigation = accessibility.keyboard_navigation;
+        self.high_contrast = accessibility.high_contrast;
+        self.invalidate();
+    }
+
+    /// Apply the clear color and scale mode from the display settings
+    pub fn set_display_options(&mut self, display: &config::DisplayConfig) {
+        let (r, g, b) = display.clear_color;
+        self.renderer.set_clear_color(Color::rgb(r, g, b));
+        match ScaleMode::from_name(&display.scale_mode) {
+            Some(mode) => self.renderer.set_scale_mode(mode),
+            None if display.scale_mode.is_empty() => {}
+            None => log::warn!("Unknown scale mode '{}', keeping {:?}", display.scale_mode, self.renderer.scale_mode()),
+        }
+        self.invalidate();
+    }
+
+    /// Apply the transparency settings from the window layout config
+    pub fn set_window_layout_options(&mut self, layout: &config::WindowLayoutConfig) {
+        self.allow_transparency = layout.allow_transparency;
+        self.default_opacity = layout.default_opacity;
+        self.invalidate();
+    }
+
+    /// Repaint the whole screen on the next render, e.g. after a mode change
+    /// left nothing of the previous frame
+    pub fn invalidate(&self) {
+        self.full_redraw.store(true, Ordering::Relaxed);
+    }
+
+    /// Redraw a window on the next render, e.g. after its content changed
+    pub fn invalidate_window(&self, id: WindowId) {
+        if let Some(window) = self.windows.lock().iter().find(|w| w.id == id) {
+            window.mark_dirty();
+        }
+    }
+
+    /// Change a window's opacity; it is recomposited on the next frame
+    pub fn set_window_opacity(&self, id: WindowId, opacity: u8) {
+        if let Some(window) = self.windows.lock().iter_mut().find(|w| w.id == id) {
+            window.set_opacity(opacity);
+        }
+    }
+
+    /// Opacity `window` is actually composited at
+    fn effective_opacity(&self, window: &Window) -> u8 {
+        if self.allow_transparency {
+            window.opacity
+        } else {
+            255
+        }
+    }
+
+    /// Current screen dimensions, in render space
+    pub fn screen_size(&self) -> (u32, u32) {
+        self.renderer.dimensions()
+    }
+
+    /// Resize the screen, scaling every window's position and size proportionally
+    pub fn set_resolution(&mut self, width: u32, height: u32) -> Result<(), &'static str> {
+        if (width, height) == self.renderer.output_dimensions() {
+            return Ok(());
+        }
+
+        let old_size = self.renderer.dimensions();
+        // The saved cursor background belongs to the old framebuffer
+        self.cursor.drawn_at = None;
+        self.renderer
+            .resize(width, height)
+            .map_err(|_| "Failed to resize renderer")?;
+        self.rescale_windows(old_size);
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Draw at `factor` of the screen resolution and upscale on present,
+    /// scaling the windows to match. Returns the factor applied.
+    pub fn set_render_scale(&mut self, factor: f32) -> Result<f32, &'static str> {
+        let old_size = self.renderer.dimensions();
+        self.cursor.drawn_at = None;
+        let applied = self
+            .renderer
+            .set_render_scale(factor)
+            .map_err(|_| "Failed to allocate the render scale buffer")?;
+        if self.renderer.dimensions() != old_size {
+            self.rescale_windows(old_size);
+        }
+        self.invalidate();
+        Ok(applied)
+    }
+
+    /// Fit the windows to a new render size after it changed from `old_size`
+    fn rescale_windows(&mut self, old_size: (u32, u32)) {
+        let (width, height) = self.renderer.dimensions();
+        fit_windows(&mut self.windows.lock(), old_size, (width, height));
+        config::mark_layout_dirty();
+
+        self.cursor.x = (width / 2) as i32;
+        self.cursor.y = (height / 2) as i32;
+        if self.cursor.on_gpu {
+            let (output_width, output_height) = self.renderer.output_dimensions();
+            let _ = gpu::move_cursor((output_width / 2) as i32, (output_height / 2) as i32);
+        }
+        self.kinetic_scroll = KineticScroll::idle();
     }
 
     /// Update window manager state
     pub fn update(&mut self) {
         // Process system events would go here
         // But we're assuming that's done by the caller
+
+        let now_ms = timer::uptime_ms();
+        for (title, body) in toast::take_posted() {
+            self.notify(&title, &body);
+        }
+        let fullscreen = self.pointer_grab_fullscreen.load(Ordering::Relaxed);
+        self.toasts.update(now_ms, fullscreen);
+
+        // Advance kinetic scrolling
+        self.tick_kinetic_scroll(now_ms);
+    }
+
+    /// Show a toast notification, per the notification settings
+    pub fn notify(&mut self, title: &str, body: &str) {
+        log::info!("Notification: {}: {}", title, body);
+        self.toasts.push(title, body);
+    }
+
+    /// Apply the notification settings from the user settings
+    pub fn set_notification_settings(&mut self, settings: &config::NotificationSettings) {
+        self.toasts.configure(settings);
+    }
+
+    /// Apply a scroll input to the scrollable window under the cursor
+    fn scroll_window_at(&mut self, x: i32, y: i32, delta: i8, now_ms: u64) {
+        let mut windows = self.windows.lock();
+
+        // Find the topmost visible scrollable window under the cursor
+        let window = match windows
+            .iter_mut()
+            .rev()
+            .find(|w| w.is_visible() && w.rect().contains(x, y))
+        {
+            Some(window) if window.is_scrollable() => window,
+            _ => return,
+        };
+
+        // Wheel up scrolls the content down
+        let delta_px = -(delta as i32) * SCROLL_STEP;
+        self.kinetic_scroll.input(window.id(), delta_px, now_ms);
+
+        if window.scroll_by(delta_px) != 0 {
+            window.dispatch(&WindowEvent::Scroll { offset: window.scroll_offset() });
+        }
+    }
+
+    /// Advance kinetic scrolling to `now_ms`, decaying the velocity after the user stops
+    pub fn tick_kinetic_scroll(&mut self, now_ms: u64) {
+        self.kinetic_scroll.tick(now_ms, &self.windows);
     }
 
     /// Handle mouse movement
     pub fn handle_mouse_event(&mut self, x: i32, y: i32, buttons: u8, scroll_delta: i8) {
+        // Relative devices can report positions past the edge
+        let (x, y) = clamp_to_screen(x, y, self.renderer.output_dimensions());
+        // Moved straight away, without waiting for the next frame
+        if self.cursor.on_gpu {
+            let _ = gpu::move_cursor(x, y);
+        }
+        // Pointer devices report display positions; windows live in render space
+        let (x, y) = self.renderer.to_render_space(x, y);
+        self.cursor.x = x;
+        self.cursor.y = y;
+
+        if scroll_delta != 0 {
+            self.scroll_window_at(x, y, scroll_delta, timer::uptime_ms());
+        }
+
+        // A button that just went down, as opposed to one held while moving
+        let pressed = buttons & !self.mouse_buttons;
+        let released = self.mouse_buttons & !buttons;
+        self.mouse_buttons = buttons;
+
         // Handle window dragging
         let dragging_id = self.dragging_window.load(Ordering::Relaxed);
-        if dragging_id != 0 && buttons & 1 != 0 {
-            // Continue dragging window
-            let mut windows = self.windows.lock();
-            if let Some(window) = windows.iter_mut().find(|w| w.id() == dragging_id) {
-                let mut rect = window.rect();
-                rect.x = x - self.drag_offset_x;
-                rect.y = y - self.drag_offset_y;
-                window.set_rect(rect);
-
-                // Send move event
-                if let Some(callback) = window.event_callback {
-                    let _ = callback(
-                        window,
-                        &WindowEvent::Move {
-                            x: rect.x,
-                            y: rect.y,
-                        },
-                    );
+        if dragging_id != 0 {
+            if buttons & 1 != 0 {
+                self.drag_window_to(dragging_id, x, y);
+                return;
+            }
+            self.end_drag();
+        }
+
+        // Find the topmost window under the pointer; the lock is released
+        // before focusing, which takes it again
+        let hit = self
+            .windows
+            .lock()
+            .iter()
+            .rev()
+            .find(|w| w.is_visible() && w.rect().contains(x, y))
+            .map(|w| (w.id(), w.rect(), w.is_focused()));
+
+        // A click brings the window forward and gives it keyboard focus
+        if let Some((id, _, focused)) = hit {
+            if pressed & 1 != 0 {
+                if focused {
+                    self.raise(id);
+                } else {
+                    self.focus_window(id);
                 }
             }
+        }
+
+        // Wherever the pointer is, so a button let go of off the window still releases
+        self.route_to_widgets(x, y, pressed, released);
+
+        let (id, rect, _) = match hit {
+            Some(hit) => hit,
+            None => return,
+        };
+
+        // Grabbing the title bar starts a drag
+        let in_title_bar = y >= rect.y && y < rect.y + TITLE_BAR_HEIGHT as i32;
+        if pressed & 1 != 0 && in_title_bar {
+            self.dragging_window.store(id, Ordering::Relaxed);
+            self.drag_offset_x = x - rect.x;
+            self.drag_offset_y = y - rect.y;
             return;
-        } else if dragging_id != 0 {
-            // Stop dragging
-            self.dragging_window.store(0, Ordering::Relaxed);
         }
 
-        // Check for hits
         let mut windows = self.windows.lock();
+        let window = match windows.iter_mut().find(|w| w.id() == id) {
+            Some(window) => window,
+            None => return,
+        };
 
-        // Process from top (front) to bottom
-        for i in (0..windows.len()).rev() {
-            let window = &mut windows[i];
-            if !window.is_visible() {
-                continue;
-            }
+        // Send mouse event to window; letterboxed content gets content pixels
+        let (window_x, window_y) = match window.letterbox(self.renderer.scale_mode()) {
+            Some(letterbox) => match letterbox.to_content(x, y) {
+                Some(position) => position,
+                // On a bar: nothing of the window's content is there
+                None => return,
+            },
+            None => (x - rect.x, y - rect.y),
+        };
 
-            let rect = window.rect();
-            if rect.contains(x, y) {
-                // Window was hit
+        if buttons & 1 != 0 {
+            window.dispatch(&WindowEvent::MouseDown {
+                x: window_x,
+                y: window_y,
+                button: 1,
+            });
+        } else {
+            window.dispatch(&WindowEvent::MouseMove {
+                x: window_x,
+                y: window_y,
+            });
+        }
+    }
 
-                // Focus window if clicked
-                if buttons & 1 != 0 && !window.is_focused() {
-                    self.focus_window(window.id());
-                }
+    /// Pass the pointer to the focused window's widgets, in content
+    /// coordinates. Only the left button presses widgets.
+    fn route_to_widgets(&self, x: i32, y: i32, pressed: u8, released: u8) {
+        let focused_id = self.focused_window.load(Ordering::Relaxed);
+        let windows = self.windows.lock();
+        let window = match windows.iter().find(|w| w.id() == focused_id && w.is_visible()) {
+            Some(window) => window,
+            None => return,
+        };
 
-                // Check if clicking on title bar (for dragging)
-                let title_bar_height = 25; // Example height
-                let in_title_bar = y >= rect.y && y < rect.y + title_bar_height;
+        let content = window.content_rect();
+        let x = x - content.x;
+        let y = y - content.y + window.scroll_offset;
+        let event = if pressed & 1 != 0 {
+            WindowEvent::MouseDown { x, y, button: 1 }
+        } else if released & 1 != 0 {
+            WindowEvent::MouseUp { x, y, button: 1 }
+        } else {
+            WindowEvent::MouseMove { x, y }
+        };
+        if window.widgets.lock().handle_event(&event) {
+            window.mark_dirty();
+        }
+    }
 
-                if buttons & 1 != 0 && in_title_bar {
-                    // Start dragging
-                    self.dragging_window.store(window.id(), Ordering::Relaxed);
-                    self.drag_offset_x = x - rect.x;
-                    self.drag_offset_y = y - rect.y;
-                } else {
-                    // Send mouse event to window
-                    let window_x = x - rect.x;
-                    let window_y = y - rect.y;
-
-                    if let Some(callback) = window.event_callback {
-                        if buttons & 1 != 0 {
-                            let _ = callback(
-                                window,
-                                &WindowEvent::MouseDown {
-                                    x: window_x,
-                                    y: window_y,
-                                    button: 1,
-                                },
-                            );
-                        } else {
-                            let _ = callback(
-                                window,
-                                &WindowEvent::MouseMove {
-                                    x: window_x,
-                                    y: window_y,
-                                },
-                            );
-                        }
-                    }
-                }
+    /// Place a widget in a window's content area
+    pub fn add_widget(&self, id: WindowId, widget: Box<dyn Widget>) -> Result<(), &'static str> {
+        let mut windows = self.windows.lock();
+        let window = windows.iter_mut().find(|w| w.id() == id).ok_or("Window not found")?;
+        window.add_widget(widget);
+        Ok(())
+    }
 
-                break;
+    /// Move the dragged window so the grab point follows the pointer,
+    /// keeping enough of its title bar on screen to grab it again
+    fn drag_window_to(&mut self, id: WindowId, x: i32, y: i32) {
+        let (screen_width, screen_height) = self.renderer.dimensions();
+        let mut windows = self.windows.lock();
+        let window = match windows.iter_mut().find(|w| w.id() == id) {
+            Some(window) => window,
+            None => {
+                self.dragging_window.store(0, Ordering::Relaxed);
+                return;
             }
+        };
+
+        let rect = window.rect();
+        let new_x = (x - self.drag_offset_x)
+            .max(DRAG_MIN_VISIBLE - rect.width as i32)
+            .min(screen_width as i32 - DRAG_MIN_VISIBLE);
+        let new_y = (y - self.drag_offset_y)
+            .min(screen_height as i32 - TITLE_BAR_HEIGHT as i32)
+            .max(0);
+        if (new_x, new_y) == (rect.x, rect.y) {
+            return;
+        }
+
+        // The layout is only recorded once the drag ends
+        window.rect.x = new_x;
+        window.rect.y = new_y;
+        window.mark_dirty();
+        window.dispatch(&WindowEvent::Move { x: new_x, y: new_y });
+    }
+
+    /// Drop the dragged window where it is and record the new layout
+    fn end_drag(&mut self) {
+        if self.dragging_window.swap(0, Ordering::Relaxed) != 0 {
+            config::mark_layout_dirty();
         }
     }
 
@@ -442,26 +1279,73 @@ impl WindowManager {
             return;
         }
 
-        let windows = self.windows.lock();
-        if let Some(window) = windows.iter().find(|w| w.id() == focused_id) {
-            if let Some(callback) = window.event_callback {
-                let event = if pressed {
-                    WindowEvent::KeyDown {
-                        key,
-                        scancode: key,
-                        modifiers,
-                    }
-                } else {
-                    WindowEvent::KeyUp {
-                        key,
-                        scancode: key,
-                        modifiers,
-                    }
+        let mut windows = self.windows.lock();
+        if let Some(window) = windows.iter_mut().find(|w| w.id() == focused_id) {
+            if pressed && self.keyboard_navigation {
+                let shift = modifiers & keyboard::MODIFIER_SHIFT != 0;
+                let moved = match key & 0x7F {
+                    SCANCODE_TAB => window.focus_step(!shift),
+                    SCANCODE_UP | SCANCODE_LEFT => window.focus_step_in_container(false),
+                    SCANCODE_DOWN | SCANCODE_RIGHT => window.focus_step_in_container(true),
+                    _ => None,
                 };
-
-                let _ = callback(window, &event);
+                if let Some(widget) = moved {
+                    window.dispatch(&WindowEvent::WidgetFocus { widget });
+                    return;
+                }
             }
+
+            let event = if pressed {
+                WindowEvent::KeyDown {
+                    key,
+                    scancode: key,
+                    modifiers,
+                }
+            } else {
+                WindowEvent::KeyUp {
+                    key,
+                    scancode: key,
+                    modifiers,
+                }
+            };
+            window.dispatch(&event);
+        }
+    }
+
+    /// Handle a navigation action: directions move widget focus in the focused
+    /// window, Confirm and Back reach it as Enter and Escape
+    pub fn handle_action(&mut self, action: input::NavAction) {
+        let focused_id = self.focused_window.load(Ordering::Relaxed);
+        if focused_id == 0 {
+            return;
         }
+
+        let mut windows = self.windows.lock();
+        let window = match windows.iter_mut().find(|w| w.id() == focused_id) {
+            Some(window) => window,
+            None => return,
+        };
+
+        let key = match action {
+            input::NavAction::Up | input::NavAction::Left => {
+                let moved = window.focus_step_in_container(false).or_else(|| window.focus_step(false));
+                if let Some(widget) = moved {
+                    window.dispatch(&WindowEvent::WidgetFocus { widget });
+                }
+                return;
+            }
+            input::NavAction::Down | input::NavAction::Right => {
+                let moved = window.focus_step_in_container(true).or_else(|| window.focus_step(true));
+                if let Some(widget) = moved {
+                    window.dispatch(&WindowEvent::WidgetFocus { widget });
+                }
+                return;
+            }
+            input::NavAction::Confirm => SCANCODE_ENTER,
+            input::NavAction::Back => SCANCODE_ESCAPE,
+        };
+        window.dispatch(&WindowEvent::KeyDown { key, scancode: key, modifiers: 0 });
+        window.dispatch(&WindowEvent::KeyUp { key, scancode: key, modifiers: 0 });
     }
 
     /// Handle touch events
@@ -470,27 +1354,216 @@ impl WindowManager {
         let buttons = if pressure > 0 { 1 } else { 0 };
         self.handle_mouse_event(x, y, buttons, 0);
     }
-    /// Render all windows
+    /// Redraw the windows that changed since the last frame
     pub fn render(&mut self) -> Result<(), RendererError> {
+        self.render_with_overlay(|_| None)
+    }
+
+    /// Redraw the windows that changed, then let `overlay` draw on top before
+    /// the cursor. `overlay` returns the area it drew, which is repainted
+    /// beneath it next frame.
+    pub fn render_with_overlay<F: FnOnce(&mut Renderer) -> Option<Rect>>(&mut self, overlay: F) -> Result<(), RendererError> {
+        let full_redraw = self.full_redraw.swap(false, Ordering::Relaxed);
+        let mut damage = core::mem::take(&mut *self.damage.lock());
+        damage.append(&mut self.overlay_damage);
+
         // Collect window references into a local Vec to avoid borrowing conflict
         let windows_to_render = {
-            let windows = self.windows.lock();
+            let mut windows = self.windows.lock();
+            for window in windows.iter_mut() {
+                let visible = window.is_visible();
+                // Where a changed or hidden window was drawn has to be uncovered
+                if window.dirty.swap(false, Ordering::Relaxed) || !visible {
+                    damage.extend(window.drawn_rect.take());
+                }
+                if visible && window.drawn_rect.is_none() {
+                    damage.push(window.rect);
+                    window.drawn_rect = Some(window.rect);
+                }
+            }
             windows
                 .iter()
                 .filter(|w| w.is_visible())
                 .cloned()
                 .collect::<Vec<_>>()
         };
-        
-        // Now render each window
-        for window in windows_to_render {
-            self.render_window(&window)?;
+
+        // Put back what the cursor covered last frame so it doesn't smear
+        self.restore_cursor_background();
+
+        if full_redraw {
+            self.renderer.clear_to_background();
+            for window in &windows_to_render {
+                self.render_window(window)?;
+            }
+        } else {
+            for region in merge_damage(damage) {
+                self.repaint_region(region, &windows_to_render)?;
+            }
         }
+
+        let fullscreen = self.pointer_grab_fullscreen.load(Ordering::Relaxed);
+        let toasts = self.toasts.render(&mut self.renderer, &self.theme, fullscreen);
+        let overlay = overlay(&mut self.renderer);
+        self.overlay_damage.extend(toasts.into_iter().chain(overlay));
+
+        // Cursor goes on top of everything
+        self.render_cursor();
         Ok(())
     }
 
-    /// Render a single window
+    /// Repaint `region` from the background up, leaving the rest of the
+    /// frame as it is
+    fn repaint_region(&mut self, region: Rect, windows: &[Window]) -> Result<(), RendererError> {
+        self.renderer.set_clip_bounds(Some(region));
+        let background = self.renderer.clear_color();
+        self.renderer.fill_rect(region, background);
+        let result = windows
+            .iter()
+            .filter(|w| w.rect().intersects(&region))
+            .try_for_each(|w| self.render_window(w));
+        self.renderer.set_clip_bounds(None);
+        result
+    }
+
+    /// Black out the screen, e.g. while idle. The next render redraws everything.
+    pub fn render_blank(&mut self) {
+        self.restore_cursor_background();
+        self.renderer.clear(Color::BLACK);
+        self.invalidate();
+    }
+
+    /// Show the rendered frame. With `tear_line` set the flip waits until
+    /// scanout passes that line instead of happening immediately.
+    pub fn present(&self, tear_line: Option<u32>) -> Result<(), RendererError> {
+        match tear_line {
+            Some(y) => self.renderer.present_after_scanline(y),
+            None => self.renderer.present(),
+        }
+    }
+
+    /// Show or hide the mouse cursor (e.g. for fullscreen games)
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor.visible = visible;
+        if self.cursor.on_gpu {
+            let _ = gpu::show_cursor(visible);
+        }
+    }
+
+    /// Current mouse cursor position
+    pub fn cursor_position(&self) -> (i32, i32) {
+        (self.cursor.x, self.cursor.y)
+    }
+
+    /// Restore the framebuffer pixels under the previously drawn cursor
+    fn restore_cursor_background(&mut self) {
+        let (old_x, old_y, width) = match self.cursor.drawn_at.take() {
+            Some(drawn_at) => drawn_at,
+            None => return,
+        };
+
+        for (i, saved) in self.cursor.saved_pixels.iter().enumerate() {
+            if let Some(value) = saved {
+                let px = old_x + (i as u32 % width) as i32;
+                let py = old_y + (i as u32 / width) as i32;
+                self.renderer.write_raw_pixel(px, py, *value);
+            }
+        }
+        self.cursor.saved_pixels.clear();
+    }
+
+    /// Hand the themed cursor to the gpu module so it moves without a redraw
+    /// and can't tear; keeps drawing it in software when that isn't possible
+    fn upload_cursor(&mut self) {
+        // A cursor composited by `gpu::present` is never seen if the renderer doesn't present through it
+        if !self.renderer.presents_through_gpu() {
+            self.cursor.on_gpu = false;
+            return;
+        }
+
+        let sprite: CursorSprite = self.theme.cursor;
+        let (outline, fill) = (self.theme.cursor_outline.to_argb(), self.theme.cursor_fill.to_argb());
+        let image: Vec<u8> = sprite
+            .pixels
+            .iter()
+            .flat_map(|&pixel| match pixel {
+                1 => outline,
+                2 => fill,
+                _ => 0,
+            }.to_le_bytes())
+            .collect();
+        let hotspot = (sprite.hotspot.0.max(0) as u16, sprite.hotspot.1.max(0) as u16);
+
+        self.cursor.on_gpu = gpu::set_cursor(&image, sprite.width, sprite.height, hotspot).is_ok();
+        if self.cursor.on_gpu {
+            self.restore_cursor_background();
+            // The gpu cursor is positioned on the display, not in render space
+            let (width, height) = self.renderer.dimensions();
+            let (output_width, output_height) = self.renderer.output_dimensions();
+            let x = self.cursor.x as i64 * output_width as i64 / width.max(1) as i64;
+            let y = self.cursor.y as i64 * output_height as i64 / height.max(1) as i64;
+            let _ = gpu::move_cursor(x as i32, y as i32);
+            let _ = gpu::show_cursor(self.cursor.visible);
+        }
+    }
+
+    /// Draw the themed cursor sprite at the current mouse position
+    fn render_cursor(&mut self) {
+        if !self.cursor.visible || self.cursor.on_gpu {
+            return;
+        }
+
+        let sprite: CursorSprite = self.theme.cursor;
+        let origin_x = self.cursor.x - sprite.hotspot.0;
+        let origin_y = self.cursor.y - sprite.hotspot.1;
+
+        // Save what's underneath before drawing
+        self.cursor.saved_pixels.clear();
+        for row in 0..sprite.height as i32 {
+            for col in 0..sprite.width as i32 {
+                let saved = self.renderer.read_raw_pixel(origin_x + col, origin_y + row);
+                self.cursor.saved_pixels.push(saved);
+            }
+        }
+        self.cursor.drawn_at = Some((origin_x, origin_y, sprite.width));
+
+        for (i, &pixel) in sprite.pixels.iter().enumerate() {
+            let color = match pixel {
+                1 => self.theme.cursor_outline,
+                2 => self.theme.cursor_fill,
+                _ => continue,
+            };
+            let px = origin_x + (i as u32 % sprite.width) as i32;
+            let py = origin_y + (i as u32 / sprite.width) as i32;
+            self.renderer.write_raw_pixel(px, py, color.to_argb());
+        }
+    }
+
+    /// Render a single window, blended over what is beneath it when translucent
     fn render_window(&mut self, window: &Window) -> Result<(), RendererError> {
+        let opacity = self.effective_opacity(window);
+        match opacity {
+            0 => Ok(()),
+            255 => self.draw_window(window),
+            _ => {
+                // Outside a partial redraw's region the window is already blended
+                let rect = match self.renderer.clip_rect() {
+                    Some(clip) => match clip.intersection(&window.rect()) {
+                        Some(rect) => rect,
+                        None => return Ok(()),
+                    },
+                    None => window.rect(),
+                };
+                let below = self.renderer.copy_region(rect);
+                self.draw_window(window)?;
+                self.renderer.composite_over(rect, &below, opacity);
+                Ok(())
+            }
+        }
+    }
+
+    /// Draw a window's frame and content straight into the framebuffer
+    fn draw_window(&mut self, window: &Window) -> Result<(), RendererError> {
         let rect = window.rect();
 
         // Draw window background
@@ -506,7 +1579,7 @@ impl WindowManager {
         self.renderer.draw_rect(rect, border_color);
 
         // Draw title bar
-        let title_bar_height = 25;
+        let title_bar_height = TITLE_BAR_HEIGHT;
         let title_bar_rect = Rect::new(rect.x, rect.y, rect.width, title_bar_height);
 
         let title_bar_color = if window.is_focused() {
@@ -517,20 +1590,23 @@ impl WindowManager {
 
         self.renderer.fill_rect(title_bar_rect, title_bar_color);
 
-        // Draw window title
-        // In a real implementation, this would use a text rendering function
+        // Draw window title, vertically centered and clipped to the bar
+        let title_color = if window.is_focused() {
+            self.theme.title_text_active
+        } else {
+            self.theme.title_text_inactive
+        };
+        let title_size = self.theme.font_size as f32;
+        let (_, text_height) = self.renderer.measure_text(&window.title, title_size);
+        let text_y = rect.y + (title_bar_height as i32 - text_height as i32) / 2;
+        self.renderer.set_clip_rect(Some(title_bar_rect));
+        self.renderer.draw_text(&window.title, rect.x + TITLE_PADDING, text_y, title_size, title_color);
+        self.renderer.set_clip_rect(None);
 
         // Draw window content
         if let Some(render_fn) = window.render_callback {
             // Set clipping to window content area
-            let content_rect = Rect::new(
-                rect.x,
-                rect.y + title_bar_height as i32,
-                rect.width,
-                rect.height - title_bar_height,
-            );
-
-            self.renderer.set_clip_rect(Some(content_rect));
+            self.renderer.set_clip_rect(Some(window.content_rect()));
 
             // Call the window's render function
             render_fn(&mut self.renderer, window);
@@ -539,15 +1615,65 @@ impl WindowManager {
             self.renderer.set_clip_rect(None);
         }
 
+        // Widgets go over the content and scroll with it
+        let widgets = window.widgets.lock();
+        if !widgets.is_empty() {
+            let content = window.content_rect();
+            self.renderer.set_clip_rect(Some(content));
+            widgets.render(&mut self.renderer, &self.theme, (content.x, content.y - window.scroll_offset));
+            self.renderer.set_clip_rect(None);
+        }
+
+        if window.is_focused() {
+            self.render_focus_ring(window, title_bar_height);
+        }
+
         Ok(())
     }
+    /// Outline the focused widget of `window`
+    fn render_focus_ring(&mut self, window: &Window, title_bar_height: u32) {
+        let target = match window.focused_widget {
+            Some(index) => window.focus_order[index],
+            None => return,
+        };
+
+        let rect = window.rect();
+        let content_rect = Rect::new(
+            rect.x,
+            rect.y + title_bar_height as i32,
+            rect.width,
+            rect.height.saturating_sub(title_bar_height),
+        );
+        let thickness = if self.high_contrast {
+            FOCUS_RING_WIDTH_HIGH_CONTRAST
+        } else {
+            FOCUS_RING_WIDTH
+        };
+
+        // Rings grow outward from the widget bounds
+        self.renderer.set_clip_rect(Some(content_rect));
+        for ring in 1..=thickness {
+            self.renderer.draw_rect(
+                Rect::new(
+                    content_rect.x + target.rect.x - ring as i32,
+                    content_rect.y + target.rect.y - window.scroll_offset - ring as i32,
+                    target.rect.width + 2 * ring,
+                    target.rect.height + 2 * ring,
+                ),
+                self.theme.selection_background,
+            );
+        }
+        self.renderer.set_clip_rect(None);
+    }
+
     /// Get window by ID
     pub fn get_window(&self, id: WindowId) -> Option<Window> {
         let windows = self.windows.lock();
         windows.iter().find(|w| w.id() == id).cloned()
     }
 
-    /// Save a simplified representation of window layout for serialization
+    /// Save a simplified representation of window layout for serialization,
+    /// back to front
     pub fn save_layout(&self) -> Vec<(WindowId, String, Rect)> {
         let windows = self.windows.lock();
         windows.iter()
@@ -555,30 +1681,43 @@ impl WindowManager {
             .collect()
     }
 
-    pub fn handle_key_press(&mut self, key: u16) {
-        // Handle key press events
-        if key == 27 { // Escape key
+    pub fn handle_key_press(&mut self, key: input::Key) {
+        if self.modifiers.update(key, true) {
+            return;
+        }
+        if key == input::Key::Tab && self.modifiers.alt {
+            self.cycle_windows(!self.modifiers.shift);
+            return;
+        }
+        if key == input::Key::Escape {
             self.exit_requested.store(true, Ordering::Relaxed);
         }
     }
-    pub fn handle_key_release(&mut self, key: u16) {
-        // Handle key release events
-        if key == 27 { // Escape key
+    pub fn handle_key_release(&mut self, key: input::Key) {
+        if self.modifiers.update(key, false) {
+            // Letting go of Alt ends an Alt+Tab switch on the selected window
+            if key == input::Key::Alt {
+                self.commit_switch();
+            }
+            return;
+        }
+        if key == input::Key::Escape {
             self.exit_requested.store(false, Ordering::Relaxed);
         }
     }
 
     pub fn handle_mouse_move(&mut self, x: i32, y: i32) {
-        // Handle mouse move events
-        self.handle_mouse_event(x, y, 0, 0);
+        // Buttons stay held while moving, e.g. during a drag
+        self.handle_mouse_event(x, y, self.mouse_buttons, 0);
     }
+    /// `button` is an `input::MouseButton` index, 0 = left
     pub fn handle_mouse_press(&mut self, button: u8, x: i32, y: i32) {
         // Handle mouse press events
-        self.handle_mouse_event(x, y, button as u8, 0);
+        self.handle_mouse_event(x, y, self.mouse_buttons | 1 << (button & 7), 0);
     }
     pub fn handle_mouse_release(&mut self, button: u8, x: i32, y: i32) {
         // Handle mouse release events
-        self.handle_mouse_event(x, y, 0, 0);
+        self.handle_mouse_event(x, y, self.mouse_buttons & !(1 << (button & 7)), 0);
     }
 
     pub fn handle_event(&mut self, event: Event) {
@@ -588,7 +1727,8 @@ impl WindowManager {
 
     pub fn handle_mouse_scroll(&mut self, delta: i32, x: i32, y: i32) {
         // Handle mouse scroll events
-        self.handle_mouse_event(x, y, 0, delta as i8);
+        let delta = delta.max(i8::MIN as i32).min(i8::MAX as i32);
+        self.handle_mouse_event(x, y, self.mouse_buttons, delta as i8);
     }
     pub fn exit_requested(&self) -> bool {
         self.exit_requested.load(Ordering::Relaxed)
@@ -612,3 +1752,181 @@ impl WindowManager {
         self.close_all_windows();
     }
 }
+
+/// Move window `id` to the front of the stack, or to the back. It is
+/// redrawn, which also repaints whatever it now covers or uncovers.
+fn restack(windows: &mut Vec<Window>, id: WindowId, to_front: bool) {
+    let index = match windows.iter().position(|w| w.id() == id) {
+        Some(index) => index,
+        None => return,
+    };
+    let target = if to_front { windows.len() - 1 } else { 0 };
+    if index == target {
+        return;
+    }
+    let window = windows.remove(index);
+    window.mark_dirty();
+    if to_front {
+        windows.push(window);
+    } else {
+        windows.insert(0, window);
+    }
+    config::mark_layout_dirty();
+}
+
+/// Drop empty damage and collapse too many rects into their bounding box
+fn merge_damage(mut damage: Vec<Rect>) -> Vec<Rect> {
+    damage.retain(|rect| rect.width > 0 && rect.height > 0);
+    if damage.len() > MAX_DAMAGE_RECTS {
+        let bounds = damage
+            .iter()
+            .map(|&rect| emath::Rect::from(rect))
+            .reduce(|a, b| a.union(&b))
+            .map(|bounds| bounds.to_pixels());
+        damage = bounds.into_iter().collect();
+    }
+    damage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One window whose content scrolls up to `max_offset` pixels
+    fn scrollable(max_offset: u32) -> Mutex<Vec<Window>> {
+        let mut window = Window::new(1, "scroll", Rect::new(0, 0, 100, 100 + TITLE_BAR_HEIGHT));
+        window.set_content_height(100 + max_offset);
+        Mutex::new(vec![window])
+    }
+
+    fn offset(windows: &Mutex<Vec<Window>>) -> i32 {
+        windows.lock()[0].scroll_offset()
+    }
+
+    /// A 60 px burst, released and coasting by the returned time
+    fn coasting(kinetic: &mut KineticScroll, windows: &Mutex<Vec<Window>>) -> u64 {
+        for now_ms in [0, 10, 20] {
+            kinetic.input(1, SCROLL_STEP, now_ms);
+        }
+        let released_ms = 20 + KINETIC_RELEASE_MS;
+        kinetic.tick(released_ms, windows);
+        assert!(kinetic.coasting);
+        assert_eq!(kinetic.velocity, 60.0 * 0.25);
+        released_ms
+    }
+
+    #[test]
+    fn velocity_accumulates_within_a_burst() {
+        let mut kinetic = KineticScroll::idle();
+        kinetic.input(1, SCROLL_STEP, 0);
+        kinetic.input(1, SCROLL_STEP, 10);
+        kinetic.input(1, SCROLL_STEP, 20);
+        assert_eq!(kinetic.velocity, 3.0 * SCROLL_STEP as f32);
+
+        // Reversing starts a new burst
+        kinetic.input(1, -SCROLL_STEP, 30);
+        assert_eq!(kinetic.velocity, -SCROLL_STEP as f32);
+
+        // So does a pause
+        kinetic.input(1, -SCROLL_STEP, 31 + KINETIC_RELEASE_MS * 4);
+        assert_eq!(kinetic.velocity, -SCROLL_STEP as f32);
+    }
+
+    #[test]
+    fn flick_below_the_momentum_threshold_does_not_coast() {
+        let windows = scrollable(1000);
+        let mut kinetic = KineticScroll::idle();
+        kinetic.input(1, SCROLL_STEP, 0);
+        assert!((SCROLL_STEP as f32) < KINETIC_MOMENTUM_THRESHOLD);
+
+        // Input is still arriving
+        kinetic.tick(KINETIC_RELEASE_MS - 1, &windows);
+        assert_eq!(kinetic.target, 1);
+
+        kinetic.tick(KINETIC_RELEASE_MS, &windows);
+        assert_eq!(kinetic.target, 0);
+        assert_eq!(offset(&windows), 0);
+    }
+
+    #[test]
+    fn momentum_decays_every_tick_until_it_settles() {
+        let windows = scrollable(1000);
+        let mut kinetic = KineticScroll::idle();
+        let mut now_ms = coasting(&mut kinetic, &windows);
+        assert_eq!(offset(&windows), 0);
+
+        // Less than a step has passed
+        kinetic.tick(now_ms + KINETIC_STEP_MS - 1, &windows);
+        assert_eq!(offset(&windows), 0);
+
+        let mut last_delta = i32::MAX;
+        for _ in 0..100 {
+            let velocity = kinetic.velocity;
+            let before = offset(&windows);
+            now_ms += KINETIC_STEP_MS;
+            kinetic.tick(now_ms, &windows);
+            if kinetic.target == 0 {
+                assert!(velocity * KINETIC_FRICTION < KINETIC_MIN_VELOCITY);
+                assert_eq!(offset(&windows), before);
+                return;
+            }
+            assert_eq!(kinetic.velocity, velocity * KINETIC_FRICTION);
+            let delta = offset(&windows) - before;
+            assert_eq!(delta, kinetic.velocity.round() as i32);
+            assert!(delta > 0 && delta <= last_delta);
+            last_delta = delta;
+        }
+        panic!("kinetic scroll never settled");
+    }
+
+    #[test]
+    fn coasting_stops_at_the_max_scroll_offset() {
+        let windows = scrollable(20);
+        let mut kinetic = KineticScroll::idle();
+        let now_ms = coasting(&mut kinetic, &windows);
+
+        // Enough steps to travel well past the end of the content
+        kinetic.tick(now_ms + 10 * KINETIC_STEP_MS, &windows);
+        assert_eq!(offset(&windows), windows.lock()[0].max_scroll_offset());
+        assert_eq!(offset(&windows), 20);
+        assert_eq!(kinetic.target, 0);
+    }
+
+    #[test]
+    fn cursor_is_clamped_to_the_screen() {
+        let screen = (640, 480);
+        assert_eq!(clamp_to_screen(320, 240, screen), (320, 240));
+        assert_eq!(clamp_to_screen(-5, -1, screen), (0, 0));
+        assert_eq!(clamp_to_screen(640, 480, screen), (639, 479));
+        assert_eq!(clamp_to_screen(i32::MAX, 12, screen), (639, 12));
+        assert_eq!(clamp_to_screen(12, i32::MIN, screen), (12, 0));
+    }
+
+    #[test]
+    fn windows_are_scaled_and_kept_on_screen_after_a_resolution_change() {
+        let mut windows = vec![
+            Window::new(1, "inside", Rect::new(100, 50, 200, 150)),
+            // Dragged partly off the left and bottom edges
+            Window::new(2, "off left", Rect::new(-40, 500, 200, 200)),
+            // Dragged partly off the right edge
+            Window::new(3, "off right", Rect::new(760, 0, 200, 100)),
+        ];
+        fit_windows(&mut windows, (800, 600), (400, 300));
+        let bounds = |window: &Window| {
+            let rect = window.rect();
+            (rect.x, rect.y, rect.width, rect.height)
+        };
+        assert_eq!(bounds(&windows[0]), (50, 25, 100, 75));
+        assert_eq!(bounds(&windows[1]), (0, 200, 100, 100));
+        assert_eq!(bounds(&windows[2]), (300, 0, 100, 50));
+
+        // Scrolled to the end, then made taller by a larger screen
+        let mut windows = vec![Window::new(1, "scrolled", Rect::new(0, 0, 100, 100 + TITLE_BAR_HEIGHT))];
+        windows[0].set_content_height(300);
+        windows[0].scroll_by(200);
+        fit_windows(&mut windows, (400, 300), (800, 600));
+        // Twice as tall, so only 300 - (2 * 125 - TITLE_BAR_HEIGHT) is left to scroll
+        assert_eq!(windows[0].max_scroll_offset(), 300 - (250 - TITLE_BAR_HEIGHT as i32));
+        assert_eq!(windows[0].scroll_offset(), windows[0].max_scroll_offset());
+    }
+}
@@ -39,6 +39,8 @@ pub struct BootConfig {
     /// Initial display refresh rate
     pub refresh_rate: Option<u32>,
     pub boot_info: Option<&'static BootInfo>,
+    /// Physical address of the Multiboot2 information, if the loader passed one
+    pub multiboot_info: Option<u64>,
 }
 
 impl Default for BootConfig {
@@ -50,6 +52,7 @@ impl Default for BootConfig {
             display_height: Some(1080),
             refresh_rate: Some(60),
             boot_info: None,
+            multiboot_info: None,
         }
     }
 }
@@ -81,9 +84,10 @@ fn set_boot_status(status: BootStatus) {
 
 
 /// Initialize the kernel and set up required subsystems
-pub fn init(boot_info: &'static BootInfo) -> Result<(), &'static str> {
+pub fn init(boot_info: &'static BootInfo, multiboot_info: Option<u64>) -> Result<(), &'static str> {
     let mut boot_config = BootConfig::default();
     boot_config.boot_info = Some(boot_info);
+    boot_config.multiboot_info = multiboot_info;
 
     // Set default display settings
     let config = Config::default();
@@ -98,6 +102,14 @@ pub fn init(boot_info: &'static BootInfo) -> Result<(), &'static str> {
 /// Internal initialization function that works with BootConfig
 pub fn internal_init(config: BootConfig) -> Result<(), &'static str> {
     set_boot_status(BootStatus::NotStarted);
+
+    // Decide panic behavior before anything that can panic
+    if let Some(name) = cmdline_value(config.cmdline, "panic") {
+        match crate::kernel::panic::PanicPolicy::from_name(name) {
+            Some(policy) => crate::kernel::panic::set_policy_from_cmdline(policy),
+            None => println!("Ignoring unknown panic policy '{}'", name),
+        }
+    }
     
     // 1. CPU Initialization and feature detection
     set_boot_status(BootStatus::CPUInitializing);
@@ -111,13 +123,24 @@ pub fn internal_init(config: BootConfig) -> Result<(), &'static str> {
         return Err("No boot information available for memory initialization");
     }
 
+    // Parsing allocates, so it waits for the heap
+    multiboot_init(&config);
+
+    // Optional RAM test, once the frame allocator knows which frames are free
+    if cmdline_has_flag(config.cmdline, "memtest") {
+        let report = crate::kernel::memory::selftest(&crate::kernel::memory::Pattern::ALL);
+        if !report.passed() {
+            println!("WARNING: memory test found {} bad words; see log for addresses", report.failure_count);
+        }
+    }
+
     // 3. Display/HDMI initialization
     set_boot_status(BootStatus::DisplayInitializing);
     display_init(&config)?;
     
     // 4. Storage subsystem initialization
     set_boot_status(BootStatus::StorageInitializing);
-    storage_init()?;
+    storage_init(cmdline_has_flag(config.cmdline, "storagebench"))?;
     
     // 5. Input devices initialization
     set_boot_status(BootStatus::InputInitializing);
@@ -154,6 +177,54 @@ pub fn internal_init(config: BootConfig) -> Result<(), &'static str> {
     Ok(())
 }
 
+/// Whether the whitespace-separated kernel command line contains `flag`
+fn cmdline_has_flag(cmdline: Option<&str>, flag: &str) -> bool {
+    cmdline.map_or(false, |line| line.split_whitespace().any(|arg| arg == flag))
+}
+
+/// Value of a `key=value` option on the kernel command line
+fn cmdline_value<'a>(cmdline: Option<&'a str>, key: &str) -> Option<&'a str> {
+    cmdline?
+        .split_whitespace()
+        .find_map(|arg| arg.strip_prefix(key).and_then(|rest| rest.strip_prefix('=')))
+}
+
+/// Keep the loader's Multiboot2 information, reached through the physical
+/// memory mapping. Without a framebuffer tag the console stays in VGA text
+/// mode until a GPU driver loads.
+fn multiboot_init(config: &BootConfig) {
+    use crate::kernel::multiboot2;
+
+    let (Some(address), Some(boot_info)) = (config.multiboot_info, config.boot_info) else {
+        println!("No Multiboot2 information, using VGA text mode");
+        return;
+    };
+    // Safety: the loader left the structure at `address` and nothing has
+    // been allocated over it yet
+    match unsafe { multiboot2::parse_at((boot_info.physical_memory_offset + address) as usize) } {
+        Ok(info) => {
+            if !info.memory_map.is_empty() {
+                println!("Multiboot2 memory map: {} KiB usable", info.usable_memory() / 1024);
+                // The frame allocator was set up from the bootloader's map; take
+                // back anything this map reserves that it still considers free
+                let pmm = crate::kernel::memory::physical::get_physical_memory_manager();
+                let reserved: usize = info.unusable_ranges().map(|range| pmm.reserve_range(range)).sum();
+                if reserved > 0 {
+                    println!("Reserved {} frames the Multiboot2 memory map marks unusable", reserved);
+                }
+            }
+            match &info.framebuffer {
+                Some(fb) if multiboot2::is_graphics(fb) => {
+                    println!("Boot framebuffer: {}x{}x{} at {:#x}", fb.width, fb.height, fb.bpp, fb.address)
+                }
+                _ => println!("No boot framebuffer, using VGA text mode"),
+            }
+            multiboot2::set_boot_info(info);
+        }
+        Err(e) => println!("Ignoring Multiboot2 information: {}", e),
+    }
+}
+
 /// Initialize CPU features and optimizations
 /// Initialize CPU features
 fn cpu_init() -> Result<(), &'static str> {
@@ -250,9 +321,18 @@ fn display_init(config: &BootConfig) -> Result<(), &'static str> {
 }
 
 /// Initialize storage subsystem
-fn storage_init() -> Result<(), &'static str> {
+fn storage_init(benchmark: bool) -> Result<(), &'static str> {
     // Initialize storage subsystem
     let storage_manager = drivers::storage::init()?;
+
+    // Optional read benchmark, requested with `storagebench` on the command line
+    if benchmark {
+        for device in storage_manager.get_devices() {
+            if let Err(e) = drivers::storage::benchmark(device) {
+                println!("Storage benchmark of {} failed: {}", device.get_name(), e);
+            }
+        }
+    }
     
     // Scan for available storage devices
     #[cfg(feature = "std")]
@@ -309,13 +389,13 @@ fn network_init() -> Result<(), &'static str> {
 /// Initialize sound subsystem
 fn sound_init() -> Result<(), &'static str> {
     // Initialize sound driver
-    let sound_system = drivers::sound::init()?;
+    drivers::sound::init()?;
     
     #[cfg(feature = "std")]
     {
         log::info!("Sound system initialized");
         
-        for (idx, device) in sound_system.get_output_devices().iter().enumerate() {
+        for (idx, device) in drivers::sound::get_sound_driver().get_output_devices().iter().enumerate() {
             log::info!("Sound device {}: {} ({:?})", 
                 idx,
                 device.get_name(),
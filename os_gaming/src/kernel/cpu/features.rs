@@ -3,8 +3,8 @@ extern crate alloc;
 use raw_cpuid::{CpuId, ProcessorBrandString};
 use alloc::vec::Vec;
 
-/// Required CPU features for OS Gaming
-const REQUIRED_FEATURES: &[CpuFeature] = &[
+/// Required CPU features for OS Gaming; both the boot check and its error report use this list
+pub const REQUIRED_FEATURES: &[CpuFeature] = &[
     CpuFeature::SSE2,
     CpuFeature::AVX,
     CpuFeature::CMPXCHG8B,
@@ -40,9 +40,60 @@ pub enum CpuFeature {
     MSR,
 }
 
+impl CpuFeature {
+    /// Conventional instruction-set name, for user-facing messages
+    pub fn name(self) -> &'static str {
+        match self {
+            CpuFeature::SSE => "SSE",
+            CpuFeature::SSE2 => "SSE2",
+            CpuFeature::SSE3 => "SSE3",
+            CpuFeature::SSSE3 => "SSSE3",
+            CpuFeature::SSE4_1 => "SSE4.1",
+            CpuFeature::SSE4_2 => "SSE4.2",
+            CpuFeature::AVX => "AVX",
+            CpuFeature::AVX2 => "AVX2",
+            CpuFeature::AVX512F => "AVX-512F",
+            CpuFeature::BMI1 => "BMI1",
+            CpuFeature::BMI2 => "BMI2",
+            CpuFeature::POPCNT => "POPCNT",
+            CpuFeature::CMPXCHG8B => "CMPXCHG8B",
+            CpuFeature::CMPXCHG16B => "CMPXCHG16B",
+            CpuFeature::RDTSC => "RDTSC",
+            CpuFeature::AES => "AES-NI",
+            CpuFeature::PCLMULQDQ => "PCLMULQDQ",
+            CpuFeature::XSAVE => "XSAVE",
+            CpuFeature::OSXSAVE => "OSXSAVE",
+            CpuFeature::F16C => "F16C",
+            CpuFeature::FMA => "FMA",
+            CpuFeature::MMX => "MMX",
+            CpuFeature::FXSR => "FXSR",
+            CpuFeature::TSC => "TSC",
+            CpuFeature::MSR => "MSR",
+        }
+    }
+}
+
 /// Check if CPU has all required features
 pub fn has_required_features() -> bool {
-    REQUIRED_FEATURES.iter().all(|&feature| has_feature(feature))
+    missing_required_iter().next().is_none()
+}
+
+/// Required features the current CPU lacks
+pub fn missing_required() -> Vec<CpuFeature> {
+    missing_required_iter().collect()
+}
+
+/// Allocation-free form of `missing_required`, usable before the heap exists
+pub fn missing_required_iter() -> impl Iterator<Item = CpuFeature> {
+    missing_features(REQUIRED_FEATURES, has_feature)
+}
+
+/// Features from `required` for which `supported` returns false
+pub fn missing_features<'a>(
+    required: &'a [CpuFeature],
+    supported: impl Fn(CpuFeature) -> bool + 'a,
+) -> impl Iterator<Item = CpuFeature> + 'a {
+    required.iter().copied().filter(move |&feature| !supported(feature))
 }
 
 /// Check if CPU has a specific feature
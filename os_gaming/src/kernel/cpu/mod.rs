@@ -11,11 +11,15 @@ pub mod performance;
 
 // Re-export commonly used items for easier access
 pub use identification::{get_cpu_info, CpuInfo};
-pub use features::{CpuFeature, has_feature, has_required_features};
+pub use features::{CpuFeature, has_feature, has_required_features, missing_required};
 pub use power::{set_performance_mode, set_balanced_mode, set_power_saving_mode};
 pub use performance::{start_monitoring, stop_monitoring, read_performance_data};
 
 use crate::kernel::interrupts;
+use core::fmt::Write;
+
+/// Capacity of the unsupported-CPU error message
+const MISSING_FEATURES_MESSAGE_LEN: usize = 192;
 
 /// Initialize CPU subsystem
 pub fn init() -> Result<(), &'static str> {
@@ -27,7 +31,10 @@ pub fn init() -> Result<(), &'static str> {
     
     // Check for required CPU features
     if !features::has_required_features() {
-        return Err("CPU does not support required features for OS Gaming");
+        for feature in features::missing_required_iter() {
+            log::error!("Required CPU feature missing: {}", feature.name());
+        }
+        return Err(missing_features_message());
     }
     
     // Initialize power management
@@ -39,6 +46,42 @@ pub fn init() -> Result<(), &'static str> {
     Ok(())
 }
 
+/// Fixed-size message buffer; CPU init runs before the heap is available
+struct MessageBuffer {
+    bytes: [u8; MISSING_FEATURES_MESSAGE_LEN],
+    len: usize,
+}
+
+impl Write for MessageBuffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(core::fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Build "CPU is missing required features: AVX, ..." without allocating
+fn missing_features_message() -> &'static str {
+    static mut MESSAGE: MessageBuffer = MessageBuffer { bytes: [0; MISSING_FEATURES_MESSAGE_LEN], len: 0 };
+
+    // Safety: only written here, once, during single-threaded early boot
+    let message = unsafe { &mut *core::ptr::addr_of_mut!(MESSAGE) };
+    message.len = 0;
+    let _ = message.write_str("CPU is missing required features: ");
+    for (i, feature) in features::missing_required_iter().enumerate() {
+        let separator = if i == 0 { "" } else { ", " };
+        if write!(message, "{}{}", separator, feature.name()).is_err() {
+            break;
+        }
+    }
+    // Only whole &str pieces are ever copied in, so the contents are valid UTF-8
+    unsafe { core::str::from_utf8_unchecked(&message.bytes[..message.len]) }
+}
+
 /// Configure gaming-optimized CPU settings
 pub fn configure_gaming_mode() -> Result<(), &'static str> {
     // Disable power saving features for maximum performance
@@ -1,7 +1,27 @@
 use crate::kernel::cpu::identification::get_cpu_info;
+use crate::kernel::drivers::power::CpuGovernor;
+use crate::kernel::drivers::timer;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
 use x86_64::instructions::port::Port;
 use x86_64::registers::model_specific::Msr;
 
+// MSRs used by the frequency governors
+const IA32_MPERF: u32 = 0xE7;               // Maximum performance frequency clock count
+const IA32_APERF: u32 = 0xE8;               // Actual performance frequency clock count
+const MSR_PLATFORM_INFO: u32 = 0xCE;        // Min/max non-turbo ratios
+const IA32_PERF_CTL: u32 = 0x199;           // Performance control
+const IA32_PM_ENABLE: u32 = 0x770;          // HWP enable
+const IA32_HWP_CAPABILITIES: u32 = 0x771;   // HWP performance range
+const IA32_HWP_REQUEST: u32 = 0x774;        // HWP request
+
+/// How often the ondemand governor samples utilization
+const ONDEMAND_SAMPLE_MS: u64 = 50;
+
+/// Lowest ratio assumed when the CPU can't report its P-state range
+const FALLBACK_LOWEST_RATIO: u8 = 8;
+
 /// Performance power profile for maximum performance
 pub struct PerformanceProfile {
     pub min_frequency: u64,
@@ -14,6 +34,7 @@ pub enum CpuFeature {
     HWP, // Hardware P-states
     TurboBoost,
     PowerLimit,   
+    AperfMperf, // APERF/MPERF frequency counters
 }
 
 impl Default for PerformanceProfile {
@@ -209,10 +230,26 @@ pub fn has_feature(feature: CpuFeature) -> bool {
             } else {
                 false
             }
+        },
+
+        CpuFeature::AperfMperf => {
+            // Hardware coordination feedback - CPUID.[EAX=06h]:ECX[bit 0]
+            if let Some(thermal) = cpuid.get_thermal_power_info() {
+                thermal.has_hw_coord_feedback()
+            } else {
+                false
+            }
         }
     }
 }
 
+/// HWP, MSR_PLATFORM_INFO and IA32_PERF_CTL are only defined on Intel parts
+fn is_intel() -> bool {
+    raw_cpuid::CpuId::new()
+        .get_vendor_info()
+        .map_or(false, |vendor| vendor.as_str() == "GenuineIntel")
+}
+
 /// Get the maximum CPU frequency
 pub fn get_max_frequency() -> u64 {
     // In a real implementation, this would read from CPUID
@@ -240,3 +277,326 @@ pub fn get_current_frequency() -> Option<u64> {
     // or ACPI tables to get the current frequency
     None
 }
+
+
+/// How P-states are requested from the hardware
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PStateControl {
+    /// Hardware P-states (Intel Speed Shift), driven through IA32_HWP_REQUEST
+    Hwp,
+    /// Legacy ratio writes to IA32_PERF_CTL
+    Legacy,
+}
+
+/// Range of P-states the governors may pick from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PStateRange {
+    pub control: PStateControl,
+    /// Lowest ratio / HWP performance level
+    pub lowest: u8,
+    /// Highest ratio / HWP performance level
+    pub highest: u8,
+}
+
+impl PStateRange {
+    /// Detect HWP vs legacy control through CPUID and read the available range
+    pub fn detect() -> Self {
+        if !is_intel() {
+            return Self::fallback(get_max_frequency());
+        }
+
+        // Safety: HWP is checked through CPUID and MSR_PLATFORM_INFO exists on every Intel part we run on
+        unsafe {
+            if has_feature(CpuFeature::HWP) {
+                let caps = Msr::new(IA32_HWP_CAPABILITIES).read();
+                Self {
+                    control: PStateControl::Hwp,
+                    highest: (caps & 0xFF) as u8,
+                    lowest: ((caps >> 24) & 0xFF) as u8,
+                }
+            } else {
+                let info = Msr::new(MSR_PLATFORM_INFO).read();
+                let highest = ((info >> 8) & 0xFF) as u8;
+                let lowest = ((info >> 40) & 0xFF) as u8;
+                Self {
+                    control: PStateControl::Legacy,
+                    highest,
+                    lowest: lowest.min(highest),
+                }
+            }
+        }
+    }
+
+    /// Fixed legacy range derived from the nominal maximum frequency
+    fn fallback(max_frequency: u64) -> Self {
+        let highest = (max_frequency / 100_000_000).min(u8::MAX as u64) as u8;
+        Self {
+            control: PStateControl::Legacy,
+            highest: highest.max(FALLBACK_LOWEST_RATIO),
+            lowest: FALLBACK_LOWEST_RATIO,
+        }
+    }
+}
+
+/// Utilization-driven P-state controller used by the ondemand governor
+#[derive(Debug, Clone, Copy)]
+pub struct OndemandController {
+    min_pstate: u8,
+    max_pstate: u8,
+    target: u8,
+    /// Utilization (percent) above which we jump to the highest P-state
+    up_threshold: u8,
+    /// Utilization (percent) under which we step down
+    down_threshold: u8,
+}
+
+impl OndemandController {
+    /// Create a controller scaling between `min_pstate` and `max_pstate`
+    pub fn new(min_pstate: u8, max_pstate: u8) -> Self {
+        Self {
+            min_pstate,
+            max_pstate: max_pstate.max(min_pstate),
+            target: min_pstate,
+            up_threshold: 80,
+            down_threshold: 30,
+        }
+    }
+
+    /// Feed a utilization sample (0-100) and get the new target P-state
+    pub fn sample(&mut self, utilization: u8) -> u8 {
+        let utilization = utilization.min(100);
+
+        if utilization >= self.up_threshold {
+            // Busy: go straight to the top like the Linux ondemand governor
+            self.target = self.max_pstate;
+        } else if utilization < self.down_threshold {
+            // Idle: step down gradually so short pauses don't tank the clock
+            let step = ((self.max_pstate - self.min_pstate) / 4).max(1);
+            self.target = self.target.saturating_sub(step).max(self.min_pstate);
+        } else {
+            // In between: scale proportionally, but never drop below what the load needs
+            let span = (self.max_pstate - self.min_pstate) as u32;
+            let wanted = self.min_pstate + (span * utilization as u32 / self.up_threshold as u32) as u8;
+            self.target = wanted.min(self.max_pstate).max(self.min_pstate);
+        }
+
+        self.target
+    }
+
+    /// Current target P-state
+    pub fn target(&self) -> u8 {
+        self.target
+    }
+}
+
+/// Governor state shared with the timer callback
+struct GovernorState {
+    governor: CpuGovernor,
+    range: Option<PStateRange>,
+    ondemand: Option<OndemandController>,
+    last_mperf: u64,
+    last_tsc: u64,
+}
+
+lazy_static! {
+    static ref GOVERNOR: Mutex<GovernorState> = Mutex::new(GovernorState {
+        governor: CpuGovernor::Ondemand,
+        range: None,
+        ondemand: None,
+        last_mperf: 0,
+        last_tsc: 0,
+    });
+}
+
+static ONDEMAND_TASK_SCHEDULED: AtomicBool = AtomicBool::new(false);
+
+/// Map a `PowerConfig.cpu_governor` name to a governor
+pub fn governor_from_name(name: &str) -> Option<CpuGovernor> {
+    match name {
+        "performance" => Some(CpuGovernor::Performance),
+        "powersave" => Some(CpuGovernor::Powersave),
+        "ondemand" => Some(CpuGovernor::Ondemand),
+        "conservative" => Some(CpuGovernor::Conservative),
+        "schedutil" => Some(CpuGovernor::Schedutil),
+        "userspace" => Some(CpuGovernor::UserSpace),
+        _ => None,
+    }
+}
+
+/// Switch the active frequency governor
+pub fn set_governor(governor: CpuGovernor) -> Result<(), &'static str> {
+    let mut state = GOVERNOR.lock();
+    let range = match state.range {
+        Some(range) => range,
+        None => {
+            let range = PStateRange::detect();
+            state.range = Some(range);
+            range
+        }
+    };
+
+    if range.highest == 0 {
+        return Err("P-state range not available");
+    }
+
+    state.governor = governor;
+    state.ondemand = None;
+
+    match governor {
+        CpuGovernor::Performance => write_pstate(&range, range.highest),
+        CpuGovernor::Powersave => write_pstate(&range, range.lowest),
+        // Load-based governors all share the ondemand control loop
+        CpuGovernor::Ondemand | CpuGovernor::Conservative | CpuGovernor::Schedutil => {
+            if !has_feature(CpuFeature::AperfMperf) {
+                return Err("Load-based governors need APERF/MPERF");
+            }
+            state.ondemand = Some(OndemandController::new(range.lowest, range.highest));
+            state.last_mperf = read_mperf().unwrap_or(0);
+            state.last_tsc = read_tsc();
+            drop(state);
+
+            if !ONDEMAND_TASK_SCHEDULED.swap(true, Ordering::SeqCst) {
+                if let Err(e) = timer::schedule_periodic_task("cpu_ondemand", ONDEMAND_SAMPLE_MS, ondemand_tick) {
+                    ONDEMAND_TASK_SCHEDULED.store(false, Ordering::SeqCst);
+                    return Err(e);
+                }
+            }
+            Ok(())
+        }
+        // Frequency is set manually
+        CpuGovernor::UserSpace => Ok(()),
+    }
+}
+
+/// Get the active frequency governor
+pub fn get_governor() -> CpuGovernor {
+    GOVERNOR.lock().governor
+}
+
+/// Timer callback driving the ondemand governor
+fn ondemand_tick() {
+    // Never block inside the timer interrupt
+    let mut state = match GOVERNOR.try_lock() {
+        Some(state) => state,
+        None => return,
+    };
+
+    let range = match state.range {
+        Some(range) => range,
+        None => return,
+    };
+
+    let mperf = match read_mperf() {
+        Some(mperf) => mperf,
+        None => return,
+    };
+    let tsc = read_tsc();
+    let delta_mperf = mperf.wrapping_sub(state.last_mperf);
+    let delta_tsc = tsc.wrapping_sub(state.last_tsc);
+    state.last_mperf = mperf;
+    state.last_tsc = tsc;
+
+    // MPERF only counts in C0 at the TSC rate, so its share of the TSC delta is the busy time
+    let utilization = if delta_tsc == 0 {
+        0
+    } else {
+        ((delta_mperf as u128 * 100) / delta_tsc as u128).min(100) as u8
+    };
+
+    if let Some(controller) = state.ondemand.as_mut() {
+        let previous = controller.target();
+        let target = controller.sample(utilization);
+        if target != previous {
+            let _ = write_pstate(&range, target);
+        }
+    }
+}
+
+/// Read the effective-to-nominal frequency ratio from APERF/MPERF (percent)
+pub fn read_effective_frequency_ratio() -> Option<u64> {
+    if !has_feature(CpuFeature::AperfMperf) {
+        return None;
+    }
+
+    // Safety: CPUID.06H:ECX[0] guarantees both counters exist
+    let (aperf, mperf) = unsafe { (Msr::new(IA32_APERF).read(), Msr::new(IA32_MPERF).read()) };
+    if mperf == 0 {
+        None
+    } else {
+        Some((aperf as u128 * 100 / mperf as u128) as u64)
+    }
+}
+
+/// Request a P-state from the hardware
+fn write_pstate(range: &PStateRange, pstate: u8) -> Result<(), &'static str> {
+    let pstate = pstate.max(range.lowest).min(range.highest);
+
+    // Safety: values stay within the range reported by the hardware
+    unsafe {
+        match range.control {
+            PStateControl::Hwp => {
+                Msr::new(IA32_PM_ENABLE).write(1);
+                // Pin min, max and desired to the same level
+                let request = (pstate as u64) | ((pstate as u64) << 8) | ((pstate as u64) << 16);
+                Msr::new(IA32_HWP_REQUEST).write(request);
+            }
+            PStateControl::Legacy => {
+                if !is_intel() {
+                    return Err("P-state control not supported on this CPU");
+                }
+                Msr::new(IA32_PERF_CTL).write((pstate as u64) << 8);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_mperf() -> Option<u64> {
+    if !has_feature(CpuFeature::AperfMperf) {
+        return None;
+    }
+
+    // Safety: IA32_MPERF is read-only and CPUID.06H:ECX[0] guarantees it exists
+    Some(unsafe { Msr::new(IA32_MPERF).read() })
+}
+
+fn read_tsc() -> u64 {
+    // Safety: RDTSC has no side effects
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_range_follows_nominal_frequency() {
+        let range = PStateRange::fallback(3_800_000_000);
+        assert_eq!(range.control, PStateControl::Legacy);
+        assert_eq!(range.lowest, FALLBACK_LOWEST_RATIO);
+        assert_eq!(range.highest, 38);
+
+        // Never report a range where highest is below lowest
+        let range = PStateRange::fallback(400_000_000);
+        assert_eq!(range.highest, FALLBACK_LOWEST_RATIO);
+    }
+
+    #[test]
+    fn ondemand_ramps_up_under_load_and_back_down_when_idle() {
+        let mut controller = OndemandController::new(8, 36);
+        assert_eq!(controller.target(), 8);
+
+        // Sustained load jumps straight to the top and stays there
+        for _ in 0..5 {
+            assert_eq!(controller.sample(95), 36);
+        }
+
+        // Idle steps down a quarter of the range per sample, then holds the floor
+        let targets: Vec<u8> = (0..6).map(|_| controller.sample(5)).collect();
+        assert_eq!(targets, [29, 22, 15, 8, 8, 8]);
+
+        // Moderate load asks for a proportional P-state: 8 + 28 * 40 / 80
+        assert_eq!(controller.sample(40), 22);
+    }
+}
@@ -0,0 +1,305 @@
+//! Persistent crash records
+//!
+//! The panic handler saves one record (message, location, registers and the
+//! tail of the kernel log) to the crash area: the first `CRASH_AREA_BYTES` of
+//! an MBR partition of type `CRASH_PARTITION_TYPE`. The filesystem lives in
+//! memory and the heap may be corrupt by then, so the record is built in a
+//! static buffer and written with a single device command, no retries, no
+//! queue and no allocation. A disk without such a partition gets no record;
+//! nothing is ever written outside it.
+//!
+//! At boot `recover` reserves the crash area, moves a record left by the
+//! previous boot into `/var/log/crash.log`, clears the area and remembers
+//! that the system recovered from a crash.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::string::String;
+use spin::Mutex;
+
+use crate::kernel::drivers::{self, filesystem, power, timer};
+use crate::kernel::drivers::storage::{Partition, StorageManager};
+
+/// Size of the reserved area, and the largest record
+pub const CRASH_AREA_BYTES: usize = 16 * 1024;
+/// MBR type of the partition holding the crash area. 0x7F is set aside for
+/// hobby and experimental systems, so no mainstream OS keeps data in one.
+pub const CRASH_PARTITION_TYPE: u8 = 0x7F;
+/// Where recovered records are appended
+pub const CRASH_LOG_PATH: &str = "/var/log/crash.log";
+
+const RECORD_MAGIC: [u8; 8] = *b"FGCRASH1";
+/// Magic, payload length, payload CRC32, uptime in ms
+const HEADER_BYTES: usize = 8 + 4 + 4 + 8;
+/// How much recent log output is kept for the record
+const LOG_TAIL_BYTES: usize = 4096;
+
+static RECOVERED: AtomicBool = AtomicBool::new(false);
+
+/// Last `LOG_TAIL_BYTES` of log output, oldest byte at `next` once it wrapped
+struct LogTail {
+    bytes: [u8; LOG_TAIL_BYTES],
+    next: usize,
+    wrapped: bool,
+}
+
+impl fmt::Write for LogTail {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.bytes[self.next] = byte;
+            self.next = (self.next + 1) % LOG_TAIL_BYTES;
+            if self.next == 0 {
+                self.wrapped = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+static LOG_TAIL: Mutex<LogTail> = Mutex::new(LogTail { bytes: [0; LOG_TAIL_BYTES], next: 0, wrapped: false });
+
+/// Keep a log line for the next crash record. Skipped if the tail is busy,
+/// so it is safe to call from any context.
+pub fn record_log(args: fmt::Arguments) {
+    if let Some(mut tail) = LOG_TAIL.try_lock() {
+        let _ = tail.write_fmt(args);
+    }
+}
+
+/// Builds a record in a fixed buffer, dropping whatever doesn't fit
+struct RecordWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl fmt::Write for RecordWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(self.buffer.len() - self.len);
+        self.buffer[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+impl RecordWriter<'_> {
+    /// Copy the log tail, oldest first, starting at a line boundary once it wrapped
+    fn write_log_tail(&mut self, tail: &LogTail) {
+        let (older, newer) = if tail.wrapped {
+            (&tail.bytes[tail.next..], &tail.bytes[..tail.next])
+        } else {
+            (&tail.bytes[..0], &tail.bytes[..tail.next])
+        };
+        let skip = if tail.wrapped {
+            older.iter().position(|&b| b == b'\n').map_or(older.len(), |i| i + 1)
+        } else {
+            0
+        };
+        for part in [&older[skip..], newer] {
+            let count = part.len().min(self.buffer.len() - self.len);
+            self.buffer[self.len..self.len + count].copy_from_slice(&part[..count]);
+            self.len += count;
+        }
+    }
+}
+
+static mut RECORD: [u8; CRASH_AREA_BYTES] = [0; CRASH_AREA_BYTES];
+
+/// Sectors set aside for crash records
+struct CrashArea {
+    device: String,
+    start: u64,
+    sectors: u32,
+}
+
+/// None until `recover` finds a crash partition
+static CRASH_AREA: Mutex<Option<CrashArea>> = Mutex::new(None);
+
+/// First sector and length in sectors of the crash area inside the first
+/// partition of `CRASH_PARTITION_TYPE` large enough to hold it
+fn find_crash_area(partitions: &[Partition], sector_size: u32) -> Option<(u64, u32)> {
+    if sector_size == 0 || CRASH_AREA_BYTES % sector_size as usize != 0 {
+        return None;
+    }
+    let sectors = (CRASH_AREA_BYTES / sector_size as usize) as u64;
+    partitions
+        .iter()
+        .find(|partition| {
+            partition.get_partition_type() == CRASH_PARTITION_TYPE && partition.get_sector_count() >= sectors
+        })
+        .map(|partition| (partition.get_start_sector(), sectors as u32))
+}
+
+/// Look for a crash partition on every storage device, first match wins
+fn reserve_area(storage: &StorageManager) {
+    for device in storage.get_devices() {
+        let partitions = match storage.scan_partitions(device.get_name()) {
+            Ok(partitions) => partitions,
+            Err(_) => continue,
+        };
+        if let Some((start, sectors)) = find_crash_area(&partitions, device.get_sector_size()) {
+            log::info!("Crash records go to {} sectors {}..{}", device.get_name(), start, start + sectors as u64);
+            *CRASH_AREA.lock() = Some(CrashArea { device: String::from(device.get_name()), start, sectors });
+            return;
+        }
+    }
+    log::warn!("No crash partition (MBR type {:#x}); kernel panics won't be recorded", CRASH_PARTITION_TYPE);
+}
+
+/// Save a crash record for `info`. Must only run once, from the panic handler
+/// with interrupts off.
+pub(crate) fn save_panic(info: &PanicInfo, registers: &dyn fmt::Debug) -> Result<(), &'static str> {
+    let uptime_ms = timer::timestamp_us_lockless() / 1000;
+
+    // Safety: the panic handler runs this at most once, on the only running CPU
+    let record = unsafe { &mut *core::ptr::addr_of_mut!(RECORD) };
+    record.fill(0);
+    let (header, payload) = record.split_at_mut(HEADER_BYTES);
+
+    let mut writer = RecordWriter { buffer: payload, len: 0 };
+    let _ = writeln!(writer, "Kernel panic at {} ms uptime", uptime_ms);
+    let _ = writeln!(writer, "{}", info);
+    let _ = writeln!(writer, "Registers: {:x?}", registers);
+    let _ = writeln!(writer, "Recent log:");
+    if LOG_TAIL.is_locked() {
+        // Safety: whoever held it was interrupted by this panic and won't resume
+        unsafe { LOG_TAIL.force_unlock() };
+    }
+    writer.write_log_tail(&LOG_TAIL.lock());
+    let len = writer.len;
+
+    header[0..8].copy_from_slice(&RECORD_MAGIC);
+    header[8..12].copy_from_slice(&(len as u32).to_le_bytes());
+    header[12..16].copy_from_slice(&power::crc32(&payload[..len]).to_le_bytes());
+    header[16..24].copy_from_slice(&uptime_ms.to_le_bytes());
+
+    if CRASH_AREA.is_locked() {
+        // Safety: as above
+        unsafe { CRASH_AREA.force_unlock() };
+    }
+    let area = CRASH_AREA.lock();
+    let area = area.as_ref().ok_or("No crash partition")?;
+
+    let manager = drivers::get_driver_manager();
+    if manager.is_locked() {
+        // Safety: as above
+        unsafe { manager.force_unlock() };
+    }
+    let manager = manager.lock();
+    let device = manager
+        .as_ref()
+        .and_then(|manager| manager.storage_manager.get_device(&area.device))
+        .ok_or("Crash partition's device is gone")?;
+    let sector_size = device.get_sector_size() as usize;
+    let count = (HEADER_BYTES + len + sector_size - 1) / sector_size;
+    if count > area.sectors as usize {
+        return Err("Crash record larger than the crash area");
+    }
+    device.write_sectors_unbuffered(area.start, count as u32, &record[..count * sector_size])?;
+    device.flush_cache_unbuffered()
+}
+
+/// Reserve the crash area, then move a crash record left by the previous
+/// boot into `CRASH_LOG_PATH`. Runs once the drivers and the filesystem are up.
+pub fn recover() {
+    let manager = drivers::get_driver_manager().lock();
+    let storage = match manager.as_ref() {
+        Some(manager) => &manager.storage_manager,
+        None => return,
+    };
+    reserve_area(storage);
+    let (device, start, sectors) = match CRASH_AREA.lock().as_ref() {
+        Some(area) => match storage.get_device(&area.device) {
+            Some(device) => (device, area.start, area.sectors),
+            None => return,
+        },
+        None => return,
+    };
+
+    let mut area = alloc::vec![0u8; sectors as usize * device.get_sector_size() as usize];
+    if let Err(e) = device.read_sectors(start, sectors, &mut area) {
+        log::warn!("Could not read the crash record area: {}", e);
+        return;
+    }
+    if area[0..8] != RECORD_MAGIC {
+        return;
+    }
+    let len = u32::from_le_bytes([area[8], area[9], area[10], area[11]]) as usize;
+    let crc = u32::from_le_bytes([area[12], area[13], area[14], area[15]]);
+    let payload = &area[HEADER_BYTES..];
+    let valid = len <= payload.len() && power::crc32(&payload[..len]) == crc;
+
+    if valid {
+        RECOVERED.store(true, Ordering::SeqCst);
+        let first_line = payload[..len].split(|&b| b == b'\n').nth(1).unwrap_or(&[]);
+        log::warn!(
+            "The system recovered from a crash: {}",
+            core::str::from_utf8(first_line).unwrap_or("<unreadable>")
+        );
+        if let Err(e) = append_to_log(&payload[..len]) {
+            log::warn!("Crash record not saved to {}: {}", CRASH_LOG_PATH, e);
+        }
+    } else {
+        log::warn!("Discarding a corrupt crash record");
+    }
+
+    // Clear the magic so the record is reported only once
+    let sector_size = device.get_sector_size() as usize;
+    let cleared = alloc::vec![0u8; sector_size];
+    if let Err(e) = device.write_sectors(start, 1, &cleared) {
+        log::warn!("Could not clear the crash record: {}", e);
+    }
+}
+
+fn append_to_log(record: &[u8]) -> Result<(), &'static str> {
+    let mut fs_manager = filesystem::get_fs_manager().lock();
+    // Either may already exist
+    let _ = fs_manager.create_directory("/var");
+    let _ = fs_manager.create_directory("/var/log");
+    if fs_manager.open_file(CRASH_LOG_PATH, true).is_err() {
+        fs_manager.create_file(CRASH_LOG_PATH)?;
+    }
+
+    let mut file = fs_manager.open_file(CRASH_LOG_PATH, false)?;
+    file.seek(file.get_size())?;
+    for part in [record, &b"\n"[..]] {
+        let mut written = 0;
+        while written < part.len() {
+            let count = file.write(&part[written..], &fs_manager)?;
+            if count == 0 {
+                return Err("Failed to write crash log");
+            }
+            written += count;
+        }
+    }
+    file.close(&fs_manager)
+}
+
+/// Whether this boot found a crash record from the previous one
+pub fn recovered_from_crash() -> bool {
+    RECOVERED.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partition(start: u64, sectors: u64, partition_type: u8) -> Partition {
+        Partition::new(String::from("sda"), start, sectors, partition_type, false)
+    }
+
+    #[test]
+    fn crash_area_is_inside_a_crash_partition() {
+        let partitions = [partition(2048, 1_000_000, 0x83), partition(1_002_048, 64, CRASH_PARTITION_TYPE)];
+        assert_eq!(find_crash_area(&partitions, 512), Some((1_002_048, 32)));
+        assert_eq!(find_crash_area(&partitions, 4096), Some((1_002_048, 4)));
+    }
+
+    #[test]
+    fn no_crash_area_without_a_big_enough_crash_partition() {
+        assert_eq!(find_crash_area(&[partition(2048, 1_000_000, 0x83)], 512), None);
+        assert_eq!(find_crash_area(&[partition(2048, 31, CRASH_PARTITION_TYPE)], 512), None);
+        assert_eq!(find_crash_area(&[partition(2048, 64, CRASH_PARTITION_TYPE)], 0), None);
+    }
+}
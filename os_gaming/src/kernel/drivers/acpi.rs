@@ -0,0 +1,292 @@
+//! ACPI fixed events: power button, sleep button and lid switch
+//!
+//! There is no AML interpreter, so only what the FADT describes directly is
+//! used. The PM1 event block's status register latches a power or sleep
+//! button press whether or not the matching enable bit is set; the enable bit
+//! only routes the event to the SCI. No SCI handler is installed, so the
+//! status register is polled from the timer with the enable bits left clear.
+//!
+//! The lid switch is a control-method device whose GPE is only known from
+//! AML. Platform code that can detect it reports lid changes with
+//! `notify_lid`.
+//!
+//! The timer callback only publishes bus events; the configured action runs
+//! from the main loop when the event bus delivers them.
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+use crate::config::PowerConfig;
+use crate::events::{self, Event, EventKind};
+use crate::kernel::drivers::{power, timer};
+use crate::kernel::memory::memory_manager::get_physical_memory_offset;
+
+/// How often the PM1 status register is checked
+const POLL_INTERVAL_MS: u64 = 100;
+/// How long the firmware gets to hand over to ACPI mode
+const ACPI_ENABLE_TIMEOUT_MS: u64 = 300;
+
+/// PM1 status/enable bits for the fixed buttons
+pub const PM1_PWRBTN: u16 = 1 << 8;
+pub const PM1_SLPBTN: u16 = 1 << 9;
+/// SCI_EN in PM1 control: set once the firmware has switched to ACPI mode
+const PM1_CNT_SCI_EN: u16 = 1 << 0;
+
+// Where the BIOS leaves the RSDP
+const EBDA_SEGMENT_PTR: u64 = 0x40E;
+const BIOS_AREA_START: u64 = 0xE0000;
+const BIOS_AREA_END: u64 = 0x100000;
+
+// FADT field offsets
+const FADT_SMI_CMD: usize = 48;
+const FADT_ACPI_ENABLE: usize = 52;
+const FADT_PM1A_EVT_BLK: usize = 56;
+const FADT_PM1A_CNT_BLK: usize = 64;
+const FADT_PM1_EVT_LEN: usize = 88;
+
+/// What a button press or lid close does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PowerAction {
+    Ignore = 0,
+    Shutdown = 1,
+    Suspend = 2,
+    Hibernate = 3,
+}
+
+impl PowerAction {
+    /// Parse the config spelling: `ignore`, `shutdown`, `suspend` or `hibernate`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ignore" => Some(PowerAction::Ignore),
+            "shutdown" => Some(PowerAction::Shutdown),
+            "suspend" => Some(PowerAction::Suspend),
+            "hibernate" => Some(PowerAction::Hibernate),
+            _ => None,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => PowerAction::Shutdown,
+            2 => PowerAction::Suspend,
+            3 => PowerAction::Hibernate,
+            _ => PowerAction::Ignore,
+        }
+    }
+
+    /// Carry out the action. Must not run in interrupt context.
+    pub fn perform(self) -> Result<(), &'static str> {
+        match self {
+            PowerAction::Ignore => Ok(()),
+            PowerAction::Shutdown => power::shutdown(),
+            PowerAction::Suspend => power::enter_sleep_mode(),
+            PowerAction::Hibernate => power::hibernate(),
+        }
+    }
+}
+
+static POWER_BUTTON_ACTION: AtomicU8 = AtomicU8::new(PowerAction::Shutdown as u8);
+static LID_CLOSE_ACTION: AtomicU8 = AtomicU8::new(PowerAction::Suspend as u8);
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// The PM1 registers found in the FADT
+#[derive(Debug, Clone, Copy)]
+pub struct Pm1Registers {
+    pub event_block: u16,
+    pub event_len: u8,
+    pub control_block: u16,
+    pub smi_command: u16,
+    pub acpi_enable: u8,
+}
+
+impl Pm1Registers {
+    fn status_port(&self) -> Port<u16> {
+        Port::new(self.event_block)
+    }
+
+    /// The enable register is the second half of the event block
+    fn enable_port(&self) -> Port<u16> {
+        Port::new(self.event_block + self.event_len as u16 / 2)
+    }
+}
+
+static PM1: Mutex<Option<Pm1Registers>> = Mutex::new(None);
+
+/// Set the actions from the power section of the config
+pub fn configure(config: &PowerConfig) {
+    let set = |slot: &AtomicU8, name: &str, what: &str| match PowerAction::from_name(name) {
+        Some(action) => slot.store(action as u8, Ordering::SeqCst),
+        None if name.is_empty() => {}
+        None => log::warn!("Unknown {} action '{}' in config", what, name),
+    };
+    set(&POWER_BUTTON_ACTION, &config.power_button_action, "power button");
+    set(&LID_CLOSE_ACTION, &config.lid_close_action, "lid close");
+}
+
+pub fn power_button_action() -> PowerAction {
+    PowerAction::from_u8(POWER_BUTTON_ACTION.load(Ordering::SeqCst))
+}
+
+pub fn lid_close_action() -> PowerAction {
+    PowerAction::from_u8(LID_CLOSE_ACTION.load(Ordering::SeqCst))
+}
+
+/// Find the PM1 registers, switch the firmware to ACPI mode and start polling
+/// for button presses. Lid events work without this.
+pub fn init() -> Result<(), &'static str> {
+    if INITIALIZED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    events::subscribe(EventKind::PowerButton, on_power_event)?;
+    events::subscribe(EventKind::SleepButton, on_power_event)?;
+    events::subscribe(EventKind::LidSwitch, on_power_event)?;
+
+    let pm1 = find_pm1_registers().ok_or("No FADT with a PM1 event block")?;
+    enable_acpi_mode(&pm1);
+
+    // Safety: the ports come from the FADT; clearing the enable bits keeps
+    // the button events off the SCI, and writing 1 clears stale status
+    unsafe {
+        let enable = pm1.enable_port().read();
+        pm1.enable_port().write(enable & !(PM1_PWRBTN | PM1_SLPBTN));
+        pm1.status_port().write(PM1_PWRBTN | PM1_SLPBTN);
+    }
+    *PM1.lock() = Some(pm1);
+
+    timer::schedule_periodic_task("acpi-pm1", POLL_INTERVAL_MS, poll_pm1)?;
+    log::info!("ACPI fixed events enabled (PM1 event block at {:#x})", pm1.event_block);
+    Ok(())
+}
+
+/// Report a lid switch change, e.g. from a platform-specific GPE handler
+pub fn notify_lid(closed: bool) {
+    events::publish(Event::LidSwitch { closed });
+}
+
+/// Events for the button bits set in a PM1 status value
+pub fn events_for_status(status: u16) -> impl Iterator<Item = Event> {
+    let power = (status & PM1_PWRBTN != 0).then_some(Event::PowerButton);
+    let sleep = (status & PM1_SLPBTN != 0).then_some(Event::SleepButton);
+    power.into_iter().chain(sleep)
+}
+
+/// Timer callback: runs in interrupt context, so it only reads, clears and publishes
+fn poll_pm1() {
+    let pm1 = match PM1.try_lock() {
+        Some(guard) => match *guard {
+            Some(pm1) => pm1,
+            None => return,
+        },
+        None => return,
+    };
+
+    // Safety: the status port came from the FADT; writing 1 clears a bit
+    let status = unsafe { pm1.status_port().read() } & (PM1_PWRBTN | PM1_SLPBTN);
+    if status == 0 {
+        return;
+    }
+    unsafe { pm1.status_port().write(status) };
+
+    for event in events_for_status(status) {
+        events::publish(event);
+    }
+}
+
+fn on_power_event(event: &Event) {
+    let action = match event {
+        Event::PowerButton => power_button_action(),
+        Event::SleepButton => PowerAction::Suspend,
+        Event::LidSwitch { closed: true } => lid_close_action(),
+        _ => return,
+    };
+    log::info!("{:?}: {:?}", event.kind(), action);
+    if let Err(e) = action.perform() {
+        log::error!("{:?} failed: {}", action, e);
+    }
+}
+
+/// Ask the firmware to hand power events over to the OS
+fn enable_acpi_mode(pm1: &Pm1Registers) {
+    let mut control: Port<u16> = Port::new(pm1.control_block);
+    // Safety: the ports come from the FADT
+    unsafe {
+        if control.read() & PM1_CNT_SCI_EN != 0 || pm1.smi_command == 0 || pm1.acpi_enable == 0 {
+            return;
+        }
+        Port::<u8>::new(pm1.smi_command).write(pm1.acpi_enable);
+    }
+
+    let deadline = timer::uptime_ms() + ACPI_ENABLE_TIMEOUT_MS;
+    while unsafe { control.read() } & PM1_CNT_SCI_EN == 0 {
+        if timer::uptime_ms() >= deadline {
+            log::warn!("Firmware did not switch to ACPI mode");
+            return;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Read a value from physical memory through the physical memory window
+///
+/// # Safety
+/// The address must be RAM or firmware tables covered by that window.
+unsafe fn read_phys<T: Copy>(addr: u64) -> T {
+    let virt = get_physical_memory_offset() + addr;
+    core::ptr::read_unaligned(virt.as_ptr::<T>())
+}
+
+unsafe fn checksum_ok(addr: u64, len: usize) -> bool {
+    (0..len as u64).fold(0u8, |sum, i| sum.wrapping_add(read_phys::<u8>(addr + i))) == 0
+}
+
+/// Physical address of the RSDP, searched where the BIOS puts it
+fn find_rsdp() -> Option<u64> {
+    // Safety: the EBDA pointer and the BIOS area are always mapped low memory
+    unsafe {
+        let ebda = (read_phys::<u16>(EBDA_SEGMENT_PTR) as u64) << 4;
+        let ranges = [(ebda, ebda + 1024), (BIOS_AREA_START, BIOS_AREA_END)];
+        ranges
+            .iter()
+            .filter(|(start, _)| *start != 0)
+            .flat_map(|&(start, end)| (start..end).step_by(16))
+            .find(|&addr| read_phys::<[u8; 8]>(addr) == *b"RSD PTR " && checksum_ok(addr, 20))
+    }
+}
+
+fn find_pm1_registers() -> Option<Pm1Registers> {
+    let rsdp = find_rsdp()?;
+    // Safety: the tables were located through checksummed firmware pointers
+    unsafe {
+        let revision = read_phys::<u8>(rsdp + 15);
+        let (root, entry_size) = if revision >= 2 && read_phys::<u64>(rsdp + 24) != 0 {
+            (read_phys::<u64>(rsdp + 24), 8)
+        } else {
+            (read_phys::<u32>(rsdp + 16) as u64, 4)
+        };
+
+        let length = read_phys::<u32>(root + 4) as u64;
+        let entries = length.saturating_sub(36) / entry_size;
+        let fadt = (0..entries)
+            .map(|i| {
+                let entry = root + 36 + i * entry_size;
+                if entry_size == 8 { read_phys::<u64>(entry) } else { read_phys::<u32>(entry) as u64 }
+            })
+            .find(|&table| read_phys::<[u8; 4]>(table) == *b"FACP")?;
+
+        let event_block = read_phys::<u32>(fadt + FADT_PM1A_EVT_BLK as u64);
+        let event_len = read_phys::<u8>(fadt + FADT_PM1_EVT_LEN as u64);
+        if event_block == 0 || event_block > u16::MAX as u32 || event_len < 4 {
+            return None;
+        }
+        Some(Pm1Registers {
+            event_block: event_block as u16,
+            event_len,
+            control_block: read_phys::<u32>(fadt + FADT_PM1A_CNT_BLK as u64) as u16,
+            smi_command: read_phys::<u32>(fadt + FADT_SMI_CMD as u64) as u16,
+            acpi_enable: read_phys::<u8>(fadt + FADT_ACPI_ENABLE as u64),
+        })
+    }
+}
@@ -0,0 +1,248 @@
+//! DisplayPort/HDMI hotplug detection
+//!
+//! Each connector's hot-plug-detect (HPD) line is sampled from the main loop,
+//! either from a status register the GPU-specific code registers with
+//! `set_hpd_register`, or from the level last reported by a GPE handler
+//! through `notify_hpd`. A level must hold for a few polls before it counts,
+//! which filters out the short HPD pulses DisplayPort sinks use for IRQs.
+//!
+//! On connect the monitor's EDID is re-read and the driver's mode list
+//! refreshed; on disconnect the mode list is dropped. Both publish a bus
+//! event so the GUI can re-evaluate the resolution. Losing the active output
+//! switches to another connected output, or to a safe mode on the boot
+//! framebuffer when there is none.
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use spin::Mutex;
+
+use super::displayport::DP_DRIVER;
+use super::gpu;
+use super::hdmi::{HdmiResolution, HDMI_DRIVER};
+use crate::events::{self, Event};
+use crate::kernel::memory::memory_manager::get_physical_memory_offset;
+use crate::runtime::{self, TaskStatus};
+
+/// How often HPD is sampled
+const POLL_INTERVAL_MS: u64 = 200;
+/// Consecutive samples a new level must hold before it is believed
+const DEBOUNCE_POLLS: u8 = 2;
+/// Used when no output is left to fall back to
+const SAFE_MODE: (u32, u32, u32) = (1024, 768, 60);
+
+/// A display output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    DisplayPort,
+    Hdmi,
+}
+
+impl Connector {
+    pub const ALL: [Connector; 2] = [Connector::DisplayPort, Connector::Hdmi];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A memory-mapped register with the connector's HPD bit
+#[derive(Debug, Clone, Copy)]
+pub struct HpdRegister {
+    /// Physical address of the 32-bit register
+    pub address: u64,
+    /// Bit set while a sink is attached
+    pub mask: u32,
+}
+
+impl HpdRegister {
+    fn asserted(&self) -> bool {
+        let virt = get_physical_memory_offset() + self.address;
+        // Safety: registered by GPU code for a mapped MMIO register
+        unsafe { core::ptr::read_volatile(virt.as_ptr::<u32>()) & self.mask != 0 }
+    }
+}
+
+/// Debounces an HPD level into connect and disconnect transitions
+#[derive(Debug, Clone, Copy)]
+pub struct HotplugDetector {
+    connected: bool,
+    /// Level that differs from `connected`, and for how many polls it has held
+    candidate: bool,
+    held: u8,
+}
+
+impl HotplugDetector {
+    pub const fn new(connected: bool) -> Self {
+        Self { connected, candidate: connected, held: 0 }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Feed one HPD sample. Returns the new state when it changes.
+    pub fn update(&mut self, asserted: bool) -> Option<bool> {
+        if asserted == self.connected {
+            self.held = 0;
+            return None;
+        }
+        if asserted != self.candidate {
+            self.candidate = asserted;
+            self.held = 0;
+        }
+        self.held += 1;
+        if self.held < DEBOUNCE_POLLS {
+            return None;
+        }
+        self.connected = asserted;
+        self.held = 0;
+        Some(asserted)
+    }
+}
+
+// HPD levels reported by GPE handlers: 0 = unknown, 1 = low, 2 = high
+const LEVEL_UNKNOWN: u8 = 0;
+const LEVEL_LOW: u8 = 1;
+const LEVEL_HIGH: u8 = 2;
+
+static REPORTED_LEVEL: [AtomicU8; 2] = [AtomicU8::new(LEVEL_UNKNOWN), AtomicU8::new(LEVEL_UNKNOWN)];
+static HPD_REGISTERS: Mutex<[Option<HpdRegister>; 2]> = Mutex::new([None, None]);
+static DETECTORS: Mutex<[HotplugDetector; 2]> = Mutex::new([HotplugDetector::new(false), HotplugDetector::new(false)]);
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Sample `connector`'s HPD from this register from now on
+pub fn set_hpd_register(connector: Connector, register: HpdRegister) {
+    HPD_REGISTERS.lock()[connector.index()] = Some(register);
+}
+
+/// Report an HPD level change, e.g. from a GPE handler. Safe in interrupt context.
+pub fn notify_hpd(connector: Connector, asserted: bool) {
+    let level = if asserted { LEVEL_HIGH } else { LEVEL_LOW };
+    REPORTED_LEVEL[connector.index()].store(level, Ordering::SeqCst);
+}
+
+/// Current HPD level, or None when nothing reports it
+fn hpd_level(connector: Connector) -> Option<bool> {
+    if let Some(register) = HPD_REGISTERS.lock()[connector.index()] {
+        return Some(register.asserted());
+    }
+    match REPORTED_LEVEL[connector.index()].load(Ordering::SeqCst) {
+        LEVEL_LOW => Some(false),
+        LEVEL_HIGH => Some(true),
+        _ => None,
+    }
+}
+
+pub fn is_connected(connector: Connector) -> bool {
+    DETECTORS.lock()[connector.index()].is_connected()
+}
+
+/// Start watching the connectors. Outputs already lit at boot count as connected.
+pub fn init() -> Result<(), &'static str> {
+    if INITIALIZED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    {
+        let mut detectors = DETECTORS.lock();
+        detectors[Connector::DisplayPort.index()] = HotplugDetector::new(DP_DRIVER.lock().is_active());
+        detectors[Connector::Hdmi.index()] = HotplugDetector::new(HDMI_DRIVER.lock().is_active());
+    }
+    runtime::spawn_periodic("display-hotplug", POLL_INTERVAL_MS, || {
+        poll();
+        TaskStatus::Continue
+    });
+    Ok(())
+}
+
+/// Sample every connector and handle transitions
+pub fn poll() {
+    for connector in Connector::ALL {
+        let level = match hpd_level(connector) {
+            Some(level) => level,
+            None => continue,
+        };
+        let change = DETECTORS.lock()[connector.index()].update(level);
+        match change {
+            Some(true) => on_connect(connector),
+            Some(false) => on_disconnect(connector),
+            None => {}
+        }
+    }
+}
+
+fn on_connect(connector: Connector) {
+    let preferred = match connector {
+        Connector::DisplayPort => DP_DRIVER.lock().handle_connect(),
+        Connector::Hdmi => HDMI_DRIVER.lock().handle_connect(),
+    };
+    let mode = match preferred {
+        Ok(mode) => mode,
+        Err(e) => {
+            log::warn!("{:?} connected, but its EDID is unusable: {}", connector, e);
+            return;
+        }
+    };
+    log::info!("{:?} connected: {}x{}@{}Hz preferred", connector, mode.width, mode.height, mode.refresh_rate);
+    events::publish(Event::DisplayConnected {
+        connector,
+        width: mode.width,
+        height: mode.height,
+        refresh_rate: mode.refresh_rate,
+    });
+}
+
+fn on_disconnect(connector: Connector) {
+    let was_active = match connector {
+        Connector::DisplayPort => {
+            let mut driver = DP_DRIVER.lock();
+            let active = driver.is_active();
+            driver.handle_disconnect();
+            active
+        }
+        Connector::Hdmi => {
+            let mut driver = HDMI_DRIVER.lock();
+            let active = driver.is_active();
+            driver.handle_disconnect();
+            active
+        }
+    };
+    log::info!("{:?} disconnected", connector);
+    events::publish(Event::DisplayDisconnected { connector });
+
+    if was_active {
+        fall_back_from(connector);
+    }
+}
+
+/// The active output went away: light another connected output, else a safe mode
+fn fall_back_from(lost: Connector) {
+    for other in Connector::ALL.iter().copied().filter(|&c| c != lost && is_connected(c)) {
+        let result = match other {
+            Connector::DisplayPort => {
+                let mut driver = DP_DRIVER.lock();
+                preferred_mode(driver.available_modes()).map(|mode| driver.init_with_resolution(mode))
+            }
+            Connector::Hdmi => {
+                let mut driver = HDMI_DRIVER.lock();
+                preferred_mode(driver.available_modes()).map(|mode| driver.init_with_resolution(mode))
+            }
+        };
+        match result {
+            Some(Ok(())) => {
+                log::info!("Switched output from {:?} to {:?}", lost, other);
+                return;
+            }
+            Some(Err(e)) => log::warn!("Could not switch output to {:?}: {}", other, e),
+            None => {}
+        }
+    }
+
+    let (width, height, refresh_rate) = SAFE_MODE;
+    match gpu::best_mode_for(width, height, refresh_rate).and_then(gpu::set_display_mode) {
+        Ok(()) => log::warn!("{:?} was the active output; fell back to a safe mode", lost),
+        Err(e) => log::error!("No display output left after {:?} was unplugged: {:?}", lost, e),
+    }
+}
+
+fn preferred_mode(modes: &[HdmiResolution]) -> Option<HdmiResolution> {
+    modes.first().copied()
+}
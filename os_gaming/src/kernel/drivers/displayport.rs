@@ -67,6 +67,10 @@ pub struct DisplayPortDriver {
     adaptive_sync_enabled: bool,
     mst_config: Option<MstConfig>,
     gpu_info: Option<GpuInfo>,
+    /// Whether a monitor is plugged in, as last reported by hotplug detection
+    connected: bool,
+    /// Modes from the connected monitor's EDID, preferred mode first
+    available_modes: Vec<DisplayPortResolution>,
 }
 
 /// DisplayPort monitor capabilities
@@ -106,6 +110,8 @@ impl DisplayPortDriver {
             adaptive_sync_enabled: false,
             mst_config: None,
             gpu_info: None,
+            connected: false,
+            available_modes: Vec::new(),
         }
     }
 
@@ -592,6 +598,46 @@ impl DisplayPortDriver {
         self.current_resolution
     }
 
+    /// Whether this output is driving a display
+    pub fn is_active(&self) -> bool {
+        self.initialized.load(Ordering::SeqCst)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Modes the connected monitor supports, preferred first
+    pub fn available_modes(&self) -> &[DisplayPortResolution] {
+        &self.available_modes
+    }
+
+    /// A monitor was plugged in: re-read its EDID and refresh the mode list.
+    /// Returns the preferred mode.
+    pub fn handle_connect(&mut self) -> Result<DisplayPortResolution, &'static str> {
+        self.connected = true;
+        // EDID arrives over the AUX channel's I2C-over-AUX, read like HDMI's DDC
+        let edid = super::hdmi::HDMI_DRIVER.lock().read_edid()?;
+        self.available_modes = super::hdmi::edid_modes(&edid)?;
+        self.available_modes.first().copied().ok_or("EDID lists no usable modes")
+    }
+
+    /// The monitor was unplugged: forget its modes and stop driving the output
+    pub fn handle_disconnect(&mut self) {
+        self.connected = false;
+        self.available_modes.clear();
+        if self.initialized.swap(false, Ordering::SeqCst) {
+            if let Some(fb) = self.framebuffer.take() {
+                // Safety: allocated by allocate_framebuffer with this capacity
+                unsafe {
+                    let _ = Vec::from_raw_parts(fb, 0, self.framebuffer_size);
+                }
+            }
+            self.framebuffer_size = 0;
+            self.current_resolution = None;
+        }
+    }
+
     /// Check if the monitor meets gaming requirements
     pub fn meets_gaming_requirements(&self, requirements: &GamingRequirements) -> bool {
         // Delegate to the HDMI driver's implementation since the requirements are the same
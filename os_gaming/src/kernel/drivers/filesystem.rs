@@ -1,18 +1,25 @@
 extern crate alloc;
-use crate::kernel::drivers::storage::{StorageDevice, StorageManager};
+use crate::kernel::drivers::storage::{StorageDevice, StorageDeviceType, StorageManager};
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use crate::alloc::string::ToString;
 use alloc::vec::Vec;
 use alloc::vec;
 use alloc::format;
-use core::cell::UnsafeCell;
 use core::ptr;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 
 use super::storage::Partition;
+use super::timer;
+
+/// How often unsynced writes are flushed when `sync_immediately` is off
+const WRITEBACK_INTERVAL_MS: u64 = 5000;
+
+/// Flush to the device after every write (`StorageConfig::sync_immediately`)
+static SYNC_IMMEDIATELY: AtomicBool = AtomicBool::new(false);
+static LAST_WRITEBACK_MS: AtomicU64 = AtomicU64::new(0);
 
 /// Filesystem types
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -37,6 +44,17 @@ pub enum FileSeekMode {
     End,
 }
 
+/// Seek target for `FileHandle::seek_from`, with the same meaning as `std::io::SeekFrom`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    /// Absolute offset from the start of the file
+    Start(u64),
+    /// Offset relative to the current position
+    Current(i64),
+    /// Offset relative to the end of the file
+    End(i64),
+}
+
 /// File types
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FileType {
@@ -69,7 +87,7 @@ pub struct FileEntry {
 }
 
 /// Inode structure for RAM filesystem
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct RamInode {
     file_type: FileType,
     size: u64,
@@ -78,14 +96,14 @@ struct RamInode {
     attributes: FileAttributes,
     creation_time: u64,
     modification_time: u64,
-    access_time: u64,
+    /// Updated by reads, which only borrow the filesystem
+    access_time: AtomicU64,
 }
 
 /// RAM filesystem implementation
 struct RamFilesystem {
     inodes: Vec<RamInode>,
     root_inode: u64,
-    next_inode_id: u64,
 }
 
 /// Filesystem structure
@@ -95,8 +113,12 @@ pub struct Filesystem {
     device: String,
     mounted: AtomicBool,
     readonly: bool,
-    ram_fs: Option<RamFilesystem>, // RAM filesystem data (only used for RamFs type)
+    /// RAM filesystem data (only used for RamFs type). Locked so file
+    /// handles can write through a shared borrow of the filesystem.
+    ram_fs: Option<Mutex<RamFilesystem>>,
     root_dir: Option<DirectoryHandle>,
+    /// Writes made since the last flush to the backing device
+    dirty: AtomicBool,
 }
 
 /// Directory handle
@@ -173,7 +195,7 @@ impl RamInode {
             },
             creation_time: get_current_time(),
             modification_time: get_current_time(),
-            access_time: get_current_time(),
+            access_time: AtomicU64::new(get_current_time()),
         }
     }
 
@@ -186,7 +208,7 @@ impl RamInode {
             attributes: FileAttributes::new(),
             creation_time: get_current_time(),
             modification_time: get_current_time(),
-            access_time: get_current_time(),
+            access_time: AtomicU64::new(get_current_time()),
         }
     }
 
@@ -198,7 +220,7 @@ impl RamInode {
             attributes: self.attributes,
             creation_time: self.creation_time,
             modification_time: self.modification_time,
-            access_time: self.access_time,
+            access_time: self.access_time.load(Ordering::Relaxed),
         }
     }
 }
@@ -208,7 +230,6 @@ impl RamFilesystem {
         let mut fs = Self {
             inodes: Vec::new(),
             root_inode: 0,
-            next_inode_id: 0,
         };
 
         // Create root directory
@@ -219,8 +240,8 @@ impl RamFilesystem {
     }
 
     fn allocate_inode(&mut self, inode: RamInode) -> u64 {
-        let id = self.next_inode_id;
-        self.next_inode_id += 1;
+        // Ids index `inodes`, so the root already holds 0
+        let id = self.inodes.len() as u64;
         self.inodes.push(inode);
         id
     }
@@ -381,12 +402,12 @@ impl RamFilesystem {
     }
 
     fn read_file(
-        &mut self,
+        &self,
         file_id: u64,
         buffer: &mut [u8],
         offset: u64,
     ) -> Result<usize, &'static str> {
-        let file = self.get_inode_mut(file_id).ok_or("File not found")?;
+        let file = self.get_inode(file_id).ok_or("File not found")?;
 
         if file.file_type != FileType::Regular {
             return Err("Not a regular file");
@@ -395,7 +416,7 @@ impl RamFilesystem {
         let data = file.data.as_ref().ok_or("File has no data buffer")?;
 
         // Update access time
-        file.access_time = get_current_time();
+        file.access_time.store(get_current_time(), Ordering::Relaxed);
 
         // Check if we're at EOF
         if offset >= file.size {
@@ -484,12 +505,76 @@ impl RamFilesystem {
 
         Ok(())
     }
+
+    /// Move an entry, replacing an existing regular file at the destination.
+    /// The destination name is switched in a single step, so it always refers
+    /// to either the old or the new file.
+    fn rename(
+        &mut self,
+        from_parent: u64,
+        from_name: &str,
+        to_parent: u64,
+        to_name: &str,
+    ) -> Result<(), &'static str> {
+        let entry_id = *self
+            .get_inode(from_parent)
+            .and_then(|parent| parent.children.as_ref())
+            .ok_or("Parent directory not found")?
+            .get(from_name)
+            .ok_or("Entry does not exist")?;
+
+        let target = self.get_inode(to_parent).ok_or("Parent directory not found")?;
+        let target_children = target.children.as_ref().ok_or("Parent is not a directory")?;
+        if let Some(&existing_id) = target_children.get(to_name) {
+            if existing_id == entry_id {
+                return Ok(());
+            }
+            let existing = self.get_inode(existing_id).ok_or("Invalid inode")?;
+            if existing.file_type == FileType::Directory {
+                return Err("Destination is a directory");
+            }
+        }
+
+        // A directory can't be moved inside itself
+        if self.is_ancestor(entry_id, to_parent) {
+            return Err("Cannot move a directory into itself");
+        }
+
+        let now = get_current_time();
+        {
+            let target = self.get_inode_mut(to_parent).ok_or("Parent directory not found")?;
+            let children = target.children.as_mut().ok_or("Parent is not a directory")?;
+            children.insert(to_name.to_string(), entry_id);
+            target.modification_time = now;
+        }
+        // Skip the removal when the name was just overwritten in the same directory
+        if from_parent != to_parent || from_name != to_name {
+            let source = self.get_inode_mut(from_parent).ok_or("Parent directory not found")?;
+            let children = source.children.as_mut().ok_or("Parent has no children map")?;
+            children.remove(from_name);
+            source.modification_time = now;
+        }
+
+        Ok(())
+    }
+
+    /// Whether `descendant` is `ancestor` or lies somewhere below it
+    fn is_ancestor(&self, ancestor: u64, descendant: u64) -> bool {
+        if ancestor == descendant {
+            return true;
+        }
+        self.get_inode(ancestor)
+            .and_then(|inode| inode.children.as_ref())
+            .map_or(false, |children| {
+                children.values().any(|&child| self.is_ancestor(child, descendant))
+            })
+    }
 }
 
 impl Filesystem {
     pub fn new(name: String, fs_type: FilesystemType, device: String, readonly: bool) -> Self {
         let ram_fs = if fs_type == FilesystemType::RamFs {
-            Some(RamFilesystem::new())
+            Some(Mutex::new(RamFilesystem::new()))
         } else {
             None
         };
@@ -502,13 +587,37 @@ impl Filesystem {
             readonly,
             ram_fs,
             root_dir: None,
+            dirty: AtomicBool::new(false),
         }
     }
 
     pub fn shutdown(&mut self) {
+        if let Err(e) = self.sync() {
+            log::warn!("Failed to sync {} at shutdown: {}", self.name, e);
+        }
         self.mounted.store(false, Ordering::SeqCst);
     }
 
+    /// Make every completed write durable on the backing device
+    pub fn sync(&self) -> Result<(), &'static str> {
+        // Nothing backs a RAM filesystem
+        if self.fs_type == FilesystemType::RamFs {
+            return Ok(());
+        }
+
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        // Blocks are written through to the device, so the device cache is
+        // the only thing left to flush
+        let result = flush_device(&self.device);
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+
     pub fn mount(&mut self) -> Result<(), &'static str> {
         if self.mounted.load(Ordering::SeqCst) {
             return Ok(());
@@ -518,11 +627,11 @@ impl Filesystem {
             FilesystemType::RamFs => {
                 // RAM filesystem is already initialized, just mark as mounted
                 if self.ram_fs.is_none() {
-                    self.ram_fs = Some(RamFilesystem::new());
+                    self.ram_fs = Some(Mutex::new(RamFilesystem::new()));
                 }
 
                 // Create root directory handle
-                let ram_fs = self.ram_fs.as_ref().unwrap();
+                let ram_fs = self.ram_fs.as_ref().unwrap().lock();
                 let entries = ram_fs.read_directory(ram_fs.root_inode)?;
 
                 self.root_dir = Some(DirectoryHandle {
@@ -594,10 +703,9 @@ impl Filesystem {
             return Ok(());
         }
 
-        // In a real driver, this would:
-        // 1. Flush any cached data
-        // 2. Release all file handles
-        // 3. Mark the filesystem as unmounted
+        self.sync()?;
+
+        // In a real driver, this would also release all file handles
 
         self.mounted.store(false, Ordering::SeqCst);
         self.root_dir = None;
@@ -639,7 +747,8 @@ impl Filesystem {
                 let ram_fs = self
                     .ram_fs
                     .as_mut()
-                    .ok_or("RAM filesystem not initialized")?;
+                    .ok_or("RAM filesystem not initialized")?
+                    .get_mut();
 
                 // Split path into parent directory and new directory name
                 let (parent_path, name) = split_path(path)?;
@@ -670,7 +779,8 @@ impl Filesystem {
                 let ram_fs = self
                     .ram_fs
                     .as_mut()
-                    .ok_or("RAM filesystem not initialized")?;
+                    .ok_or("RAM filesystem not initialized")?
+                    .get_mut();
 
                 // Split path into parent directory and file name
                 let (parent_path, name) = split_path(path)?;
@@ -697,7 +807,8 @@ impl Filesystem {
                 let ram_fs = self
                     .ram_fs
                     .as_ref()
-                    .ok_or("RAM filesystem not initialized")?;
+                    .ok_or("RAM filesystem not initialized")?
+                    .lock();
 
                 // Find directory inode
                 let dir_id = ram_fs.lookup_path(path)?;
@@ -743,7 +854,8 @@ impl Filesystem {
                 let ram_fs = self
                     .ram_fs
                     .as_ref()
-                    .ok_or("RAM filesystem not initialized")?;
+                    .ok_or("RAM filesystem not initialized")?
+                    .lock();
 
                 // Find file inode
                 let file_id = ram_fs.lookup_path(path)?;
@@ -794,7 +906,8 @@ impl Filesystem {
                 let ram_fs = self
                     .ram_fs
                     .as_mut()
-                    .ok_or("RAM filesystem not initialized")?;
+                    .ok_or("RAM filesystem not initialized")?
+                    .get_mut();
 
                 // Split path into parent directory and entry name
                 let (parent_path, name) = split_path(path)?;
@@ -810,6 +923,38 @@ impl Filesystem {
             _ => Err("Delete operation not implemented for this filesystem type"),
         }
     }
+
+    /// Move `from` to `to`, atomically replacing `to` if it is a file
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), &'static str> {
+        if !self.mounted.load(Ordering::SeqCst) {
+            return Err("Filesystem not mounted");
+        }
+
+        if self.readonly {
+            return Err("Cannot rename entry on readonly filesystem");
+        }
+
+        match self.fs_type {
+            FilesystemType::RamFs => {
+                let ram_fs = self
+                    .ram_fs
+                    .as_mut()
+                    .ok_or("RAM filesystem not initialized")?
+                    .get_mut();
+
+                let (from_parent_path, from_name) = split_path(from)?;
+                let (to_parent_path, to_name) = split_path(to)?;
+                let from_parent = ram_fs.lookup_path(from_parent_path)?;
+                let to_parent = ram_fs.lookup_path(to_parent_path)?;
+
+                ram_fs.rename(from_parent, from_name, to_parent, to_name)?;
+                self.dirty.store(true, Ordering::Release);
+
+                Ok(())
+            }
+            _ => Err("Rename operation not implemented for this filesystem type"),
+        }
+    }
 }
 
 impl DirectoryHandle {
@@ -856,18 +1001,9 @@ impl FileHandle {
                 FilesystemType::RamFs => {
                     if let Some(inode_id) = self.inode_id {
                         if let Some(ram_fs) = fs.ram_fs.as_ref() {
-                            // We need to cheat a bit here since we need a mutable reference
-                            // to the RAM filesystem to update access times
-                            let ram_fs_cell = UnsafeCell::new(self);
-                            let ram_fs_ptr =
-                                ram_fs_cell.get() as *const RamFilesystem as *mut RamFilesystem;
-
-                            unsafe {
-                                let ram_fs_mut = &mut *ram_fs_ptr;
-                                let bytes_written =
-                                    ram_fs_mut.write_file(inode_id, buffer, position)?;
-                                return Ok(bytes_written);
-                            }
+                            let bytes_read = ram_fs.lock().read_file(inode_id, buffer, position)?;
+                            self.position = position + bytes_read as u64;
+                            return Ok(bytes_read);
                         }
                     }
                     Err("Invalid file handle")
@@ -910,23 +1046,15 @@ impl FileHandle {
                 FilesystemType::RamFs => {
                     if let Some(inode_id) = self.inode_id {
                         if let Some(ram_fs) = fs.ram_fs.as_ref() {
-                            // We need to cheat a bit here since we need a mutable reference
-                            // to the RAM filesystem to update metadata
-                            let ram_fs_cell = UnsafeCell::new(ram_fs);
-                            let ram_fs_ptr = ram_fs_cell.get() as *const RamFilesystem as *mut RamFilesystem;
-    
-                            unsafe {
-                                let ram_fs_mut = &mut *ram_fs_ptr;
-                                let bytes_written = ram_fs_mut.write_file(inode_id, buffer, self.position)?;
-                                self.position += bytes_written as u64;
-    
-                                // Update file size
-                                if self.position > self.size {
-                                    self.size = self.position;
-                                }
-    
-                                return Ok(bytes_written);
+                            let bytes_written = ram_fs.lock().write_file(inode_id, buffer, self.position)?;
+                            self.position += bytes_written as u64;
+
+                            // Update file size
+                            if self.position > self.size {
+                                self.size = self.position;
                             }
+
+                            return Ok(bytes_written);
                         }
                     }
                     Err("Invalid file handle")
@@ -938,6 +1066,11 @@ impl FileHandle {
                     if self.position > self.size {
                         self.size = self.position;
                     }
+
+                    fs.dirty.store(true, Ordering::Release);
+                    if SYNC_IMMEDIATELY.load(Ordering::Relaxed) {
+                        fs.sync()?;
+                    }
     
                     Ok(to_write)
                 }
@@ -948,12 +1081,42 @@ impl FileHandle {
     }
 
     pub fn seek(&mut self, position: u64) -> Result<(), &'static str> {
+        self.seek_from(SeekFrom::Start(position)).map(|_| ())
+    }
+
+    /// Move the file position and return the new absolute position.
+    /// Seeking to EOF is allowed so that subsequent writes append.
+    pub fn seek_from(&mut self, pos: SeekFrom) -> Result<u64, &'static str> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(position) => (position, 0),
+            SeekFrom::Current(offset) => (self.position, offset),
+            SeekFrom::End(offset) => (self.size, offset),
+        };
+
+        let position = if offset >= 0 {
+            base.checked_add(offset as u64).ok_or("Seek position overflow")?
+        } else {
+            base.checked_sub(offset.unsigned_abs())
+                .ok_or("Seek position before start of file")?
+        };
+
         if position > self.size {
             return Err("Seek position beyond file size");
         }
 
         self.position = position;
-        Ok(())
+        Ok(position)
+    }
+
+    /// Flush this file's writes to the device (fsync)
+    pub fn sync(&self, fs_manager: &FilesystemManager) -> Result<(), &'static str> {
+        if self.closed {
+            return Err("File is closed");
+        }
+        fs_manager
+            .get_filesystem(&self.fs_name)
+            .ok_or("Filesystem not found")?
+            .sync()
     }
 
     pub fn get_size(&self) -> u64 {
@@ -976,15 +1139,9 @@ impl FileHandle {
                     FilesystemType::RamFs => {
                         if let Some(inode_id) = self.inode_id {
                             if let Some(ram_fs) = fs.ram_fs.as_ref() {
-                                // We need a mutable reference to update metadata
-                                let ram_fs_cell = UnsafeCell::new(ram_fs);
-                                let ram_fs_ptr =
-                                    ram_fs_cell.get() as *const RamFilesystem as *mut RamFilesystem;
-
-                                unsafe {
-                                    let ram_fs_mut = &mut *ram_fs_ptr;
-                                    // Update the modification time or any other metadata
-                                    ram_fs_mut.get_inode_mut(inode_id);
+                                // Update the modification time or any other metadata
+                                if let Some(inode) = ram_fs.lock().get_inode_mut(inode_id) {
+                                    inode.modification_time = get_current_time();
                                 }
                             }
                         }
@@ -1134,10 +1291,36 @@ impl FilesystemManager {
         self.filesystems.iter_mut().find(|fs| fs.get_name() == name)
     }
 
+    /// Flush every mounted filesystem. All are attempted; the first error is returned.
+    pub fn sync_all(&self) -> Result<(), &'static str> {
+        let mut result = Ok(());
+        for fs in self.filesystems.iter().filter(|fs| fs.is_mounted()) {
+            if let Err(e) = fs.sync() {
+                log::warn!("Failed to sync {}: {}", fs.name, e);
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
     pub fn get_filesystems(&self) -> &[Filesystem] {
         &self.filesystems
     }
 
+    /// Writable, mounted filesystem on a USB mass-storage device. With several
+    /// sticks plugged in, the one whose name sorts first wins so the choice is stable.
+    pub fn first_usb_filesystem(&self) -> Option<&Filesystem> {
+        self.first_writable_where(|fs| storage_device_type(&fs.device) == Some(StorageDeviceType::Usb))
+    }
+
+    /// Lowest-named mounted, writable filesystem matching `is_candidate`
+    pub fn first_writable_where(&self, is_candidate: impl Fn(&Filesystem) -> bool) -> Option<&Filesystem> {
+        self.filesystems
+            .iter()
+            .filter(|fs| fs.is_mounted() && !fs.readonly && is_candidate(fs))
+            .min_by(|a, b| a.name.cmp(&b.name))
+    }
+
     pub fn set_current_directory(&mut self, path: String) {
         self.current_directory = path;
     }
@@ -1201,6 +1384,21 @@ impl FilesystemManager {
         Err("No mounted filesystem found")
     }
 
+    /// Read a whole file from the first mounted filesystem
+    pub fn read_bytes(path: &str) -> Result<Vec<u8>, &'static str> {
+        let fs_manager = FS_MANAGER.lock();
+
+        if let Some(fs) = fs_manager.filesystems.iter().find(|fs| fs.is_mounted()) {
+            let mut file = fs.open_file(path, true)?;
+            let mut buffer = vec![0u8; file.get_size() as usize];
+            let bytes_read = file.read(&mut buffer, &fs_manager, 0)?;
+            buffer.truncate(bytes_read);
+            return Ok(buffer);
+        }
+
+        Err("No mounted filesystem found")
+    }
+
     pub fn open_directory(&self, path: &str) -> Result<DirectoryHandle, &'static str> {
         // Find the appropriate filesystem
         // For now, we just use the first mounted filesystem
@@ -1220,6 +1418,15 @@ impl FilesystemManager {
 
         Err("No mounted filesystem found")
     }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), &'static str> {
+        // Both paths are on the first mounted filesystem, like every other operation
+        if let Some(fs) = self.filesystems.iter_mut().find(|fs| fs.is_mounted()) {
+            return fs.rename(from, to);
+        }
+
+        Err("No mounted filesystem found")
+    }
 }
 
 const DIRECTORY_LIST: [&str; 41] = [
@@ -1374,6 +1581,48 @@ fn split_path(path: &str) -> Result<(&str, &str), &'static str> {
     }
 }
 
+/// Flush a storage device's write cache
+fn flush_device(device_name: &str) -> Result<(), &'static str> {
+    let manager = super::get_driver_manager()
+        .try_lock()
+        .ok_or("Driver manager busy")?;
+    manager
+        .as_ref()
+        .ok_or("Drivers not initialized")?
+        .storage_manager
+        .get_device(device_name)
+        .ok_or("Storage device not found")?
+        .flush_cache()
+}
+
+/// Type of the storage device a filesystem lives on, if the driver manager knows it
+fn storage_device_type(device_name: &str) -> Option<StorageDeviceType> {
+    let manager = super::get_driver_manager().try_lock()?;
+    manager
+        .as_ref()?
+        .storage_manager
+        .get_device(device_name)
+        .map(|device| device.get_device_type())
+}
+
+/// Apply `StorageConfig::sync_immediately`
+pub fn set_sync_immediately(enabled: bool) {
+    SYNC_IMMEDIATELY.store(enabled, Ordering::Relaxed);
+}
+
+/// Flush unsynced writes every `WRITEBACK_INTERVAL_MS`. Call from the main loop.
+pub fn periodic_writeback() {
+    let now = timer::uptime_ms();
+    if now.saturating_sub(LAST_WRITEBACK_MS.load(Ordering::Relaxed)) < WRITEBACK_INTERVAL_MS {
+        return;
+    }
+    LAST_WRITEBACK_MS.store(now, Ordering::Relaxed);
+
+    if let Some(fs_manager) = FS_MANAGER.try_lock() {
+        let _ = fs_manager.sync_all();
+    }
+}
+
 pub fn shutdown() {
     // Get a mutable reference to the filesystem manager and shutdown all mounted filesystems
     if let Some(mut fs_manager) = FS_MANAGER.try_lock() {
@@ -1382,3 +1631,56 @@ pub fn shutdown() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A manager with a RAM filesystem holding `/seek.bin` = "0123456789",
+    /// and a writable handle to it positioned at EOF
+    fn ten_byte_file() -> (FilesystemManager, FileHandle) {
+        let mut manager = FilesystemManager::new();
+        let ram = Filesystem::new("ram".to_string(), FilesystemType::RamFs, String::new(), false);
+        manager.add_filesystem(ram).unwrap();
+        manager.create_file("/seek.bin").unwrap();
+        let mut file = manager.open_file("/seek.bin", false).unwrap();
+        assert_eq!(file.write(b"0123456789", &manager), Ok(10));
+        (manager, file)
+    }
+
+    #[test]
+    fn seek_from_start_current_and_end() {
+        let (manager, mut file) = ten_byte_file();
+        assert_eq!(file.seek_from(SeekFrom::Start(2)), Ok(2));
+        assert_eq!(file.seek_from(SeekFrom::Current(3)), Ok(5));
+        assert_eq!(file.seek_from(SeekFrom::Current(-4)), Ok(1));
+        assert_eq!(file.seek_from(SeekFrom::End(-3)), Ok(7));
+
+        let mut buffer = [0u8; 8];
+        let read = file.read(&mut buffer, &manager, file.get_position()).unwrap();
+        assert_eq!(&buffer[..read], b"789");
+
+        // EOF itself is a valid position, so writes can append
+        assert_eq!(file.seek_from(SeekFrom::End(0)), Ok(10));
+        assert_eq!(file.seek_from(SeekFrom::Start(10)), Ok(10));
+        assert_eq!(file.write(b"ab", &manager), Ok(2));
+        assert_eq!(file.get_size(), 12);
+    }
+
+    #[test]
+    fn seek_refuses_negative_and_past_eof_positions() {
+        let (_manager, mut file) = ten_byte_file();
+        file.seek_from(SeekFrom::Start(5)).unwrap();
+
+        assert_eq!(file.seek_from(SeekFrom::Current(-6)), Err("Seek position before start of file"));
+        assert_eq!(file.seek_from(SeekFrom::End(-11)), Err("Seek position before start of file"));
+        assert_eq!(file.seek_from(SeekFrom::Current(i64::MIN)), Err("Seek position before start of file"));
+        assert_eq!(file.seek_from(SeekFrom::Start(11)), Err("Seek position beyond file size"));
+        assert_eq!(file.seek_from(SeekFrom::End(1)), Err("Seek position beyond file size"));
+        assert_eq!(file.seek_from(SeekFrom::Current(i64::MAX)), Err("Seek position beyond file size"));
+        assert_eq!(file.seek_from(SeekFrom::Start(u64::MAX)), Err("Seek position beyond file size"));
+
+        // A refused seek leaves the position alone
+        assert_eq!(file.get_position(), 5);
+    }
+}
@@ -6,6 +6,9 @@ use core::arch::asm;
 use spin::Mutex;
 use lazy_static::lazy_static;
 use micromath::F32Ext;
+use super::usb::hid::{self, ReportLayout};
+use crate::config::InputConfig;
+use crate::events;
 
 /// Gamepad types we can support
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -31,6 +34,113 @@ pub struct GamepadState {
     pub right_trigger: u8,  // 0 to 255
 }
 
+/// Analog sticks on a gamepad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stick {
+    Left = 0,
+    Right = 1,
+}
+
+/// How the deadzone around a stick's center is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadzoneShape {
+    /// Each axis is zeroed on its own while inside the deadzone
+    Axial,
+    /// The whole vector is zeroed while its length is inside the deadzone
+    Radial,
+    /// Radial cut, with the remaining range stretched so output starts at zero
+    ScaledRadial,
+}
+
+impl DeadzoneShape {
+    /// Parse the config spelling; empty or unknown names fall back to `Radial`
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "axial" => DeadzoneShape::Axial,
+            "scaled_radial" | "scaled-radial" => DeadzoneShape::ScaledRadial,
+            _ => DeadzoneShape::Radial,
+        }
+    }
+
+    /// Apply the deadzone to a stick position with axes in -1.0..=1.0
+    pub fn apply(self, x: f32, y: f32, deadzone: f32) -> (f32, f32) {
+        let deadzone = deadzone.clamp(0.0, 0.99);
+        match self {
+            DeadzoneShape::Axial => {
+                let cut = |v: f32| if v.abs() < deadzone { 0.0 } else { v };
+                (cut(x), cut(y))
+            }
+            DeadzoneShape::Radial => {
+                if (x * x + y * y).sqrt() < deadzone { (0.0, 0.0) } else { (x, y) }
+            }
+            DeadzoneShape::ScaledRadial => {
+                let magnitude = (x * x + y * y).sqrt();
+                if magnitude < deadzone || magnitude == 0.0 {
+                    return (0.0, 0.0);
+                }
+                let scaled = ((magnitude - deadzone) / (1.0 - deadzone)).min(1.0);
+                (x / magnitude * scaled, y / magnitude * scaled)
+            }
+        }
+    }
+}
+
+/// Deadzone applied by `read_stick` to one stick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StickDeadzone {
+    /// Radius of the deadzone as a fraction of full deflection
+    pub size: f32,
+    pub shape: DeadzoneShape,
+}
+
+impl StickDeadzone {
+    pub const DEFAULT: StickDeadzone = StickDeadzone { size: 0.1, shape: DeadzoneShape::Radial };
+}
+
+/// Per-stick deadzones, indexed by `Stick`
+static STICK_DEADZONES: Mutex<[StickDeadzone; 2]> = Mutex::new([StickDeadzone::DEFAULT; 2]);
+
+/// Apply deadzone size and per-stick shapes from the input config
+pub fn configure(config: &InputConfig) {
+    let size = config.controller_deadzone;
+    *STICK_DEADZONES.lock() = [
+        StickDeadzone { size, shape: DeadzoneShape::from_name(&config.left_stick_deadzone_shape) },
+        StickDeadzone { size, shape: DeadzoneShape::from_name(&config.right_stick_deadzone_shape) },
+    ];
+}
+
+pub fn stick_deadzone(stick: Stick) -> StickDeadzone {
+    STICK_DEADZONES.lock()[stick as usize]
+}
+
+pub fn set_stick_deadzone(stick: Stick, deadzone: StickDeadzone) {
+    STICK_DEADZONES.lock()[stick as usize] = deadzone;
+}
+
+/// Position of `stick` on a gamepad with each axis in -1.0..=1.0, after its
+/// configured deadzone. `None` if the gamepad isn't connected.
+pub fn read_stick(device_id: usize, stick: Stick) -> Option<(f32, f32)> {
+    let state = {
+        let manager = GAMEPAD_MANAGER.lock();
+        let device = manager.get_device(device_id)?;
+        if !device.is_connected() {
+            return None;
+        }
+        device.get_state()
+    };
+    let (raw_x, raw_y) = match stick {
+        Stick::Left => (state.left_stick_x, state.left_stick_y),
+        Stick::Right => (state.right_stick_x, state.right_stick_y),
+    };
+    let deadzone = stick_deadzone(stick);
+    Some(deadzone.shape.apply(normalize_axis(raw_x), normalize_axis(raw_y), deadzone.size))
+}
+
+/// Map a raw axis to -1.0..=1.0; the extra negative step of i16 is clamped
+fn normalize_axis(value: i16) -> f32 {
+    (value as f32 / i16::MAX as f32).max(-1.0)
+}
+
 /// Button definitions
 pub const BTN_A: u32 = 0x00000001;
 pub const BTN_B: u32 = 0x00000002;
@@ -62,6 +172,8 @@ pub struct GamepadDevice {
     gamepad_type: GamepadType,
     state: GamepadState,
     connected: AtomicBool,
+    /// Report layout for report-protocol (generic HID) devices
+    report_layout: Option<ReportLayout>,
 }
 
 /// Manages all gamepad devices
@@ -130,7 +242,53 @@ impl GamepadDevice {
                 right_trigger: 0,
             },
             connected: AtomicBool::new(true),
+            report_layout: None,
+        }
+    }
+
+    /// Parse the device's HID report descriptor so its reports can be decoded
+    pub fn set_report_descriptor(&mut self, descriptor: &[u8]) -> Result<(), &'static str> {
+        self.report_layout = Some(hid::parse_report_descriptor(descriptor)?);
+        Ok(())
+    }
+
+    /// Update the state from a raw HID input report
+    pub fn apply_hid_report(&mut self, report: &[u8]) -> Result<(), &'static str> {
+        let layout = self.report_layout.as_ref().ok_or("Gamepad has no report descriptor")?;
+        let decoded = hid::decode_report(layout, report);
+
+        let mut state = self.state;
+        // Button usages 1..=14 line up with BTN_A..BTN_DPAD_RIGHT, 15 is the guide button
+        state.buttons = decoded.buttons & 0x7FFF;
+        state.buttons |= match decoded.hat {
+            Some(0) => BTN_DPAD_UP,
+            Some(1) => BTN_DPAD_UP | BTN_DPAD_RIGHT,
+            Some(2) => BTN_DPAD_RIGHT,
+            Some(3) => BTN_DPAD_DOWN | BTN_DPAD_RIGHT,
+            Some(4) => BTN_DPAD_DOWN,
+            Some(5) => BTN_DPAD_DOWN | BTN_DPAD_LEFT,
+            Some(6) => BTN_DPAD_LEFT,
+            Some(7) => BTN_DPAD_UP | BTN_DPAD_LEFT,
+            _ => 0,
+        };
+
+        for axis in &decoded.axes {
+            match (axis.usage_page, axis.usage) {
+                (hid::USAGE_PAGE_GENERIC_DESKTOP, hid::USAGE_X) => state.left_stick_x = axis.normalized(),
+                (hid::USAGE_PAGE_GENERIC_DESKTOP, hid::USAGE_Y) => state.left_stick_y = axis.normalized(),
+                (hid::USAGE_PAGE_GENERIC_DESKTOP, hid::USAGE_Z) => state.right_stick_x = axis.normalized(),
+                (hid::USAGE_PAGE_GENERIC_DESKTOP, hid::USAGE_RZ) => state.right_stick_y = axis.normalized(),
+                (hid::USAGE_PAGE_GENERIC_DESKTOP, hid::USAGE_RX) => state.left_trigger = axis.normalized_u8(),
+                (hid::USAGE_PAGE_GENERIC_DESKTOP, hid::USAGE_RY) => state.right_trigger = axis.normalized_u8(),
+                // Brake and accelerator, as reported by racing wheels and some pads
+                (hid::USAGE_PAGE_SIMULATION, 0xC5) => state.left_trigger = axis.normalized_u8(),
+                (hid::USAGE_PAGE_SIMULATION, 0xC4) => state.right_trigger = axis.normalized_u8(),
+                _ => {}
+            }
         }
+
+        self.update_state(state);
+        Ok(())
     }
     
     /// Update the gamepad state
@@ -188,6 +346,17 @@ impl GamepadManager {
         
         id
     }
+
+    /// Add a report-protocol HID gamepad described by its report descriptor
+    pub fn add_hid_device(&mut self, name: String, descriptor: &[u8]) -> Result<usize, &'static str> {
+        let mut device = GamepadDevice::new(self.next_id, name, GamepadType::Generic);
+        device.set_report_descriptor(descriptor)?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.devices.push(device);
+        Ok(id)
+    }
     
     /// Get a gamepad by ID
     pub fn get_device(&self, id: usize) -> Option<&GamepadDevice> {
@@ -318,6 +487,7 @@ impl Clone for GamepadDevice {
             gamepad_type: self.gamepad_type,
             state: self.state,
             connected: AtomicBool::new(self.connected.load(Ordering::SeqCst)),
+            report_layout: self.report_layout.clone(),
         }
     }
 }
@@ -1000,6 +1170,13 @@ fn queue_connection_event(device_id: usize, connected: bool, timestamp: u64) {
     
     let mut buffer = EVENT_BUFFER.lock();
     buffer.push(event);
+    drop(buffer);
+
+    events::publish(if connected {
+        events::Event::ControllerConnected { id: device_id }
+    } else {
+        events::Event::ControllerDisconnected { id: device_id }
+    });
     
     #[cfg(feature = "log")]
     {
@@ -0,0 +1,69 @@
+//! Off-screen frame for the `gpu` drawing calls
+//!
+//! Drawing straight into scanout memory shows every half-finished frame as
+//! flicker. While a back buffer exists, `clear`, `fill_rect` and the line
+//! calls draw into it on the CPU, `get_framebuffer` hands it out for direct
+//! pixel access, and `present` gives the finished frame to the device in one
+//! `blit_backbuffer`.
+extern crate alloc;
+use alloc::vec::Vec;
+
+use super::raster::Surface;
+use super::GpuError;
+
+/// A 32bpp 0xAARRGGBB frame in system memory, rows packed without padding
+pub struct BackBuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<u32>,
+}
+
+impl BackBuffer {
+    /// Allocate a cleared `width` x `height` buffer, failing instead of
+    /// aborting when the heap can't hold it
+    pub fn new(width: u32, height: u32) -> Result<Self, GpuError> {
+        if width == 0 || height == 0 {
+            return Err(GpuError::InvalidParameter);
+        }
+        let len = width as usize * height as usize;
+        let mut pixels = Vec::new();
+        pixels.try_reserve_exact(len).map_err(|_| GpuError::OutOfMemory)?;
+        pixels.resize(len, 0);
+        Ok(Self { width, height, pixels })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Bytes per row
+    pub fn pitch(&self) -> u32 {
+        self.width * 4
+    }
+
+    /// Address of the first pixel, as `get_framebuffer` returns it
+    pub fn address(&mut self) -> usize {
+        self.pixels.as_mut_ptr() as usize
+    }
+
+    /// The frame as raw bytes, for `GpuDevice::blit_backbuffer`
+    pub fn as_bytes(&self) -> &[u8] {
+        // Safety: any u32 slice is also a valid, four times longer byte slice
+        unsafe { core::slice::from_raw_parts(self.pixels.as_ptr() as *const u8, self.pixels.len() * 4) }
+    }
+
+    pub fn surface(&mut self, clip: Option<(i32, i32, u32, u32)>) -> Surface {
+        let mut surface = Surface::from_slice(&mut self.pixels, self.width, self.height);
+        surface.set_clip(clip);
+        surface
+    }
+
+    pub fn clear(&mut self, color: u32) {
+        // Safety: the span is exactly the buffer
+        unsafe { super::raster::fill_span(self.pixels.as_mut_ptr(), self.pixels.len(), color) };
+    }
+}
@@ -0,0 +1,67 @@
+//! Mouse pointer image for `gpu::set_cursor`
+//!
+//! Shown by the display controller on devices with `Feature::HardwareCursor`.
+//! Elsewhere it is blended onto the visible framebuffer after the back buffer
+//! is copied out in `present`, so it never ends up in the frame being drawn.
+extern crate alloc;
+use alloc::vec::Vec;
+
+use super::raster::Surface;
+use super::{BlendMode, GpuError};
+
+/// Largest cursor edge accepted; hardware planes top out at 256
+pub const MAX_CURSOR_SIZE: u32 = 256;
+
+pub struct Cursor {
+    width: u32,
+    hotspot: (u16, u16),
+    /// 0xAARRGGBB
+    pixels: Vec<u32>,
+    /// Position of the hotspot on screen
+    pub x: i32,
+    pub y: i32,
+    pub visible: bool,
+    /// Shown by the device rather than composited in `present`
+    pub hardware: bool,
+}
+
+impl Cursor {
+    /// `image` holds `width * height` little-endian 0xAARRGGBB pixels
+    pub fn new(image: &[u8], width: u32, height: u32, hotspot: (u16, u16)) -> Result<Self, GpuError> {
+        if width == 0 || height == 0 || width > MAX_CURSOR_SIZE || height > MAX_CURSOR_SIZE {
+            return Err(GpuError::InvalidParameter);
+        }
+        if hotspot.0 as u32 >= width || hotspot.1 as u32 >= height {
+            return Err(GpuError::InvalidParameter);
+        }
+        let count = (width * height) as usize;
+        if image.len() < count * 4 {
+            return Err(GpuError::InvalidParameter);
+        }
+
+        let pixels = image
+            .chunks_exact(4)
+            .take(count)
+            .map(|p| u32::from_le_bytes([p[0], p[1], p[2], p[3]]))
+            .collect();
+        Ok(Self { width, hotspot, pixels, x: 0, y: 0, visible: true, hardware: false })
+    }
+
+    /// Blend the image over a 32bpp framebuffer at the current position
+    ///
+    /// # Safety
+    /// `framebuffer` must be valid for writes of `pitch_bytes * height` bytes.
+    pub unsafe fn composite(&self, framebuffer: *mut u32, width: u32, height: u32, pitch_bytes: u32) {
+        let mut surface = Surface::from_raw(framebuffer, width, height, pitch_bytes);
+        let left = self.x - self.hotspot.0 as i32;
+        let top = self.y - self.hotspot.1 as i32;
+        for (i, &pixel) in self.pixels.iter().enumerate() {
+            if pixel >> 24 == 0 {
+                continue;
+            }
+            let x = left + (i as u32 % self.width) as i32;
+            let y = top + (i as u32 / self.width) as i32;
+            surface.plot(x, y, pixel, 1.0, BlendMode::Alpha);
+        }
+    }
+}
@@ -45,6 +45,7 @@ pub fn detect_gpu() -> Result<Box<dyn GpuDevice>, GpuError> {
         return Ok(driver);
     }
     
-    // No suitable GPU found
-    Err(GpuError::NoDevice)
+    // No display hardware at all: render on the CPU so the GUI still runs
+    log::warn!("No supported GPU found, falling back to the software renderer");
+    super::software::create_driver(super::software::DEFAULT_WIDTH, super::software::DEFAULT_HEIGHT)
 }
\ No newline at end of file
@@ -0,0 +1,146 @@
+//! VRAM budget
+//!
+//! Tracks how much video memory textures use against what the device has.
+//! When an allocation would leave less than `PRESSURE_THRESHOLD_DIVISOR`-th
+//! of VRAM free, the registered pressure hooks run first so caches can evict
+//! entries, and `create_texture` retries once before giving up with
+//! `OutOfMemory`, the same way the heap gives its OOM hook a chance.
+//!
+//! A configured limit (`GpuConfig.vram_limit`) caps the budget below the
+//! physical VRAM, and the pressure threshold is taken from the capped size.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// Hooks run when free VRAM drops below 1/16 of the total
+const PRESSURE_THRESHOLD_DIVISOR: usize = 16;
+
+/// Called under VRAM pressure with the number of bytes the pending
+/// allocation needs. Hooks free what they can, e.g. with `destroy_texture`.
+pub type PressureHook = fn(needed: usize);
+
+struct VramBudget {
+    /// Bytes available for textures, 0 when the device keeps textures in
+    /// system memory and nothing is budgeted
+    capacity: usize,
+    /// Configured cap in bytes, 0 for none
+    limit: usize,
+    used: usize,
+    /// Size charged for each live texture
+    textures: BTreeMap<u32, usize>,
+}
+
+static BUDGET: Mutex<VramBudget> = Mutex::new(VramBudget {
+    capacity: 0,
+    limit: 0,
+    used: 0,
+    textures: BTreeMap::new(),
+});
+static PRESSURE_HOOKS: Mutex<Vec<PressureHook>> = Mutex::new(Vec::new());
+
+impl VramBudget {
+    /// Bytes textures may use, 0 for unbudgeted
+    fn available(&self) -> usize {
+        match (self.capacity, self.limit) {
+            (0, limit) => limit,
+            (capacity, 0) => capacity,
+            (capacity, limit) => capacity.min(limit),
+        }
+    }
+}
+
+/// Cap texture VRAM at `limit` bytes even when the device has more; 0 removes the cap.
+/// Textures already allocated are kept, but nothing new fits until usage drops below it.
+pub fn set_limit(limit: usize) {
+    BUDGET.lock().limit = limit;
+}
+
+/// Start a fresh budget for a newly installed device
+pub(super) fn reset(capacity: usize) {
+    let mut budget = BUDGET.lock();
+    budget.capacity = capacity;
+    budget.used = 0;
+    budget.textures.clear();
+}
+
+/// Bytes a texture of this size and format occupies
+pub(super) fn texture_size(width: u32, height: u32, format: u32) -> usize {
+    let bytes_per_pixel = match format {
+        1 | 3 => 3,
+        4 => 1,
+        _ => 4,
+    };
+    width as usize * height as usize * bytes_per_pixel
+}
+
+/// Register a hook to run under VRAM pressure
+pub fn register_pressure_hook(hook: PressureHook) {
+    let mut hooks = PRESSURE_HOOKS.lock();
+    if !hooks.iter().any(|&registered| registered as usize == hook as usize) {
+        hooks.push(hook);
+    }
+}
+
+pub fn unregister_pressure_hook(hook: PressureHook) {
+    PRESSURE_HOOKS.lock().retain(|&registered| registered as usize != hook as usize);
+}
+
+/// Run every pressure hook. Must be called without the GPU device locked,
+/// since hooks free textures.
+pub(super) fn on_vram_pressure(needed: usize) {
+    // Copy the list so hooks may register or unregister
+    let hooks = PRESSURE_HOOKS.lock().clone();
+    if hooks.is_empty() {
+        return;
+    }
+    log::debug!("VRAM pressure: {} bytes needed, {} free", needed, free());
+    for hook in hooks {
+        hook(needed);
+    }
+}
+
+/// Whether `size` more bytes fit in the budget
+pub(super) fn fits(size: usize) -> bool {
+    let budget = BUDGET.lock();
+    let available = budget.available();
+    available == 0 || budget.used + size <= available
+}
+
+/// Whether allocating `size` bytes would leave VRAM under pressure
+pub(super) fn under_pressure_after(size: usize) -> bool {
+    let budget = BUDGET.lock();
+    let available = budget.available();
+    available != 0 && available.saturating_sub(budget.used + size) < available / PRESSURE_THRESHOLD_DIVISOR
+}
+
+/// Charge a created texture to the budget
+pub(super) fn charge(texture_id: u32, size: usize) {
+    let mut budget = BUDGET.lock();
+    if let Some(previous) = budget.textures.insert(texture_id, size) {
+        budget.used -= previous;
+    }
+    budget.used += size;
+}
+
+/// Return a destroyed texture's memory to the budget
+pub(super) fn release(texture_id: u32) {
+    let mut budget = BUDGET.lock();
+    if let Some(size) = budget.textures.remove(&texture_id) {
+        budget.used -= size;
+    }
+}
+
+/// Bytes of VRAM in use by textures
+pub fn used() -> usize {
+    BUDGET.lock().used
+}
+
+/// Bytes of VRAM left for textures, `usize::MAX` when nothing is budgeted
+pub fn free() -> usize {
+    let budget = BUDGET.lock();
+    match budget.available() {
+        0 => usize::MAX,
+        available => available.saturating_sub(budget.used),
+    }
+}
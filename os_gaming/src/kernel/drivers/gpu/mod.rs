@@ -5,7 +5,7 @@
 
 extern crate alloc;
 use alloc::boxed::Box;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use spin::Mutex;
 use alloc::vec::Vec;
 
@@ -16,8 +16,15 @@ mod command;
 mod memory;
 mod specific;
 mod common;
+mod raster;
+mod software;
+mod backbuffer;
+mod cursor;
 
 use specific::GpuDevice;
+pub use memory::{register_pressure_hook, unregister_pressure_hook, PressureHook};
+pub use raster::{blend_pixel, fill_span, linear_blending, set_linear_blending, Surface};
+pub use pci::{enumerate_functions as enumerate_pci_functions, enumerate_gpus, PciDevice, PciFunction};
 
 /// GPU capabilities and information
 #[derive(Debug, Clone)]
@@ -30,14 +37,117 @@ pub struct GpuInfo {
     pub vram_size: usize,
     /// Maximum texture dimensions
     pub max_texture_size: u32,
-    /// Supported features bitmap
-    pub features: u32,
+    /// Supported features
+    pub features: FeatureSet,
     /// Current display mode
     pub current_mode: DisplayMode,
     /// Available display modes
     pub available_modes: &'static [DisplayMode],
 }
 
+impl GpuInfo {
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.features.contains(feature)
+    }
+
+    /// The 32bpp mode closest to the requested resolution, then refresh rate
+    pub fn closest_mode(&self, width: u32, height: u32, refresh_rate: u32) -> Option<DisplayMode> {
+        let distance = |mode: &DisplayMode| {
+            let dw = (mode.width as i64 - width as i64).abs();
+            let dh = (mode.height as i64 - height as i64).abs();
+            let dr = (mode.refresh_rate as i64 - refresh_rate as i64).abs();
+            (dw + dh, dr)
+        };
+
+        self.available_modes
+            .iter()
+            .filter(|mode| mode.bpp == 32)
+            .min_by_key(|mode| distance(mode))
+            .copied()
+    }
+}
+
+/// What the active GPU can do, for deciding which settings to offer.
+/// Everything is explicitly unsupported when no GPU is available.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuCapabilities {
+    pub features: FeatureSet,
+    /// Total video memory in bytes
+    pub vram_size: usize,
+    pub max_texture_size: u32,
+    /// Distinct refresh rates across all available modes, ascending
+    pub refresh_rates: Vec<u16>,
+    pub display_modes: Vec<DisplayMode>,
+    pub hardware_acceleration: bool,
+    pub hardware_cursor: bool,
+    pub vsync: bool,
+    pub variable_refresh: bool,
+    pub ray_tracing: bool,
+}
+
+impl GpuCapabilities {
+    /// No GPU: nothing supported
+    pub fn none() -> Self {
+        Self::from_info(None)
+    }
+
+    pub fn from_info(info: Option<&GpuInfo>) -> Self {
+        let info = match info {
+            Some(info) => info,
+            None => {
+                return Self {
+                    features: FeatureSet::empty(),
+                    vram_size: 0,
+                    max_texture_size: 0,
+                    refresh_rates: Vec::new(),
+                    display_modes: Vec::new(),
+                    hardware_acceleration: false,
+                    hardware_cursor: false,
+                    vsync: false,
+                    variable_refresh: false,
+                    ray_tracing: false,
+                }
+            }
+        };
+
+        let features = info.features;
+        let mut refresh_rates: Vec<u16> = info.available_modes.iter().map(|mode| mode.refresh_rate).collect();
+        refresh_rates.sort_unstable();
+        refresh_rates.dedup();
+
+        Self {
+            features,
+            vram_size: info.vram_size,
+            max_texture_size: info.max_texture_size,
+            refresh_rates,
+            display_modes: info.available_modes.to_vec(),
+            hardware_acceleration: features.contains(Feature::Acceleration2D) || features.contains(Feature::Rendering3D),
+            hardware_cursor: features.contains(Feature::HardwareCursor),
+            vsync: features.contains(Feature::VSync),
+            variable_refresh: [
+                Feature::VariableRefreshRate,
+                Feature::VariableRefresh,
+                Feature::FreeSync,
+                Feature::GSync,
+                Feature::AdaptiveSync,
+            ]
+            .iter()
+            .any(|&feature| features.contains(feature)),
+            ray_tracing: features.contains(Feature::RayTracing) || features.contains(Feature::RayTracingCores),
+        }
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.features.contains(feature)
+    }
+
+    pub fn supports_mode(&self, width: u32, height: u32, refresh_rate: u16) -> bool {
+        self.display_modes
+            .iter()
+            .any(|mode| mode.width == width && mode.height == height && mode.refresh_rate == refresh_rate)
+    }
+}
+
 /// Display mode information
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DisplayMode {
@@ -53,6 +163,7 @@ pub struct DisplayMode {
 
 /// GPU feature flags
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u64)]  // Specify u64 representation to ensure values fit on all targets
 pub enum Feature {
     /// Hardware blending support
@@ -105,6 +216,97 @@ pub enum Feature {
     VideoCodecs = 0x200000000,
 }
 
+impl Feature {
+    /// Every feature, in bit order
+    pub const ALL: [Feature; 34] = [
+        Feature::Blending,
+        Feature::Acceleration2D,
+        Feature::Rendering3D,
+        Feature::Shaders,
+        Feature::RenderTargets,
+        Feature::HardwareCursor,
+        Feature::MemoryMapping,
+        Feature::DmaTransfers,
+        Feature::TextureCompression,
+        Feature::VariableRefreshRate,
+        Feature::VariableRefresh,
+        Feature::TensorAcceleration,
+        Feature::RayTracing,
+        Feature::VideoAcceleration,
+        Feature::ComputeAcceleration,
+        Feature::DisplayPort,
+        Feature::HDMI,
+        Feature::VSync,
+        Feature::FreeSync,
+        Feature::GSync,
+        Feature::AdaptiveSync,
+        Feature::VariableRateShading,
+        Feature::MeshShading,
+        Feature::SamplerFeedback,
+        Feature::TextureFiltering,
+        Feature::TextureArray,
+        Feature::TextureAtlas,
+        Feature::ComputeShaders,
+        Feature::GeometryShaders,
+        Feature::TessellationShaders,
+        Feature::ComputeUnits,
+        Feature::RayTracingCores,
+        Feature::TensorCores,
+        Feature::VideoCodecs,
+    ];
+
+    pub const fn bit(self) -> u64 {
+        self as u64
+    }
+}
+
+/// Set of `Feature` flags, stored in the full 64 bits of the enum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FeatureSet(u64);
+
+impl FeatureSet {
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn of(features: &[Feature]) -> Self {
+        let mut set = Self::empty();
+        for &feature in features {
+            set.insert(feature);
+        }
+        set
+    }
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    pub const fn contains(self, feature: Feature) -> bool {
+        self.0 & feature.bit() != 0
+    }
+
+    pub fn insert(&mut self, feature: Feature) {
+        self.0 |= feature.bit();
+    }
+
+    pub fn remove(&mut self, feature: Feature) {
+        self.0 &= !feature.bit();
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Features present in the set, in bit order
+    pub fn iter(self) -> impl Iterator<Item = Feature> {
+        Feature::ALL.into_iter().filter(move |&feature| self.contains(feature))
+    }
+}
+
 /// GPU texture formats
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TextureFormat {
@@ -124,6 +326,43 @@ pub enum BlendMode {
     Multiply = 3,
 }
 
+impl BlendMode {
+    pub fn from_u32(mode: u32) -> Option<Self> {
+        match mode {
+            0 => Some(BlendMode::None),
+            1 => Some(BlendMode::Alpha),
+            2 => Some(BlendMode::Additive),
+            3 => Some(BlendMode::Multiply),
+            _ => None,
+        }
+    }
+}
+
+/// How textures are sampled when drawn at a different size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFilter {
+    /// Exact texels, blocky when magnified; right for pixel art
+    Nearest = 0,
+    /// Interpolate between the four nearest texels
+    Bilinear = 1,
+}
+
+impl TextureFilter {
+    pub fn from_u32(filter: u32) -> Option<Self> {
+        match filter {
+            0 => Some(TextureFilter::Nearest),
+            1 => Some(TextureFilter::Bilinear),
+            _ => None,
+        }
+    }
+
+    /// Filter for `GpuConfig::anisotropic_filtering`: 0 turns filtering off.
+    /// Levels above 1 sample bilinearly until anisotropic sampling exists.
+    pub fn from_anisotropic_level(level: u8) -> Self {
+        if level == 0 { TextureFilter::Nearest } else { TextureFilter::Bilinear }
+    }
+}
+
 /// GPU errors
 #[derive(Debug)]
 pub enum GpuError {
@@ -156,29 +395,84 @@ pub enum GpuError {
     DrawingFailed,
     CommunicationError,
     DisplayModeFailed,
-    OperationFailed
+    OperationFailed,
+    /// Timed out waiting for the GPU
+    Timeout,
+}
+
+/// Identifies a point in a device's command stream
+pub type FenceId = u64;
+
+/// Poll a fence write-back value until it reaches `id`
+///
+/// `read_completed` returns the last fence value the engine wrote back. Only the
+/// low 32 bits are compared, with wrap-around, since that is what scratch registers hold.
+/// Fences past `last_issued` were never inserted and would never signal.
+pub fn poll_fence(
+    read_completed: impl Fn() -> u32,
+    id: FenceId,
+    last_issued: FenceId,
+    timeout_ms: u64,
+) -> Result<(), GpuError> {
+    if id > last_issued {
+        return Err(GpuError::InvalidParameter);
+    }
+
+    let target = id as u32;
+    let reached = || (read_completed().wrapping_sub(target) as i32) >= 0;
+
+    let start = crate::kernel::drivers::timer::uptime_ms();
+    while !reached() {
+        if crate::kernel::drivers::timer::uptime_ms().saturating_sub(start) >= timeout_ms {
+            return Err(GpuError::Timeout);
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
 }
 
 // Global GPU device instance
 static GPU_DEVICE: Mutex<Option<Box<dyn GpuDevice>>> = Mutex::new(None);
 static INITIALIZED: AtomicBool = AtomicBool::new(false);
 
+// State mirrored for the software fallback paths
+static BLEND_MODE: AtomicU32 = AtomicU32::new(BlendMode::None as u32);
+static CLIP_RECT: Mutex<Option<(i32, i32, u32, u32)>> = Mutex::new(None);
+static TEXTURE_FILTER: AtomicU32 = AtomicU32::new(TextureFilter::Bilinear as u32);
+
+/// Frame the drawing calls target until `present`; None draws to the device
+/// directly. Always locked after `GPU_DEVICE`.
+static BACK_BUFFER: Mutex<Option<backbuffer::BackBuffer>> = Mutex::new(None);
+/// Pointer set by `set_cursor`. Locked after `GPU_DEVICE` and `BACK_BUFFER`.
+static CURSOR: Mutex<Option<cursor::Cursor>> = Mutex::new(None);
+
 /// Initialize the GPU subsystem
 pub fn init() -> Result<(), GpuError> {
     if INITIALIZED.load(Ordering::SeqCst) {
         return Ok(());
     }
     
+    // The loader's framebuffer works on any card, so it is the surface
+    // until a driver for the actual hardware replaces it
+    let mut boot_surface = false;
+    if crate::kernel::multiboot2::boot_framebuffer().is_some() {
+        match vesa::create_driver() {
+            Ok(device) => {
+                install(device)?;
+                boot_surface = true;
+            }
+            Err(e) => log::warn!("GPU: bootloader framebuffer unusable: {:?}", e),
+        }
+    } else {
+        log::info!("GPU: no bootloader framebuffer, console stays in VGA text mode");
+    }
+
     // Detect available GPU hardware
-    let device = detection::detect_gpu()
-        .map_err(|_| GpuError::NoDevice)?;
-    
-    // Store the device
-    let mut gpu_lock = GPU_DEVICE.lock();
-    *gpu_lock = Some(device);
-    
-    // Mark as initialized
-    INITIALIZED.store(true, Ordering::SeqCst);
+    match detection::detect_gpu() {
+        Ok(device) => install(device)?,
+        Err(e) if boot_surface => log::warn!("GPU: no driver loaded ({:?}), keeping the bootloader framebuffer", e),
+        Err(_) => return Err(GpuError::NoDevice),
+    }
     
     // Initialize VESA fallback if no hardware acceleration
     if !supports_feature(Feature::Acceleration2D)? {
@@ -189,6 +483,64 @@ pub fn init() -> Result<(), GpuError> {
     Ok(())
 }
 
+/// Use the CPU renderer with a `width` x `height` framebuffer in RAM,
+/// replacing any device already initialized. For headless runs and
+/// hardware without a supported GPU.
+pub fn init_software(width: u32, height: u32) -> Result<(), GpuError> {
+    shutdown()?;
+    install(software::create_driver(width, height)?)?;
+    log::info!("GPU: software renderer at {}x{}", width, height);
+    Ok(())
+}
+
+/// Make `device` the active GPU
+fn install(device: Box<dyn GpuDevice>) -> Result<(), GpuError> {
+    memory::reset(texture_vram(device.as_ref()));
+    let mode = device.get_info().map(|info| info.current_mode);
+    *GPU_DEVICE.lock() = Some(device);
+    // A cursor loaded into the previous device is gone
+    *CURSOR.lock() = None;
+    INITIALIZED.store(true, Ordering::SeqCst);
+
+    // Only 32bpp frames can be copied out as they are
+    match mode {
+        Ok(mode) if mode.bpp == 32 => {
+            if let Err(e) = resize_backbuffer(mode.width, mode.height) {
+                log::warn!("GPU: no back buffer ({:?}), drawing to the screen directly", e);
+            }
+        }
+        _ => *BACK_BUFFER.lock() = None,
+    }
+    Ok(())
+}
+
+/// Reallocate the back buffer for a `width` x `height` mode. The contents
+/// are cleared. On failure drawing goes straight to the device again.
+pub fn resize_backbuffer(width: u32, height: u32) -> Result<(), GpuError> {
+    let mut back = BACK_BUFFER.lock();
+    if back.as_ref().map_or(false, |b| (b.width(), b.height()) == (width, height)) {
+        return Ok(());
+    }
+    // Free the old frame first; the heap may not hold both
+    *back = None;
+    *back = Some(backbuffer::BackBuffer::new(width, height)?);
+    Ok(())
+}
+
+/// VRAM left for textures once the visible framebuffer is carved out. A
+/// device whose VRAM is all framebuffer keeps textures in system memory, so
+/// they are not budgeted (0).
+fn texture_vram(device: &dyn GpuDevice) -> usize {
+    match device.get_info() {
+        Ok(info) => {
+            let mode = info.current_mode;
+            let framebuffer = mode.width as usize * mode.height as usize * (mode.bpp as usize / 8).max(1);
+            info.vram_size.saturating_sub(framebuffer)
+        }
+        Err(_) => 0,
+    }
+}
+
 /// Shut down the GPU subsystem
 pub fn shutdown() -> Result<(), GpuError> {
     if !INITIALIZED.load(Ordering::SeqCst) {
@@ -201,6 +553,9 @@ pub fn shutdown() -> Result<(), GpuError> {
     }
     
     *gpu_lock = None;
+    *BACK_BUFFER.lock() = None;
+    *CURSOR.lock() = None;
+    memory::reset(0);
     INITIALIZED.store(false, Ordering::SeqCst);
     Ok(())
 }
@@ -217,36 +572,150 @@ pub fn get_info() -> Result<GpuInfo, GpuError> {
     }
 }
 
-/// Get the framebuffer address
+/// Capabilities of the active GPU; all unsupported if there is none
+pub fn capabilities() -> GpuCapabilities {
+    match get_info() {
+        Ok(info) => GpuCapabilities::from_info(Some(&info)),
+        Err(_) => GpuCapabilities::none(),
+    }
+}
+
+/// Get the address of the framebuffer to draw into: the back buffer when
+/// there is one, otherwise the device's visible framebuffer
 pub fn get_framebuffer(width: u32, height: u32) -> Result<usize, GpuError> {
     ensure_initialized()?;
     
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
-        device.get_framebuffer(width, height)
+        let visible = device.get_framebuffer(width, height)?;
+        if BACK_BUFFER.lock().is_none() {
+            return Ok(visible);
+        }
+        // The device may have changed mode to match the request
+        if resize_backbuffer(width, height).is_err() {
+            log::warn!("GPU: back buffer lost at {}x{}, drawing to the screen directly", width, height);
+            return Ok(visible);
+        }
+        Ok(BACK_BUFFER.lock().as_mut().map_or(visible, |back| back.address()))
     } else {
         Err(GpuError::NoDevice)
     }
 }
 
-/// Get the framebuffer pitch (bytes per row)
+/// Get the pitch (bytes per row) of the framebuffer `get_framebuffer` returns
 pub fn get_framebuffer_pitch() -> Result<u32, GpuError> {
     ensure_initialized()?;
     
     let gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_ref() {
-        device.get_framebuffer_pitch()
+        match BACK_BUFFER.lock().as_ref() {
+            Some(back) => Ok(back.pitch()),
+            None => device.get_framebuffer_pitch(),
+        }
     } else {
         Err(GpuError::NoDevice)
     }
 }
 
+/// Get the current display mode
+pub fn current_mode() -> Result<DisplayMode, GpuError> {
+    ensure_initialized()?;
+
+    let gpu_lock = GPU_DEVICE.lock();
+    if let Some(device) = gpu_lock.as_ref() {
+        Ok(device.get_info()?.current_mode)
+    } else {
+        Err(GpuError::NoDevice)
+    }
+}
+
+/// Pick the available mode closest to the requested resolution and refresh rate
+///
+/// An exact resolution match always wins; ties are broken by refresh rate.
+pub fn best_mode_for(width: u32, height: u32, refresh_rate: u32) -> Result<DisplayMode, GpuError> {
+    ensure_initialized()?;
+
+    let gpu_lock = GPU_DEVICE.lock();
+    let device = gpu_lock.as_ref().ok_or(GpuError::NoDevice)?;
+    device.get_info()?
+        .closest_mode(width, height, refresh_rate)
+        .ok_or(GpuError::NotSupported)
+}
+
+/// Switch the display to a new mode
+pub fn set_display_mode(mode: DisplayMode) -> Result<(), GpuError> {
+    ensure_initialized()?;
+
+    let mut gpu_lock = GPU_DEVICE.lock();
+    if let Some(device) = gpu_lock.as_mut() {
+        device.set_display_mode(mode)?;
+        // The previous clip rectangle may lie outside the new mode
+        *CLIP_RECT.lock() = None;
+        if BACK_BUFFER.lock().is_some() {
+            if let Err(e) = resize_backbuffer(mode.width, mode.height) {
+                log::warn!("GPU: no back buffer at {}x{} ({:?})", mode.width, mode.height, e);
+            }
+        }
+        device.clear_clip_rect()
+    } else {
+        Err(GpuError::NoDevice)
+    }
+}
+
+/// Switch to `hz` at the current resolution, if the device has such a mode
+pub fn set_refresh_rate(hz: u32) -> Result<(), GpuError> {
+    let current = current_mode()?;
+    let mode = get_info()?
+        .available_modes
+        .iter()
+        .find(|mode| {
+            mode.width == current.width
+                && mode.height == current.height
+                && mode.bpp == current.bpp
+                && mode.refresh_rate as u32 == hz
+        })
+        .copied()
+        .ok_or(GpuError::NotSupported)?;
+    if mode == current {
+        return Ok(());
+    }
+    set_display_mode(mode)
+}
+
+/// Enable adaptive sync between `min_hz` and `max_hz`, or disable it.
+/// Devices that report no variable refresh support return `UnsupportedFeature`.
+pub fn set_variable_refresh(enabled: bool, min_hz: u32, max_hz: u32) -> Result<(), GpuError> {
+    ensure_initialized()?;
+    if enabled && (min_hz == 0 || min_hz >= max_hz) {
+        return Err(GpuError::InvalidParameter);
+    }
+
+    let mut gpu_lock = GPU_DEVICE.lock();
+    let device = gpu_lock.as_mut().ok_or(GpuError::NoDevice)?;
+    let features = device.get_info()?.features;
+    let vrr = [
+        Feature::VariableRefreshRate,
+        Feature::VariableRefresh,
+        Feature::AdaptiveSync,
+        Feature::FreeSync,
+        Feature::GSync,
+    ];
+    if !vrr.iter().any(|&feature| features.contains(feature)) {
+        return Err(GpuError::UnsupportedFeature);
+    }
+    device.set_variable_refresh(enabled, min_hz, max_hz)
+}
+
 /// Clear the screen with the specified color
 pub fn clear(color: u32) -> Result<(), GpuError> {
     ensure_initialized()?;
     
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
+        if let Some(back) = BACK_BUFFER.lock().as_mut() {
+            back.clear(color);
+            return Ok(());
+        }
         device.clear(color)
     } else {
         Err(GpuError::NoDevice)
@@ -259,6 +728,10 @@ pub fn fill_rect(x: i32, y: i32, width: u32, height: u32, color: u32) -> Result<
     
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
+        if let Some(back) = BACK_BUFFER.lock().as_mut() {
+            back.surface(*CLIP_RECT.lock()).fill_rect(x, y, width, height, color, blend_mode());
+            return Ok(());
+        }
         device.fill_rect(x, y, width, height, color)
     } else {
         Err(GpuError::NoDevice)
@@ -271,19 +744,101 @@ pub fn draw_line(x1: i32, y1: i32, x2: i32, y2: i32, color: u32) -> Result<(), G
     
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
+        if let Some(back) = BACK_BUFFER.lock().as_mut() {
+            raster::draw_line(&mut back.surface(*CLIP_RECT.lock()), x1, y1, x2, y2, color, blend_mode());
+            return Ok(());
+        }
         device.draw_line(x1, y1, x2, y2, color)
     } else {
         Err(GpuError::NoDevice)
     }
 }
 
+/// Move a `width` x `height` block of the frame from (src_x, src_y) to
+/// (dst_x, dst_y); the blocks may overlap. Scrolling this way only leaves
+/// the uncovered strip to redraw.
+pub fn copy_rect(src_x: i32, src_y: i32, dst_x: i32, dst_y: i32, width: u32, height: u32) -> Result<(), GpuError> {
+    ensure_initialized()?;
+
+    let mut gpu_lock = GPU_DEVICE.lock();
+    if let Some(device) = gpu_lock.as_mut() {
+        if let Some(back) = BACK_BUFFER.lock().as_mut() {
+            back.surface(None).copy_rect(src_x, src_y, dst_x, dst_y, width, height);
+            return Ok(());
+        }
+        device.copy_rect(src_x, src_y, dst_x, dst_y, width, height)
+    } else {
+        Err(GpuError::NoDevice)
+    }
+}
+
+/// Draw an anti-aliased line of the given width
+///
+/// Uses the device's own AA lines when available, otherwise rasterizes
+/// into the framebuffer with the active blend mode.
+pub fn draw_line_aa(x1: f32, y1: f32, x2: f32, y2: f32, width: f32, color: u32) -> Result<(), GpuError> {
+    ensure_initialized()?;
+
+    let mut gpu_lock = GPU_DEVICE.lock();
+    let device = gpu_lock.as_mut().ok_or(GpuError::NoDevice)?;
+    if let Some(back) = BACK_BUFFER.lock().as_mut() {
+        raster::draw_line_aa(&mut back.surface(*CLIP_RECT.lock()), x1, y1, x2, y2, width, color, blend_mode());
+        return Ok(());
+    }
+    match device.draw_line_aa(x1, y1, x2, y2, width, color) {
+        Err(GpuError::NotSupported) | Err(GpuError::UnsupportedFeature) => {}
+        result => return result,
+    }
+
+    let mode = device.get_info()?.current_mode;
+    if mode.bpp != 32 {
+        return Err(GpuError::UnsupportedFormat);
+    }
+    let pitch = device.get_framebuffer_pitch()?;
+    let framebuffer = device.get_framebuffer(mode.width, mode.height)?;
+
+    let mut surface = unsafe {
+        raster::Surface::from_raw(framebuffer as *mut u32, mode.width, mode.height, pitch)
+    };
+    surface.set_clip(*CLIP_RECT.lock());
+    raster::draw_line_aa(&mut surface, x1, y1, x2, y2, width, color, blend_mode());
+    Ok(())
+}
+
+/// Blend mode the software paths draw with
+fn blend_mode() -> BlendMode {
+    BlendMode::from_u32(BLEND_MODE.load(Ordering::Relaxed)).unwrap_or(BlendMode::None)
+}
+
 /// Create a texture
+///
+/// If it would leave VRAM under pressure the pressure hooks run first, and an
+/// allocation that still fails is retried once after them.
 pub fn create_texture(width: u32, height: u32, format: u32, data: &[u8]) -> Result<u32, GpuError> {
     ensure_initialized()?;
-    
+
+    let size = memory::texture_size(width, height, format);
+    if memory::under_pressure_after(size) {
+        memory::on_vram_pressure(size);
+    }
+    match try_create_texture(width, height, format, data, size) {
+        Err(GpuError::OutOfMemory) => {
+            memory::on_vram_pressure(size);
+            try_create_texture(width, height, format, data, size)
+        }
+        result => result,
+    }
+}
+
+fn try_create_texture(width: u32, height: u32, format: u32, data: &[u8], size: usize) -> Result<u32, GpuError> {
+    if !memory::fits(size) {
+        return Err(GpuError::OutOfMemory);
+    }
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
-        device.create_texture(width, height, format, data)
+        let id = device.create_texture(width, height, format, data)?;
+        memory::charge(id, size);
+        Ok(id)
     } else {
         Err(GpuError::NoDevice)
     }
@@ -295,12 +850,24 @@ pub fn destroy_texture(texture_id: u32) -> Result<(), GpuError> {
     
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
-        device.destroy_texture(texture_id)
+        device.destroy_texture(texture_id)?;
+        memory::release(texture_id);
+        Ok(())
     } else {
         Err(GpuError::NoDevice)
     }
 }
 
+/// Bytes of VRAM textures currently use
+pub fn vram_used() -> usize {
+    memory::used()
+}
+
+/// Bytes of VRAM left for textures, `usize::MAX` when they are not budgeted
+pub fn vram_free() -> usize {
+    memory::free()
+}
+
 /// Get texture data
 pub fn get_texture_data(texture_id: u32) -> Result<Vec<u8>, GpuError> {
     ensure_initialized()?;
@@ -313,24 +880,51 @@ pub fn get_texture_data(texture_id: u32) -> Result<Vec<u8>, GpuError> {
     }
 }
 
-/// Draw a texture
+/// Draw a texture with the current texture filter
 pub fn draw_texture(texture_id: u32, x: i32, y: i32, width: u32, height: u32) -> Result<(), GpuError> {
+    draw_texture_filtered(texture_id, x, y, width, height, texture_filter())
+}
+
+/// Draw a texture with `filter`, regardless of the current texture filter
+pub fn draw_texture_filtered(
+    texture_id: u32,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    filter: TextureFilter,
+) -> Result<(), GpuError> {
     ensure_initialized()?;
     
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
-        device.draw_texture(texture_id, x, y, width, height)
+        // Textures live on the device and can't be sampled into the back
+        // buffer here; callers fall back to drawing the texture data themselves
+        if BACK_BUFFER.lock().is_some() {
+            return Err(GpuError::NotSupported);
+        }
+        device.draw_texture_filtered(texture_id, x, y, width, height, filter)
     } else {
         Err(GpuError::NoDevice)
     }
 }
 
+/// Filter used by subsequent `draw_texture` calls
+pub fn set_texture_filter(filter: TextureFilter) {
+    TEXTURE_FILTER.store(filter as u32, Ordering::Relaxed);
+}
+
+pub fn texture_filter() -> TextureFilter {
+    TextureFilter::from_u32(TEXTURE_FILTER.load(Ordering::Relaxed)).unwrap_or(TextureFilter::Bilinear)
+}
+
 /// Set clipping rectangle
 pub fn set_clip_rect(x: i32, y: i32, width: u32, height: u32) -> Result<(), GpuError> {
     ensure_initialized()?;
     
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
+        *CLIP_RECT.lock() = Some((x, y, width, height));
         device.set_clip_rect(x, y, width, height)
     } else {
         Err(GpuError::NoDevice)
@@ -343,6 +937,7 @@ pub fn clear_clip_rect() -> Result<(), GpuError> {
     
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
+        *CLIP_RECT.lock() = None;
         device.clear_clip_rect()
     } else {
         Err(GpuError::NoDevice)
@@ -355,24 +950,266 @@ pub fn set_blend_mode(mode: u32) -> Result<(), GpuError> {
     
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
+        if BlendMode::from_u32(mode).is_some() {
+            BLEND_MODE.store(mode, Ordering::Relaxed);
+        }
         device.set_blend_mode(mode)
     } else {
         Err(GpuError::NoDevice)
     }
 }
 
-/// Present the frame to the screen
+/// Set the mouse cursor image: `width * height` little-endian 0xAARRGGBB
+/// pixels, `hotspot` being the pixel that points at the cursor position.
+///
+/// Uses the display controller's cursor plane when the device has one, and
+/// otherwise composites the image in `present`, which needs the back buffer;
+/// without either this fails and the caller has to draw the cursor itself.
+/// Position and visibility carry over from the previous image.
+pub fn set_cursor(image: &[u8], width: u32, height: u32, hotspot: (u16, u16)) -> Result<(), GpuError> {
+    ensure_initialized()?;
+    let mut cursor = cursor::Cursor::new(image, width, height, hotspot)?;
+
+    let mut gpu_lock = GPU_DEVICE.lock();
+    let device = gpu_lock.as_mut().ok_or(GpuError::NoDevice)?;
+    let has_back_buffer = BACK_BUFFER.lock().is_some();
+    let mut current = CURSOR.lock();
+    if let Some(previous) = current.as_ref() {
+        cursor.x = previous.x;
+        cursor.y = previous.y;
+        cursor.visible = previous.visible;
+    }
+
+    cursor.hardware = device.get_info()?.features.contains(Feature::HardwareCursor)
+        && device.set_cursor(image, width, height, hotspot).is_ok();
+    if cursor.hardware {
+        let _ = device.move_cursor(cursor.x, cursor.y);
+        let _ = device.show_cursor(cursor.visible);
+    } else {
+        if current.as_ref().map_or(false, |previous| previous.hardware) {
+            let _ = device.show_cursor(false);
+        }
+        if !has_back_buffer {
+            *current = None;
+            return Err(GpuError::UnsupportedFeature);
+        }
+    }
+    *current = Some(cursor);
+    Ok(())
+}
+
+/// Put the cursor's hotspot at screen position (x, y). A hardware cursor
+/// moves at once, independent of frames; a composited one at the next present.
+pub fn move_cursor(x: i32, y: i32) -> Result<(), GpuError> {
+    ensure_initialized()?;
+
+    let mut gpu_lock = GPU_DEVICE.lock();
+    let device = gpu_lock.as_mut().ok_or(GpuError::NoDevice)?;
+    let mut current = CURSOR.lock();
+    let cursor = current.as_mut().ok_or(GpuError::NotInitialized)?;
+    cursor.x = x;
+    cursor.y = y;
+    if cursor.hardware {
+        device.move_cursor(x, y)?;
+    }
+    Ok(())
+}
+
+/// Show or hide the cursor set with `set_cursor`
+pub fn show_cursor(visible: bool) -> Result<(), GpuError> {
+    ensure_initialized()?;
+
+    let mut gpu_lock = GPU_DEVICE.lock();
+    let device = gpu_lock.as_mut().ok_or(GpuError::NoDevice)?;
+    let mut current = CURSOR.lock();
+    let cursor = current.as_mut().ok_or(GpuError::NotInitialized)?;
+    cursor.visible = visible;
+    if cursor.hardware {
+        device.show_cursor(visible)?;
+    }
+    Ok(())
+}
+
+/// Copy the back buffer to the screen and blend a software cursor over it
+fn blit_frame(device: &mut dyn GpuDevice) -> Result<(), GpuError> {
+    let back = BACK_BUFFER.lock();
+    let back = match back.as_ref() {
+        Some(back) => back,
+        None => return Ok(()),
+    };
+    device.blit_backbuffer(back.as_bytes(), back.pitch())?;
+
+    // Drawn on the visible frame only, so the next frame starts without it
+    if let Some(cursor) = CURSOR.lock().as_ref().filter(|cursor| cursor.visible && !cursor.hardware) {
+        let mode = device.get_info()?.current_mode;
+        if mode.bpp == 32 {
+            let pitch = device.get_framebuffer_pitch()?;
+            let framebuffer = device.get_framebuffer(mode.width, mode.height)?;
+            // Safety: the device's framebuffer spans `pitch * height` bytes
+            unsafe { cursor.composite(framebuffer as *mut u32, mode.width, mode.height, pitch) };
+        }
+    }
+    Ok(())
+}
+
+/// Quality levels rendering should use. Starts from `GpuConfig` and may be
+/// lowered at runtime by the adaptive quality controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QualitySettings {
+    /// 0=Low, 1=Medium, 2=High, 3=Ultra
+    pub texture: u8,
+    /// 0=Off, 1=Low, 2=Medium, 3=High
+    pub shadow: u8,
+    /// 0=Off, 1=FXAA, 2=MSAA2x, 3=MSAA4x, 4=MSAA8x
+    pub antialiasing: u8,
+}
+
+impl QualitySettings {
+    pub fn from_config(config: &crate::config::GpuConfig) -> Self {
+        Self {
+            texture: config.texture_quality,
+            shadow: config.shadow_quality,
+            antialiasing: config.antialiasing,
+        }
+    }
+}
+
+static QUALITY: Mutex<QualitySettings> = Mutex::new(QualitySettings { texture: 2, shadow: 2, antialiasing: 2 });
+
+/// Apply the quality, filtering and VRAM limit sections of the GPU config
+pub fn configure(config: &crate::config::GpuConfig) {
+    set_quality(QualitySettings::from_config(config));
+    set_texture_filter(TextureFilter::from_anisotropic_level(config.anisotropic_filtering));
+    memory::set_limit(config.vram_limit as usize * 1024 * 1024);
+}
+
+/// Lowest rate a default variable refresh range goes down to
+const DEFAULT_VRR_MIN_HZ: u32 = 48;
+
+/// Apply the refresh rate and variable refresh sections of the display config
+pub fn configure_refresh(config: &crate::config::DisplayConfig) {
+    if config.refresh_rate != 0 {
+        if let Err(e) = set_refresh_rate(config.refresh_rate) {
+            log::warn!("Keeping the current refresh rate, {} Hz unavailable: {:?}", config.refresh_rate, e);
+        }
+    }
+
+    let (min_hz, max_hz) = match config.variable_refresh_range {
+        (0, 0) => (DEFAULT_VRR_MIN_HZ, config.refresh_rate.max(DEFAULT_VRR_MIN_HZ + 1)),
+        range => range,
+    };
+    match set_variable_refresh(config.variable_refresh, min_hz, max_hz) {
+        Ok(()) => {}
+        // Nothing to turn off
+        Err(GpuError::UnsupportedFeature) if !config.variable_refresh => {}
+        Err(GpuError::UnsupportedFeature) => log::info!("Variable refresh is not supported by this GPU"),
+        Err(e) => log::warn!("Variable refresh not applied: {:?}", e),
+    }
+}
+
+pub fn set_quality(settings: QualitySettings) {
+    let mut quality = QUALITY.lock();
+    if *quality != settings {
+        log::debug!("GPU quality: {:?}", settings);
+        *quality = settings;
+    }
+}
+
+pub fn quality() -> QualitySettings {
+    *QUALITY.lock()
+}
+
+/// Timeout for outstanding GPU work before a frame is presented anyway
+const PRESENT_FENCE_TIMEOUT_MS: u64 = 100;
+/// Longest scanline wait; a little over one frame at 60 Hz
+const SCANLINE_WAIT_TIMEOUT_MS: u64 = 20;
+
+/// Insert a fence after all work submitted so far
+pub fn insert_fence() -> Result<FenceId, GpuError> {
+    ensure_initialized()?;
+
+    let mut gpu_lock = GPU_DEVICE.lock();
+    if let Some(device) = gpu_lock.as_mut() {
+        Ok(device.insert_fence())
+    } else {
+        Err(GpuError::NoDevice)
+    }
+}
+
+/// Wait until the GPU has executed past a fence
+pub fn wait_fence(id: FenceId, timeout_ms: u64) -> Result<(), GpuError> {
+    ensure_initialized()?;
+
+    let gpu_lock = GPU_DEVICE.lock();
+    if let Some(device) = gpu_lock.as_ref() {
+        device.wait_fence(id, timeout_ms)
+    } else {
+        Err(GpuError::NoDevice)
+    }
+}
+
+/// Present the frame to the screen once all prior work has completed,
+/// copying the back buffer out first if there is one
 pub fn present() -> Result<(), GpuError> {
     ensure_initialized()?;
     
     let mut gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_mut() {
+        let fence = device.insert_fence();
+        if let Err(e) = device.wait_fence(fence, PRESENT_FENCE_TIMEOUT_MS) {
+            log::warn!("GPU work still pending at present: {:?}", e);
+        }
+        blit_frame(device.as_mut())?;
         device.present()
     } else {
         Err(GpuError::NoDevice)
     }
 }
 
+/// Present once the display has scanned out past line `y`, so any tear
+/// lands in the lower part of the screen instead of near the top.
+///
+/// Cheaper than a full vblank wait and doesn't cap the frame rate. Presents
+/// immediately when the device can't report its scanline.
+pub fn present_after_scanline(y: u32) -> Result<(), GpuError> {
+    ensure_initialized()?;
+
+    let mut gpu_lock = GPU_DEVICE.lock();
+    if let Some(device) = gpu_lock.as_mut() {
+        let fence = device.insert_fence();
+        if let Err(e) = device.wait_fence(fence, PRESENT_FENCE_TIMEOUT_MS) {
+            log::warn!("GPU work still pending at present: {:?}", e);
+        }
+        if !wait_for_scanline(|| device.current_scanline(), y, SCANLINE_WAIT_TIMEOUT_MS) {
+            log::trace!("Scanline unavailable or wait timed out, presenting immediately");
+        }
+        // Copied only now: for most devices the copy is what reaches the screen
+        blit_frame(device.as_mut())?;
+        device.present()
+    } else {
+        Err(GpuError::NoDevice)
+    }
+}
+
+/// Spin until `read_scanline` reports a line at or below `target`.
+///
+/// Returns `false` without waiting if the scanline can't be read, or once
+/// `timeout_ms` has elapsed, so a stuck counter never stalls the frame.
+pub fn wait_for_scanline(read_scanline: impl Fn() -> Option<u32>, target: u32, timeout_ms: u64) -> bool {
+    let start = crate::kernel::drivers::timer::uptime_ms();
+    loop {
+        match read_scanline() {
+            None => return false,
+            Some(line) if line >= target => return true,
+            Some(_) => {}
+        }
+        if crate::kernel::drivers::timer::uptime_ms().saturating_sub(start) >= timeout_ms {
+            return false;
+        }
+        core::hint::spin_loop();
+    }
+}
+
 /// Check if a feature is supported
 pub fn supports_feature(feature: Feature) -> Result<bool, GpuError> {
     ensure_initialized()?;
@@ -380,7 +1217,7 @@ pub fn supports_feature(feature: Feature) -> Result<bool, GpuError> {
     let gpu_lock = GPU_DEVICE.lock();
     if let Some(device) = gpu_lock.as_ref() {
         let info = device.get_info()?;
-        Ok((info.features & feature as u32) != 0)
+        Ok(info.supports(feature))
     } else {
         Err(GpuError::NoDevice)
     }
@@ -406,4 +1243,89 @@ fn ensure_initialized() -> Result<(), GpuError> {
     } else {
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info_with(features: FeatureSet) -> GpuInfo {
+        GpuInfo {
+            vendor: "Test",
+            device: "Test GPU",
+            vram_size: 0,
+            max_texture_size: 0,
+            features,
+            current_mode: DisplayMode { width: 640, height: 480, bpp: 32, refresh_rate: 60 },
+            available_modes: &[],
+        }
+    }
+
+    #[test]
+    fn features_above_bit_31_are_reported() {
+        let info = info_with(FeatureSet::of(&[Feature::TensorCores]));
+        assert_eq!(Feature::TensorCores.bit(), 1 << 32);
+        assert!(info.supports(Feature::TensorCores));
+        assert!(!info.supports(Feature::VideoCodecs));
+        // The low 32 bits alone, what a `u32` mask used to see, are empty
+        assert!(!info.supports(Feature::Blending));
+    }
+
+    #[test]
+    fn iter_yields_set_features_in_bit_order() {
+        let set = FeatureSet::of(&[Feature::VideoCodecs, Feature::Blending, Feature::TensorCores]);
+        let features: Vec<Feature> = set.iter().collect();
+        assert_eq!(features, [Feature::Blending, Feature::TensorCores, Feature::VideoCodecs]);
+    }
+
+    #[test]
+    fn fence_signals_once_the_engine_passes_it() {
+        // Each poll retires one more fence, as if the command stream were executing
+        let completed = core::cell::Cell::new(0u32);
+        let engine = || {
+            let value = completed.get();
+            completed.set(value + 1);
+            value
+        };
+        assert!(poll_fence(engine, 3, 3, 1000).is_ok());
+        assert_eq!(completed.get(), 4);
+
+        // Already passed
+        assert!(poll_fence(|| 10, 3, 10, 0).is_ok());
+        // Submitted but not yet reached
+        assert!(matches!(poll_fence(|| 2, 3, 3, 0), Err(GpuError::Timeout)));
+
+        // Only the low 32 bits reach the scratch register
+        let wrapped = (1u64 << 32) + 2;
+        assert!(poll_fence(|| 2, wrapped, wrapped, 0).is_ok());
+        assert!(matches!(poll_fence(|| u32::MAX, wrapped, wrapped, 0), Err(GpuError::Timeout)));
+    }
+
+    #[test]
+    fn fence_that_was_never_inserted_is_refused() {
+        // Refused up front rather than timing out
+        assert!(matches!(poll_fence(|| 0, 4, 3, 1000), Err(GpuError::InvalidParameter)));
+    }
+
+    #[test]
+    fn closest_mode_prefers_resolution_then_refresh_rate() {
+        const fn mode(width: u32, height: u32, bpp: u8, refresh_rate: u16) -> DisplayMode {
+            DisplayMode { width, height, bpp, refresh_rate }
+        }
+        const MODES: &[DisplayMode] = &[
+            mode(1280, 720, 32, 60),
+            mode(1920, 1080, 32, 60),
+            mode(1920, 1080, 32, 144),
+            mode(2560, 1440, 16, 60),
+            mode(2560, 1440, 32, 60),
+        ];
+        let info = GpuInfo { available_modes: MODES, ..info_with(FeatureSet::empty()) };
+
+        assert_eq!(info.closest_mode(1920, 1080, 60), Some(mode(1920, 1080, 32, 60)));
+        // An exact resolution beats a closer refresh rate elsewhere
+        assert_eq!(info.closest_mode(1920, 1080, 120), Some(mode(1920, 1080, 32, 144)));
+        assert_eq!(info.closest_mode(1500, 850, 60), Some(mode(1280, 720, 32, 60)));
+        // 16bpp modes are never picked
+        assert_eq!(info.closest_mode(2560, 1440, 60), Some(mode(2560, 1440, 32, 60)));
+        assert_eq!(info_with(FeatureSet::empty()).closest_mode(640, 480, 60), None);
+    }
+}
@@ -290,6 +290,48 @@ fn estimate_core_count(vendor_id: u16, device_id: u16) -> u32 {
     }
 }
 
+/// Identity of any PCI function found on the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciFunction {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+}
+
+/// Enumerate every function present on the PCI bus, regardless of class
+pub fn enumerate_functions() -> Vec<PciFunction> {
+    let mut functions = Vec::new();
+
+    for bus in 0..=255u8 {
+        for device in 0..32 {
+            for function in 0..8 {
+                let (valid, id, _, _) = read_pci_config(bus, device, function, 0);
+                let vendor_id = (id & 0xFFFF) as u16;
+                if !valid || vendor_id == 0xFFFF {
+                    continue;
+                }
+
+                let (_, class_data, _, _) = read_pci_config(bus, device, function, 0x08);
+                functions.push(PciFunction {
+                    bus,
+                    device,
+                    function,
+                    vendor_id,
+                    device_id: (id >> 16) as u16,
+                    class: (class_data >> 24) as u8,
+                    subclass: (class_data >> 16) as u8,
+                });
+            }
+        }
+    }
+
+    functions
+}
+
 /// Enumerate all GPU devices on the PCI bus
 pub fn enumerate_gpus() -> Result<Vec<PciDevice>, &'static str> {
     let mut devices = Vec::new();
@@ -0,0 +1,519 @@
+//! Software rasterization helpers
+//!
+//! Used when the active GPU device cannot perform an operation itself.
+//! All routines operate on 32-bit 0xAARRGGBB surfaces.
+
+use core::arch::asm;
+use core::arch::x86_64::*;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use lazy_static::lazy_static;
+use micromath::F32Ext;
+use raw_cpuid::CpuId;
+use super::BlendMode;
+
+const FILL_UNPROBED: u8 = 0;
+const FILL_SCALAR: u8 = 1;
+const FILL_SSE2: u8 = 2;
+const FILL_AVX: u8 = 3;
+
+/// Widest store usable for span fills, probed on first use
+static FILL_PATH: AtomicU8 = AtomicU8::new(FILL_UNPROBED);
+
+/// Blend in linear light instead of on the gamma-encoded values
+static LINEAR_BLENDING: AtomicBool = AtomicBool::new(false);
+
+/// Entries in the linear-to-sRGB table; finer than 8 bits so dark shades survive the round trip
+const LINEAR_LEVELS: usize = 4096;
+
+lazy_static! {
+    /// sRGB channel value to linear intensity in 0.0..=255.0
+    static ref SRGB_TO_LINEAR: [f32; 256] = {
+        let mut table = [0.0; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let c = i as f32 / 255.0;
+            let linear = if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+            *entry = linear * 255.0;
+        }
+        table
+    };
+
+    /// Linear intensity, quantized to `LINEAR_LEVELS`, to sRGB channel value
+    static ref LINEAR_TO_SRGB: [u8; LINEAR_LEVELS] = {
+        let mut table = [0u8; LINEAR_LEVELS];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let l = i as f32 / (LINEAR_LEVELS - 1) as f32;
+            let c = if l <= 0.003_130_8 { l * 12.92 } else { 1.055 * l.powf(1.0 / 2.4) - 0.055 };
+            *entry = (c * 255.0 + 0.5).min(255.0) as u8;
+        }
+        table
+    };
+}
+
+/// Choose between linear-light blending (correct edges and overlays) and the
+/// faster blending on gamma-encoded values
+pub fn set_linear_blending(enabled: bool) {
+    LINEAR_BLENDING.store(enabled, Ordering::Relaxed);
+}
+
+pub fn linear_blending() -> bool {
+    LINEAR_BLENDING.load(Ordering::Relaxed)
+}
+
+fn encode_srgb(linear: f32) -> u32 {
+    let index = (linear * (LINEAR_LEVELS - 1) as f32 / 255.0 + 0.5) as usize;
+    LINEAR_TO_SRGB[index.min(LINEAR_LEVELS - 1)] as u32
+}
+
+fn fill_path() -> u8 {
+    let path = FILL_PATH.load(Ordering::Relaxed);
+    if path != FILL_UNPROBED {
+        return path;
+    }
+
+    let path = if avx_usable() {
+        FILL_AVX
+    } else if CpuId::new().get_feature_info().map_or(false, |f| f.has_sse2()) {
+        FILL_SSE2
+    } else {
+        FILL_SCALAR
+    };
+    FILL_PATH.store(path, Ordering::Relaxed);
+    path
+}
+
+/// AVX needs CPU support and the OS having enabled YMM state in XCR0
+fn avx_usable() -> bool {
+    let (avx, osxsave) = CpuId::new()
+        .get_feature_info()
+        .map_or((false, false), |f| (f.has_avx(), f.has_oxsave()));
+    if !avx || !osxsave {
+        return false;
+    }
+
+    let xcr0: u32;
+    unsafe {
+        asm!("xgetbv", in("ecx") 0, out("eax") xcr0, out("edx") _, options(nomem, nostack));
+    }
+    xcr0 & 0b110 == 0b110
+}
+
+/// Fill `len` pixels starting at `dst` with `color`, using the widest stores available
+///
+/// # Safety
+/// `dst` must be valid for `len` u32 writes.
+pub unsafe fn fill_span(dst: *mut u32, len: usize, color: u32) {
+    match fill_path() {
+        FILL_AVX => fill_span_avx(dst, len, color),
+        FILL_SSE2 => fill_span_sse2(dst, len, color),
+        _ => fill_span_scalar(dst, len, color),
+    }
+}
+
+unsafe fn fill_span_scalar(dst: *mut u32, len: usize, color: u32) {
+    for i in 0..len {
+        *dst.add(i) = color;
+    }
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn fill_span_sse2(dst: *mut u32, len: usize, color: u32) {
+    // Scalar head up to 16-byte alignment, aligned 4-pixel stores, scalar tail
+    let head = dst.align_offset(16).min(len);
+    fill_span_scalar(dst, head, color);
+
+    let lanes = _mm_set1_epi32(color as i32);
+    let mut ptr = dst.add(head);
+    let mut remaining = len - head;
+    while remaining >= 4 {
+        _mm_store_si128(ptr as *mut __m128i, lanes);
+        ptr = ptr.add(4);
+        remaining -= 4;
+    }
+    fill_span_scalar(ptr, remaining, color);
+}
+
+#[target_feature(enable = "avx")]
+unsafe fn fill_span_avx(dst: *mut u32, len: usize, color: u32) {
+    // Scalar head up to 32-byte alignment, aligned 8-pixel stores, scalar tail
+    let head = dst.align_offset(32).min(len);
+    fill_span_scalar(dst, head, color);
+
+    let lanes = _mm256_set1_epi32(color as i32);
+    let mut ptr = dst.add(head);
+    let mut remaining = len - head;
+    while remaining >= 8 {
+        _mm256_store_si256(ptr as *mut __m256i, lanes);
+        ptr = ptr.add(8);
+        remaining -= 8;
+    }
+    fill_span_scalar(ptr, remaining, color);
+}
+
+/// A 32bpp pixel surface in memory (framebuffer or off-screen buffer)
+pub struct Surface {
+    pixels: *mut u32,
+    width: u32,
+    height: u32,
+    /// Row stride in pixels
+    pitch: u32,
+    /// Clip rectangle as (x, y, width, height)
+    clip: Option<(i32, i32, u32, u32)>,
+}
+
+impl Surface {
+    /// Wrap raw 32bpp memory
+    ///
+    /// # Safety
+    /// `pixels` must be valid for writes of `pitch_bytes * height` bytes.
+    pub unsafe fn from_raw(pixels: *mut u32, width: u32, height: u32, pitch_bytes: u32) -> Self {
+        Self {
+            pixels,
+            width,
+            height,
+            pitch: pitch_bytes / 4,
+            clip: None,
+        }
+    }
+
+    /// Wrap an in-memory pixel buffer
+    pub fn from_slice(pixels: &mut [u32], width: u32, height: u32) -> Self {
+        assert!(pixels.len() >= (width * height) as usize);
+        Self {
+            pixels: pixels.as_mut_ptr(),
+            width,
+            height,
+            pitch: width,
+            clip: None,
+        }
+    }
+
+    pub fn set_clip(&mut self, clip: Option<(i32, i32, u32, u32)>) {
+        self.clip = clip;
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return false;
+        }
+        match self.clip {
+            Some((cx, cy, cw, ch)) => {
+                x >= cx && x < cx + cw as i32 && y >= cy && y < cy + ch as i32
+            }
+            None => true,
+        }
+    }
+
+    /// Blend `color` into a pixel with the given coverage (0.0..=1.0)
+    pub fn plot(&mut self, x: i32, y: i32, color: u32, coverage: f32, mode: BlendMode) {
+        if coverage <= 0.0 || !self.contains(x, y) {
+            return;
+        }
+        unsafe {
+            let ptr = self.pixels.add(y as usize * self.pitch as usize + x as usize);
+            *ptr = blend_pixel(*ptr, color, coverage.min(1.0), mode);
+        }
+    }
+
+    /// Fill a rectangle, clipped to the surface and clip rect
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: u32, mode: BlendMode) {
+        let (cx, cy, cw, ch) = self.clip.unwrap_or((0, 0, self.width, self.height));
+        let left = x.max(cx).max(0);
+        let top = y.max(cy).max(0);
+        let right = (x + width as i32).min(cx + cw as i32).min(self.width as i32);
+        let bottom = (y + height as i32).min(cy + ch as i32).min(self.height as i32);
+        if left >= right || top >= bottom {
+            return;
+        }
+
+        let len = (right - left) as usize;
+        for row in top..bottom {
+            // Safety: the span was clipped to this row of the surface
+            unsafe {
+                let start = self.pixels.add(row as usize * self.pitch as usize + left as usize);
+                if mode == BlendMode::None {
+                    fill_span(start, len, color);
+                } else {
+                    for i in 0..len {
+                        *start.add(i) = blend_pixel(*start.add(i), color, 1.0, mode);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Surface {
+    /// Move a `width` x `height` block from (src_x, src_y) to (dst_x, dst_y),
+    /// e.g. to scroll. Overlapping blocks are handled; parts that fall off
+    /// the surface at either end are dropped. The clip rect is not applied.
+    pub fn copy_rect(&mut self, src_x: i32, src_y: i32, dst_x: i32, dst_y: i32, width: u32, height: u32) {
+        let (mut src_x, mut src_y, mut dst_x, mut dst_y) = (src_x as i64, src_y as i64, dst_x as i64, dst_y as i64);
+        let (mut width, mut height) = (width as i64, height as i64);
+
+        // Trim both rectangles by the same amount so they stay aligned
+        let cut = (-src_x).max(-dst_x).max(0);
+        src_x += cut;
+        dst_x += cut;
+        width -= cut;
+        let cut = (-src_y).max(-dst_y).max(0);
+        src_y += cut;
+        dst_y += cut;
+        height -= cut;
+        width = width.min(self.width as i64 - src_x).min(self.width as i64 - dst_x);
+        height = height.min(self.height as i64 - src_y).min(self.height as i64 - dst_y);
+        if width <= 0 || height <= 0 || (src_x, src_y) == (dst_x, dst_y) {
+            return;
+        }
+
+        let pitch = self.pitch as usize;
+        let row_at = |y: i64, x: i64| y as usize * pitch + x as usize;
+        // Copying down, the rows below must be read before they're overwritten
+        let copy_row = |row: i64| unsafe {
+            // Safety: both rows were clipped to the surface; `copy` allows overlap within a row
+            core::ptr::copy(
+                self.pixels.add(row_at(src_y + row, src_x)),
+                self.pixels.add(row_at(dst_y + row, dst_x)),
+                width as usize,
+            );
+        };
+        if dst_y > src_y {
+            (0..height).rev().for_each(copy_row);
+        } else {
+            (0..height).for_each(copy_row);
+        }
+    }
+}
+
+/// Draw a one-pixel line with Bresenham's algorithm
+pub fn draw_line(surface: &mut Surface, x1: i32, y1: i32, x2: i32, y2: i32, color: u32, mode: BlendMode) {
+    let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+    let (sx, sy) = (if x1 < x2 { 1 } else { -1 }, if y1 < y2 { 1 } else { -1 });
+    let (mut x, mut y, mut err) = (x1, y1, dx + dy);
+    loop {
+        surface.plot(x, y, color, 1.0, mode);
+        if x == x2 && y == y2 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+/// Combine a source color into a destination pixel, scaled by coverage.
+/// Blends in linear light when `set_linear_blending` is on.
+pub fn blend_pixel(dst: u32, src: u32, coverage: f32, mode: BlendMode) -> u32 {
+    let weight = match mode {
+        BlendMode::Alpha => coverage * ((src >> 24) & 0xFF) as f32 / 255.0,
+        _ => coverage,
+    };
+    let linear = linear_blending();
+
+    let mut out = dst & 0xFF00_0000;
+    for shift in [0u32, 8, 16] {
+        let (d, s) = (((dst >> shift) & 0xFF) as usize, ((src >> shift) & 0xFF) as usize);
+        let (d, s) = if linear {
+            (SRGB_TO_LINEAR[d], SRGB_TO_LINEAR[s])
+        } else {
+            (d as f32, s as f32)
+        };
+        let c = match mode {
+            BlendMode::None | BlendMode::Alpha => d + (s - d) * weight,
+            BlendMode::Additive => (d + s * weight).min(255.0),
+            BlendMode::Multiply => d + (d * s / 255.0 - d) * weight,
+        };
+        let c = if linear { encode_srgb(c) } else { ((c + 0.5) as u32).min(255) };
+        out |= c << shift;
+    }
+    out
+}
+
+/// Coverage of the pixel centred on `pixel` by the span `[lo, hi]`
+fn span_coverage(pixel: i32, lo: f32, hi: f32) -> f32 {
+    let start = lo.max(pixel as f32 - 0.5);
+    let end = hi.min(pixel as f32 + 0.5);
+    (end - start).max(0.0)
+}
+
+/// Draw an anti-aliased line using Xiaolin Wu's algorithm
+///
+/// `width` is measured along the minor axis, so a width of 1.0 gives the
+/// classic two-pixel Wu line. Axis-aligned lines take an exact span fill.
+pub fn draw_line_aa(
+    surface: &mut Surface,
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+    width: f32,
+    color: u32,
+    mode: BlendMode,
+) {
+    let half = width.max(1.0) / 2.0;
+
+    if (y1 - y0).abs() < f32::EPSILON {
+        let (start, end) = (x0.min(x1).round() as i32, x0.max(x1).round() as i32);
+        for y in (y0 - half).floor() as i32..=(y0 + half).ceil() as i32 {
+            let coverage = span_coverage(y, y0 - half, y0 + half);
+            for x in start..=end {
+                surface.plot(x, y, color, coverage, mode);
+            }
+        }
+        return;
+    }
+
+    if (x1 - x0).abs() < f32::EPSILON {
+        let (start, end) = (y0.min(y1).round() as i32, y0.max(y1).round() as i32);
+        for x in (x0 - half).floor() as i32..=(x0 + half).ceil() as i32 {
+            let coverage = span_coverage(x, x0 - half, x0 + half);
+            for y in start..=end {
+                surface.plot(x, y, color, coverage, mode);
+            }
+        }
+        return;
+    }
+
+    // Walk the major axis; `steep` swaps x/y so the walk is always along x
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    let (mut x0, mut y0, mut x1, mut y1) = if steep {
+        (y0, x0, y1, x1)
+    } else {
+        (x0, y0, x1, y1)
+    };
+    if x0 > x1 {
+        core::mem::swap(&mut x0, &mut x1);
+        core::mem::swap(&mut y0, &mut y1);
+    }
+
+    let gradient = (y1 - y0) / (x1 - x0);
+    let first = x0.round() as i32;
+    let last = x1.round() as i32;
+
+    for x in first..=last {
+        // Endpoints only cover the part of the pixel the line actually spans
+        let gap = if first == last {
+            x1 - x0
+        } else if x == first {
+            1.0 - (x0 + 0.5).fract()
+        } else if x == last {
+            (x1 + 0.5).fract()
+        } else {
+            1.0
+        };
+
+        let center = y0 + gradient * (x as f32 - x0);
+        for minor in (center - half).floor() as i32..=(center + half).ceil() as i32 {
+            let coverage = span_coverage(minor, center - half, center + half) * gap;
+            if steep {
+                surface.plot(minor, x, color, coverage, mode);
+            } else {
+                surface.plot(x, minor, color, coverage, mode);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Gamma-space blending; nothing in the tests turns linear blending on
+
+    #[test]
+    fn alpha_blend_weights_by_source_alpha() {
+        // Half-transparent white over black lands mid-grey, keeping the destination's alpha byte
+        assert_eq!(blend_pixel(0x0000_0000, 0x80FF_FFFF, 1.0, BlendMode::Alpha), 0x0080_8080);
+        // Opaque red replaces blue
+        assert_eq!(blend_pixel(0xFF00_00FF, 0xFFFF_0000, 1.0, BlendMode::Alpha), 0xFFFF_0000);
+        // Fully transparent leaves the destination alone
+        assert_eq!(blend_pixel(0xFF12_3456, 0x00FF_FFFF, 1.0, BlendMode::Alpha), 0xFF12_3456);
+    }
+
+    #[test]
+    fn additive_blend_saturates() {
+        assert_eq!(blend_pixel(0x0080_8080, 0x00A0_1010, 1.0, BlendMode::Additive), 0x00FF_9090);
+    }
+
+    #[test]
+    fn multiply_blend_darkens() {
+        assert_eq!(blend_pixel(0x00FF_8040, 0x0080_8080, 1.0, BlendMode::Multiply), 0x0080_4020);
+        // White is the identity
+        assert_eq!(blend_pixel(0x0012_3456, 0x00FF_FFFF, 1.0, BlendMode::Multiply), 0x0012_3456);
+    }
+
+    #[test]
+    fn plain_fill_ignores_alpha_but_honors_coverage() {
+        assert_eq!(blend_pixel(0x0000_0000, 0x00FF_FFFF, 1.0, BlendMode::None), 0x00FF_FFFF);
+        assert_eq!(blend_pixel(0x0000_0000, 0x00FF_FFFF, 0.5, BlendMode::None), 0x0080_8080);
+    }
+
+    const SIZE: u32 = 16;
+
+    /// Draw a white one-pixel AA line on black and return each pixel's intensity
+    fn aa_line(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<u32> {
+        let mut pixels = vec![0u32; (SIZE * SIZE) as usize];
+        let mut surface = Surface::from_slice(&mut pixels, SIZE, SIZE);
+        draw_line_aa(&mut surface, x0, y0, x1, y1, 1.0, 0x00FF_FFFF, BlendMode::None);
+        pixels.iter().map(|p| p & 0xFF).collect()
+    }
+
+    fn at(pixels: &[u32], x: u32, y: u32) -> u32 {
+        pixels[(y * SIZE + x) as usize]
+    }
+
+    /// Each step along the major axis between the endpoints adds up to one
+    /// full-intensity pixel, split across its two neighbours
+    fn assert_full_coverage(pixels: &[u32], major: core::ops::RangeInclusive<u32>, steep: bool) {
+        for m in major {
+            let covering: Vec<u32> = (0..SIZE)
+                .map(|n| if steep { at(pixels, n, m) } else { at(pixels, m, n) })
+                .filter(|&i| i > 0)
+                .collect();
+            assert!(covering.len() <= 2, "{} pixels lit at {}", covering.len(), m);
+            let total: u32 = covering.iter().sum();
+            assert!((254..=256).contains(&total), "coverage {} at {}", total, m);
+        }
+    }
+
+    #[test]
+    fn aa_line_draws_endpoints_and_splits_coverage() {
+        let pixels = aa_line(2.0, 3.0, 12.0, 7.0);
+        // Endpoints at pixel centres cover half their pixel
+        assert_eq!(at(&pixels, 2, 3), 128);
+        assert_eq!(at(&pixels, 12, 7), 128);
+        assert_full_coverage(&pixels, 3..=11, false);
+        // Nothing beyond the endpoints
+        assert!((0..SIZE).all(|y| at(&pixels, 1, y) == 0 && at(&pixels, 13, y) == 0));
+
+        // Direction doesn't matter
+        assert_eq!(aa_line(12.0, 7.0, 2.0, 3.0), pixels);
+    }
+
+    #[test]
+    fn aa_line_walks_the_major_axis_of_steep_lines() {
+        let pixels = aa_line(3.0, 2.0, 7.0, 12.0);
+        assert_eq!(at(&pixels, 3, 2), 128);
+        assert_eq!(at(&pixels, 7, 12), 128);
+        assert_full_coverage(&pixels, 3..=11, true);
+    }
+
+    #[test]
+    fn axis_aligned_aa_lines_are_solid() {
+        let horizontal = aa_line(2.0, 5.0, 12.0, 5.0);
+        let vertical = aa_line(5.0, 2.0, 5.0, 12.0);
+        for i in 0..SIZE {
+            for j in 0..SIZE {
+                let expected = if j == 5 && (2..=12).contains(&i) { 255 } else { 0 };
+                assert_eq!(at(&horizontal, i, j), expected);
+                assert_eq!(at(&vertical, j, i), expected);
+            }
+        }
+    }
+}
+
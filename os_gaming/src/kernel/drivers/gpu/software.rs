@@ -0,0 +1,336 @@
+//! Software GPU backend
+//!
+//! Renders everything on the CPU into a framebuffer in RAM. Used when no
+//! display hardware is detected and for headless runs, so the whole GUI stack
+//! works without a GPU.
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::raster::{self, Surface};
+use super::specific::GpuDevice;
+use super::{BlendMode, DisplayMode, Feature, FeatureSet, GpuError, GpuInfo, TextureFilter, TextureFormat};
+
+/// Size used when the software backend stands in for missing hardware
+pub const DEFAULT_WIDTH: u32 = 1024;
+pub const DEFAULT_HEIGHT: u32 = 768;
+/// Largest surface or texture edge accepted
+const MAX_DIMENSION: u32 = 8192;
+
+static MODES: [DisplayMode; 4] = [
+    DisplayMode { width: 800, height: 600, bpp: 32, refresh_rate: 60 },
+    DisplayMode { width: 1024, height: 768, bpp: 32, refresh_rate: 60 },
+    DisplayMode { width: 1280, height: 720, bpp: 32, refresh_rate: 60 },
+    DisplayMode { width: 1920, height: 1080, bpp: 32, refresh_rate: 60 },
+];
+
+/// A texture converted to 0xAARRGGBB when created
+struct SoftTexture {
+    width: u32,
+    height: u32,
+    /// The data as uploaded, returned by `get_texture_data`
+    data: Vec<u8>,
+    pixels: Vec<u32>,
+}
+
+/// CPU-backed implementation of `GpuDevice`
+pub struct SoftwareGpu {
+    width: u32,
+    height: u32,
+    framebuffer: Vec<u32>,
+    clip: Option<(i32, i32, u32, u32)>,
+    blend_mode: BlendMode,
+    textures: BTreeMap<u32, SoftTexture>,
+    next_texture_id: u32,
+}
+
+/// Create a software device with a `width` x `height` framebuffer
+pub fn create_driver(width: u32, height: u32) -> Result<Box<dyn GpuDevice>, GpuError> {
+    Ok(Box::new(SoftwareGpu::new(width, height)?))
+}
+
+impl SoftwareGpu {
+    pub fn new(width: u32, height: u32) -> Result<Self, GpuError> {
+        validate_size(width, height)?;
+        Ok(Self {
+            width,
+            height,
+            framebuffer: vec![0; (width * height) as usize],
+            clip: None,
+            blend_mode: BlendMode::None,
+            textures: BTreeMap::new(),
+            next_texture_id: 1,
+        })
+    }
+
+    /// Pixel at (x, y) as 0xAARRGGBB
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.framebuffer[(y * self.width + x) as usize])
+    }
+
+    fn resize(&mut self, width: u32, height: u32) -> Result<(), GpuError> {
+        validate_size(width, height)?;
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.framebuffer = vec![0; (width * height) as usize];
+            self.clip = None;
+        }
+        Ok(())
+    }
+
+    fn surface(&mut self) -> Surface {
+        let mut surface = Surface::from_slice(&mut self.framebuffer, self.width, self.height);
+        surface.set_clip(self.clip);
+        surface
+    }
+
+    /// Intersect a rectangle with the screen and clip rect
+    fn clipped(&self, x: i32, y: i32, width: u32, height: u32) -> Option<(usize, usize, usize, usize)> {
+        let (cx, cy, cw, ch) = self.clip.unwrap_or((0, 0, self.width, self.height));
+        let left = x.max(cx).max(0);
+        let top = y.max(cy).max(0);
+        let right = (x + width as i32).min(cx + cw as i32).min(self.width as i32);
+        let bottom = (y + height as i32).min(cy + ch as i32).min(self.height as i32);
+        if left >= right || top >= bottom {
+            return None;
+        }
+        Some((left as usize, top as usize, (right - left) as usize, (bottom - top) as usize))
+    }
+}
+
+fn validate_size(width: u32, height: u32) -> Result<(), GpuError> {
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(GpuError::InvalidParameter);
+    }
+    Ok(())
+}
+
+/// Texel-space position (16.16 fixed point) of the centre of destination
+/// pixel `offset` when `dst_size` pixels cover `src_size` texels
+fn texel_coordinate(offset: i64, dst_size: u32, src_size: u32) -> i64 {
+    ((2 * offset + 1) * src_size as i64 * 65536) / (2 * dst_size as i64)
+}
+
+impl SoftTexture {
+    /// The texel containing the point: exact, no blending between neighbours
+    fn nearest(&self, u: i64, v: i64) -> u32 {
+        let x = (u >> 16).clamp(0, self.width as i64 - 1) as usize;
+        let y = (v >> 16).clamp(0, self.height as i64 - 1) as usize;
+        self.pixels[y * self.width as usize + x]
+    }
+
+    /// Weighted average of the four texels around the point, edges clamped
+    fn bilinear(&self, u: i64, v: i64) -> u32 {
+        // Texel centres sit at +0.5
+        let u = (u - 32768).clamp(0, (self.width as i64 - 1) << 16);
+        let v = (v - 32768).clamp(0, (self.height as i64 - 1) << 16);
+        let (x0, y0) = ((u >> 16) as usize, (v >> 16) as usize);
+        let x1 = (x0 + 1).min(self.width as usize - 1);
+        let y1 = (y0 + 1).min(self.height as usize - 1);
+        let fx = ((u >> 8) & 0xFF) as u32;
+        let fy = ((v >> 8) & 0xFF) as u32;
+
+        let row = self.width as usize;
+        let (p00, p10) = (self.pixels[y0 * row + x0], self.pixels[y0 * row + x1]);
+        let (p01, p11) = (self.pixels[y1 * row + x0], self.pixels[y1 * row + x1]);
+        let mut out = 0;
+        for shift in [0, 8, 16, 24] {
+            let channel = |p: u32| (p >> shift) & 0xFF;
+            let top = channel(p00) * (256 - fx) + channel(p10) * fx;
+            let bottom = channel(p01) * (256 - fx) + channel(p11) * fx;
+            let value = (top * (256 - fy) + bottom * fy + (1 << 15)) >> 16;
+            out |= value.min(255) << shift;
+        }
+        out
+    }
+}
+
+/// Convert uploaded texture data to 0xAARRGGBB
+fn to_argb(format: TextureFormat, data: &[u8], count: usize) -> Vec<u32> {
+    let argb = |a: u8, r: u8, g: u8, b: u8| (a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | b as u32;
+    match format {
+        TextureFormat::RGBA8 => data.chunks_exact(4).take(count).map(|p| argb(p[3], p[0], p[1], p[2])).collect(),
+        TextureFormat::BGRA8 => data.chunks_exact(4).take(count).map(|p| argb(p[3], p[2], p[1], p[0])).collect(),
+        TextureFormat::RGB8 => data.chunks_exact(3).take(count).map(|p| argb(0xFF, p[0], p[1], p[2])).collect(),
+        TextureFormat::BGR8 => data.chunks_exact(3).take(count).map(|p| argb(0xFF, p[2], p[1], p[0])).collect(),
+        // Alpha-only textures are white with the given coverage
+        TextureFormat::A8 => data.iter().take(count).map(|&a| argb(a, 0xFF, 0xFF, 0xFF)).collect(),
+    }
+}
+
+fn texture_format(format: u32) -> Result<(TextureFormat, usize), GpuError> {
+    match format {
+        0 => Ok((TextureFormat::RGBA8, 4)),
+        1 => Ok((TextureFormat::RGB8, 3)),
+        2 => Ok((TextureFormat::BGRA8, 4)),
+        3 => Ok((TextureFormat::BGR8, 3)),
+        4 => Ok((TextureFormat::A8, 1)),
+        _ => Err(GpuError::UnsupportedFormat),
+    }
+}
+
+impl GpuDevice for SoftwareGpu {
+    fn get_info(&self) -> Result<GpuInfo, GpuError> {
+        Ok(GpuInfo {
+            vendor: "Software",
+            device: "CPU Renderer",
+            vram_size: self.framebuffer.len() * 4,
+            max_texture_size: MAX_DIMENSION,
+            features: FeatureSet::of(&[Feature::Blending, Feature::TextureFiltering]),
+            current_mode: DisplayMode { width: self.width, height: self.height, bpp: 32, refresh_rate: 60 },
+            available_modes: &MODES,
+        })
+    }
+
+    fn get_framebuffer(&mut self, width: u32, height: u32) -> Result<usize, GpuError> {
+        self.resize(width, height)?;
+        Ok(self.framebuffer.as_mut_ptr() as usize)
+    }
+
+    fn get_framebuffer_pitch(&self) -> Result<u32, GpuError> {
+        Ok(self.width * 4)
+    }
+
+    fn set_display_mode(&mut self, mode: DisplayMode) -> Result<(), GpuError> {
+        if mode.bpp != 32 {
+            return Err(GpuError::UnsupportedFormat);
+        }
+        self.resize(mode.width, mode.height)
+    }
+
+    fn clear(&mut self, color: u32) -> Result<(), GpuError> {
+        // Safety: the span is exactly the framebuffer
+        unsafe { raster::fill_span(self.framebuffer.as_mut_ptr(), self.framebuffer.len(), color) };
+        Ok(())
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: u32) -> Result<(), GpuError> {
+        let (left, top, w, h) = match self.clipped(x, y, width, height) {
+            Some(rect) => rect,
+            None => return Ok(()),
+        };
+        let stride = self.width as usize;
+        if self.blend_mode == BlendMode::None {
+            for row in top..top + h {
+                // Safety: `clipped` keeps the span inside this row
+                unsafe { raster::fill_span(self.framebuffer.as_mut_ptr().add(row * stride + left), w, color) };
+            }
+        } else {
+            for row in top..top + h {
+                for pixel in &mut self.framebuffer[row * stride + left..row * stride + left + w] {
+                    *pixel = raster::blend_pixel(*pixel, color, 1.0, self.blend_mode);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u32) -> Result<(), GpuError> {
+        let mode = self.blend_mode;
+        raster::draw_line(&mut self.surface(), x1, y1, x2, y2, color, mode);
+        Ok(())
+    }
+
+    fn draw_line_aa(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, width: f32, color: u32) -> Result<(), GpuError> {
+        let mode = self.blend_mode;
+        raster::draw_line_aa(&mut self.surface(), x1, y1, x2, y2, width, color, mode);
+        Ok(())
+    }
+
+    fn create_texture(&mut self, width: u32, height: u32, format: u32, data: &[u8]) -> Result<u32, GpuError> {
+        validate_size(width, height).map_err(|_| GpuError::TextureCreationFailed)?;
+        let (format, bytes_per_pixel) = texture_format(format)?;
+        let count = (width * height) as usize;
+        if data.len() < count * bytes_per_pixel {
+            return Err(GpuError::InvalidParameter);
+        }
+
+        let id = self.next_texture_id;
+        self.next_texture_id += 1;
+        self.textures.insert(id, SoftTexture {
+            width,
+            height,
+            data: data[..count * bytes_per_pixel].to_vec(),
+            pixels: to_argb(format, data, count),
+        });
+        Ok(id)
+    }
+
+    fn destroy_texture(&mut self, texture_id: u32) -> Result<(), GpuError> {
+        self.textures.remove(&texture_id).map(|_| ()).ok_or(GpuError::InvalidTexture)
+    }
+
+    fn get_texture_data(&self, texture_id: u32) -> Result<&[u8], GpuError> {
+        self.textures.get(&texture_id).map(|t| t.data.as_slice()).ok_or(GpuError::InvalidTexture)
+    }
+
+    fn draw_texture(&mut self, texture_id: u32, x: i32, y: i32, width: u32, height: u32) -> Result<(), GpuError> {
+        self.draw_texture_filtered(texture_id, x, y, width, height, super::texture_filter())
+    }
+
+    fn draw_texture_filtered(
+        &mut self,
+        texture_id: u32,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        filter: TextureFilter,
+    ) -> Result<(), GpuError> {
+        let texture = self.textures.get(&texture_id).ok_or(GpuError::InvalidTexture)?;
+        let (left, top, w, h) = match self.clipped(x, y, width, height) {
+            Some(rect) => rect,
+            None => return Ok(()),
+        };
+
+        // Map each destination pixel centre back into the texture
+        let stride = self.width as usize;
+        for row in top..top + h {
+            let v = texel_coordinate(row as i64 - y as i64, height, texture.height);
+            for col in left..left + w {
+                let u = texel_coordinate(col as i64 - x as i64, width, texture.width);
+                let src = match filter {
+                    TextureFilter::Nearest => texture.nearest(u, v),
+                    TextureFilter::Bilinear => texture.bilinear(u, v),
+                };
+                let dst = &mut self.framebuffer[row * stride + col];
+                *dst = match self.blend_mode {
+                    BlendMode::None => src,
+                    mode => raster::blend_pixel(*dst, src, 1.0, mode),
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn set_clip_rect(&mut self, x: i32, y: i32, width: u32, height: u32) -> Result<(), GpuError> {
+        self.clip = Some((x, y, width, height));
+        Ok(())
+    }
+
+    fn clear_clip_rect(&mut self) -> Result<(), GpuError> {
+        self.clip = None;
+        Ok(())
+    }
+
+    fn set_blend_mode(&mut self, mode: u32) -> Result<(), GpuError> {
+        self.blend_mode = BlendMode::from_u32(mode).ok_or(GpuError::InvalidParameter)?;
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<(), GpuError> {
+        // The framebuffer in RAM is the output
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> Result<(), GpuError> {
+        self.textures.clear();
+        Ok(())
+    }
+}
@@ -5,7 +5,7 @@ use alloc::vec;
 use alloc::string::{String, ToString};
 use alloc::boxed::Box;
 use core::sync::atomic::{AtomicBool, Ordering};
-use crate::kernel::drivers::gpu::{GpuError, Feature, DisplayMode, GpuInfo};
+use crate::kernel::drivers::gpu::{self, BlendMode, GpuError, Feature, FeatureSet, DisplayMode, GpuInfo};
 use crate::kernel::drivers::gpu::specific::GpuDevice;
 use crate::kernel::drivers::gpu::pci::PciDevice;
 
@@ -165,6 +165,8 @@ pub struct AmdGpuDevice {
     pub framebuffer_width: u32,
     pub framebuffer_height: u32,
     pub framebuffer_bpp: u8,
+    /// Fills other than `BlendMode::None` are done on the CPU; the 2D engine only copies
+    blend_mode: BlendMode,
     
     // Power management
     pub current_power_state: u32,
@@ -220,6 +222,7 @@ impl Default for AmdGpuDevice {
             framebuffer_width: 0,
             framebuffer_height: 0,
             framebuffer_bpp: 32,
+            blend_mode: BlendMode::None,
             current_power_state: commands::POWER_NORMAL,
             supports_hw_cursor: false,
             supports_3d: false,
@@ -706,6 +709,41 @@ impl AmdGpuDevice {
         Ok(())
     }
     
+    /// Blend a color over a rectangle of the framebuffer with the active
+    /// blend mode, treating the color's high byte as alpha
+    fn blend_rect(&self, rect: Rect, color: u32) -> Result<(), AmdGpuError> {
+        if !self.initialized {
+            return Err(AmdGpuError::NotInitialized);
+        }
+        if self.framebuffer_bpp != 32 || self.framebuffer_address == 0 {
+            return Err(AmdGpuError::InvalidParameter);
+        }
+
+        let left = rect.x.max(0);
+        let top = rect.y.max(0);
+        let right = (rect.x + rect.width as i32).min(self.framebuffer_width as i32);
+        let bottom = (rect.y + rect.height as i32).min(self.framebuffer_height as i32);
+        if left >= right || top >= bottom {
+            return Ok(());
+        }
+
+        // Earlier engine fills must land before their pixels are read back
+        self.wait_for_2d_idle()?;
+
+        let framebuffer = self.framebuffer_address as *mut u8;
+        for y in top..bottom {
+            // Safety: the rows and columns were clipped to the framebuffer
+            unsafe {
+                let row = framebuffer.add(y as usize * self.framebuffer_pitch as usize) as *mut u32;
+                for x in left..right {
+                    let pixel = row.add(x as usize);
+                    *pixel = gpu::blend_pixel(*pixel, color, 1.0, self.blend_mode);
+                }
+            }
+        }
+        Ok(())
+    }
+    
     /// Draw a line
     pub fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u32) -> Result<(), AmdGpuError> {
         if !self.initialized {
@@ -965,18 +1003,18 @@ impl GpuDevice for AmdGpuDevice {
         }
         
         // Create features flags
-        let mut features = Feature::Acceleration2D as u32;
+        let mut features = FeatureSet::of(&[Feature::Acceleration2D]);
         
         if self.supports_3d {
-            features |= Feature::Rendering3D as u32;
+            features.insert(Feature::Rendering3D);
         }
         
         if self.supports_hw_cursor {
-            features |= Feature::HardwareCursor as u32;
+            features.insert(Feature::HardwareCursor);
         }
         
         if self.supports_compute {
-            features |= Feature::ComputeShaders as u32;
+            features.insert(Feature::ComputeShaders);
         }
         
         // Get display modes
@@ -1048,8 +1086,12 @@ impl GpuDevice for AmdGpuDevice {
         }
         
         let rect = Rect { x, y, width, height };
+        let result = match self.blend_mode {
+            BlendMode::None => self.fill_rect(rect, color),
+            _ => self.blend_rect(rect, color),
+        };
         
-        match self.fill_rect(rect, color) {
+        match result {
             Ok(_) => Ok(()),
             Err(_) => Err(GpuError::DrawingFailed),
         }
@@ -1158,7 +1200,7 @@ impl GpuDevice for AmdGpuDevice {
             return Err(GpuError::NotInitialized);
         }
         
-        // In a real implementation, this would set blending mode
+        self.blend_mode = BlendMode::from_u32(mode).ok_or(GpuError::InvalidParameter)?;
         Ok(())
     }
     
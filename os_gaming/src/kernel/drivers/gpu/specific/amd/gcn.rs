@@ -8,7 +8,7 @@ use alloc::collections::BTreeMap;
 use alloc::string::String;
 use core::sync::atomic::{AtomicBool, Ordering};
 use crate::kernel::drivers::gpu::pci::PciDevice;
-use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, Feature};
+use crate::kernel::drivers::gpu::{self, GpuInfo, GpuError, DisplayMode, Feature, FeatureSet, FenceId};
 use super::super::{GpuDevice};
 use super::common;
 
@@ -53,6 +53,9 @@ pub struct GcnDevice {
     // Hardware acceleration
     acceleration_enabled: AtomicBool,
     
+    // Fencing
+    last_fence: FenceId,
+    
     // Device identification
     device_name: &'static str,
 }
@@ -145,6 +148,7 @@ impl GcnDevice {
             supports_freesync,
             supports_hdr,
             acceleration_enabled: AtomicBool::new(true),
+            last_fence: 0,
             device_name,
         };
         
@@ -249,6 +253,9 @@ impl GcnDevice {
         // Configure command processor
         self.write_reg32(CP_CONFIG, 0x00000001);
         
+        // Fence ids start at 1, so a cleared scratch register means nothing has completed
+        self.write_reg32(Self::SCRATCH_REG0, 0);
+        
         Ok(())
     }
     
@@ -277,6 +284,11 @@ impl GcnDevice {
         common::write_register(self.mmio_base, offset, value);
     }
     
+    /// Scratch register the command processor writes fence values back to
+    const SCRATCH_REG0: usize = 0x8500;
+    /// Command processor register queueing a scratch write-back behind prior work
+    const CP_SCRATCH_WRITEBACK: usize = 0xC010;
+    
     /// Software implementation of rectangle fill
     fn sw_fill_rect(&self, mut x: i32, mut y: i32, mut width: u32, mut height: u32, color: u32) -> Result<(), GpuError> {
         // Apply clipping if enabled
@@ -320,6 +332,9 @@ impl GcnDevice {
             height = (self.height as i32 - y) as u32;
         }
         
+        // Anything but a plain copy has to read the destination back
+        let blend = gpu::BlendMode::from_u32(self.blend_mode).unwrap_or(gpu::BlendMode::None);
+
         // Draw the rectangle
         unsafe {
             let framebuffer = self.framebuffer as *mut u32;
@@ -330,7 +345,11 @@ impl GcnDevice {
                 let row_ptr = framebuffer.add(row_offset as usize);
                 
                 for col in 0..width {
-                    *row_ptr.add(col as usize) = color;
+                    let pixel = row_ptr.add(col as usize);
+                    *pixel = match blend {
+                        gpu::BlendMode::None => color,
+                        mode => gpu::blend_pixel(*pixel, color, 1.0, mode),
+                    };
                 }
             }
         }
@@ -410,21 +429,24 @@ impl GpuDevice for GcnDevice {
         };
         
         // Create features based on GCN generation
-        let mut features = Feature::Acceleration2D as u32 | 
-                          Feature::Rendering3D as u32 |
-                          Feature::HardwareCursor as u32 | 
-                          Feature::MemoryMapping as u32 |
-                          Feature::Shaders as u32 |
-                          Feature::RenderTargets as u32;
+        let mut features = FeatureSet::of(&[
+            Feature::Acceleration2D,
+            Feature::Rendering3D,
+            Feature::HardwareCursor,
+            Feature::MemoryMapping,
+            Feature::Shaders,
+            Feature::RenderTargets,
+        ]);
                           
         // Add GCN specific features
         if self.gcn_version >= 3 {
-            features |= Feature::Blending as u32 | Feature::DmaTransfers as u32;
+            features.insert(Feature::Blending);
+            features.insert(Feature::DmaTransfers);
         }
         
         // Add FreeSync feature if supported
         if self.supports_freesync {
-            features |= Feature::VariableRefresh as u32;
+            features.insert(Feature::VariableRefresh);
         }
         
         // Create GPU info with AMD-specific capabilities
@@ -849,15 +871,62 @@ impl GpuDevice for GcnDevice {
         Ok(())
     }
 
+    fn insert_fence(&mut self) -> FenceId {
+        if !self.is_initialized || !self.acceleration_enabled.load(Ordering::Relaxed) {
+            // Software rendering has already finished by the time we get here
+            return self.last_fence;
+        }
+        
+        // The CP writes the value into SCRATCH_REG0 once it reaches this point in the stream
+        self.last_fence += 1;
+        self.write_reg32(Self::CP_SCRATCH_WRITEBACK, self.last_fence as u32);
+        self.last_fence
+    }
+    
+    fn wait_fence(&self, id: FenceId, timeout_ms: u64) -> Result<(), GpuError> {
+        if !self.is_initialized {
+            return Err(GpuError::NotInitialized);
+        }
+        
+        gpu::poll_fence(|| self.read_reg32(Self::SCRATCH_REG0), id, self.last_fence, timeout_ms)
+    }
+    
+    fn set_variable_refresh(&mut self, enabled: bool, min_hz: u32, max_hz: u32) -> Result<(), GpuError> {
+        if !self.is_initialized {
+            return Err(GpuError::NotInitialized);
+        }
+        if !self.supports_freesync {
+            return Err(GpuError::UnsupportedFeature);
+        }
+        
+        // AMD-specific registers (simplified)
+        const CRTC_VRR_CONTROL: usize = 0xA300;
+        const CRTC_VRR_MIN_HZ: usize = 0xA304;
+        const CRTC_VRR_MAX_HZ: usize = 0xA308;
+        
+        if enabled {
+            // The range must be in place before the CRTC starts stretching vblank
+            self.write_reg32(CRTC_VRR_MIN_HZ, min_hz);
+            self.write_reg32(CRTC_VRR_MAX_HZ, max_hz);
+            self.write_reg32(CRTC_VRR_CONTROL, 0x1);
+            log::info!("FreeSync enabled, {}-{} Hz", min_hz, max_hz);
+        } else {
+            self.write_reg32(CRTC_VRR_CONTROL, 0x0);
+            log::info!("FreeSync disabled");
+        }
+        
+        Ok(())
+    }
+
     fn present(&mut self) -> Result<(), GpuError> {
         if !self.is_initialized {
             return Err(GpuError::NotInitialized);
         }
         
         // In a real implementation with double buffering, we would:
-        // 1. Wait for GPU to complete rendering
-        // 2. Update the display controller to use the new buffer
-        // 3. Swap front and back buffers
+        // 1. Update the display controller to use the new buffer
+        // 2. Swap front and back buffers
+        // Completion of prior rendering is ensured by gpu::present() waiting on a fence
         
         // AMD-specific registers (simplified)
         const CRTC_UPDATE: usize = 0xA200;
@@ -6,7 +6,7 @@ use alloc::boxed::Box;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicBool, Ordering};
 use crate::kernel::drivers::gpu::pci::PciDevice;
-use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, TextureFormat, Feature};
+use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, TextureFormat, Feature, FeatureSet};
 use super::super::GpuDevice;
 use super::common;
 
@@ -140,9 +140,14 @@ impl GpuDevice for AmdRdnaGpu {
             device: "Radeon RDNA",
             vram_size: self.framebuffer_size,
             max_texture_size: 16384,
-            features: Feature::Acceleration2D as u32 | Feature::Blending as u32 | 
-                     Feature::HardwareCursor as u32 | Feature::MemoryMapping as u32 |
-                     Feature::Rendering3D as u32 | Feature::Shaders as u32,
+            features: FeatureSet::of(&[
+                Feature::Acceleration2D,
+                Feature::Blending,
+                Feature::HardwareCursor,
+                Feature::MemoryMapping,
+                Feature::Rendering3D,
+                Feature::Shaders,
+            ]),
             current_mode,
             available_modes: Box::leak(Box::new(modes)),
         };
@@ -2,6 +2,9 @@
 //!
 //! This module provides shared functionality for all Intel GPU generations.
 
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::PhysAddr;
+
 use crate::kernel::drivers::gpu::GpuError;
 use crate::kernel::memory;
 
@@ -22,6 +25,31 @@ pub fn unmap_mmio(physical_address: usize, size: usize) -> Result<(), GpuError>
     Ok(())
 }
 
+/// Physical address of a memory BAR, joining the upper half of a 64-bit BAR
+pub fn bar_address(low: u32, high: u32) -> u64 {
+    let base = (low & 0xFFFF_FFF0) as u64;
+    if low & 0x6 == 0x4 {
+        base | (high as u64) << 32
+    } else {
+        base
+    }
+}
+
+/// Map `size` bytes of a BAR into kernel space, uncached, and return the
+/// virtual address
+pub fn map_bar(physical_address: u64, size: usize) -> Result<usize, GpuError> {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    memory::map_phys_mem_to_kernel_virt(PhysAddr::new(physical_address), size, flags)
+        .map(|virt| virt.as_u64() as usize)
+        .map_err(|_| GpuError::MappingFailed)
+}
+
+/// Undo `map_bar`
+pub fn unmap_bar(virtual_address: usize, size: usize) -> Result<(), GpuError> {
+    memory::unmap_kernel_virt_region(x86_64::VirtAddr::new(virtual_address as u64), size)
+        .map_err(|_| GpuError::MappingFailed)
+}
+
 /// Read a 32-bit register
 pub fn read_reg32(base: usize, offset: usize) -> u32 {
     unsafe {
@@ -38,6 +66,16 @@ pub fn write_reg32(base: usize, offset: usize, value: u32) {
     }
 }
 
+/// Pipe A display scan line register
+pub const PIPE_A_DSL: usize = 0x70000;
+/// Scan line counter bits of the DSL register
+pub const DSL_LINE_MASK: u32 = 0x1FFF;
+
+/// Current scan line of pipe A
+pub fn read_scanline(base: usize) -> u32 {
+    read_reg32(base, PIPE_A_DSL) & DSL_LINE_MASK
+}
+
 /// Wait for a register bit to be set or cleared
 pub fn wait_for_reg32(base: usize, offset: usize, mask: u32, value: u32, timeout_ms: u32) -> Result<(), GpuError> {
     // In a real driver, this would wait with a timeout
@@ -8,7 +8,7 @@ use alloc::collections::BTreeMap;
 use alloc::string::String;
 use core::sync::atomic::{AtomicBool, Ordering};
 use crate::kernel::drivers::gpu::pci::PciDevice;
-use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, TextureFormat, Feature};
+use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, TextureFormat, Feature, FeatureSet};
 use super::{GpuDevice};
 use super::common;
 
@@ -299,12 +299,14 @@ impl GpuDevice for IntelGen11 {
         };
         
         // Gen11 has more features than Gen9
-        let features = Feature::Acceleration2D as u32 | 
-                      Feature::Blending as u32 | 
-                      Feature::HardwareCursor as u32 | 
-                      Feature::MemoryMapping as u32 |
-                      Feature::Shaders as u32 |      // Gen11 has better shader support
-                      Feature::RenderTargets as u32; // Gen11 supports render targets
+        let features = FeatureSet::of(&[
+            Feature::Acceleration2D,
+            Feature::Blending,
+            Feature::HardwareCursor,
+            Feature::MemoryMapping,
+            Feature::Shaders,       // Gen11 has better shader support
+            Feature::RenderTargets, // Gen11 supports render targets
+        ]);
         
         // Create GPU info with Intel-specific capabilities
         let info = GpuInfo {
@@ -678,6 +680,13 @@ impl GpuDevice for IntelGen11 {
         Ok(())
     }
 
+    fn current_scanline(&self) -> Option<u32> {
+        if !self.is_initialized {
+            return None;
+        }
+        Some(common::read_scanline(self.mmio_base))
+    }
+
     fn present(&mut self) -> Result<(), GpuError> {
         if !self.is_initialized {
             return Err(GpuError::NotInitialized);
@@ -7,7 +7,7 @@ use alloc::vec::Vec;
 use alloc::string::String;
 use core::sync::atomic::{AtomicBool, Ordering};
 use crate::kernel::drivers::gpu::pci::PciDevice;
-use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, TextureFormat, Feature};
+use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, TextureFormat, Feature, FeatureSet};
 use super::super::GpuDevice;
 use super::common::{map_mmio, unmap_mmio};
 
@@ -149,8 +149,12 @@ impl GpuDevice for IntelGen12Gpu {
             device: "Xe Graphics",
             vram_size: self.framebuffer_size,
             max_texture_size: 16384,
-            features: Feature::Acceleration2D as u32 | Feature::Blending as u32 | 
-                     Feature::HardwareCursor as u32 | Feature::MemoryMapping as u32,
+            features: FeatureSet::of(&[
+                Feature::Acceleration2D,
+                Feature::Blending,
+                Feature::HardwareCursor,
+                Feature::MemoryMapping,
+            ]),
             current_mode,
             available_modes: Box::leak(Box::new(modes)),
         };
@@ -1,17 +1,52 @@
 //! Intel Gen 9 GPU Driver
 //!
 //! This module provides driver implementations specific to Intel's Gen 9 architecture.
+//!
+//! Display bring-up reuses what the firmware left running: pipe A's timings,
+//! transcoder and port are kept, and only primary plane 1 is reprogrammed to
+//! scan out a linear XRGB8888 surface through the GMADR aperture. Modes no
+//! larger than the pipe source are shown from the top-left corner; anything
+//! else would need PLL and link training, which this driver doesn't do yet.
+//! Drawing is done by the CPU straight into the scanned-out surface.
 extern crate alloc;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 use alloc::collections::BTreeMap;
 use alloc::string::String;
-use core::sync::atomic::{AtomicBool, Ordering};
 use crate::kernel::drivers::gpu::pci::PciDevice;
-use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, Feature};
+use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, Feature, FeatureSet};
 use super::{GpuDevice};
 use super::common;
 
+/// Display engine registers of pipe A and its primary plane
+mod display {
+    pub const PIPECONF_A: usize = 0x70008;
+    pub const PIPECONF_STATE: u32 = 1 << 30;
+    /// Pipe source size, (width - 1) << 16 | (height - 1)
+    pub const PIPESRC_A: usize = 0x6001C;
+
+    pub const PLANE_CTL_1_A: usize = 0x70180;
+    pub const PLANE_STRIDE_1_A: usize = 0x70188;
+    pub const PLANE_POS_1_A: usize = 0x7018C;
+    pub const PLANE_SIZE_1_A: usize = 0x70190;
+    /// Graphics address of the surface; writing it arms the update
+    pub const PLANE_SURF_1_A: usize = 0x7019C;
+    pub const PLANE_OFFSET_1_A: usize = 0x701A4;
+
+    pub const PLANE_CTL_ENABLE: u32 = 1 << 31;
+    pub const PLANE_CTL_FORMAT_MASK: u32 = 0xF << 24;
+    pub const PLANE_CTL_FORMAT_XRGB8888: u32 = 0x4 << 24;
+    pub const PLANE_CTL_TILED_MASK: u32 = 0x7 << 10;
+    pub const PLANE_CTL_ROTATION_MASK: u32 = 0x3;
+    pub const PLANE_SURF_ADDR_MASK: u32 = !0xFFF;
+
+    /// Linear surfaces need their stride in 64-byte units
+    pub const STRIDE_ALIGN: u32 = 64;
+}
+
+/// Size of the register range mapped from GTTMMADR
+const MMIO_SIZE: usize = 2 * 1024 * 1024;
+
 /// Represents the Intel Gen 9 GPU device.
 pub struct IntelGen9 {
     // Device identification
@@ -22,8 +57,15 @@ pub struct IntelGen9 {
     mmio_base: usize,
     mmio_size: usize,
     framebuffer: usize,
+    framebuffer_size: usize,
+    /// Graphics address of the scanned-out surface
+    surface: u32,
     vram_size: usize,
     
+    // Size of the pipe the firmware set up
+    pipe_width: u32,
+    pipe_height: u32,
+    
     // Display configuration
     width: u32,
     height: u32,
@@ -42,9 +84,6 @@ pub struct IntelGen9 {
     next_texture_id: u32,
     textures: BTreeMap<u32, TextureInfo>,
     
-    // Hardware acceleration
-    acceleration_enabled: AtomicBool,
-    
     // Device identification
     device_name: &'static str,
     eu_count: u32,  // Execution Units count
@@ -68,11 +107,13 @@ impl IntelGen9 {
             return Err(GpuError::InvalidDevice);
         }
         
-        // Map MMIO registers (usually in BAR0)
-        let mmio_base = (device.bar0 & 0xFFFFFFF0) as usize;
-        let mmio_size = 2 * 1024 * 1024; // 2MB typical for Intel GPU MMIO
-        
-        common::map_mmio(mmio_base, mmio_size)?;
+        // Registers live at the start of GTTMMADR (BAR0/1)
+        let mmio_phys = common::bar_address(device.bar0, device.bar1);
+        if mmio_phys == 0 {
+            return Err(GpuError::InvalidDevice);
+        }
+        let mmio_size = MMIO_SIZE;
+        let mmio_base = common::map_bar(mmio_phys, mmio_size)?;
         
         // Determine which specific Gen9 GPU we have
         let (device_name, eu_count) = match device.device_id {
@@ -87,8 +128,36 @@ impl IntelGen9 {
             _ => ("Intel Gen9 Graphics", 24),
         };
         
-        // Find framebuffer (usually in BAR2)
-        let framebuffer = (device.bar2 & 0xFFFFFFF0) as usize;
+        // Without a running pipe there is nothing to attach a plane to
+        if common::read_reg32(mmio_base, display::PIPECONF_A) & display::PIPECONF_STATE == 0 {
+            common::unmap_bar(mmio_base, mmio_size)?;
+            log::warn!("{}: pipe A is not running, leaving the display to the firmware framebuffer", device_name);
+            return Err(GpuError::InitializationFailed);
+        }
+        let pipe_src = common::read_reg32(mmio_base, display::PIPESRC_A);
+        let pipe_width = ((pipe_src >> 16) & 0x1FFF) + 1;
+        let pipe_height = (pipe_src & 0xFFF) + 1;
+        
+        // Keep the firmware's surface, which the GTT already maps; with the
+        // plane off, the start of the aperture is where firmware puts it
+        let plane_ctl = common::read_reg32(mmio_base, display::PLANE_CTL_1_A);
+        let surface = if plane_ctl & display::PLANE_CTL_ENABLE != 0 {
+            common::read_reg32(mmio_base, display::PLANE_SURF_1_A) & display::PLANE_SURF_ADDR_MASK
+        } else {
+            0
+        };
+        
+        // The CPU reaches the surface through GMADR (BAR2/3); map enough for
+        // the largest mode the pipe can show
+        let aperture = common::bar_address(device.bar2, device.bar3);
+        let framebuffer_size = (stride_for(pipe_width) * pipe_height) as usize;
+        let framebuffer = match common::map_bar(aperture + surface as u64, framebuffer_size) {
+            Ok(virt) => virt,
+            Err(e) => {
+                common::unmap_bar(mmio_base, mmio_size)?;
+                return Err(e);
+            }
+        };
         
         // Intel integrated GPUs use system memory as VRAM
         let vram_size = 512 * 1024 * 1024; // Typical 512MB allocation
@@ -100,11 +169,15 @@ impl IntelGen9 {
             mmio_base,
             mmio_size,
             framebuffer,
+            framebuffer_size,
+            surface,
             vram_size,
-            width: 1920, // Default to 1080p
-            height: 1080,
+            pipe_width,
+            pipe_height,
+            width: pipe_width,
+            height: pipe_height,
             bpp: 32,
-            pitch: 1920 * 4, // width * bytes per pixel
+            pitch: stride_for(pipe_width),
             clip_x: 0,
             clip_y: 0,
             clip_width: 0,
@@ -113,7 +186,6 @@ impl IntelGen9 {
             blend_mode: 0,
             next_texture_id: 1,
             textures: BTreeMap::new(),
-            acceleration_enabled: AtomicBool::new(true),
             device_name,
             eu_count,
         };
@@ -127,19 +199,42 @@ impl IntelGen9 {
     
     /// Initialize the GPU hardware
     fn initialize_hardware(&mut self) -> Result<(), GpuError> {
-        // In a real driver, we would:
-        // 1. Reset the GPU if needed
-        // 2. Initialize display planes
-        // 3. Set up command buffers
-        // 4. Configure power management
-        // 5. Enable interrupts
-        
-        // For our implementation, we'll simulate success
-        log::info!("Initialized {} with {} execution units", self.device_name, self.eu_count);
+        self.program_plane();
+        log::info!(
+            "Initialized {} with {} execution units, {}x{} on pipe A",
+            self.device_name, self.eu_count, self.width, self.height
+        );
         
         Ok(())
     }
     
+    /// Point primary plane 1 of pipe A at the surface as a linear XRGB8888
+    /// image of the current size. Takes effect at the next vblank.
+    fn program_plane(&self) {
+        let ctl = self.read_reg32(display::PLANE_CTL_1_A)
+            & !(display::PLANE_CTL_FORMAT_MASK | display::PLANE_CTL_TILED_MASK | display::PLANE_CTL_ROTATION_MASK);
+        self.write_reg32(display::PLANE_CTL_1_A, ctl | display::PLANE_CTL_ENABLE | display::PLANE_CTL_FORMAT_XRGB8888);
+        self.write_reg32(display::PLANE_STRIDE_1_A, self.pitch / display::STRIDE_ALIGN);
+        self.write_reg32(display::PLANE_POS_1_A, 0);
+        self.write_reg32(display::PLANE_OFFSET_1_A, 0);
+        self.write_reg32(display::PLANE_SIZE_1_A, ((self.height - 1) << 16) | (self.width - 1));
+        // Written last: it latches everything above
+        self.write_reg32(display::PLANE_SURF_1_A, self.surface);
+    }
+    
+    /// Resize the plane, which must fit inside the pipe
+    fn set_plane_mode(&mut self, width: u32, height: u32) -> Result<(), GpuError> {
+        if width == 0 || height == 0 || width > self.pipe_width || height > self.pipe_height {
+            return Err(GpuError::NotSupported);
+        }
+        self.width = width;
+        self.height = height;
+        self.pitch = stride_for(width);
+        self.program_plane();
+        log::debug!("Changed resolution to {}x{}", width, height);
+        Ok(())
+    }
+    
     // Helper methods for hardware interaction
     
     /// Read from a GPU register
@@ -152,11 +247,6 @@ impl IntelGen9 {
         common::write_reg32(self.mmio_base, offset, value)
     }
     
-    /// Wait for a register bit
-    fn wait_for_reg32(&self, offset: usize, mask: u32, value: u32) -> Result<(), GpuError> {
-        common::wait_for_reg32(self.mmio_base, offset, mask, value, 1000)
-    }
-    
     /// Software implementation of rectangle fill
     fn sw_fill_rect(&self, mut x: i32, mut y: i32, mut width: u32, mut height: u32, color: u32) -> Result<(), GpuError> {
         // Apply clipping if enabled
@@ -217,38 +307,6 @@ impl IntelGen9 {
         
         Ok(())
     }
-    
-    /// Hardware implementation of rectangle fill using Gen9 blitter
-    fn hw_fill_rect(&self, x: i32, y: i32, width: u32, height: u32, color: u32) -> Result<(), GpuError> {
-        // In a real driver, we would:
-        // 1. Create a blit command in the command buffer
-        // 2. Set source and destination coordinates
-        // 3. Set color and operation
-        // 4. Submit the command
-        
-        // Gen9 specific register offsets (simplified)
-        const REG_BLIT_DST_BASE: usize = 0x70000;
-        const REG_BLIT_DST_PITCH: usize = 0x70004;
-        const REG_BLIT_COLOR: usize = 0x70008;
-        const REG_BLIT_RECT: usize = 0x7000C;
-        const REG_BLIT_CONTROL: usize = 0x70010;
-        const REG_BLIT_STATUS: usize = 0x70014;
-        
-        // Write blit parameters
-        self.write_reg32(REG_BLIT_DST_BASE, self.framebuffer as u32);
-        self.write_reg32(REG_BLIT_DST_PITCH, self.pitch);
-        self.write_reg32(REG_BLIT_COLOR, color);
-        self.write_reg32(REG_BLIT_RECT, ((x & 0xFFFF) | ((y & 0xFFFF) << 16)).try_into().unwrap());
-        self.write_reg32(REG_BLIT_RECT + 4, (width & 0xFFFF) | ((height & 0xFFFF) << 16));
-        
-        // Start the blit operation
-        self.write_reg32(REG_BLIT_CONTROL, 0x01); // Start bit
-        
-        // Wait for completion
-        self.wait_for_reg32(REG_BLIT_STATUS, 0x01, 0x01)?;
-        
-        Ok(())
-    }
 }
 
 impl GpuDevice for IntelGen9 {
@@ -257,16 +315,20 @@ impl GpuDevice for IntelGen9 {
             return Err(GpuError::NotInitialized);
         }
         
-        // List available display modes
-        let modes = [
-            DisplayMode { width: 3840, height: 2160, bpp: 32, refresh_rate: 60 },
-            DisplayMode { width: 2560, height: 1440, bpp: 32, refresh_rate: 60 },
-            DisplayMode { width: 1920, height: 1080, bpp: 32, refresh_rate: 60 },
-            DisplayMode { width: 1680, height: 1050, bpp: 32, refresh_rate: 60 },
-            DisplayMode { width: 1600, height: 900, bpp: 32, refresh_rate: 60 },
-            DisplayMode { width: 1366, height: 768, bpp: 32, refresh_rate: 60 },
-            DisplayMode { width: 1280, height: 720, bpp: 32, refresh_rate: 60 },
-        ];
+        // List the display modes the pipe can show
+        let modes: Vec<DisplayMode> = [
+            (3840, 2160),
+            (2560, 1440),
+            (1920, 1080),
+            (1680, 1050),
+            (1600, 900),
+            (1366, 768),
+            (1280, 720),
+        ]
+        .iter()
+        .filter(|&&(width, height)| width <= self.pipe_width && height <= self.pipe_height)
+        .map(|&(width, height)| DisplayMode { width, height, bpp: 32, refresh_rate: 60 })
+        .collect();
         
         // Current mode
         let current_mode = DisplayMode {
@@ -282,10 +344,13 @@ impl GpuDevice for IntelGen9 {
             device: self.device_name,
             vram_size: self.vram_size,
             max_texture_size: 16384,
-            features: Feature::Acceleration2D as u32 | Feature::Blending as u32 | 
-                      Feature::HardwareCursor as u32 | Feature::MemoryMapping as u32,
+            features: FeatureSet::of(&[
+                Feature::Blending,
+                Feature::HardwareCursor,
+                Feature::MemoryMapping,
+            ]),
             current_mode,
-            available_modes: Box::leak(Box::new(modes)),
+            available_modes: Box::leak(modes.into_boxed_slice()),
         };
         
         Ok(info)
@@ -298,16 +363,7 @@ impl GpuDevice for IntelGen9 {
         
         // Check if mode change is needed
         if width != self.width || height != self.height {
-            // Set new mode
-            self.width = width;
-            self.height = height;
-            self.pitch = width * (self.bpp as u32 / 8);
-            
-            // In a real driver, we would:
-            // 1. Update the display controller settings
-            // 2. Possibly reallocate framebuffer memory
-            
-            log::debug!("Changed resolution to {}x{}", width, height);
+            self.set_plane_mode(width, height)?;
         }
         
         Ok(self.framebuffer)
@@ -321,6 +377,16 @@ impl GpuDevice for IntelGen9 {
         Ok(self.pitch)
     }
 
+    fn set_display_mode(&mut self, mode: DisplayMode) -> Result<(), GpuError> {
+        if !self.is_initialized {
+            return Err(GpuError::NotInitialized);
+        }
+        if mode.bpp != 32 {
+            return Err(GpuError::UnsupportedFormat);
+        }
+        self.set_plane_mode(mode.width, mode.height)
+    }
+
     fn clear(&mut self, color: u32) -> Result<(), GpuError> {
         if !self.is_initialized {
             return Err(GpuError::NotInitialized);
@@ -339,19 +405,7 @@ impl GpuDevice for IntelGen9 {
             return Ok(());
         }
         
-        // Use hardware acceleration if available, otherwise fall back to software
-        if self.acceleration_enabled.load(Ordering::Relaxed) {
-            match self.hw_fill_rect(x, y, width, height, color) {
-                Ok(_) => return Ok(()),
-                Err(_) => {
-                    // Hardware acceleration failed, disable it and fall back to software
-                    log::warn!("Hardware acceleration failed, falling back to software rendering");
-                    self.acceleration_enabled.store(false, Ordering::Relaxed);
-                }
-            }
-        }
-        
-        // Software fallback
+        // No blitter yet: the CPU writes the plane surface directly
         self.sw_fill_rect(x, y, width, height, color)
     }
 
@@ -643,15 +697,21 @@ impl GpuDevice for IntelGen9 {
         Ok(())
     }
 
+    fn current_scanline(&self) -> Option<u32> {
+        if !self.is_initialized {
+            return None;
+        }
+        Some(common::read_scanline(self.mmio_base))
+    }
+
     fn present(&mut self) -> Result<(), GpuError> {
         if !self.is_initialized {
             return Err(GpuError::NotInitialized);
         }
         
-        // For a simple framebuffer model, there's nothing to do
-        // In a real implementation with double/triple buffering:
-        // 1. We would swap buffers
-        // 2. Signal a page flip to the display controller
+        // The plane scans out the surface drawn into, so presenting only has
+        // to make the uncached writes visible before the next scanout
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
         
         Ok(())
     }
@@ -661,17 +721,12 @@ impl GpuDevice for IntelGen9 {
             return Ok(());
         }
         
-        // In a real implementation, we would:
-        // 1. Stop all GPU operations
-        // 2. Free all textures
-        // 3. Unmap MMIO regions
-        // 4. Release any other hardware resources
-        
         // Free textures
         self.textures.clear();
         
-        // Unmap MMIO
-        common::unmap_mmio(self.mmio_base, self.mmio_size)?;
+        // The plane stays enabled so the last frame remains on screen
+        common::unmap_bar(self.framebuffer, self.framebuffer_size)?;
+        common::unmap_bar(self.mmio_base, self.mmio_size)?;
         
         self.is_initialized = false;
         
@@ -679,6 +734,11 @@ impl GpuDevice for IntelGen9 {
     }
 }
 
+/// Bytes per row of a 32-bit linear surface `width` pixels wide
+fn stride_for(width: u32) -> u32 {
+    (width * 4 + display::STRIDE_ALIGN - 1) / display::STRIDE_ALIGN * display::STRIDE_ALIGN
+}
+
 /// Create an Intel Gen9 driver for the specified PCI device
 pub fn create_driver(device: &PciDevice) -> Result<Box<dyn GpuDevice>, GpuError> {
     IntelGen9::new(device)
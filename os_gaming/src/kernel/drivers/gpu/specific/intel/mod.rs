@@ -22,7 +22,9 @@ pub fn create_driver(device: &PciDevice) -> Result<Box<dyn GpuDevice>, GpuError>
         0x8A50 | 0x8A51 | 0x8A52 | 0x8A53 => gen11::create_driver(device),
         
         // Intel UHD Graphics (Gen9)
-        0x3E90 | 0x3E91 | 0x3E92 | 0x3E93 | 0x3E94 => gen9::create_driver(device),
+        0x3E90 | 0x3E91 | 0x3E92 | 0x3E93 | 0x3E94 | 0x3E96 | 0x3E9B => gen9::create_driver(device),
+        // Skylake and Kaby Lake (Gen9)
+        0x1912 | 0x1916 | 0x191B | 0x191E | 0x5912 | 0x5916 | 0x591B | 0x591E => gen9::create_driver(device),
         
         // Unknown or unsupported device
         _ => Err(GpuError::UnsupportedFeature),
@@ -5,7 +5,8 @@
 extern crate alloc;
 use alloc::boxed::Box;
 use crate::kernel::drivers::gpu::pci::PciDevice;
-use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, TextureFormat};
+use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, TextureFormat, TextureFilter, FenceId};
+use crate::kernel::drivers::gpu::raster::Surface;
 
 /// Interface for GPU device drivers
 pub trait GpuDevice: Send + Sync {
@@ -18,6 +19,17 @@ pub trait GpuDevice: Send + Sync {
     /// Get the framebuffer pitch
     fn get_framebuffer_pitch(&self) -> Result<u32, GpuError>;
     
+    /// Change the display mode; the framebuffer address may change afterwards
+    fn set_display_mode(&mut self, _mode: DisplayMode) -> Result<(), GpuError> {
+        Err(GpuError::NotSupported)
+    }
+    
+    /// Let the panel refresh in step with presents between `min_hz` and
+    /// `max_hz`, or go back to the fixed mode rate when `enabled` is false
+    fn set_variable_refresh(&mut self, _enabled: bool, _min_hz: u32, _max_hz: u32) -> Result<(), GpuError> {
+        Err(GpuError::UnsupportedFeature)
+    }
+    
     /// Clear the screen with the specified color
     fn clear(&mut self, color: u32) -> Result<(), GpuError>;
     
@@ -27,6 +39,32 @@ pub trait GpuDevice: Send + Sync {
     /// Draw a line
     fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u32) -> Result<(), GpuError>;
     
+    /// Move a block of the framebuffer, overlapping or not; used to scroll.
+    /// The default waits for pending work and copies on the CPU.
+    fn copy_rect(&mut self, src_x: i32, src_y: i32, dst_x: i32, dst_y: i32, width: u32, height: u32) -> Result<(), GpuError> {
+        let mode = self.get_info()?.current_mode;
+        if mode.bpp != 32 {
+            return Err(GpuError::UnsupportedFormat);
+        }
+        let fence = self.insert_fence();
+        self.wait_fence(fence, 100)?;
+
+        let pitch = self.get_framebuffer_pitch()?;
+        let framebuffer = self.get_framebuffer(mode.width, mode.height)?;
+        if framebuffer == 0 {
+            return Err(GpuError::NotInitialized);
+        }
+        // Safety: the device's framebuffer spans `pitch * height` bytes
+        let mut surface = unsafe { Surface::from_raw(framebuffer as *mut u32, mode.width, mode.height, pitch) };
+        surface.copy_rect(src_x, src_y, dst_x, dst_y, width, height);
+        Ok(())
+    }
+    
+    /// Draw an anti-aliased line; devices without hardware AA use the software path
+    fn draw_line_aa(&mut self, _x1: f32, _y1: f32, _x2: f32, _y2: f32, _width: f32, _color: u32) -> Result<(), GpuError> {
+        Err(GpuError::NotSupported)
+    }
+    
     /// Create a texture
     fn create_texture(&mut self, width: u32, height: u32, format: u32, data: &[u8]) -> Result<u32, GpuError>;
     
@@ -39,6 +77,20 @@ pub trait GpuDevice: Send + Sync {
     /// Draw a texture
     fn draw_texture(&mut self, texture_id: u32, x: i32, y: i32, width: u32, height: u32) -> Result<(), GpuError>;
     
+    /// Draw a texture with a specific filter. Devices that can't choose
+    /// their sampler filter draw it the way `draw_texture` does.
+    fn draw_texture_filtered(
+        &mut self,
+        texture_id: u32,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        _filter: TextureFilter,
+    ) -> Result<(), GpuError> {
+        self.draw_texture(texture_id, x, y, width, height)
+    }
+    
     /// Set clipping rectangle
     fn set_clip_rect(&mut self, x: i32, y: i32, width: u32, height: u32) -> Result<(), GpuError>;
     
@@ -48,6 +100,62 @@ pub trait GpuDevice: Send + Sync {
     /// Set blend mode
     fn set_blend_mode(&mut self, mode: u32) -> Result<(), GpuError>;
     
+    /// Insert a fence after all previously submitted work.
+    /// Devices that execute synchronously complete every fence immediately.
+    fn insert_fence(&mut self) -> FenceId {
+        0
+    }
+    
+    /// Wait until the command stream has passed a fence
+    fn wait_fence(&self, _id: FenceId, _timeout_ms: u64) -> Result<(), GpuError> {
+        Ok(())
+    }
+    
+    /// Line the display controller is currently scanning out, if the
+    /// hardware exposes it
+    fn current_scanline(&self) -> Option<u32> {
+        None
+    }
+    
+    /// Load a cursor image: `width * height` little-endian 0xAARRGGBB
+    /// pixels, with `hotspot` the pixel that points at the cursor position
+    fn set_cursor(&mut self, _image: &[u8], _width: u32, _height: u32, _hotspot: (u16, u16)) -> Result<(), GpuError> {
+        Err(GpuError::UnsupportedFeature)
+    }
+    
+    /// Put the cursor's hotspot at screen position (x, y)
+    fn move_cursor(&mut self, _x: i32, _y: i32) -> Result<(), GpuError> {
+        Err(GpuError::UnsupportedFeature)
+    }
+    
+    fn show_cursor(&mut self, _visible: bool) -> Result<(), GpuError> {
+        Err(GpuError::UnsupportedFeature)
+    }
+    
+    /// Copy a finished frame into the visible framebuffer before `present`.
+    /// `src` holds rows of `pitch` bytes in the current mode's format. The
+    /// default copies row by row on the CPU; devices that can page-flip or
+    /// DMA override it.
+    fn blit_backbuffer(&mut self, src: &[u8], pitch: u32) -> Result<(), GpuError> {
+        if pitch == 0 {
+            return Err(GpuError::InvalidParameter);
+        }
+        let mode = self.get_info()?.current_mode;
+        let dst_pitch = self.get_framebuffer_pitch()? as usize;
+        let dst = self.get_framebuffer(mode.width, mode.height)? as *mut u8;
+        if dst.is_null() {
+            return Err(GpuError::NotInitialized);
+        }
+
+        let row_bytes = (mode.width as usize * (mode.bpp as usize / 8)).min(dst_pitch);
+        for (row, line) in src.chunks(pitch as usize).take(mode.height as usize).enumerate() {
+            let len = row_bytes.min(line.len());
+            // Safety: the row fits the framebuffer's pitch and the mode's height
+            unsafe { core::ptr::copy_nonoverlapping(line.as_ptr(), dst.add(row * dst_pitch), len) };
+        }
+        Ok(())
+    }
+    
     /// Present the frame to the screen
     fn present(&mut self) -> Result<(), GpuError>;
     
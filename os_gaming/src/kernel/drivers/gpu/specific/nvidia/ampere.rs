@@ -6,7 +6,7 @@ use alloc::boxed::Box;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicBool, Ordering};
 use crate::kernel::drivers::gpu::pci::PciDevice;
-use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, TextureFormat, Feature};
+use crate::kernel::drivers::gpu::{GpuInfo, GpuError, DisplayMode, TextureFormat, Feature, FeatureSet};
 use super::super::GpuDevice;
 use super::common;
 
@@ -149,10 +149,16 @@ impl GpuDevice for AmpereGpu {
             device: self.gpu_model,
             vram_size: self.framebuffer_size,
             max_texture_size: 32768,
-            features: Feature::Acceleration2D as u32 | Feature::Blending as u32 | 
-                     Feature::HardwareCursor as u32 | Feature::MemoryMapping as u32 |
-                     Feature::Rendering3D as u32 | Feature::Shaders as u32 |
-                     Feature::RenderTargets as u32 | Feature::DmaTransfers as u32,
+            features: FeatureSet::of(&[
+                Feature::Acceleration2D,
+                Feature::Blending,
+                Feature::HardwareCursor,
+                Feature::MemoryMapping,
+                Feature::Rendering3D,
+                Feature::Shaders,
+                Feature::RenderTargets,
+                Feature::DmaTransfers,
+            ]),
             current_mode,
             available_modes: Box::leak(Box::new(modes)),
         };
@@ -1,5 +1,5 @@
 extern crate alloc;
-use crate::kernel::drivers::gpu::{self, DisplayMode, GpuInfo, Feature, GpuError};
+use crate::kernel::drivers::gpu::{self, DisplayMode, GpuInfo, Feature, FeatureSet, GpuError};
 use crate::kernel::drivers::gpu::pci::PciDevice;
 use crate::kernel::drivers::gpu::specific::GpuDevice;
 use alloc::vec::Vec;
@@ -247,20 +247,22 @@ impl GpuDevice for CudaDevice {
         };
         
         // Create features based on compute capability
-        let mut features = Feature::Acceleration2D as u32 | 
-                          Feature::Rendering3D as u32 |
-                          Feature::HardwareCursor as u32 | 
-                          Feature::MemoryMapping as u32 |
-                          Feature::Shaders as u32 |
-                          Feature::RenderTargets as u32;
+        let mut features = FeatureSet::of(&[
+            Feature::Acceleration2D,
+            Feature::Rendering3D,
+            Feature::HardwareCursor,
+            Feature::MemoryMapping,
+            Feature::Shaders,
+            Feature::RenderTargets,
+        ]);
         
         // Add special features
         if self.tensor_cores {
-            features |= Feature::TensorAcceleration as u32;
+            features.insert(Feature::TensorAcceleration);
         }
         
         if self.ray_tracing_cores {
-            features |= Feature::RayTracing as u32;
+            features.insert(Feature::RayTracing);
         }
         
         // Create GPU info with NVIDIA-specific capabilities
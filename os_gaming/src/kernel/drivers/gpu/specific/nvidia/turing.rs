@@ -51,7 +51,7 @@ impl TuringGpu {
 
 impl GpuDevice for TuringGpu {
     fn get_info(&self) -> Result<crate::kernel::drivers::gpu::GpuInfo, crate::kernel::drivers::gpu::GpuError> {
-        use crate::kernel::drivers::gpu::{GpuInfo, DisplayMode, Feature, GpuError};
+        use crate::kernel::drivers::gpu::{GpuInfo, DisplayMode, Feature, FeatureSet, GpuError};
 
         if !self.is_initialized {
             return Err(GpuError::NotInitialized);
@@ -85,16 +85,19 @@ impl GpuDevice for TuringGpu {
         };
 
         // Determine feature support based on GPU model
-        let mut features = Feature::Acceleration2D as u32 | 
-                          Feature::Blending as u32 | 
-                          Feature::HardwareCursor as u32 | 
-                          Feature::MemoryMapping as u32 |
-                          Feature::Rendering3D as u32 |
-                          Feature::DmaTransfers as u32;
+        let mut features = FeatureSet::of(&[
+            Feature::Acceleration2D,
+            Feature::Blending,
+            Feature::HardwareCursor,
+            Feature::MemoryMapping,
+            Feature::Rendering3D,
+            Feature::DmaTransfers,
+        ]);
                           
         // RTX features only on RTX cards
         if self.device_id >= 0x1E00 && self.device_id <= 0x1E8F {
-            features |= Feature::Shaders as u32 | Feature::RenderTargets as u32;
+            features.insert(Feature::Shaders);
+            features.insert(Feature::RenderTargets);
         }
 
         // Create GPU info
@@ -1,84 +1,285 @@
 //! VESA/VBE GPU driver
 //!
-//! Provides basic framebuffer access through VESA BIOS Extensions.
+//! Provides basic framebuffer access through VESA BIOS Extensions. When the
+//! bootloader passed a framebuffer in the Multiboot2 information, that
+//! surface is used as-is instead of guessing one.
+//!
+//! The VBE BIOS calls need real mode, which is gone once the kernel runs in
+//! long mode. Modes are instead set through the VBE "DISPI" registers that
+//! QEMU, Bochs and VirtualBox expose on I/O ports 0x1CE/0x1CF; this is what
+//! their VBE BIOS does underneath. Elsewhere only the loader's mode is usable.
 extern crate alloc;
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::ptr;
 use core::slice;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
 
 use super::specific::GpuDevice;
-use super::{GpuInfo, GpuError, DisplayMode, TextureFormat};
+use super::{pci, GpuInfo, GpuError, DisplayMode, FeatureSet, TextureFormat};
+use crate::kernel::memory;
+use crate::kernel::sync::OnceCell;
+use crate::kernel::multiboot2::{self, BootFramebuffer};
+
+const VBE_DISPI_IOPORT_INDEX: u16 = 0x01CE;
+const VBE_DISPI_IOPORT_DATA: u16 = 0x01CF;
+
+const VBE_DISPI_INDEX_ID: u16 = 0x0;
+const VBE_DISPI_INDEX_XRES: u16 = 0x1;
+const VBE_DISPI_INDEX_YRES: u16 = 0x2;
+const VBE_DISPI_INDEX_BPP: u16 = 0x3;
+const VBE_DISPI_INDEX_ENABLE: u16 = 0x4;
+const VBE_DISPI_INDEX_VIRT_WIDTH: u16 = 0x6;
+const VBE_DISPI_INDEX_X_OFFSET: u16 = 0x8;
+const VBE_DISPI_INDEX_Y_OFFSET: u16 = 0x9;
+const VBE_DISPI_INDEX_VIDEO_MEMORY_64K: u16 = 0xA;
+
+const VBE_DISPI_ID0: u16 = 0xB0C0;
+const VBE_DISPI_ID5: u16 = 0xB0C5;
+const VBE_DISPI_DISABLED: u16 = 0x00;
+const VBE_DISPI_ENABLED: u16 = 0x01;
+const VBE_DISPI_LFB_ENABLED: u16 = 0x40;
+
+/// Where the linear framebuffer sits when no PCI BAR reports it
+const VBE_DISPI_LFB_PHYSICAL_ADDRESS: u64 = 0xE000_0000;
+
+/// Mode the fallback path brings the GUI up in
+pub const DEFAULT_MODE: DisplayMode = DisplayMode { width: 1024, height: 768, bpp: 32, refresh_rate: 60 };
+
+/// Resolutions of the standard VBE mode numbers
+const VBE_RESOLUTIONS: [(u32, u32); 8] = [
+    (640, 480),
+    (800, 600),
+    (1024, 768),
+    (1280, 720),
+    (1280, 1024),
+    (1600, 900),
+    (1600, 1200),
+    (1920, 1080),
+];
+
+/// Filled by the first `available_modes` call
+static MODES: OnceCell<Vec<DisplayMode>> = OnceCell::new();
+
+/// Current mapping of the linear framebuffer: (physical, virtual, bytes)
+static LFB_MAPPING: Mutex<Option<(u64, usize, usize)>> = Mutex::new(None);
 
 /// Initialize VESA/VBE
 pub fn init() -> Result<(), GpuError> {
-    // In a real implementation, you'd initialize VESA here
-    // For now, we'll assume it's working
+    match dispi_version() {
+        Some(version) => log::info!("VBE: DISPI interface {:#x}, {} KiB of video memory", version, dispi_video_memory() / 1024),
+        None if multiboot2::boot_framebuffer().is_some() => log::info!("VBE: using the bootloader's mode only"),
+        None => log::warn!("VBE: no mode-setting interface and no bootloader framebuffer"),
+    }
     Ok(())
 }
 
+/// Modes `set_mode` accepts: the bootloader's mode plus, with the DISPI
+/// interface, the standard VBE resolutions at 24 and 32bpp that fit in
+/// video memory
+pub fn available_modes() -> &'static [DisplayMode] {
+    MODES.get_or_init(probe_modes)
+}
+
+/// Switch to `width` x `height` x `bpp` and return the mapped framebuffer
+/// base and its pitch in bytes
+pub fn set_mode(width: u32, height: u32, bpp: u8) -> Result<(usize, u32), GpuError> {
+    if let Some(framebuffer) = multiboot2::boot_framebuffer() {
+        if framebuffer.width == width && framebuffer.height == height && framebuffer.bpp == bpp {
+            let base = map_lfb(framebuffer.address, framebuffer.size())?;
+            return Ok((base, framebuffer.pitch));
+        }
+    }
+
+    if dispi_version().is_none() {
+        log::warn!("VBE: can't set {}x{}x{} without the BIOS", width, height, bpp);
+        return Err(GpuError::NotSupported);
+    }
+    if !available_modes().iter().any(|m| m.width == width && m.height == height && m.bpp == bpp) {
+        return Err(GpuError::InvalidParameter);
+    }
+
+    // Safety: the DISPI registers were identified above
+    unsafe {
+        dispi_write(VBE_DISPI_INDEX_ENABLE, VBE_DISPI_DISABLED);
+        dispi_write(VBE_DISPI_INDEX_XRES, width as u16);
+        dispi_write(VBE_DISPI_INDEX_YRES, height as u16);
+        dispi_write(VBE_DISPI_INDEX_BPP, bpp as u16);
+        dispi_write(VBE_DISPI_INDEX_VIRT_WIDTH, width as u16);
+        dispi_write(VBE_DISPI_INDEX_X_OFFSET, 0);
+        dispi_write(VBE_DISPI_INDEX_Y_OFFSET, 0);
+        dispi_write(VBE_DISPI_INDEX_ENABLE, VBE_DISPI_ENABLED | VBE_DISPI_LFB_ENABLED);
+
+        if dispi_read(VBE_DISPI_INDEX_XRES) as u32 != width
+            || dispi_read(VBE_DISPI_INDEX_YRES) as u32 != height
+            || dispi_read(VBE_DISPI_INDEX_BPP) as u8 != bpp
+        {
+            return Err(GpuError::InvalidParameter);
+        }
+    }
+
+    // The whole video memory stays mapped so later mode switches reuse it
+    let base = map_lfb(dispi_lfb_address(), dispi_video_memory())?;
+    let pitch = width * (bpp as u32 / 8);
+    log::info!("VBE: set {}x{}x{}", width, height, bpp);
+    Ok((base, pitch))
+}
+
 /// Create a VESA driver
 pub fn create_driver() -> Result<Box<dyn GpuDevice>, GpuError> {
-    // Get current video mode
-    let mode = get_current_mode()?;
-    
+    if let Some(framebuffer) = multiboot2::boot_framebuffer() {
+        return create_boot_framebuffer_driver(framebuffer);
+    }
+
+    let mode = DEFAULT_MODE;
+    let (framebuffer, pitch) = set_mode(mode.width, mode.height, mode.bpp)?;
+
     let driver = VesaDriver {
         info: GpuInfo {
             vendor: "VESA",
             device: "VESA VBE Framebuffer",
-            vram_size: 16 * 1024 * 1024, // Assume 16MB of VRAM
+            vram_size: dispi_video_memory(),
             max_texture_size: 2048,
-            features: 0, // No hardware acceleration
+            features: FeatureSet::empty(), // No hardware acceleration
             current_mode: mode,
-            available_modes: get_available_modes(),
+            available_modes: available_modes(),
         },
-        framebuffer: get_framebuffer_address()?,
-        pitch: get_framebuffer_pitch()?,
+        framebuffer,
+        pitch,
         width: mode.width,
         height: mode.height,
         bpp: mode.bpp,
         clip_rect: None,
+        boot_framebuffer: None,
     };
     
     Ok(Box::new(driver))
 }
 
-/// Get current video mode
-fn get_current_mode() -> Result<DisplayMode, GpuError> {
-    // In a real implementation, you'd query VESA
-    // For now, we'll return a default mode
-    Ok(DisplayMode {
-        width: 1024,
-        height: 768,
-        bpp: 32,
+/// Drive the framebuffer the bootloader already set up
+fn create_boot_framebuffer_driver(framebuffer: BootFramebuffer) -> Result<Box<dyn GpuDevice>, GpuError> {
+    if !matches!(framebuffer.bpp, 8 | 16 | 24 | 32) || framebuffer.width == 0 || framebuffer.height == 0 {
+        return Err(GpuError::UnsupportedFeature);
+    }
+    let virt = map_lfb(framebuffer.address, framebuffer.size())?;
+
+    let mode = DisplayMode {
+        width: framebuffer.width,
+        height: framebuffer.height,
+        bpp: framebuffer.bpp,
         refresh_rate: 60,
-    })
+    };
+    log::info!(
+        "Using the bootloader framebuffer: {}x{}x{} at {:#x}",
+        mode.width, mode.height, mode.bpp, framebuffer.address
+    );
+    Ok(Box::new(VesaDriver {
+        info: GpuInfo {
+            vendor: "VESA",
+            device: "Bootloader framebuffer",
+            vram_size: framebuffer.size(),
+            max_texture_size: 2048,
+            features: FeatureSet::empty(),
+            current_mode: mode,
+            available_modes: available_modes(),
+        },
+        framebuffer: virt,
+        pitch: framebuffer.pitch,
+        width: framebuffer.width,
+        height: framebuffer.height,
+        bpp: framebuffer.bpp,
+        clip_rect: None,
+        boot_framebuffer: Some(framebuffer),
+    }))
 }
 
-/// Get available video modes
-fn get_available_modes() -> &'static [DisplayMode] {
-    static MODES: [DisplayMode; 3] = [
-        DisplayMode { width: 800, height: 600, bpp: 32, refresh_rate: 60 },
-        DisplayMode { width: 1024, height: 768, bpp: 32, refresh_rate: 60 },
-        DisplayMode { width: 1280, height: 720, bpp: 32, refresh_rate: 60 },
-    ];
-    
-    &MODES
+fn probe_modes() -> Vec<DisplayMode> {
+    let mut modes = Vec::new();
+    if let Some(framebuffer) = multiboot2::boot_framebuffer() {
+        modes.push(DisplayMode {
+            width: framebuffer.width,
+            height: framebuffer.height,
+            bpp: framebuffer.bpp,
+            refresh_rate: 60,
+        });
+    }
+    if dispi_version().is_some() {
+        let video_memory = dispi_video_memory();
+        for bpp in [32u8, 24] {
+            for &(width, height) in VBE_RESOLUTIONS.iter() {
+                let mode = DisplayMode { width, height, bpp, refresh_rate: 60 };
+                let size = width as usize * height as usize * (bpp as usize / 8);
+                if size <= video_memory && !modes.contains(&mode) {
+                    modes.push(mode);
+                }
+            }
+        }
+    }
+    modes
+}
+
+/// Map `size` bytes of framebuffer at `phys`, reusing the current mapping
+/// when it already covers them
+fn map_lfb(phys: u64, size: usize) -> Result<usize, GpuError> {
+    let mut mapping = LFB_MAPPING.lock();
+    if let Some((mapped_phys, virt, mapped_size)) = *mapping {
+        if mapped_phys == phys && mapped_size >= size {
+            return Ok(virt);
+        }
+        let _ = memory::unmap_kernel_virt_region(VirtAddr::new(virt as u64), mapped_size);
+        *mapping = None;
+    }
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::WRITE_THROUGH;
+    let virt = memory::map_phys_mem_to_kernel_virt(PhysAddr::new(phys), size, flags)
+        .map_err(|_| GpuError::MappingFailed)?;
+    let virt = virt.as_u64() as usize;
+    *mapping = Some((phys, virt, size));
+    Ok(virt)
 }
 
-/// Get framebuffer address
-fn get_framebuffer_address() -> Result<usize, GpuError> {
-    // In a real implementation, you'd query VESA
-    // For now, we'll return a placeholder value
-    // This should be replaced with the actual framebuffer address
-    Ok(0xFD000000) // Example address
+unsafe fn dispi_write(index: u16, value: u16) {
+    Port::<u16>::new(VBE_DISPI_IOPORT_INDEX).write(index);
+    Port::<u16>::new(VBE_DISPI_IOPORT_DATA).write(value);
+}
+
+unsafe fn dispi_read(index: u16) -> u16 {
+    Port::<u16>::new(VBE_DISPI_IOPORT_INDEX).write(index);
+    Port::<u16>::new(VBE_DISPI_IOPORT_DATA).read()
+}
+
+/// DISPI interface version, None when the registers aren't there
+fn dispi_version() -> Option<u16> {
+    // Safety: reading the ID register has no side effects, and an absent
+    // device floats the bus to 0xFFFF
+    let id = unsafe { dispi_read(VBE_DISPI_INDEX_ID) };
+    (VBE_DISPI_ID0..=VBE_DISPI_ID5).contains(&id).then_some(id)
+}
+
+/// Video memory behind the DISPI interface, in bytes
+fn dispi_video_memory() -> usize {
+    // Safety: plain register read, older versions report 0
+    match unsafe { dispi_read(VBE_DISPI_INDEX_VIDEO_MEMORY_64K) } {
+        0 => 4 * 1024 * 1024,
+        blocks => blocks as usize * 64 * 1024,
+    }
 }
 
-/// Get framebuffer pitch
-fn get_framebuffer_pitch() -> Result<u32, GpuError> {
-    // In a real implementation, you'd query VESA
-    // For now, we'll calculate based on width and bpp
-    let mode = get_current_mode()?;
-    Ok(mode.width * (mode.bpp as u32 / 8))
+/// Linear framebuffer base from BAR0 of the emulated adapter
+fn dispi_lfb_address() -> u64 {
+    pci::enumerate_gpus()
+        .ok()
+        .and_then(|devices| {
+            devices
+                .into_iter()
+                .find(|d| matches!((d.vendor_id, d.device_id), (0x1234, 0x1111) | (0x80EE, 0xBEEF)))
+        })
+        .map(|d| (d.bar0 & 0xFFFF_FFF0) as u64)
+        .filter(|&address| address != 0)
+        .unwrap_or(VBE_DISPI_LFB_PHYSICAL_ADDRESS)
 }
 
 /// Clipping rectangle
@@ -106,6 +307,8 @@ pub struct VesaDriver {
     bpp: u8,
     /// Current clipping rectangle
     clip_rect: Option<ClipRect>,
+    /// Pixel layout when drawing to the bootloader's framebuffer
+    boot_framebuffer: Option<BootFramebuffer>,
 }
 
 impl GpuDevice for VesaDriver {
@@ -122,10 +325,21 @@ impl GpuDevice for VesaDriver {
     }
     
     fn clear(&mut self, color: u32) -> Result<(), GpuError> {
+        let color = self.encode_color(color);
         // Simple implementation that just fills the entire framebuffer
         let bytes_per_pixel = self.bpp as usize / 8;
         let framebuffer_size = self.pitch as usize * self.height as usize;
         
+        if self.bpp == 32 {
+            for row in 0..self.height as usize {
+                unsafe {
+                    let ptr = (self.framebuffer + row * self.pitch as usize) as *mut u32;
+                    super::fill_span(ptr, self.width as usize, color);
+                }
+            }
+            return Ok(());
+        }
+        
         unsafe {
             let mut ptr = self.framebuffer as *mut u8;
             let end = ptr.add(framebuffer_size);
@@ -142,6 +356,7 @@ impl GpuDevice for VesaDriver {
     }
     
     fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: u32) -> Result<(), GpuError> {
+        let color = self.encode_color(color);
         // Check bounds and apply clipping
         let (x, y, width, height) = self.apply_clip(x, y, width, height);
         if width == 0 || height == 0 {
@@ -151,6 +366,16 @@ impl GpuDevice for VesaDriver {
         let bytes_per_pixel = self.bpp as usize / 8;
         let stride = self.pitch as usize;
         
+        if self.bpp == 32 {
+            for row in 0..height as usize {
+                unsafe {
+                    let offset = (y as usize + row) * stride + x as usize * bytes_per_pixel;
+                    super::fill_span((self.framebuffer + offset) as *mut u32, width as usize, color);
+                }
+            }
+            return Ok(());
+        }
+        
         unsafe {
             for row in 0..height {
                 let mut ptr = self.framebuffer as *mut u8;
@@ -169,6 +394,7 @@ impl GpuDevice for VesaDriver {
     }
     
     fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u32) -> Result<(), GpuError> {
+        let color = self.encode_color(color);
         // Simple Bresenham's line algorithm
         let mut x = x1;
         let mut y = y1;
@@ -255,6 +481,25 @@ impl GpuDevice for VesaDriver {
         Err(GpuError::UnsupportedFeature)
     }
     
+    fn set_display_mode(&mut self, mode: DisplayMode) -> Result<(), GpuError> {
+        let (framebuffer, pitch) = set_mode(mode.width, mode.height, mode.bpp)?;
+        // Any mode other than the loader's is plain xRGB
+        let from_loader = self.boot_framebuffer.as_ref().map_or(false, |fb| {
+            fb.width == mode.width && fb.height == mode.height && fb.bpp == mode.bpp
+        });
+        if !from_loader {
+            self.boot_framebuffer = None;
+        }
+        self.framebuffer = framebuffer;
+        self.pitch = pitch;
+        self.width = mode.width;
+        self.height = mode.height;
+        self.bpp = mode.bpp;
+        self.clip_rect = None;
+        self.info.current_mode = DisplayMode { refresh_rate: 60, ..mode };
+        Ok(())
+    }
+    
     fn present(&mut self) -> Result<(), GpuError> {
         // For VESA, we're directly drawing to the framebuffer
         // so there's nothing to do here
@@ -268,6 +513,14 @@ impl GpuDevice for VesaDriver {
 }
 
 impl VesaDriver {
+    /// Convert 0xAARRGGBB to the framebuffer's pixel format
+    fn encode_color(&self, color: u32) -> u32 {
+        match &self.boot_framebuffer {
+            Some(framebuffer) => framebuffer.encode_color(color),
+            None => color,
+        }
+    }
+    
     /// Check if a point is within the clipping rectangle
     fn is_in_clip(&self, x: i32, y: i32) -> bool {
         if let Some(clip) = self.clip_rect {
@@ -13,7 +13,7 @@ use crate::alloc::string::ToString;
 use std::process::Command;
 
 /// HDMI resolution configuration
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct HdmiResolution {
     pub width: u32,
     pub height: u32,
@@ -63,6 +63,10 @@ pub struct HdmiDriver {
     framebuffer_size: usize,
     detected_gpus: Vec<GpuInfo>,
     primary_gpu: Option<usize>, // Index into detected_gpus
+    /// Whether a monitor is plugged in, as last reported by hotplug detection
+    connected: bool,
+    /// Modes from the connected monitor's EDID, preferred mode first
+    available_modes: Vec<HdmiResolution>,
 }
 
 #[derive(Debug, Clone)]
@@ -122,6 +126,8 @@ impl HdmiDriver {
             framebuffer_size: 0,
             detected_gpus: Vec::new(),
             primary_gpu: None,
+            connected: false,
+            available_modes: Vec::new(),
         }
     }
 
@@ -210,6 +216,45 @@ impl HdmiDriver {
         self.current_resolution
     }
 
+    /// Whether this output is driving a display
+    pub fn is_active(&self) -> bool {
+        self.initialized.load(Ordering::SeqCst)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Modes the connected monitor supports, preferred first
+    pub fn available_modes(&self) -> &[HdmiResolution] {
+        &self.available_modes
+    }
+
+    /// A monitor was plugged in: re-read its EDID and refresh the mode list.
+    /// Returns the preferred mode.
+    pub fn handle_connect(&mut self) -> Result<HdmiResolution, &'static str> {
+        self.connected = true;
+        let edid = self.read_edid()?;
+        self.available_modes = edid_modes(&edid)?;
+        self.available_modes.first().copied().ok_or("EDID lists no usable modes")
+    }
+
+    /// The monitor was unplugged: forget its modes and stop driving the output
+    pub fn handle_disconnect(&mut self) {
+        self.connected = false;
+        self.available_modes.clear();
+        if self.initialized.swap(false, Ordering::SeqCst) {
+            if let Some(fb) = self.framebuffer.take() {
+                // Safety: allocated by allocate_framebuffer with this capacity
+                unsafe {
+                    let _ = Vec::from_raw_parts(fb, 0, self.framebuffer_size);
+                }
+            }
+            self.framebuffer_size = 0;
+            self.current_resolution = None;
+        }
+    }
+
     /// Set a pixel at the specified coordinates
     pub fn set_pixel(
         &self,
@@ -865,6 +910,85 @@ impl HdmiDriver {
     }
 }
 
+/// EDID established timings: (byte, bit, width, height, refresh)
+const ESTABLISHED_TIMINGS: [(usize, u8, u32, u32, u32); 10] = [
+    (35, 5, 640, 480, 60),
+    (35, 2, 640, 480, 75),
+    (35, 0, 800, 600, 60),
+    (36, 7, 800, 600, 72),
+    (36, 6, 800, 600, 75),
+    (36, 3, 1024, 768, 60),
+    (36, 2, 1024, 768, 70),
+    (36, 1, 1024, 768, 75),
+    (36, 0, 1280, 1024, 75),
+    (37, 7, 1152, 870, 75),
+];
+
+/// Every mode an EDID base block advertises, preferred (first detailed
+/// timing) first, the rest largest first, without duplicates
+pub fn edid_modes(edid: &[u8]) -> Result<Vec<HdmiResolution>, &'static str> {
+    if edid.len() < 128 {
+        return Err("EDID data too short");
+    }
+    if edid[0..8] != [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00] {
+        return Err("Invalid EDID header");
+    }
+
+    let mut detailed = Vec::new();
+    for block in 0..4 {
+        let d = &edid[54 + block * 18..72 + block * 18];
+        // Pixel clock in 10 kHz units; zero marks a display descriptor
+        let pixel_clock = u16::from_le_bytes([d[0], d[1]]) as u64 * 10_000;
+        if pixel_clock == 0 {
+            continue;
+        }
+        let h_active = d[2] as u32 | ((d[4] as u32 & 0xF0) << 4);
+        let h_blank = d[3] as u32 | ((d[4] as u32 & 0x0F) << 8);
+        let v_active = d[5] as u32 | ((d[7] as u32 & 0xF0) << 4);
+        let v_blank = d[6] as u32 | ((d[7] as u32 & 0x0F) << 8);
+        let total = (h_active + h_blank) as u64 * (v_active + v_blank) as u64;
+        if h_active == 0 || v_active == 0 || total == 0 {
+            continue;
+        }
+        let refresh_rate = ((pixel_clock + total / 2) / total) as u32;
+        detailed.push(HdmiResolution { width: h_active, height: v_active, refresh_rate });
+    }
+
+    let mut others = Vec::new();
+    for &(byte, bit, width, height, refresh_rate) in ESTABLISHED_TIMINGS.iter() {
+        if edid[byte] & (1 << bit) != 0 {
+            others.push(HdmiResolution { width, height, refresh_rate });
+        }
+    }
+    for entry in edid[38..54].chunks_exact(2) {
+        // 0x01 0x01 marks an unused slot
+        if entry[0] <= 1 {
+            continue;
+        }
+        let width = (entry[0] as u32 + 31) * 8;
+        let height = match entry[1] >> 6 {
+            0 if edid[19] < 3 => width,          // 1:1 before EDID 1.3
+            0 => width * 10 / 16,
+            1 => width * 3 / 4,
+            2 => width * 4 / 5,
+            _ => width * 9 / 16,
+        };
+        others.push(HdmiResolution { width, height, refresh_rate: (entry[1] & 0x3F) as u32 + 60 });
+    }
+    others.extend(detailed.iter().skip(1).copied());
+    others.sort_unstable_by(|a, b| {
+        (b.width * b.height, b.refresh_rate).cmp(&(a.width * a.height, a.refresh_rate))
+    });
+
+    let mut modes: Vec<HdmiResolution> = detailed.into_iter().take(1).collect();
+    for mode in others {
+        if !modes.contains(&mode) {
+            modes.push(mode);
+        }
+    }
+    Ok(modes)
+}
+
 // Implement proper cleanup when the driver is dropped
 impl Drop for HdmiDriver {
     fn drop(&mut self) {
@@ -0,0 +1,239 @@
+//! Storage I/O scheduling
+//!
+//! Requests are queued per device and dispatched in an order chosen by the
+//! active scheduler:
+//!
+//! - `noop` dispatches in submission order.
+//! - `deadline` sweeps upward through LBAs from the last dispatched sector,
+//!   wrapping to the lowest, so the many small reads of a game load are served
+//!   in disk order. A request waiting longer than its deadline (reads 500 ms,
+//!   writes 5 s) is dispatched first regardless of position.
+//!
+//! `cfq` from older configs has no implementation of its own and maps to
+//! `deadline`. The scheduler is global and read at every dispatch, so a
+//! config reload applies to requests already queued.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// How long a read may wait before it jumps the queue
+const READ_DEADLINE_MS: u64 = 500;
+/// Writes are usually buffered, so they tolerate more delay
+const WRITE_DEADLINE_MS: u64 = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedulerKind {
+    Noop = 0,
+    Deadline = 1,
+}
+
+impl SchedulerKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "noop" | "none" => Some(SchedulerKind::Noop),
+            "deadline" | "cfq" => Some(SchedulerKind::Deadline),
+            _ => None,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => SchedulerKind::Noop,
+            _ => SchedulerKind::Deadline,
+        }
+    }
+}
+
+static SCHEDULER: AtomicU8 = AtomicU8::new(SchedulerKind::Deadline as u8);
+
+pub fn scheduler() -> SchedulerKind {
+    SchedulerKind::from_u8(SCHEDULER.load(Ordering::Relaxed))
+}
+
+pub fn set_scheduler(kind: SchedulerKind) {
+    SCHEDULER.store(kind as u8, Ordering::Relaxed);
+}
+
+/// Apply `storage.io_scheduler`; unknown names keep the current scheduler
+pub fn configure(config: &crate::config::StorageConfig) {
+    match SchedulerKind::from_name(&config.io_scheduler) {
+        Some(kind) => {
+            if kind != scheduler() {
+                log::info!("I/O scheduler: {:?}", kind);
+            }
+            set_scheduler(kind);
+        }
+        None => log::warn!("Unknown I/O scheduler '{}', keeping {:?}", config.io_scheduler, scheduler()),
+    }
+}
+
+pub type RequestId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoOperation {
+    Read,
+    Write,
+}
+
+/// Outcome of a request, handed to its completion
+#[derive(Debug)]
+pub struct IoCompletion {
+    pub id: RequestId,
+    pub operation: IoOperation,
+    pub lba: u64,
+    pub count: u32,
+    pub result: Result<(), &'static str>,
+    /// The sectors read, or the data that was written
+    pub data: Vec<u8>,
+}
+
+/// Called once the request has been dispatched
+pub type CompletionFn = Box<dyn FnOnce(IoCompletion) + Send>;
+
+/// What happens when a request completes
+pub enum Completion {
+    Callback(CompletionFn),
+    /// A blocked caller collects the result with `RequestQueue::take_finished`
+    Wait,
+}
+
+pub struct IoRequest {
+    pub id: RequestId,
+    pub operation: IoOperation,
+    pub lba: u64,
+    pub count: u32,
+    pub data: Vec<u8>,
+    pub submitted_ms: u64,
+    pub deadline_ms: u64,
+    pub completion: Completion,
+}
+
+impl IoRequest {
+    /// Run the completion callback. A `Completion::Wait` outcome is returned
+    /// instead, for `RequestQueue::finish`.
+    pub fn complete(self, result: Result<(), &'static str>) -> Option<IoCompletion> {
+        let completion = IoCompletion {
+            id: self.id,
+            operation: self.operation,
+            lba: self.lba,
+            count: self.count,
+            result,
+            data: self.data,
+        };
+        match self.completion {
+            Completion::Callback(callback) => {
+                callback(completion);
+                None
+            }
+            Completion::Wait => Some(completion),
+        }
+    }
+}
+
+/// Pending requests of one device
+pub struct RequestQueue {
+    pending: Vec<IoRequest>,
+    /// Completed requests whose callers block on them
+    finished: Vec<IoCompletion>,
+    next_id: RequestId,
+    /// Sector just past the last dispatched request, where the sweep continues
+    head_lba: u64,
+}
+
+impl RequestQueue {
+    pub const fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            finished: Vec::new(),
+            next_id: 1,
+            head_lba: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Queue a request submitted at `now_ms`
+    pub fn submit(
+        &mut self,
+        operation: IoOperation,
+        lba: u64,
+        count: u32,
+        data: Vec<u8>,
+        completion: Completion,
+        now_ms: u64,
+    ) -> RequestId {
+        let id = self.next_id;
+        self.next_id += 1;
+        let deadline = match operation {
+            IoOperation::Read => READ_DEADLINE_MS,
+            IoOperation::Write => WRITE_DEADLINE_MS,
+        };
+        self.pending.push(IoRequest {
+            id,
+            operation,
+            lba,
+            count,
+            data,
+            submitted_ms: now_ms,
+            deadline_ms: now_ms + deadline,
+            completion,
+        });
+        id
+    }
+
+    /// Remove and return the request to dispatch next under `kind`
+    pub fn next(&mut self, kind: SchedulerKind, now_ms: u64) -> Option<IoRequest> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let index = match kind {
+            // Pending is kept in submission order
+            SchedulerKind::Noop => 0,
+            SchedulerKind::Deadline => self.deadline_pick(now_ms),
+        };
+        let request = self.pending.remove(index);
+        self.head_lba = request.lba + request.count as u64;
+        Some(request)
+    }
+
+    fn deadline_pick(&self, now_ms: u64) -> usize {
+        // The most overdue request wins outright
+        let expired = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, request)| request.deadline_ms <= now_ms)
+            .min_by_key(|(_, request)| request.deadline_ms);
+        if let Some((index, _)) = expired {
+            return index;
+        }
+
+        // Otherwise the nearest LBA at or past the head, wrapping to the lowest
+        let by_lba = |(_, request): &(usize, &IoRequest)| (request.lba, request.id);
+        self.pending
+            .iter()
+            .enumerate()
+            .filter(|(_, request)| request.lba >= self.head_lba)
+            .min_by_key(by_lba)
+            .or_else(|| self.pending.iter().enumerate().min_by_key(by_lba))
+            .map_or(0, |(index, _)| index)
+    }
+
+    /// Hold a completed `Completion::Wait` request for its caller
+    pub fn finish(&mut self, completion: IoCompletion) {
+        self.finished.push(completion);
+    }
+
+    /// Collect the outcome of a `Completion::Wait` request, if it is done
+    pub fn take_finished(&mut self, id: RequestId) -> Option<IoCompletion> {
+        let index = self.finished.iter().position(|completion| completion.id == id)?;
+        Some(self.finished.swap_remove(index))
+    }
+}
@@ -1,5 +1,5 @@
 use crate::kernel::interrupts;
-use core::sync::atomic::{AtomicU8, Ordering};
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use alloc::vec;
@@ -16,6 +16,11 @@ type KeyEventCallback = Box<dyn Fn(KeyEvent) + Send + 'static>;
 // Keyboard port for data read
 const KEYBOARD_PORT: u16 = 0x60;
 
+/// Bits of `KeyEvent::modifiers`
+pub const MODIFIER_SHIFT: u8 = 0x01;
+pub const MODIFIER_CTRL: u8 = 0x02;
+pub const MODIFIER_ALT: u8 = 0x04;
+
 // Scancode buffers to store last pressed key
 lazy_static! {
     static ref KEYBOARD_STATE: Mutex<KeyboardState> = Mutex::new(KeyboardState::new());
@@ -26,6 +31,36 @@ lazy_static! {
 // Track if a key is pressed or released
 static LAST_SCANCODE: AtomicU8 = AtomicU8::new(0);
 
+/// Scancodes not yet consumed by `pop_scancode`. Single producer (the IRQ
+/// handler) and single consumer (the input sampler), so no lock is needed.
+const SCANCODE_RING_SIZE: usize = 64;
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: AtomicU8 = AtomicU8::new(0);
+static SCANCODE_RING: [AtomicU8; SCANCODE_RING_SIZE] = [EMPTY_SLOT; SCANCODE_RING_SIZE];
+static SCANCODE_HEAD: AtomicUsize = AtomicUsize::new(0);
+static SCANCODE_TAIL: AtomicUsize = AtomicUsize::new(0);
+
+fn push_scancode(scancode: u8) {
+    let head = SCANCODE_HEAD.load(Ordering::Relaxed);
+    if head.wrapping_sub(SCANCODE_TAIL.load(Ordering::Acquire)) >= SCANCODE_RING_SIZE {
+        return; // Full; drop the newest rather than corrupt ordering
+    }
+    SCANCODE_RING[head % SCANCODE_RING_SIZE].store(scancode, Ordering::Relaxed);
+    SCANCODE_HEAD.store(head.wrapping_add(1), Ordering::Release);
+}
+
+/// Oldest scancode received since the last call, in arrival order.
+/// Lock-free, so it may be called from interrupt context.
+pub fn pop_scancode() -> Option<u8> {
+    let tail = SCANCODE_TAIL.load(Ordering::Relaxed);
+    if tail == SCANCODE_HEAD.load(Ordering::Acquire) {
+        return None;
+    }
+    let scancode = SCANCODE_RING[tail % SCANCODE_RING_SIZE].load(Ordering::Relaxed);
+    SCANCODE_TAIL.store(tail.wrapping_add(1), Ordering::Release);
+    Some(scancode)
+}
+
 pub struct KeyboardState {
     scancode: u8,
     shift_pressed: bool,
@@ -67,6 +102,21 @@ impl KeyboardState {
         }
     }
 
+    /// Held modifier keys as `MODIFIER_*` bits
+    fn modifier_bits(&self) -> u8 {
+        let mut bits = 0;
+        if self.shift_pressed {
+            bits |= MODIFIER_SHIFT;
+        }
+        if self.ctrl_pressed {
+            bits |= MODIFIER_CTRL;
+        }
+        if self.alt_pressed {
+            bits |= MODIFIER_ALT;
+        }
+        bits
+    }
+
     fn update_modifiers(&mut self, scancode: u8) {
         let released = scancode & 0x80 != 0;
         let key = scancode & 0x7F;
@@ -181,6 +231,7 @@ pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: Interrupt
 
     // Store the scancode
     LAST_SCANCODE.store(scancode, Ordering::SeqCst);
+    push_scancode(scancode);
 
     // Update keyboard state
     let mut keyboard = KEYBOARD_STATE.lock();
@@ -197,7 +248,7 @@ pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: Interrupt
             alt_pressed: keyboard.alt_pressed,
             num_lock: keyboard.num_lock,
             key_code: scancode as u16,
-            modifiers: 0,
+            modifiers: keyboard.modifier_bits(),
         };
 
         #[cfg(feature = "std")]
@@ -223,7 +274,7 @@ pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: Interrupt
 
     // Send End-Of-Interrupt signal
     unsafe {
-        interrupts::PICS.notify_end_of_interrupt(interrupts::KEYBOARD_INTERRUPT_INDEX);
+        interrupts::irq::end_of_interrupt(interrupts::KEYBOARD_INTERRUPT_INDEX);
     }
 }
 
@@ -233,9 +284,7 @@ pub fn init() {
     interrupts::set_irq_handler(interrupts::KEYBOARD_INTERRUPT_INDEX, keyboard_interrupt_handler);
     
     // Enable the keyboard IRQ in the PIC
-    unsafe {
-        interrupts::PICS.initialize();
-    }
+    interrupts::irq::pic::unmask(interrupts::irq::pic::Irq::KEYBOARD);
 }
 
 // Get the last scancode
@@ -257,7 +306,7 @@ pub fn get_modifiers() -> (bool, bool, bool) {
 
 pub fn handle_interrupt() {
     unsafe {
-        interrupts::PICS.notify_end_of_interrupt(interrupts::KEYBOARD_INTERRUPT_INDEX);
+        interrupts::irq::end_of_interrupt(interrupts::KEYBOARD_INTERRUPT_INDEX);
     }
 }
 
@@ -277,7 +326,7 @@ pub fn get_pending_events() -> Result<Vec<KeyEvent>, ()> {
         alt_pressed,
         num_lock,
         key_code: scancode as u16,
-        modifiers: 0,
+        modifiers: state.modifier_bits(),
     };
     
     Ok(vec![event])
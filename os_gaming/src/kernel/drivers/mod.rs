@@ -1,17 +1,22 @@
+pub mod acpi;
 pub mod keyboard;
 pub mod vga;
 pub mod hdmi;
 pub mod mouse;
 pub mod sound;
+pub mod notification_sound;
 pub mod storage;
+pub mod io_scheduler;
 pub mod network;
 pub mod display;
 pub mod usb;
 pub mod gamepad;
+pub mod touch;
 pub mod filesystem;
 pub mod timer;
 pub mod power;
 pub mod displayport;
+pub mod display_hotplug;
 pub mod gpu;
 mod inputs;
 
@@ -23,24 +28,61 @@ use displayport::DisplayPortDriver;
 use hdmi::HdmiDriver;
 use vga::Writer;
 use crate::println;
-use sound::SoundDriver;
+
 use mouse::MouseState;
 use spin::Mutex;
 use lazy_static::lazy_static;
 
+extern crate alloc;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Outcome of a single driver's initialization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverStatus {
+    Ready,
+    Failed(&'static str),
+}
+
+impl DriverStatus {
+    pub fn is_ready(&self) -> bool {
+        matches!(self, DriverStatus::Ready)
+    }
+}
+
+impl fmt::Display for DriverStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DriverStatus::Ready => write!(f, "ok"),
+            DriverStatus::Failed(reason) => write!(f, "failed ({})", reason),
+        }
+    }
+}
+
 /// Container for all driver managers
 pub struct DriverManager {
-    pub network_manager: network::NetworkManager,
+    pub network_manager: Option<network::NetworkManager>,
     pub storage_manager: storage::StorageManager,
-    pub usb_manager: usb::UsbManager,
+    pub usb_manager: Option<usb::UsbManager>,
     pub input_manager: InputManager,
-    pub sound_system: sound::SoundDriver,
     pub power_manager: power::PowerManager,
     pub filesystem_manager: filesystem::FilesystemManager,
     pub timer_manager: timer::TimerManager,
     pub displayport_manager: displayport::DisplayPortDriver,
     pub hdmi_manager: hdmi::HdmiDriver,
     pub vga_manager: vga::Writer,
+    /// Per-driver init results, in the order they were attempted
+    pub driver_status: Vec<(&'static str, DriverStatus)>,
+}
+
+impl DriverManager {
+    /// Look up the init result of a driver by name
+    pub fn status(&self, name: &str) -> Option<DriverStatus> {
+        self.driver_status
+            .iter()
+            .find(|(driver, _)| *driver == name)
+            .map(|(_, status)| *status)
+    }
 }
 
 /// Input device abstraction
@@ -50,77 +92,211 @@ pub struct InputManager {
     pub gamepads: gamepad::GamepadManager,
 }
 
+/// Managers produced while the init table runs
+#[derive(Default)]
+struct DetectedDrivers {
+    network: Option<network::NetworkManager>,
+    storage: Option<storage::StorageManager>,
+    usb: Option<usb::UsbManager>,
+    gamepads: Option<GamepadManager>,
+}
+
+/// One step of driver bring-up
+struct DriverInit {
+    name: &'static str,
+    /// A critical driver aborts init on failure, others are logged and skipped
+    critical: bool,
+    init: fn(&mut DetectedDrivers) -> Result<(), &'static str>,
+}
+
+/// Driver bring-up order. Display comes first so later failures are visible,
+/// and storage must precede the filesystem.
+const DRIVER_INIT_ORDER: &[DriverInit] = &[
+    DriverInit { name: "display", critical: true, init: |_| display::init() },
+    DriverInit {
+        name: "timer",
+        critical: true,
+        init: |_| {
+            timer::init()?;
+            crate::logger::set_timestamp_source(timer::timestamp_us_lockless);
+            Ok(())
+        },
+    },
+    DriverInit {
+        name: "network",
+        critical: false,
+        init: |d| network::init().map(|m| d.network = Some(m)),
+    },
+    DriverInit {
+        name: "storage",
+        critical: true,
+        init: |d| storage::init().map(|m| d.storage = Some(m)),
+    },
+    DriverInit {
+        name: "usb",
+        critical: false,
+        init: |d| usb::init().map(|m| d.usb = Some(m)),
+    },
+    DriverInit {
+        name: "keyboard",
+        critical: true,
+        init: |_| {
+            keyboard::init();
+            Ok(())
+        },
+    },
+    DriverInit {
+        name: "mouse",
+        critical: true,
+        init: |_| {
+            mouse::init();
+            Ok(())
+        },
+    },
+    DriverInit {
+        name: "gamepad",
+        critical: false,
+        init: |d| gamepad::init().map(|m| d.gamepads = Some(m)),
+    },
+    DriverInit {
+        name: "sound",
+        critical: false,
+        init: |_| sound::init(),
+    },
+    DriverInit {
+        name: "filesystem",
+        critical: true,
+        init: |d| {
+            let storage = d.storage.as_ref().ok_or("Storage not initialized")?;
+            filesystem::init(storage)
+        },
+    },
+    DriverInit { name: "power", critical: true, init: |_| power::init() },
+    DriverInit { name: "acpi-events", critical: false, init: |_| acpi::init() },
+    DriverInit { name: "display-hotplug", critical: false, init: |_| display_hotplug::init() },
+];
+
 // Global access to driver manager
 lazy_static! {
     pub static ref DRIVER_MANAGER: Mutex<Option<DriverManager>> = Mutex::new(None);
 }
 
+/// Init results of a bring-up a critical driver aborted, which never got a `DriverManager`
+static ABORTED_INIT_STATUS: Mutex<Vec<(&'static str, DriverStatus)>> = Mutex::new(Vec::new());
+
+/// Run `table` in order, recording each driver's result in `driver_status`.
+/// Stops at the first critical failure, after recording it.
+fn run_init_table(
+    table: &[DriverInit],
+    detected: &mut DetectedDrivers,
+    driver_status: &mut Vec<(&'static str, DriverStatus)>,
+) -> Result<(), &'static str> {
+    for driver in table {
+        match (driver.init)(detected) {
+            Ok(()) => {
+                log::info!("{}: ok", driver.name);
+                driver_status.push((driver.name, DriverStatus::Ready));
+            }
+            Err(e) if driver.critical => {
+                log::error!("{}: failed: {}", driver.name, e);
+                driver_status.push((driver.name, DriverStatus::Failed(e)));
+                return Err(e);
+            }
+            Err(e) => {
+                log::warn!("{}: failed: {} (continuing without it)", driver.name, e);
+                crate::gui::toast::post("Driver unavailable", &alloc::format!("{}: {}", driver.name, e));
+                driver_status.push((driver.name, DriverStatus::Failed(e)));
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Initialize all drivers
 pub fn init() -> Result<(), &'static str> {
-    // Initialize display first for debugging output
-    display::init()?;
-    
-    // Initialize hardware timer/clock
-    timer::init()?;
-    
-    // Initialize ACPI for power management
-    
-    // Initialize network subsystem
-    let net_manager = network::init()?;
-    
-    // Initialize storage devices
-    let storage_manager = storage::init()?;
-    
-    // Initialize USB bus
-    let usb_manager = usb::init()?;
-    
-    // Initialize input devices
-    keyboard::init();
-    mouse::init();
-    let gamepad_manager = gamepad::init()?;
-    let mouse_state = mouse::MouseState::new();
-    let keyboard_state = KeyboardState::new();
-    
+    let mut detected = DetectedDrivers::default();
+    let mut driver_status = Vec::with_capacity(DRIVER_INIT_ORDER.len());
+
+    if let Err(e) = run_init_table(DRIVER_INIT_ORDER, &mut detected, &mut driver_status) {
+        *ABORTED_INIT_STATUS.lock() = driver_status;
+        return Err(e);
+    }
+
     let input_manager = InputManager {
-        keyboard: keyboard_state.into(),
-        mouse: mouse_state.into(),
-        gamepads: gamepad_manager.clone(),
+        keyboard: KeyboardState::new(),
+        mouse: MouseState::new(),
+        gamepads: detected.gamepads.unwrap_or_else(GamepadManager::new),
     };
-    
-    // Initialize sound system
-    let sound_system = sound::init()?;
-    
-    // Initialize filesystem
-    filesystem::init(&storage_manager)?;
-    
-    // Initialize power management
-    power::init()?;
-    
+
     // Create driver manager instance
     let manager = DriverManager {
-        network_manager: net_manager,
-        storage_manager,
-        usb_manager,
+        network_manager: detected.network,
+        storage_manager: detected.storage.ok_or("Storage not initialized")?,
+        usb_manager: detected.usb,
         input_manager,
-        sound_system,
+
         power_manager : power::PowerManager::new(),
         filesystem_manager: filesystem::FilesystemManager::new(),
         timer_manager: timer::TimerManager::new(),
         displayport_manager: DisplayPortDriver::new(),
         hdmi_manager: HdmiDriver::new(),
         vga_manager: Writer::new(),
+        driver_status,
     };
-    
+
     // Store global reference
     *DRIVER_MANAGER.lock() = Some(manager);
-    
+
+    // Needs storage and the filesystem
+    crate::kernel::crash_log::recover();
+
     #[cfg(debug_assertions)]
     println!("All drivers initialized successfully");
-    
+
     Ok(())
 }
 
+/// Init results of every driver attempted so far, e.g. ("network", Failed(..)),
+/// including the critical driver that aborted bring-up
+pub fn driver_status() -> Vec<(&'static str, DriverStatus)> {
+    DRIVER_MANAGER
+        .lock()
+        .as_ref()
+        .map(|manager| manager.driver_status.clone())
+        .unwrap_or_else(|| ABORTED_INIT_STATUS.lock().clone())
+}
+
 /// Get the driver manager
 pub fn get_driver_manager() -> &'static Mutex<Option<DriverManager>> {
     &DRIVER_MANAGER
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOCK_INIT_ORDER: &[DriverInit] = &[
+        DriverInit { name: "first", critical: true, init: |_| Ok(()) },
+        DriverInit { name: "optional", critical: false, init: |_| Err("no device") },
+        DriverInit { name: "critical", critical: true, init: |_| Err("controller timeout") },
+        DriverInit { name: "never-reached", critical: false, init: |_| Ok(()) },
+    ];
+
+    #[test]
+    fn critical_failure_stops_bring_up_and_is_recorded() {
+        let mut detected = DetectedDrivers::default();
+        let mut driver_status = Vec::new();
+
+        let result = run_init_table(MOCK_INIT_ORDER, &mut detected, &mut driver_status);
+
+        assert_eq!(result, Err("controller timeout"));
+        assert_eq!(
+            driver_status,
+            [
+                ("first", DriverStatus::Ready),
+                ("optional", DriverStatus::Failed("no device")),
+                ("critical", DriverStatus::Failed("controller timeout")),
+            ]
+        );
+    }
+}
@@ -3,12 +3,61 @@ use spin::Mutex;
 use lazy_static::lazy_static;
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use micromath::F32Ext;
+use crate::config::InputConfig;
 
 
 lazy_static! {
     static ref MOUSE: Mutex<Mouse> = Mutex::new(Mouse::new());
 }
 
+/// Raw mode requested by `set_raw_mode`; applied at the next poll
+static RAW_MODE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Sensitivity setting that maps to an unscaled 1:1 response
+const NEUTRAL_SENSITIVITY: f32 = 5.0;
+/// Counts per packet at which acceleration reaches its full gain
+const ACCELERATION_FULL_SPEED: f32 = 16.0;
+/// Sample rates a PS/2 mouse accepts with the 0xF3 command, ascending
+const PS2_SAMPLE_RATES: [u8; 7] = [10, 20, 40, 60, 80, 100, 200];
+/// Rate after "set defaults" (0xF6)
+const PS2_DEFAULT_SAMPLE_RATE: u32 = 100;
+/// Controller status polls to wait for the device's acknowledgement
+const PS2_ACK_SPINS: u32 = 100_000;
+const PS2_ACK: u8 = 0xFA;
+
+/// Pointer processing derived from `InputConfig`
+#[derive(Debug, Clone, Copy)]
+pub struct PointerCurve {
+    /// Linear gain, 1.0 at the neutral sensitivity
+    pub gain: f32,
+    /// Extra gain reached at high speed (1.0 = no acceleration)
+    pub acceleration: f32,
+    pub invert_y: bool,
+}
+
+impl PointerCurve {
+    pub const IDENTITY: Self = Self { gain: 1.0, acceleration: 1.0, invert_y: false };
+
+    pub fn from_config(config: &InputConfig) -> Self {
+        Self {
+            gain: config.mouse_sensitivity.clamp(1, 10) as f32 / NEUTRAL_SENSITIVITY,
+            acceleration: config.mouse_acceleration.max(1.0),
+            invert_y: config.invert_mouse_y,
+        }
+    }
+
+    /// Scale one packet's movement, ramping acceleration in with pointer speed
+    pub fn apply(&self, dx: f32, dy: f32) -> (f32, f32) {
+        let speed = (dx * dx + dy * dy).sqrt();
+        let ramp = (speed / ACCELERATION_FULL_SPEED).min(1.0);
+        let scale = self.gain * (1.0 + (self.acceleration - 1.0) * ramp);
+        let dy = if self.invert_y { -dy } else { dy };
+        (dx * scale, dy * scale)
+    }
+}
+
 pub struct MouseState {
     pub x: i32,
     pub y: i32,
@@ -23,8 +72,16 @@ pub struct Mouse {
     data_port: PortReadOnly<u8>,
     command_port: PortWriteOnly<u8>,
     status_port: Port<u8>,
+    device_port: PortWriteOnly<u8>,
     cycle: u8,
     packet: [u8; 3],
+    curve: PointerCurve,
+    /// Whether packets bypass `curve`; only changes between polls
+    raw_mode: bool,
+    /// Sub-count movement carried over so slow motion isn't lost to rounding
+    remainder: (f32, f32),
+    /// Reports per second the device was last set to
+    sample_rate: u32,
 }
 
 impl MouseState {
@@ -61,8 +118,13 @@ impl Mouse {
             data_port: PortReadOnly::new(0x60),
             command_port: PortWriteOnly::new(0x64),
             status_port: Port::new(0x64),
+            device_port: PortWriteOnly::new(0x60),
             cycle: 0,
             packet: [0; 3],
+            curve: PointerCurve::IDENTITY,
+            raw_mode: false,
+            remainder: (0.0, 0.0),
+            sample_rate: PS2_DEFAULT_SAMPLE_RATE,
         }
     }
 
@@ -76,6 +138,22 @@ impl Mouse {
         }
     }
 
+    /// Send a byte to the mouse itself and wait for its acknowledgement
+    fn write_device(&mut self, byte: u8) -> bool {
+        unsafe {
+            while (self.status_port.read() & 2) != 0 {}
+            self.command_port.write(0xD4);
+            while (self.status_port.read() & 2) != 0 {}
+            self.device_port.write(byte);
+            for _ in 0..PS2_ACK_SPINS {
+                if self.status_port.read() & 1 != 0 {
+                    return self.data_port.read() == PS2_ACK;
+                }
+            }
+        }
+        false
+    }
+
     fn read_data(&mut self) -> u8 {
         unsafe { self.data_port.read() }
     }
@@ -96,8 +174,17 @@ impl Mouse {
         let x_sign = (self.packet[0] & 0x10) != 0;
         let y_sign = (self.packet[0] & 0x20) != 0;
         
-        let x_movement = if x_sign { x - 256 } else { x };
-        let y_movement = if y_sign { 256 - y } else { -y };
+        let mut x_movement = if x_sign { x - 256 } else { x };
+        let mut y_movement = if y_sign { 256 - y } else { -y };
+
+        if !self.raw_mode {
+            let (dx, dy) = self.curve.apply(x_movement as f32, y_movement as f32);
+            let dx = dx + self.remainder.0;
+            let dy = dy + self.remainder.1;
+            x_movement = dx.trunc() as i32;
+            y_movement = dy.trunc() as i32;
+            self.remainder = (dx.fract(), dy.fract());
+        }
         
         self.state.x += x_movement;
         self.state.y += y_movement;
@@ -111,6 +198,51 @@ impl Mouse {
     }
 }
 
+/// Apply sensitivity, acceleration and Y inversion from the input config
+pub fn configure(config: &InputConfig) {
+    MOUSE.lock().curve = PointerCurve::from_config(config);
+}
+
+/// Set the PS/2 sample rate to the fastest supported rate not above `hz`
+/// (10 Hz at the least). Returns the rate in effect afterwards.
+pub fn set_sample_rate(hz: u32) -> u32 {
+    let rate = PS2_SAMPLE_RATES
+        .iter()
+        .rev()
+        .copied()
+        .find(|&rate| rate as u32 <= hz)
+        .unwrap_or(PS2_SAMPLE_RATES[0]);
+
+    // The acknowledgements must not be taken for packet bytes by the IRQ handler
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut mouse = MOUSE.lock();
+        if mouse.write_device(0xF3) && mouse.write_device(rate) {
+            mouse.sample_rate = rate as u32;
+            // A partial packet from before the change is stale
+            mouse.cycle = 0;
+        } else {
+            log::warn!("PS/2 mouse did not accept a sample rate of {} Hz", rate);
+        }
+        mouse.sample_rate
+    })
+}
+
+/// Reports per second of the PS/2 mouse
+pub fn sample_rate() -> u32 {
+    MOUSE.lock().sample_rate
+}
+
+/// Request raw (unprocessed) pointer counts. The switch takes effect at the
+/// next `get_pending_events` poll so one frame never mixes raw and processed motion.
+pub fn set_raw_mode(enabled: bool) {
+    RAW_MODE_REQUESTED.store(enabled, Ordering::SeqCst);
+}
+
+/// Whether packets are currently delivered unprocessed
+pub fn is_raw_mode() -> bool {
+    MOUSE.lock().raw_mode
+}
+
 pub fn init() {
     let mut mouse = MOUSE.lock();
     
@@ -177,6 +309,11 @@ pub fn get_state() -> MouseState {
     }
 }
 
+/// Current state without blocking; `None` if the driver is busy. Safe from interrupt context.
+pub fn try_get_state() -> Option<MouseState> {
+    MOUSE.try_lock().map(|mouse| mouse.state.clone())
+}
+
 pub fn get_pending_events() -> Result<Vec<MouseState>, &'static str> {
     // Static for tracking previous state to detect changes
     static mut PREV_STATE: Option<MouseState> = None;
@@ -248,5 +385,13 @@ pub fn get_pending_events() -> Result<Vec<MouseState>, &'static str> {
         PREV_STATE = Some(current_state);
     }
 
+    // Frame boundary: switch raw/processed mode only between polls
+    let raw_mode = RAW_MODE_REQUESTED.load(Ordering::SeqCst);
+    if raw_mode != mouse.raw_mode {
+        mouse.raw_mode = raw_mode;
+        mouse.remainder = (0.0, 0.0);
+        log::info!("Mouse {} mode", if raw_mode { "raw" } else { "processed" });
+    }
+
     Ok(events)
 }
\ No newline at end of file
@@ -40,6 +40,30 @@ pub struct NetworkInterface {
     driver: NetworkDriverType,
 }
 
+/// Capabilities of one network interface
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceCapabilities {
+    pub name: String,
+    pub card_type: NetworkCardType,
+    pub mac_address: [u8; 6],
+    pub mtu: u16,
+    /// A hardware driver is bound, so the interface can send and receive
+    pub has_driver: bool,
+    pub active: bool,
+}
+
+/// What the network hardware can do, for deciding which settings to offer
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NetworkCapabilities {
+    pub interfaces: Vec<InterfaceCapabilities>,
+    /// At least one interface has a working driver
+    pub connectivity: bool,
+    /// There are no wireless drivers yet, so this is always false
+    pub wireless: bool,
+    /// Largest MTU across interfaces with a driver, 0 if none
+    pub max_mtu: u16,
+}
+
 /// Type of network driver implementation to use
 enum NetworkDriverType {
     None,
@@ -196,6 +220,18 @@ impl NetworkInterface {
         }
     }
 
+    /// Capabilities of this interface
+    pub fn capabilities(&self) -> InterfaceCapabilities {
+        InterfaceCapabilities {
+            name: self.name.clone(),
+            card_type: self.card_type,
+            mac_address: self.mac_address,
+            mtu: self.mtu,
+            has_driver: !matches!(self.driver, NetworkDriverType::None),
+            active: self.active.load(Ordering::SeqCst),
+        }
+    }
+
     /// Set the IP address for this interface
     pub fn set_ip_address(&mut self, ip: [u8; 4]) {
         self.ip_address = Some(ip);
@@ -931,6 +967,19 @@ impl NetworkManager {
         })
     }
 
+    /// Capabilities of all interfaces combined
+    pub fn capabilities(&self) -> NetworkCapabilities {
+        let interfaces: Vec<InterfaceCapabilities> =
+            self.interfaces.iter().map(NetworkInterface::capabilities).collect();
+        let max_mtu = interfaces.iter().filter(|i| i.has_driver).map(|i| i.mtu).max().unwrap_or(0);
+        NetworkCapabilities {
+            connectivity: interfaces.iter().any(|i| i.has_driver),
+            wireless: false,
+            max_mtu,
+            interfaces,
+        }
+    }
+
     /// Get a list of all interfaces
     pub fn get_interfaces(&self) -> &[NetworkInterface] {
         &self.interfaces
@@ -1040,6 +1089,17 @@ pub fn init() -> Result<NetworkManager, &'static str> {
     NetworkManager::new()
 }
 
+/// Capabilities of the network interfaces found at boot; empty if
+/// networking failed to initialize
+pub fn capabilities() -> NetworkCapabilities {
+    super::DRIVER_MANAGER
+        .lock()
+        .as_ref()
+        .and_then(|drivers| drivers.network_manager.as_ref())
+        .map(NetworkManager::capabilities)
+        .unwrap_or_default()
+}
+
 pub fn handle_gaming_interrupt() {
     // Handle gaming interrupts here
     // This is a placeholder for actual interrupt handling
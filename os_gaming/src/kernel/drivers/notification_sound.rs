@@ -0,0 +1,209 @@
+//! Notification sounds
+//!
+//! Each notification level has its own short cue: info is a single chirp,
+//! warnings a double tap, errors a descending two-tone. The config can swap
+//! any of them for a PCM WAV file or silence them. Cues are rendered (or
+//! loaded) once when the config is applied, already scaled by the master and
+//! effects volume, and submitted as voices so playing one never blocks.
+//! A cue is skipped rather than cutting off audio that is already playing.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+use micromath::F32Ext;
+
+use super::filesystem::FilesystemManager;
+use super::sound::{self, SampleRate};
+use crate::config::{AudioConfig, NotificationSettings};
+
+/// Rate cues are rendered at when the pipeline has not been negotiated yet
+const DEFAULT_RATE: SampleRate = SampleRate::Hz16000;
+/// Fade at each end of a tone, to avoid clicks
+const RAMP_MS: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationLevel {
+    Info = 0,
+    Warning = 1,
+    Error = 2,
+}
+
+/// One step of a built-in cue; a frequency of 0 is a pause
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tone {
+    pub frequency: u16,
+    pub duration_ms: u32,
+}
+
+const fn tone(frequency: u16, duration_ms: u32) -> Tone {
+    Tone { frequency, duration_ms }
+}
+
+const INFO_TONES: &[Tone] = &[tone(1320, 70)];
+const WARNING_TONES: &[Tone] = &[tone(988, 60), tone(0, 50), tone(988, 60)];
+const ERROR_TONES: &[Tone] = &[tone(880, 120), tone(587, 180)];
+
+impl NotificationLevel {
+    /// The built-in cue for this level
+    pub fn default_tones(self) -> &'static [Tone] {
+        match self {
+            NotificationLevel::Info => INFO_TONES,
+            NotificationLevel::Warning => WARNING_TONES,
+            NotificationLevel::Error => ERROR_TONES,
+        }
+    }
+}
+
+/// A cue ready to submit
+#[derive(Debug, Clone)]
+pub struct Voice {
+    pub samples: Vec<i16>,
+    pub rate: SampleRate,
+}
+
+struct Cues {
+    voices: [Option<Voice>; 3],
+}
+
+static CUES: Mutex<Cues> = Mutex::new(Cues { voices: [None, None, None] });
+
+/// Render or load every level's cue from the config. Muted audio, zero
+/// master or effects volume, or `play_sound` off leaves every level silent.
+pub fn configure(audio: &AudioConfig, notifications: &NotificationSettings) {
+    let gain = audio.master_volume.min(100) as f32 / 100.0 * audio.sfx_volume.min(100) as f32 / 100.0;
+    let muted = !audio.enabled || !notifications.play_sound || gain <= 0.0;
+    let rate = sound::pipeline_rate().unwrap_or(DEFAULT_RATE);
+
+    let sources = [
+        (NotificationLevel::Info, &notifications.info_sound),
+        (NotificationLevel::Warning, &notifications.warning_sound),
+        (NotificationLevel::Error, &notifications.error_sound),
+    ];
+    let mut cues = CUES.lock();
+    for (level, source) in sources {
+        cues.voices[level as usize] = if muted {
+            None
+        } else {
+            load_cue(level, source, rate, gain)
+        };
+    }
+}
+
+fn load_cue(level: NotificationLevel, source: &str, rate: SampleRate, gain: f32) -> Option<Voice> {
+    let mut voice = match source {
+        "none" => return None,
+        "" => Voice { samples: render_tones(level.default_tones(), rate), rate },
+        path => match FilesystemManager::read_bytes(path).and_then(|data| parse_wav(&data)) {
+            Ok(voice) => voice,
+            Err(e) => {
+                log::warn!("Notification sound '{}' unusable ({}), using the built-in {:?} tone", path, e, level);
+                Voice { samples: render_tones(level.default_tones(), rate), rate }
+            }
+        },
+    };
+    for sample in voice.samples.iter_mut() {
+        *sample = (*sample as f32 * gain) as i16;
+    }
+    Some(voice)
+}
+
+/// The cue that `play` would submit for `level`, if any
+pub fn voice(level: NotificationLevel) -> Option<Voice> {
+    CUES.lock().voices[level as usize].clone()
+}
+
+/// Play `level`'s cue without blocking. Returns whether it was submitted.
+pub fn play(level: NotificationLevel) -> bool {
+    let voice = match voice(level) {
+        Some(voice) => voice,
+        None => return false,
+    };
+    if sound::get_sound_driver().is_playing() {
+        log::debug!("Skipping {:?} notification sound, audio is playing", level);
+        return false;
+    }
+    match sound::submit_voice(&voice.samples, voice.rate) {
+        Ok(()) => true,
+        Err(e) => {
+            log::debug!("{:?} notification sound not played: {}", level, e);
+            false
+        }
+    }
+}
+
+/// Sine tones back to back, each faded in and out
+pub fn render_tones(tones: &[Tone], rate: SampleRate) -> Vec<i16> {
+    let hz = rate.hz();
+    let amplitude = i16::MAX as f32 * 0.5;
+    let mut samples = Vec::new();
+    for tone in tones {
+        let count = (hz as u64 * tone.duration_ms as u64 / 1000) as usize;
+        if tone.frequency == 0 {
+            samples.resize(samples.len() + count, 0);
+            continue;
+        }
+        let ramp = ((hz * RAMP_MS / 1000) as usize).min(count / 2).max(1);
+        let step = 2.0 * core::f32::consts::PI * tone.frequency as f32 / hz as f32;
+        for i in 0..count {
+            let envelope = (i.min(count - 1 - i) as f32 / ramp as f32).min(1.0);
+            samples.push(((i as f32 * step).sin() * amplitude * envelope) as i16);
+        }
+    }
+    samples
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Decode an uncompressed 8- or 16-bit PCM WAV, mixing channels down to mono
+pub fn parse_wav(data: &[u8]) -> Result<Voice, &'static str> {
+    if data.get(0..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WAVE") {
+        return Err("Not a WAV file");
+    }
+
+    let mut format = None;
+    let mut offset = 12;
+    while let (Some(id), Some(size)) = (data.get(offset..offset + 4), read_u32(data, offset + 4)) {
+        let body = offset + 8;
+        let size = size as usize;
+        match id {
+            b"fmt " => {
+                let tag = read_u16(data, body).ok_or("Truncated WAV format")?;
+                let channels = read_u16(data, body + 2).ok_or("Truncated WAV format")?;
+                let hz = read_u32(data, body + 4).ok_or("Truncated WAV format")?;
+                let bits = read_u16(data, body + 14).ok_or("Truncated WAV format")?;
+                if tag != 1 {
+                    return Err("WAV is not PCM");
+                }
+                format = Some((channels.max(1) as usize, hz, bits));
+            }
+            b"data" => {
+                let (channels, hz, bits) = format.ok_or("WAV data before format")?;
+                let rate = SampleRate::ALL
+                    .iter()
+                    .copied()
+                    .find(|rate| rate.hz() == hz)
+                    .ok_or("Unsupported WAV sample rate")?;
+                let pcm = &data[body..(body + size).min(data.len())];
+                let samples: Vec<i32> = match bits {
+                    8 => pcm.iter().map(|&b| (b as i32 - 128) << 8).collect(),
+                    16 => pcm.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]) as i32).collect(),
+                    _ => return Err("Unsupported WAV sample size"),
+                };
+                let samples = samples
+                    .chunks_exact(channels)
+                    .map(|frame| (frame.iter().sum::<i32>() / channels as i32) as i16)
+                    .collect();
+                return Ok(Voice { samples, rate });
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even size
+        offset = body + size + (size & 1);
+    }
+    Err("WAV has no data")
+}
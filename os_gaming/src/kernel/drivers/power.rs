@@ -7,6 +7,8 @@
 //! - Battery monitoring
 //! - Performance profiles for gaming
 
+extern crate alloc;
+use alloc::vec::Vec;
 use core::sync::atomic::{AtomicBool, Ordering};
 use core::arch::asm;
 use lazy_static::lazy_static;
@@ -328,6 +330,11 @@ impl PowerManager {
             return Err("CPU frequency scaling not supported");
         }
 
+        // Program the hardware, keep the selection even if P-states can't be driven
+        if let Err(e) = crate::kernel::cpu::power::set_governor(governor) {
+            log::warn!("Failed to apply CPU governor {:?}: {}", governor, e);
+        }
+
         self.cpu_governor = governor;
 
         #[cfg(feature = "std")]
@@ -532,6 +539,26 @@ pub fn reboot() -> Result<(), &'static str> {
     manager.reboot()
 }
 
+/// Reboot from a context where the power manager may be locked or broken,
+/// such as a panic. Goes through `reboot()` when possible and otherwise
+/// resets the machine directly.
+pub fn emergency_reboot() -> ! {
+    if let Some(manager) = POWER_MANAGER.try_lock() {
+        let _ = manager.reboot();
+    }
+
+    unsafe {
+        asm!("cli");
+        // Pulse the reset line through the PS/2 controller
+        let mut port = Port::new(0x64);
+        port.write(0xFEu8);
+
+        // Still running: load an empty IDT and fault, which triple-faults into a reset
+        let empty_idt = [0u64; 2];
+        asm!("lidt [{}]", "int3", in(reg) &empty_idt, options(noreturn));
+    }
+}
+
 pub fn enter_sleep_mode() -> Result<(), &'static str> {
     let mut manager = POWER_MANAGER.lock();
     manager.set_power_state(PowerState::S3)
@@ -542,3 +569,356 @@ pub fn shutdown() -> Result<(), &'static str> {
     let manager = POWER_MANAGER.lock();
     manager.shutdown()
 }
+
+
+/// Path of the hibernation image
+const HIBERNATE_IMAGE_PATH: &str = "/hibernate.img";
+
+/// Magic bytes at the start of a hibernation image
+const HIBERNATE_MAGIC: [u8; 8] = *b"FGHIBER1";
+
+/// Size of the image header: magic, region count, payload length, CRC32
+const HIBERNATE_HEADER_SIZE: usize = 8 + 4 + 8 + 4;
+
+/// Size of a record header: tag, data length
+const HIBERNATE_RECORD_HEADER_SIZE: usize = 4 + 8;
+
+/// A memory region saved to disk on hibernate and restored on resume
+#[derive(Debug, Clone, Copy)]
+struct HibernationRegion {
+    tag: u32,
+    base: usize,
+    len: usize,
+}
+
+/// State saved and restored by its owner instead of as raw memory, for data
+/// that holds heap pointers, which differ on the next boot
+#[derive(Clone, Copy)]
+struct HibernationState {
+    tag: u32,
+    save: fn() -> Result<Vec<u8>, &'static str>,
+    /// Checks saved bytes without applying them, so a bad image is refused
+    /// before any region is overwritten
+    validate: fn(&[u8]) -> Result<(), &'static str>,
+    restore: fn(&[u8]) -> Result<(), &'static str>,
+}
+
+lazy_static! {
+    static ref HIBERNATION_REGIONS: Mutex<Vec<HibernationRegion>> = Mutex::new(Vec::new());
+    static ref HIBERNATION_STATES: Mutex<Vec<HibernationState>> = Mutex::new(Vec::new());
+}
+
+fn hibernation_tag_in_use(tag: u32) -> bool {
+    HIBERNATION_REGIONS.lock().iter().any(|r| r.tag == tag) || HIBERNATION_STATES.lock().iter().any(|s| s.tag == tag)
+}
+
+/// Register a memory region to be preserved across hibernation
+///
+/// # Safety
+/// `base..base + len` must stay valid and writable for as long as it is registered.
+pub unsafe fn register_hibernation_region(tag: u32, base: *mut u8, len: usize) -> Result<(), &'static str> {
+    if hibernation_tag_in_use(tag) {
+        return Err("Hibernation region tag already registered");
+    }
+
+    HIBERNATION_REGIONS.lock().push(HibernationRegion { tag, base: base as usize, len });
+    Ok(())
+}
+
+/// Stop preserving a memory region
+pub fn unregister_hibernation_region(tag: u32) {
+    HIBERNATION_REGIONS.lock().retain(|r| r.tag != tag);
+}
+
+/// Preserve state across hibernation through `save`, whose bytes are handed
+/// back to `restore` on resume. `restore` must not fail on bytes `validate`
+/// accepted. None may register or unregister hibernation state.
+pub fn register_hibernation_state(
+    tag: u32,
+    save: fn() -> Result<Vec<u8>, &'static str>,
+    validate: fn(&[u8]) -> Result<(), &'static str>,
+    restore: fn(&[u8]) -> Result<(), &'static str>,
+) -> Result<(), &'static str> {
+    if hibernation_tag_in_use(tag) {
+        return Err("Hibernation region tag already registered");
+    }
+
+    HIBERNATION_STATES.lock().push(HibernationState { tag, save, validate, restore });
+    Ok(())
+}
+
+/// Stop preserving registered state
+pub fn unregister_hibernation_state(tag: u32) {
+    HIBERNATION_STATES.lock().retain(|s| s.tag != tag);
+}
+
+/// Serialize all registered regions and state into a hibernation image
+///
+/// Layout: header (magic, record count, payload length, CRC32 of the payload)
+/// followed by one `tag: u32, len: u64, data` record per region or state.
+pub fn build_hibernation_image() -> Result<Vec<u8>, &'static str> {
+    let regions = HIBERNATION_REGIONS.lock().clone();
+    let states = HIBERNATION_STATES.lock().clone();
+
+    let mut payload = Vec::new();
+    let mut push_record = |tag: u32, data: &[u8]| {
+        payload.extend_from_slice(&tag.to_le_bytes());
+        payload.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        payload.extend_from_slice(data);
+    };
+    for region in regions.iter() {
+        // Safety: registration guarantees the region is valid
+        let data = unsafe { core::slice::from_raw_parts(region.base as *const u8, region.len) };
+        push_record(region.tag, data);
+    }
+    for state in states.iter() {
+        push_record(state.tag, &(state.save)()?);
+    }
+
+    let mut image = Vec::with_capacity(HIBERNATE_HEADER_SIZE + payload.len());
+    image.extend_from_slice(&HIBERNATE_MAGIC);
+    image.extend_from_slice(&((regions.len() + states.len()) as u32).to_le_bytes());
+    image.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    image.extend_from_slice(&crc32(&payload).to_le_bytes());
+    image.extend_from_slice(&payload);
+    Ok(image)
+}
+
+/// Validate a hibernation image, copy its contents back into the registered
+/// regions and hand saved state back to its owners
+///
+/// Returns the number of regions and states restored.
+pub fn restore_hibernation_image(image: &[u8]) -> Result<usize, &'static str> {
+    if image.len() < HIBERNATE_HEADER_SIZE || image[..8] != HIBERNATE_MAGIC {
+        return Err("Not a hibernation image");
+    }
+
+    // The header comes from disk, so every size in it is checked before use
+    let region_count = u32::from_le_bytes(image[8..12].try_into().unwrap()) as usize;
+    let payload_len = usize::try_from(u64::from_le_bytes(image[12..20].try_into().unwrap()))
+        .map_err(|_| "Truncated hibernation image")?;
+    let expected_crc = u32::from_le_bytes(image[20..24].try_into().unwrap());
+
+    let payload_end = HIBERNATE_HEADER_SIZE.checked_add(payload_len).ok_or("Truncated hibernation image")?;
+    let payload = image
+        .get(HIBERNATE_HEADER_SIZE..payload_end)
+        .ok_or("Truncated hibernation image")?;
+    if crc32(payload) != expected_crc {
+        return Err("Hibernation image checksum mismatch");
+    }
+
+    let regions = HIBERNATION_REGIONS.lock().clone();
+    let states = HIBERNATION_STATES.lock().clone();
+
+    // Every record needs at least its header
+    if region_count > payload.len() / HIBERNATE_RECORD_HEADER_SIZE {
+        return Err("Truncated hibernation record");
+    }
+
+    // Check every record, state payloads included, before touching memory
+    // so a bad image can't half-restore
+    let mut records = Vec::with_capacity(region_count);
+    let mut saved_states = Vec::new();
+    let mut offset: usize = 0;
+    for _ in 0..region_count {
+        let header_end = offset
+            .checked_add(HIBERNATE_RECORD_HEADER_SIZE)
+            .ok_or("Truncated hibernation record")?;
+        let header = payload.get(offset..header_end).ok_or("Truncated hibernation record")?;
+        let tag = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let len = usize::try_from(u64::from_le_bytes(header[4..12].try_into().unwrap()))
+            .map_err(|_| "Truncated hibernation record")?;
+        offset = header_end;
+
+        let data_end = offset.checked_add(len).ok_or("Truncated hibernation record")?;
+        let data = payload.get(offset..data_end).ok_or("Truncated hibernation record")?;
+        offset = data_end;
+
+        if let Some(state) = states.iter().find(|s| s.tag == tag) {
+            (state.validate)(data)?;
+            saved_states.push((*state, data));
+            continue;
+        }
+        let region = regions
+            .iter()
+            .find(|r| r.tag == tag)
+            .ok_or("Hibernation image references an unknown region")?;
+        if region.len != len {
+            return Err("Hibernation region size changed");
+        }
+
+        records.push((*region, data));
+    }
+
+    for (region, data) in records.iter() {
+        // Safety: registration guarantees the region is valid and the length matches
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), region.base as *mut u8, region.len);
+        }
+    }
+    for (state, data) in saved_states.iter() {
+        (state.restore)(data)?;
+    }
+
+    Ok(records.len() + saved_states.len())
+}
+
+/// Save the registered memory regions to disk and power off (S4)
+pub fn hibernate() -> Result<(), &'static str> {
+    let image = build_hibernation_image()?;
+
+    {
+        let mut fs_manager = super::filesystem::get_fs_manager().lock();
+        if fs_manager.open_file(HIBERNATE_IMAGE_PATH, true).is_err() {
+            fs_manager.create_file(HIBERNATE_IMAGE_PATH)?;
+        }
+
+        let mut file = fs_manager.open_file(HIBERNATE_IMAGE_PATH, false)?;
+        let mut written = 0;
+        while written < image.len() {
+            let count = file.write(&image[written..], &fs_manager)?;
+            if count == 0 {
+                return Err("Failed to write hibernation image");
+            }
+            written += count;
+        }
+        file.close(&fs_manager)?;
+    }
+
+    log::info!("Hibernation image written ({} bytes)", image.len());
+
+    let mut manager = POWER_MANAGER.lock();
+    manager.set_power_state(PowerState::S4)?;
+    manager.shutdown()
+}
+
+/// Look for a hibernation image and restore it
+///
+/// Returns `Ok(true)` if the system was resumed from an image. The image is
+/// deleted afterwards so a crash can't resume the same state twice.
+pub fn resume_from_hibernation() -> Result<bool, &'static str> {
+    let mut fs_manager = super::filesystem::get_fs_manager().lock();
+    let mut file = match fs_manager.open_file(HIBERNATE_IMAGE_PATH, true) {
+        Ok(file) => file,
+        Err(_) => return Ok(false),
+    };
+
+    let mut image = alloc::vec![0u8; file.get_size() as usize];
+    let mut read = 0;
+    while read < image.len() {
+        let count = file.read(&mut image[read..], &fs_manager, read as u64)?;
+        if count == 0 {
+            break;
+        }
+        read += count;
+    }
+    file.close(&fs_manager)?;
+    image.truncate(read);
+
+    let result = restore_hibernation_image(&image);
+    fs_manager.delete_entry(HIBERNATE_IMAGE_PATH)?;
+
+    let restored = result?;
+    log::info!("Resumed from hibernation ({} regions restored)", restored);
+    Ok(true)
+}
+
+/// CRC32 (IEEE 802.3, reflected) of a byte slice
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SAVED_STATE: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+    fn save_state() -> Result<Vec<u8>, &'static str> {
+        Ok(b"window layout".to_vec())
+    }
+
+    fn validate_state(bytes: &[u8]) -> Result<(), &'static str> {
+        if bytes.starts_with(b"window") { Ok(()) } else { Err("Bad window state") }
+    }
+
+    fn restore_state(bytes: &[u8]) -> Result<(), &'static str> {
+        *SAVED_STATE.lock() = bytes.to_vec();
+        Ok(())
+    }
+
+    /// Image with the given header fields and `payload`, its CRC filled in
+    fn image_with(record_count: u32, payload_len: u64, payload: &[u8]) -> Vec<u8> {
+        let mut image = HIBERNATE_MAGIC.to_vec();
+        image.extend_from_slice(&record_count.to_le_bytes());
+        image.extend_from_slice(&payload_len.to_le_bytes());
+        image.extend_from_slice(&crc32(payload).to_le_bytes());
+        image.extend_from_slice(payload);
+        image
+    }
+
+    #[test]
+    fn hostile_header_sizes_are_refused() {
+        assert_eq!(restore_hibernation_image(&image_with(0, u64::MAX, &[])), Err("Truncated hibernation image"));
+        assert_eq!(restore_hibernation_image(&image_with(u32::MAX, 0, &[])), Err("Truncated hibernation record"));
+
+        // A record claiming more data than the payload holds
+        let mut payload = u32::from_le_bytes(*b"NONE").to_le_bytes().to_vec();
+        payload.extend_from_slice(&u64::MAX.to_le_bytes());
+        let image = image_with(1, payload.len() as u64, &payload);
+        assert_eq!(restore_hibernation_image(&image), Err("Truncated hibernation record"));
+    }
+
+    // One test: the registries are global and every image covers all of them
+    #[test]
+    fn hibernation_image_restores_regions_and_state() {
+        let mut region = [0u8; 64];
+        for (i, byte) in region.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let region_tag = u32::from_le_bytes(*b"TEST");
+        let state_tag = u32::from_le_bytes(*b"TSTS");
+        unsafe { register_hibernation_region(region_tag, region.as_mut_ptr(), region.len()).unwrap() };
+        register_hibernation_state(state_tag, save_state, validate_state, restore_state).unwrap();
+        assert!(register_hibernation_state(region_tag, save_state, validate_state, restore_state).is_err());
+
+        let image = build_hibernation_image().unwrap();
+        region.fill(0xAA);
+        assert_eq!(restore_hibernation_image(&image), Ok(2));
+        assert!(region.iter().enumerate().all(|(i, &byte)| byte == i as u8));
+        assert_eq!(SAVED_STATE.lock().as_slice(), b"window layout");
+
+        // A corrupted image is refused without touching memory
+        let mut corrupt = image.clone();
+        *corrupt.last_mut().unwrap() ^= 0xFF;
+        region.fill(0xAA);
+        assert_eq!(restore_hibernation_image(&corrupt), Err("Hibernation image checksum mismatch"));
+        assert!(region.iter().all(|&byte| byte == 0xAA));
+
+        // State its owner rejects stops the restore before any region is written
+        let mut bad_state = Vec::new();
+        bad_state.extend_from_slice(&region_tag.to_le_bytes());
+        bad_state.extend_from_slice(&(region.len() as u64).to_le_bytes());
+        bad_state.extend_from_slice(&[0x55; 64]);
+        bad_state.extend_from_slice(&state_tag.to_le_bytes());
+        bad_state.extend_from_slice(&5u64.to_le_bytes());
+        bad_state.extend_from_slice(b"bogus");
+        let image_bad = image_with(2, bad_state.len() as u64, &bad_state);
+        SAVED_STATE.lock().clear();
+        assert_eq!(restore_hibernation_image(&image_bad), Err("Bad window state"));
+        assert!(region.iter().all(|&byte| byte == 0xAA));
+        assert!(SAVED_STATE.lock().is_empty());
+
+        unregister_hibernation_region(region_tag);
+        unregister_hibernation_state(state_tag);
+        assert_eq!(restore_hibernation_image(&image), Err("Hibernation image references an unknown region"));
+    }
+}
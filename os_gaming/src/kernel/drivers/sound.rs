@@ -1,15 +1,16 @@
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use core::arch::asm;
 use core::ops;
 use spin::Mutex;
 #[cfg(feature = "std")]
 use std::vec::Vec;
-use x86_64::instructions::port::Port;
+use crate::kernel::io::{PortRange, RoPort, RwPort, WoPort};
 use x86_64::structures::idt::InterruptStackFrame;
 #[macro_use]
 use lazy_static::lazy_static;
+use crate::kernel::sync::DebugMutex;
 use micromath::F32Ext;
 
 
@@ -27,17 +28,80 @@ pub enum SampleRate {
     Hz48000 = 48000,
 }
 
-// Constants for hardware ports
-const PC_SPEAKER_PORT: u16 = 0x61;
-const PIT_COMMAND_PORT: u16 = 0x43;
-const PIT_CHANNEL2_PORT: u16 = 0x42;
+impl SampleRate {
+    pub const ALL: [SampleRate; 7] = [
+        SampleRate::Hz8000,
+        SampleRate::Hz11025,
+        SampleRate::Hz16000,
+        SampleRate::Hz22050,
+        SampleRate::Hz32000,
+        SampleRate::Hz44100,
+        SampleRate::Hz48000,
+    ];
+
+    pub fn hz(self) -> u32 {
+        self as u32
+    }
+
+    /// The supported rate closest to `hz`; ties go to the higher rate
+    pub fn closest(hz: u32, supported: &[SampleRate]) -> Option<SampleRate> {
+        supported
+            .iter()
+            .copied()
+            .min_by_key(|rate| (rate.hz().abs_diff(hz), u32::MAX - rate.hz()))
+    }
+}
+
+// Hardware ports. These are shared with the timer and keyboard controller, so
+// they are not claimed through `PortRange`.
+const PC_SPEAKER_PORT: RwPort<u8> = RwPort::new(0x61);
+const PIT_COMMAND_PORT: WoPort<u8> = WoPort::new(0x43);
+const PIT_CHANNEL2_PORT: RwPort<u8> = RwPort::new(0x42);
 
 // Sound Blaster ports (will be detected dynamically)
 const SB16_DEFAULT_PORT: u16 = 0x220;
+const SB16_PORT_COUNT: u16 = 0x10;
 const SB16_DEFAULT_IRQ: u8 = 5;
 const SB16_DEFAULT_DMA: u8 = 1;
 const SB16_DEFAULT_DMA16: u8 = 5;
 
+/// Sound Blaster DSP and mixer registers relative to the card's base port
+#[derive(Debug, Clone, Copy)]
+struct Sb16Ports {
+    mixer_addr: WoPort<u8>,
+    mixer_data: RwPort<u8>,
+    reset: WoPort<u8>,
+    read_data: RoPort<u8>,
+    /// Command/data writes, and write-buffer status on read
+    write: RwPort<u8>,
+    /// Read-buffer status; reading it also acknowledges the 8-bit IRQ
+    read_status: RoPort<u8>,
+}
+
+impl Sb16Ports {
+    const fn at(base: u16) -> Self {
+        Self {
+            mixer_addr: WoPort::new(base + 0x4),
+            mixer_data: RwPort::new(base + 0x5),
+            reset: WoPort::new(base + 0x6),
+            read_data: RoPort::new(base + 0xA),
+            write: RwPort::new(base + 0xC),
+            read_status: RoPort::new(base + 0xE),
+        }
+    }
+
+    fn from_range(range: &PortRange) -> Self {
+        Self {
+            mixer_addr: range.wo(0x4),
+            mixer_data: range.rw(0x5),
+            reset: range.wo(0x6),
+            read_data: range.ro(0xA),
+            write: range.rw(0xC),
+            read_status: range.ro(0xE),
+        }
+    }
+}
+
 /// Sound hardware types
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SoundHardwareType {
@@ -48,6 +112,18 @@ pub enum SoundHardwareType {
     Virtualized,
 }
 
+/// What the sound hardware can do, for deciding which settings to offer
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundCapabilities {
+    pub hardware: SoundHardwareType,
+    /// Output rates for PCM playback; empty when only tones are possible
+    pub sample_rates: Vec<SampleRate>,
+    pub max_channels: u8,
+    pub pcm_playback: bool,
+    pub tone_generation: bool,
+    pub volume_control: bool,
+}
+
 /// Represents the sound hardware state
 pub struct SoundDriver {
     initialized: AtomicBool,
@@ -55,6 +131,9 @@ pub struct SoundDriver {
     samples_buffer: Vec<i16>,
     hardware_type: SoundHardwareType,
     sb_base_port: u16,
+    sb_ports: Sb16Ports,
+    /// Ownership of the detected card's I/O ports
+    sb_port_claim: Option<PortRange>,
     sb_irq: u8,
     sb_dma: u8,
     sb_dma16: u8,
@@ -103,6 +182,8 @@ impl SoundDriver {
             samples_buffer: Vec::new(),
             hardware_type: SoundHardwareType::None,
             sb_base_port: SB16_DEFAULT_PORT,
+            sb_ports: Sb16Ports::at(SB16_DEFAULT_PORT),
+            sb_port_claim: None,
             sb_irq: SB16_DEFAULT_IRQ,
             sb_dma: SB16_DEFAULT_DMA,
             sb_dma16: SB16_DEFAULT_DMA16,
@@ -153,8 +234,16 @@ impl SoundDriver {
         let possible_ports = [0x220, 0x240, 0x260, 0x280];
 
         for &port in &possible_ports {
-            if self.reset_dsp(port).is_ok() {
+            // Skip bases another driver already owns
+            let claim = match PortRange::claim("sb16", port, SB16_PORT_COUNT) {
+                Ok(claim) => claim,
+                Err(_) => continue,
+            };
+            let ports = Sb16Ports::from_range(&claim);
+            if self.reset_dsp(&ports).is_ok() {
                 self.sb_base_port = port;
+                self.sb_ports = ports;
+                self.sb_port_claim = Some(claim);
 
                 // Get DSP version
                 if let Ok(version) = self.get_dsp_version() {
@@ -182,28 +271,24 @@ impl SoundDriver {
     }
 
     /// Reset the Sound Blaster DSP
-    fn reset_dsp(&self, port: u16) -> Result<(), &'static str> {
-        let reset_port = port + 0x6;
-        let read_data_port = port + 0xA;
-        let read_status_port = port + 0xE;
-
+    fn reset_dsp(&self, ports: &Sb16Ports) -> Result<(), &'static str> {
         // Safety: Direct port I/O requires unsafe
         unsafe {
             // 1. Write 1 to the reset port
-            Port::new(reset_port).write(1u8);
+            ports.reset.write(1);
 
             // 2. Delay at least 3 microseconds
             self.delay(10);
 
             // 3. Write 0 to the reset port
-            Port::new(reset_port).write(0u8);
+            ports.reset.write(0);
 
             // 4. Wait for bit 7 of read-status to be set and read 0xAA from read-data
             let timeout = 100; // Arbitrary timeout value
             for _ in 0..timeout {
-                let status: u8 = Port::new(read_status_port).read();
+                let status = ports.read_status.read();
                 if (status & 0x80) != 0 {
-                    let value: u8 = Port::new(read_data_port).read();
+                    let value = ports.read_data.read();
                     if value == 0xAA {
                         return Ok(());
                     }
@@ -217,18 +302,15 @@ impl SoundDriver {
 
     /// Write a command to the DSP
     fn write_dsp(&self, command: u8) -> Result<(), &'static str> {
-        let write_port = self.sb_base_port + 0xC;
-        let write_status_port = self.sb_base_port + 0xC;
-
         // Safety: Direct port I/O requires unsafe
         unsafe {
             // Wait until the DSP is ready to receive a command
             let timeout = 100;
             for _ in 0..timeout {
-                let status: u8 = Port::new(write_status_port).read();
+                let status = self.sb_ports.write.read();
                 if (status & 0x80) == 0 {
                     // Write the command
-                    Port::new(write_port).write(command);
+                    self.sb_ports.write.write(command);
                     return Ok(());
                 }
                 self.delay(1);
@@ -304,18 +386,15 @@ impl SoundDriver {
 
     /// Read a byte from the DSP
     fn read_dsp(&self) -> Result<u8, &'static str> {
-        let read_data_port = self.sb_base_port + 0xA;
-        let read_status_port = self.sb_base_port + 0xE;
-
         // Safety: Direct port I/O requires unsafe
         unsafe {
             // Wait until data is available
             let timeout = 100;
             for _ in 0..timeout {
-                let status: u8 = Port::new(read_status_port).read();
+                let status = self.sb_ports.read_status.read();
                 if (status & 0x80) != 0 {
                     // Read the data
-                    return Ok(Port::new(read_data_port).read());
+                    return Ok(self.sb_ports.read_data.read());
                 }
                 self.delay(1);
             }
@@ -340,13 +419,12 @@ impl SoundDriver {
     fn detect_sb16_settings(&mut self) {
         // Read the mixer register to detect IRQ and DMA settings
         // This is a simplified implementation
-        let mixer_addr_port = self.sb_base_port + 0x4;
-        let mixer_data_port = self.sb_base_port + 0x5;
+        let ports = self.sb_ports;
 
         unsafe {
             // Read IRQ configuration (mixer register 0x80)
-            Port::new(mixer_addr_port).write(0x80u8);
-            let irq_config: u8 = Port::new(mixer_data_port).read();
+            ports.mixer_addr.write(0x80);
+            let irq_config = ports.mixer_data.read();
 
             self.sb_irq = match irq_config & 0x0F {
                 0x01 => 2,
@@ -357,8 +435,8 @@ impl SoundDriver {
             };
 
             // Read DMA configuration (mixer register 0x81)
-            Port::new(mixer_addr_port).write(0x81u8);
-            let dma_config: u8 = Port::new(mixer_data_port).read();
+            ports.mixer_addr.write(0x81);
+            let dma_config = ports.mixer_data.read();
 
             self.sb_dma = match dma_config & 0x0F {
                 0x01 => 0,
@@ -368,8 +446,8 @@ impl SoundDriver {
             };
 
             // Read 16-bit DMA configuration (mixer register 0x82)
-            Port::new(mixer_addr_port).write(0x82u8);
-            let dma16_config: u8 = Port::new(mixer_data_port).read();
+            ports.mixer_addr.write(0x82);
+            let dma16_config = ports.mixer_data.read();
 
             self.sb_dma16 = match dma16_config & 0x0F {
                 0x01 => 5,
@@ -382,20 +460,19 @@ impl SoundDriver {
 
     /// Set the SB16 master volume
     fn set_sb16_volume(&self, volume: u8) -> Result<(), &'static str> {
-        let mixer_addr_port = self.sb_base_port + 0x4;
-        let mixer_data_port = self.sb_base_port + 0x5;
+        let ports = self.sb_ports;
 
         // Map 0-100 to 0-255 for the SB16 volume
         let sb_volume = ((volume as u16) * 255 / 100) as u8;
 
         unsafe {
             // Set master volume left (register 0x22)
-            Port::new(mixer_addr_port).write(0x22u8);
-            Port::new(mixer_data_port).write(sb_volume);
+            ports.mixer_addr.write(0x22);
+            ports.mixer_data.write(sb_volume);
 
             // Set master volume right (register 0x23)
-            Port::new(mixer_addr_port).write(0x23u8);
-            Port::new(mixer_data_port).write(sb_volume);
+            ports.mixer_addr.write(0x23);
+            ports.mixer_data.write(sb_volume);
         }
 
         Ok(())
@@ -527,16 +604,16 @@ impl SoundDriver {
         // Safety: Direct port I/O requires unsafe
         unsafe {
             // Set up the PIT channel 2 in mode 3 (square wave generator)
-            Port::new(PIT_COMMAND_PORT).write(0xB6u8); // 10110110 in binary
+            PIT_COMMAND_PORT.write(0xB6); // 10110110 in binary
 
             // Set the divisor
-            Port::new(PIT_CHANNEL2_PORT).write((divisor & 0xFF) as u8); // Low byte
-            Port::new(PIT_CHANNEL2_PORT).write(((divisor >> 8) & 0xFF) as u8); // High byte
+            PIT_CHANNEL2_PORT.write((divisor & 0xFF) as u8); // Low byte
+            PIT_CHANNEL2_PORT.write(((divisor >> 8) & 0xFF) as u8); // High byte
 
             // Turn on the PC speaker
-            let mut speaker_state: u8 = Port::new(PC_SPEAKER_PORT).read();
+            let mut speaker_state = PC_SPEAKER_PORT.read();
             speaker_state |= 0x03; // Set bits 0 and 1 (gate and data)
-            Port::new(PC_SPEAKER_PORT).write(speaker_state);
+            PC_SPEAKER_PORT.write(speaker_state);
         }
 
         // Sleep for the specified duration, if available
@@ -563,9 +640,9 @@ impl SoundDriver {
     fn pc_speaker_off(&self) -> Result<(), &'static str> {
         // Safety: Direct port I/O requires unsafe
         unsafe {
-            let mut speaker_state: u8 = Port::new(PC_SPEAKER_PORT).read();
+            let mut speaker_state = PC_SPEAKER_PORT.read();
             speaker_state &= 0xFC; // Clear bits 0 and 1
-            Port::new(PC_SPEAKER_PORT).write(speaker_state);
+            PC_SPEAKER_PORT.write(speaker_state);
         }
         Ok(())
     }
@@ -920,6 +997,45 @@ impl SoundDriver {
         self.hardware_type
     }
 
+    /// Capabilities of the detected hardware; nothing is supported until initialized
+    pub fn capabilities(&self) -> SoundCapabilities {
+        let hardware = if self.initialized.load(Ordering::SeqCst) {
+            self.hardware_type
+        } else {
+            SoundHardwareType::None
+        };
+        let sample_rates = match hardware {
+            SoundHardwareType::None => Vec::new(),
+            _ => self.supported_rates().to_vec(),
+        };
+        SoundCapabilities {
+            hardware,
+            pcm_playback: !sample_rates.is_empty(),
+            sample_rates,
+            max_channels: match hardware {
+                SoundHardwareType::None => 0,
+                SoundHardwareType::PcSpeaker => 1,
+                _ => 2,
+            },
+            tone_generation: hardware != SoundHardwareType::None,
+            // The PC speaker is either on or off
+            volume_control: !matches!(hardware, SoundHardwareType::None | SoundHardwareType::PcSpeaker),
+        }
+    }
+
+    /// Output rates the detected hardware can run at
+    pub fn supported_rates(&self) -> &'static [SampleRate] {
+        match self.hardware_type {
+            // The SB16 DSP tops out at 44.1 kHz
+            SoundHardwareType::SoundBlaster16 => &SampleRate::ALL[..6],
+            // Rates every HDA codec is required to support
+            SoundHardwareType::HdAudio => &[SampleRate::Hz44100, SampleRate::Hz48000],
+            SoundHardwareType::Virtualized => &SampleRate::ALL,
+            // The PC speaker only produces tones
+            SoundHardwareType::PcSpeaker | SoundHardwareType::None => &[],
+        }
+    }
+
     /// Generate a test tone (sine wave) of the specified frequency and duration
     fn generate_test_tone(
     &self,
@@ -946,10 +1062,64 @@ impl SoundDriver {
 
 }
 
-pub fn init() -> Result<SoundDriver, &'static str> {
-    let mut sound_driver = SoundDriver::new();
-    sound_driver.initialize()?;
-    Ok(sound_driver)
+/// Convert `input` from one rate to another with linear interpolation
+pub fn resample(input: &[i16], from: SampleRate, to: SampleRate) -> Vec<i16> {
+    if from == to || input.is_empty() {
+        return input.to_vec();
+    }
+
+    let output_len = (input.len() as u64 * to.hz() as u64 / from.hz() as u64) as usize;
+    let mut output = Vec::with_capacity(output_len);
+    let step = from.hz() as f32 / to.hz() as f32;
+    for i in 0..output_len {
+        let source = i as f32 * step;
+        let index = source as usize;
+        let frac = source - index as f32;
+        let a = input[index.min(input.len() - 1)] as f32;
+        let b = input[(index + 1).min(input.len() - 1)] as f32;
+        output.push((a + (b - a) * frac) as i16);
+    }
+    output
+}
+
+/// Rate the mixer and hardware agreed on, 0 until `configure_pipeline` runs
+static PIPELINE_RATE_HZ: AtomicU32 = AtomicU32::new(0);
+
+/// Negotiate one rate for the whole output path: the supported hardware rate
+/// closest to `target_hz` becomes the mixer rate, so every voice is resampled
+/// exactly once on submission and never again downstream.
+pub fn configure_pipeline(target_hz: u32) -> Result<SampleRate, &'static str> {
+    let driver = SOUND_DRIVER.lock();
+    let rate = SampleRate::closest(target_hz, driver.supported_rates())
+        .ok_or("Sound hardware has no PCM output")?;
+    drop(driver);
+
+    AUDIO_BUFFERS.lock().sample_rate = rate;
+    PIPELINE_RATE_HZ.store(rate.hz(), Ordering::Release);
+
+    if rate.hz() != target_hz {
+        log::info!("Audio pipeline runs at {} Hz ({} Hz requested)", rate.hz(), target_hz);
+    }
+    Ok(rate)
+}
+
+/// Negotiated pipeline rate, if `configure_pipeline` has run
+pub fn pipeline_rate() -> Option<SampleRate> {
+    let hz = PIPELINE_RATE_HZ.load(Ordering::Acquire);
+    SampleRate::ALL.iter().copied().find(|rate| rate.hz() == hz)
+}
+
+/// Play a voice recorded at `rate`, converting it to the pipeline rate once
+pub fn submit_voice(samples: &[i16], rate: SampleRate) -> Result<(), &'static str> {
+    let pipeline = pipeline_rate().ok_or("Audio pipeline not configured")?;
+    let converted = resample(samples, rate, pipeline);
+    SOUND_DRIVER.lock().queue_audio(&converted, pipeline, None)
+}
+
+/// Bring up the sound hardware in the driver shared by the pipeline, voice
+/// submission and the interrupt handler. Calling it again does nothing.
+pub fn init() -> Result<(), &'static str> {
+    SOUND_DRIVER.lock().initialize()
 }
 
 
@@ -971,7 +1141,7 @@ enum AudioBufferActive {
 }
 
 lazy_static! {
-    static ref SOUND_DRIVER: Mutex<SoundDriver> = Mutex::new(SoundDriver::new());
+    static ref SOUND_DRIVER: DebugMutex<SoundDriver> = DebugMutex::new("sound driver", SoundDriver::new());
     static ref AUDIO_BUFFERS: Mutex<AudioBuffers> = Mutex::new(AudioBuffers::new());
 }
 
@@ -1084,9 +1254,8 @@ pub fn handle_interrupt() {
 fn handle_sb16_interrupt(driver: &mut SoundDriver) {
     // 1. Acknowledge the interrupt
     // Read SB16 interrupt status register to clear the interrupt
-    let status_port = driver.sb_base_port + 0xE;
     unsafe {
-        let _status: u8 = Port::new(status_port).read();
+        let _status = driver.sb_ports.read_status.read();
     }
 
     // 2. Get audio buffers
@@ -1189,6 +1358,11 @@ pub fn beep(
     driver.beep(frequency, duration_ms)
 }
 
+/// Capabilities of the sound hardware
+pub fn capabilities() -> SoundCapabilities {
+    SOUND_DRIVER.lock().capabilities()
+}
+
 pub fn get_volume() -> u8 {
     let driver = SOUND_DRIVER.lock();
     driver.get_volume()
@@ -1216,3 +1390,27 @@ pub fn shutdown() {
     driver.stop_playback().unwrap_or_default();
     driver.initialized.store(false, Ordering::SeqCst);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_rate_falls_back_to_closest_supported() {
+        // An SB16 stops at 44.1 kHz
+        let sb16 = &SampleRate::ALL[..6];
+        assert_eq!(SampleRate::closest(48_000, sb16), Some(SampleRate::Hz44100));
+        assert_eq!(SampleRate::closest(48_000, &SampleRate::ALL), Some(SampleRate::Hz48000));
+        assert_eq!(SampleRate::closest(48_000, &[]), None);
+    }
+
+    #[test]
+    fn voice_is_resampled_once_to_the_pipeline_rate() {
+        let voice = vec![1000i16; 48_000];
+        let converted = resample(&voice, SampleRate::Hz48000, SampleRate::Hz44100);
+        assert_eq!(converted.len(), 44_100);
+        assert!(converted.iter().all(|&sample| sample == 1000));
+        // Already at the pipeline rate: passed through untouched
+        assert_eq!(resample(&converted, SampleRate::Hz44100, SampleRate::Hz44100), converted);
+    }
+}
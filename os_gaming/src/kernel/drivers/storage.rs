@@ -1,9 +1,131 @@
 extern crate alloc;
 use alloc::vec::Vec;
+use alloc::collections::BTreeMap;
 use alloc::vec;
 use alloc::string::String;
 use crate::alloc::string::ToString;
 use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use super::io_scheduler::{self, Completion, CompletionFn, IoOperation, RequestId, RequestQueue};
+use super::timer;
+
+/// Failure reported by the device for a single command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError {
+    /// The device didn't complete the command in time
+    Timeout,
+    /// Data was corrupted on the bus (ATA ICRC, NVMe data transfer error)
+    Crc,
+    /// The link or controller was reset while the command was in flight
+    BusReset,
+    /// The medium itself is bad (unrecoverable read, write fault)
+    MediaError,
+    /// The device refused the write
+    WriteProtected,
+    /// The device rejected the command as invalid
+    InvalidCommand,
+}
+
+impl CommandError {
+    /// Whether reissuing the same command may succeed
+    pub fn is_retryable(self) -> bool {
+        matches!(self, CommandError::Timeout | CommandError::Crc | CommandError::BusReset)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommandError::Timeout => "Storage command timed out",
+            CommandError::Crc => "Storage transfer CRC error",
+            CommandError::BusReset => "Storage bus reset during command",
+            CommandError::MediaError => "Storage media error",
+            CommandError::WriteProtected => "Storage device is write protected",
+            CommandError::InvalidCommand => "Storage command rejected by device",
+        }
+    }
+}
+
+/// How transient command failures are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total tries including the first; at least 1
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for each further one
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl RetryPolicy {
+    pub const DEFAULT: RetryPolicy = RetryPolicy {
+        max_attempts: 4,
+        base_delay_ms: 10,
+        max_delay_ms: 500,
+    };
+
+    /// Delay before retry number `retry` (1-based)
+    pub fn delay_ms(&self, retry: u32) -> u64 {
+        let factor = 1u64 << (retry.saturating_sub(1)).min(16);
+        self.base_delay_ms.saturating_mul(factor).min(self.max_delay_ms)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static RETRY_POLICY: Mutex<RetryPolicy> = Mutex::new(RetryPolicy::DEFAULT);
+
+pub fn retry_policy() -> RetryPolicy {
+    *RETRY_POLICY.lock()
+}
+
+pub fn set_retry_policy(policy: RetryPolicy) {
+    *RETRY_POLICY.lock() = RetryPolicy {
+        max_attempts: policy.max_attempts.max(1),
+        ..policy
+    };
+}
+
+/// Run `command` until it succeeds, fails with a non-retryable error, or
+/// `policy.max_attempts` tries are used up, sleeping with exponential
+/// backoff between tries. `device` and `operation` are only used for logging.
+pub fn with_retry<T>(
+    policy: &RetryPolicy,
+    device: &str,
+    operation: &str,
+    mut command: impl FnMut() -> Result<T, CommandError>,
+) -> Result<T, &'static str> {
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match command() {
+            Ok(value) => {
+                if attempt > 1 {
+                    log::info!("{}: {} succeeded after {} retries", device, operation, attempt - 1);
+                }
+                return Ok(value);
+            }
+            Err(e) if !e.is_retryable() => {
+                log::error!("{}: {} failed: {}", device, operation, e.as_str());
+                return Err(e.as_str());
+            }
+            Err(e) if attempt >= max_attempts => {
+                log::error!("{}: {} failed after {} attempts, last error: {}", device, operation, attempt, e.as_str());
+                return Err(e.as_str());
+            }
+            Err(e) => {
+                let delay = policy.delay_ms(attempt);
+                log::warn!(
+                    "{}: {} failed ({}), retry {}/{} in {} ms",
+                    device, operation, e.as_str(), attempt, max_attempts - 1, delay
+                );
+                timer::sleep(delay);
+                attempt += 1;
+            }
+        }
+    }
+}
 
 /// Types of storage devices
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -16,6 +138,38 @@ pub enum StorageDeviceType {
     VirtIO,
 }
 
+/// Command that flushes a device's volatile write cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushCommand {
+    /// ATA/SATA FLUSH CACHE EXT
+    AtaFlushCacheExt,
+    /// NVMe Flush for the namespace
+    NvmeFlush,
+    /// SCSI SYNCHRONIZE CACHE (10), also used by USB mass storage
+    ScsiSynchronizeCache,
+}
+
+impl FlushCommand {
+    /// Flush command understood by `device_type`, if it has one
+    pub fn for_device(device_type: StorageDeviceType) -> Option<Self> {
+        match device_type {
+            StorageDeviceType::Ata => Some(FlushCommand::AtaFlushCacheExt),
+            StorageDeviceType::Nvme => Some(FlushCommand::NvmeFlush),
+            StorageDeviceType::Usb | StorageDeviceType::Scsi => Some(FlushCommand::ScsiSynchronizeCache),
+            StorageDeviceType::VirtIO | StorageDeviceType::Unknown => None,
+        }
+    }
+
+    /// Opcode placed in the command register, submission queue entry or CDB
+    pub fn opcode(self) -> u8 {
+        match self {
+            FlushCommand::AtaFlushCacheExt => 0xEA,
+            FlushCommand::NvmeFlush => 0x00,
+            FlushCommand::ScsiSynchronizeCache => 0x35,
+        }
+    }
+}
+
 /// Represents a storage device in the system
 pub struct StorageDevice {
     name: String,
@@ -24,6 +178,8 @@ pub struct StorageDevice {
     sector_count: u64,
     initialized: AtomicBool,
     read_only: bool,
+    /// Requests waiting for dispatch by the I/O scheduler
+    queue: Mutex<RequestQueue>,
 }
 
 pub struct Partition {
@@ -50,6 +206,7 @@ impl StorageDevice {
             sector_count,
             initialized: AtomicBool::new(false),
             read_only,
+            queue: Mutex::new(RequestQueue::new()),
         }
     }
     
@@ -67,7 +224,7 @@ impl StorageDevice {
         Ok(())
     }
     
-    /// Read sectors from the device
+    /// Read sectors from the device, retrying transient failures
     pub fn read_sectors(&self, start_sector: u64, count: u32, buffer: &mut [u8]) -> Result<(), &'static str> {
         if !self.initialized.load(Ordering::SeqCst) {
             return Err("Storage device not initialized");
@@ -81,6 +238,11 @@ impl StorageDevice {
             return Err("Buffer too small for requested sectors");
         }
         
+        with_retry(&retry_policy(), &self.name, "read", || self.issue_read(start_sector, count, buffer))
+    }
+
+    /// Issue a single read command
+    fn issue_read(&self, start_sector: u64, count: u32, buffer: &mut [u8]) -> Result<(), CommandError> {
         // Device-specific read operation would go here
         // For now, we just fill the buffer with a pattern for demonstration
         #[cfg(feature = "std")]
@@ -93,7 +255,7 @@ impl StorageDevice {
         Ok(())
     }
     
-    /// Write sectors to the device
+    /// Write sectors to the device, retrying transient failures
     pub fn write_sectors(&self, start_sector: u64, count: u32, buffer: &[u8]) -> Result<(), &'static str> {
         if !self.initialized.load(Ordering::SeqCst) {
             return Err("Storage device not initialized");
@@ -111,11 +273,160 @@ impl StorageDevice {
             return Err("Buffer too small for requested sectors");
         }
         
+        with_retry(&retry_policy(), &self.name, "write", || self.issue_write(start_sector, count, buffer))
+    }
+
+    /// Issue a single write command
+    fn issue_write(&self, _start_sector: u64, _count: u32, _buffer: &[u8]) -> Result<(), CommandError> {
         // Device-specific write operation would go here
         
         Ok(())
     }
     
+    /// Write sectors with a single command: no retries, queue or allocation.
+    /// For the panic path, where sleeping and the heap can't be trusted.
+    pub fn write_sectors_unbuffered(&self, start_sector: u64, count: u32, buffer: &[u8]) -> Result<(), &'static str> {
+        self.validate_request(IoOperation::Write, start_sector, count)?;
+        if buffer.len() < (count as usize * self.sector_size as usize) {
+            return Err("Buffer too small for requested sectors");
+        }
+        self.issue_write(start_sector, count, buffer).map_err(CommandError::as_str)
+    }
+    
+    /// Flush the device's volatile write cache so completed writes are durable
+    pub fn flush_cache(&self) -> Result<(), &'static str> {
+        if !self.initialized.load(Ordering::SeqCst) {
+            return Err("Storage device not initialized");
+        }
+
+        if self.read_only {
+            return Ok(());
+        }
+
+        let command = FlushCommand::for_device(self.device_type)
+            .ok_or("Cache flush not supported for this device type")?;
+        with_retry(&retry_policy(), &self.name, "flush", || self.issue_flush(command))
+    }
+
+    /// Flush with a single command and no retries, for the panic path
+    pub fn flush_cache_unbuffered(&self) -> Result<(), &'static str> {
+        if !self.initialized.load(Ordering::SeqCst) {
+            return Err("Storage device not initialized");
+        }
+
+        if self.read_only {
+            return Ok(());
+        }
+
+        let command = FlushCommand::for_device(self.device_type)
+            .ok_or("Cache flush not supported for this device type")?;
+        self.issue_flush(command).map_err(CommandError::as_str)
+    }
+
+    /// Issue a single cache flush command
+    fn issue_flush(&self, _command: FlushCommand) -> Result<(), CommandError> {
+        // Device-specific flush submission would go here
+
+        Ok(())
+    }
+    
+    /// Check a request against the device before it is queued
+    fn validate_request(&self, operation: IoOperation, start_sector: u64, count: u32) -> Result<(), &'static str> {
+        if !self.initialized.load(Ordering::SeqCst) {
+            return Err("Storage device not initialized");
+        }
+        if operation == IoOperation::Write && self.read_only {
+            return Err("Cannot write to read-only device");
+        }
+        if start_sector + count as u64 > self.sector_count {
+            return Err("Request exceeds device bounds");
+        }
+        Ok(())
+    }
+
+    fn submit(
+        &self,
+        operation: IoOperation,
+        start_sector: u64,
+        count: u32,
+        data: Vec<u8>,
+        completion: Completion,
+    ) -> Result<RequestId, &'static str> {
+        self.validate_request(operation, start_sector, count)?;
+        Ok(self.queue.lock().submit(operation, start_sector, count, data, completion, timer::uptime_ms()))
+    }
+
+    /// Queue a read; `on_complete` gets the sectors when it is dispatched
+    pub fn submit_read(&self, start_sector: u64, count: u32, on_complete: CompletionFn) -> Result<RequestId, &'static str> {
+        let data = vec![0u8; count as usize * self.sector_size as usize];
+        self.submit(IoOperation::Read, start_sector, count, data, Completion::Callback(on_complete))
+    }
+
+    /// Queue a write of whole sectors from `data`
+    pub fn submit_write(&self, start_sector: u64, data: Vec<u8>, on_complete: CompletionFn) -> Result<RequestId, &'static str> {
+        let count = (data.len() / self.sector_size.max(1) as usize) as u32;
+        self.submit(IoOperation::Write, start_sector, count, data, Completion::Callback(on_complete))
+    }
+
+    /// Read through the queue, blocking until the request is dispatched
+    pub fn read_queued(&self, start_sector: u64, count: u32, buffer: &mut [u8]) -> Result<(), &'static str> {
+        let size = count as usize * self.sector_size as usize;
+        if buffer.len() < size {
+            return Err("Buffer too small for requested sectors");
+        }
+        let id = self.submit(IoOperation::Read, start_sector, count, vec![0u8; size], Completion::Wait)?;
+        let completion = self.wait_for(id);
+        buffer[..size].copy_from_slice(&completion.data[..size]);
+        completion.result
+    }
+
+    /// Write through the queue, blocking until the request is dispatched
+    pub fn write_queued(&self, start_sector: u64, count: u32, buffer: &[u8]) -> Result<(), &'static str> {
+        let size = count as usize * self.sector_size as usize;
+        if buffer.len() < size {
+            return Err("Buffer too small for requested sectors");
+        }
+        let id = self.submit(IoOperation::Write, start_sector, count, buffer[..size].to_vec(), Completion::Wait)?;
+        self.wait_for(id).result
+    }
+
+    /// Dispatch queued requests until `id` has completed
+    fn wait_for(&self, id: RequestId) -> io_scheduler::IoCompletion {
+        loop {
+            if let Some(completion) = self.queue.lock().take_finished(id) {
+                return completion;
+            }
+            self.run_queue(1);
+        }
+    }
+
+    /// Dispatch up to `max` queued requests in scheduler order. Returns how many ran.
+    pub fn run_queue(&self, max: usize) -> usize {
+        let mut dispatched = 0;
+        while dispatched < max {
+            // The queue is unlocked during I/O so completions can queue more
+            let next = self.queue.lock().next(io_scheduler::scheduler(), timer::uptime_ms());
+            let mut request = match next {
+                Some(request) => request,
+                None => break,
+            };
+            let result = match request.operation {
+                IoOperation::Read => self.read_sectors(request.lba, request.count, &mut request.data),
+                IoOperation::Write => self.write_sectors(request.lba, request.count, &request.data),
+            };
+            if let Some(completion) = request.complete(result) {
+                self.queue.lock().finish(completion);
+            }
+            dispatched += 1;
+        }
+        dispatched
+    }
+
+    /// Requests waiting for dispatch
+    pub fn queued_requests(&self) -> usize {
+        self.queue.lock().len()
+    }
+
     /// Get device name
     pub fn get_name(&self) -> &str {
         &self.name
@@ -142,6 +453,70 @@ impl StorageDevice {
     }
 }
 
+/// Sector writes and a cache flush: what a write-back cache needs from a device
+pub trait BlockDevice {
+    fn sector_size(&self) -> u32;
+    fn write_sectors(&self, start_sector: u64, count: u32, buffer: &[u8]) -> Result<(), &'static str>;
+    fn flush(&self) -> Result<(), &'static str>;
+}
+
+impl BlockDevice for StorageDevice {
+    fn sector_size(&self) -> u32 {
+        self.sector_size
+    }
+
+    fn write_sectors(&self, start_sector: u64, count: u32, buffer: &[u8]) -> Result<(), &'static str> {
+        StorageDevice::write_sectors(self, start_sector, count, buffer)
+    }
+
+    fn flush(&self) -> Result<(), &'static str> {
+        self.flush_cache()
+    }
+}
+
+/// Write-back cache of whole sectors. Writes stay in memory until `sync`.
+pub struct BlockCache<D: BlockDevice> {
+    device: D,
+    dirty: BTreeMap<u64, Vec<u8>>,
+}
+
+impl<D: BlockDevice> BlockCache<D> {
+    pub fn new(device: D) -> Self {
+        Self { device, dirty: BTreeMap::new() }
+    }
+
+    /// Cache whole sectors from `data` starting at `start_sector`
+    pub fn write(&mut self, start_sector: u64, data: &[u8]) -> Result<(), &'static str> {
+        let sector_size = self.device.sector_size() as usize;
+        if sector_size == 0 || data.len() % sector_size != 0 {
+            return Err("Cached writes must be whole sectors");
+        }
+        for (i, sector) in data.chunks(sector_size).enumerate() {
+            self.dirty.insert(start_sector + i as u64, sector.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Sectors written but not yet on the device
+    pub fn dirty_sectors(&self) -> usize {
+        self.dirty.len()
+    }
+
+    /// Write dirty sectors back in order, then flush the device's own cache.
+    /// Sectors that failed to write stay dirty.
+    pub fn sync(&mut self) -> Result<(), &'static str> {
+        while let Some((&sector, data)) = self.dirty.iter().next() {
+            self.device.write_sectors(sector, 1, data)?;
+            self.dirty.remove(&sector);
+        }
+        self.device.flush()
+    }
+
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+}
+
 impl Partition {
     /// Create a new partition
     pub fn new(device_name: String, start_sector: u64, sector_count: u64, partition_type: u8, bootable: bool) -> Self {
@@ -180,7 +555,7 @@ impl StorageManager {
         }
     }
 
-    pub fn scan_partitions(&mut self, device_name: &str) -> Result<Vec<Partition>, &'static str> {
+    pub fn scan_partitions(&self, device_name: &str) -> Result<Vec<Partition>, &'static str> {
         let device = self.get_device(device_name)
             .ok_or("Device not found")?;
         
@@ -302,6 +677,11 @@ impl StorageManager {
         self.default_device.map(|idx| &self.devices[idx])
     }
     
+    /// Dispatch up to `max_per_device` queued requests on every device
+    pub fn run_queues(&self, max_per_device: usize) -> usize {
+        self.devices.iter().map(|device| device.run_queue(max_per_device)).sum()
+    }
+
     /// Set the default device by name
     pub fn set_default_device(&mut self, name: &str) -> Result<(), &'static str> {
         let idx = self.devices.iter().position(|dev| dev.get_name() == name)
@@ -347,4 +727,204 @@ pub fn init() -> Result<StorageManager, &'static str> {
     }
     
     Ok(manager)
-}
\ No newline at end of file
+}
+/// Upper bound on the region a benchmark reads (64 MiB)
+const BENCH_SCRATCH_BYTES: u64 = 64 * 1024 * 1024;
+/// Request size for the sequential pass
+const BENCH_SEQUENTIAL_CHUNK_BYTES: u64 = 1024 * 1024;
+/// Request size and count for the random pass
+const BENCH_RANDOM_BLOCK_BYTES: u64 = 4096;
+const BENCH_RANDOM_OPS: u32 = 1024;
+
+/// Access patterns measured by `benchmark`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessPattern {
+    SequentialRead,
+    RandomRead,
+}
+
+/// Timing of one access pattern
+#[derive(Debug, Clone, Copy)]
+pub struct BenchResult {
+    pub pattern: AccessPattern,
+    pub bytes: u64,
+    pub operations: u32,
+    pub elapsed_ns: u64,
+}
+
+impl BenchResult {
+    /// Throughput in MB/s (10^6 bytes)
+    pub fn mb_per_sec(&self) -> f64 {
+        if self.elapsed_ns == 0 {
+            return 0.0;
+        }
+        self.bytes as f64 * 1000.0 / self.elapsed_ns as f64
+    }
+
+    pub fn iops(&self) -> f64 {
+        if self.elapsed_ns == 0 {
+            return 0.0;
+        }
+        self.operations as f64 * 1_000_000_000.0 / self.elapsed_ns as f64
+    }
+}
+
+/// Results of `benchmark` for one device
+#[derive(Debug, Clone)]
+pub struct StorageBenchReport {
+    pub device: String,
+    /// Region read, in sectors
+    pub scratch_start: u64,
+    pub scratch_sectors: u64,
+    pub results: Vec<BenchResult>,
+}
+
+/// Measure read throughput and IOPS of `device` over the start of the disk.
+///
+/// Only reads are issued, so live data is never touched. Takes a few seconds
+/// on slow media; not run during a normal boot.
+pub fn benchmark(device: &StorageDevice) -> Result<StorageBenchReport, &'static str> {
+    let sectors = (BENCH_SCRATCH_BYTES / device.sector_size.max(1) as u64).min(device.sector_count);
+    benchmark_region(device, 0, sectors, timer::timestamp_ns)
+}
+
+/// Benchmark reads confined to `sector_count` sectors from `start_sector`,
+/// timed with `clock_ns`
+pub fn benchmark_region(
+    device: &StorageDevice,
+    start_sector: u64,
+    sector_count: u64,
+    clock_ns: fn() -> u64,
+) -> Result<StorageBenchReport, &'static str> {
+    let sector_size = device.sector_size as u64;
+    if sector_size == 0 || sector_count == 0 {
+        return Err("Empty benchmark region");
+    }
+    if start_sector + sector_count > device.sector_count {
+        return Err("Benchmark region exceeds device bounds");
+    }
+
+    let chunk = (BENCH_SEQUENTIAL_CHUNK_BYTES / sector_size).clamp(1, sector_count) as u32;
+    let block = (BENCH_RANDOM_BLOCK_BYTES / sector_size).clamp(1, sector_count) as u32;
+    let mut buffer = vec![0u8; chunk.max(block) as usize * sector_size as usize];
+
+    // Sequential: the whole region front to back
+    let started = clock_ns();
+    let mut sector = start_sector;
+    let mut operations = 0;
+    while sector < start_sector + sector_count {
+        let count = chunk.min((start_sector + sector_count - sector) as u32);
+        device.read_sectors(sector, count, &mut buffer)?;
+        sector += count as u64;
+        operations += 1;
+    }
+    let sequential = BenchResult {
+        pattern: AccessPattern::SequentialRead,
+        bytes: sector_count * sector_size,
+        operations,
+        elapsed_ns: clock_ns().saturating_sub(started),
+    };
+
+    // Random: block-aligned reads at pseudo-random offsets, same sequence every run
+    let slots = sector_count / block as u64;
+    let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+    let started = clock_ns();
+    for _ in 0..BENCH_RANDOM_OPS {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        device.read_sectors(start_sector + (seed % slots) * block as u64, block, &mut buffer)?;
+    }
+    let random = BenchResult {
+        pattern: AccessPattern::RandomRead,
+        bytes: BENCH_RANDOM_OPS as u64 * block as u64 * sector_size,
+        operations: BENCH_RANDOM_OPS,
+        elapsed_ns: clock_ns().saturating_sub(started),
+    };
+
+    for result in [&sequential, &random] {
+        log::info!(
+            "{}: {:?} {:.1} MB/s, {:.0} IOPS",
+            device.name,
+            result.pattern,
+            result.mb_per_sec(),
+            result.iops()
+        );
+    }
+
+    Ok(StorageBenchReport {
+        device: device.name.clone(),
+        scratch_start: start_sector,
+        scratch_sectors: sector_count,
+        results: vec![sequential, random],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flush_uses_the_command_set_of_each_device_type() {
+        assert_eq!(FlushCommand::for_device(StorageDeviceType::Ata).map(FlushCommand::opcode), Some(0xEA));
+        assert_eq!(FlushCommand::for_device(StorageDeviceType::Nvme).map(FlushCommand::opcode), Some(0x00));
+        assert_eq!(FlushCommand::for_device(StorageDeviceType::Scsi).map(FlushCommand::opcode), Some(0x35));
+        assert_eq!(FlushCommand::for_device(StorageDeviceType::Usb).map(FlushCommand::opcode), Some(0x35));
+    }
+
+    #[test]
+    fn flush_fails_on_device_types_without_a_flush_command() {
+        for device_type in [StorageDeviceType::VirtIO, StorageDeviceType::Unknown] {
+            let device = StorageDevice::new("test".to_string(), device_type, 512, 1024, false);
+            device.initialize().unwrap();
+            assert!(device.flush_cache().is_err());
+            assert!(device.flush_cache_unbuffered().is_err());
+        }
+    }
+
+    /// Records what reaches the device, in order
+    #[derive(Default)]
+    struct MockDevice {
+        writes: Mutex<Vec<(u64, Vec<u8>)>>,
+        flushes: Mutex<usize>,
+    }
+
+    impl BlockDevice for MockDevice {
+        fn sector_size(&self) -> u32 {
+            4
+        }
+
+        fn write_sectors(&self, start_sector: u64, count: u32, buffer: &[u8]) -> Result<(), &'static str> {
+            assert_eq!(count, 1);
+            self.writes.lock().push((start_sector, buffer.to_vec()));
+            Ok(())
+        }
+
+        fn flush(&self) -> Result<(), &'static str> {
+            *self.flushes.lock() += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sync_writes_dirty_blocks_back_then_flushes() {
+        let mut cache = BlockCache::new(MockDevice::default());
+        cache.write(7, &[1, 1, 1, 1, 2, 2, 2, 2]).unwrap();
+        cache.write(3, &[3, 3, 3, 3]).unwrap();
+        cache.write(8, &[4, 4, 4, 4]).unwrap();
+        assert!(cache.write(0, &[0; 3]).is_err());
+
+        // Write-back: nothing reaches the device before sync
+        assert!(cache.device().writes.lock().is_empty());
+        assert_eq!(*cache.device().flushes.lock(), 0);
+        assert_eq!(cache.dirty_sectors(), 3);
+
+        cache.sync().unwrap();
+        assert_eq!(
+            *cache.device().writes.lock(),
+            vec![(3, vec![3; 4]), (7, vec![1; 4]), (8, vec![4; 4])]
+        );
+        assert_eq!(*cache.device().flushes.lock(), 1);
+        assert_eq!(cache.dirty_sectors(), 0);
+    }
+}
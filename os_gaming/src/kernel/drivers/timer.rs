@@ -429,6 +429,15 @@ pub fn timestamp_ns() -> u64 {
     manager.timestamp_ns()
 }
 
+/// Microseconds since boot without taking any lock, so it is safe from
+/// interrupt context. Uses the TSC once the CPU clock is known, else the tick count.
+pub fn timestamp_us_lockless() -> u64 {
+    match CPU_MHZ.load(Ordering::Relaxed) {
+        0 => TICKS.load(Ordering::Relaxed) * 1_000_000 / DEFAULT_TICK_RATE as u64,
+        mhz => (unsafe { core::arch::x86_64::_rdtsc() }) / mhz,
+    }
+}
+
 /// Get the CPU frequency in MHz
 pub fn get_cpu_mhz() -> u64 {
     CPU_MHZ.load(Ordering::SeqCst)
@@ -0,0 +1,251 @@
+//! HID digitizers: touch screens and other absolute pointers
+//!
+//! Reports are decoded with the generic HID parser. Each finger collection of
+//! a report carries one contact (tip switch, contact ID, X and Y); devices in
+//! hybrid mode spread a frame over several reports and give the total in the
+//! first report's contact count. Positions are scaled from the device's
+//! logical range to the screen, and contacts are tracked across reports so
+//! every finger produces a down, moves and an up.
+
+extern crate alloc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::usb::hid::{self, HidState, ReportLayout};
+
+/// Most contacts tracked at once per device; more are ignored
+pub const MAX_CONTACTS: usize = 10;
+
+/// A contact position in screen pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchPoint {
+    /// Contact ID as reported by the device, stable while the finger is down
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchPhase {
+    Down,
+    Move,
+    Up,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchEvent {
+    pub phase: TouchPhase,
+    pub point: TouchPoint,
+    /// First finger down while no other was; drives the emulated mouse
+    pub primary: bool,
+}
+
+/// One contact pulled out of a report
+#[derive(Debug, Clone, Copy)]
+struct Contact {
+    id: u32,
+    touching: bool,
+    x: f32,
+    y: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tracked {
+    point: TouchPoint,
+    primary: bool,
+    /// Seen in the frame being assembled
+    seen: bool,
+}
+
+pub struct Digitizer {
+    layout: ReportLayout,
+    contacts: [Option<Tracked>; MAX_CONTACTS],
+    /// Contacts still expected before the current hybrid-mode frame is complete
+    pending: usize,
+}
+
+impl Digitizer {
+    pub fn new(descriptor: &[u8]) -> Result<Self, &'static str> {
+        let layout = hid::parse_report_descriptor(descriptor)?;
+        if !is_digitizer(&layout) {
+            return Err("Not a touch digitizer");
+        }
+        Ok(Self {
+            layout,
+            contacts: [None; MAX_CONTACTS],
+            pending: 0,
+        })
+    }
+
+    /// Decode one input report into touch events for a `width` x `height` screen
+    pub fn handle_report(&mut self, data: &[u8], width: u32, height: u32) -> Vec<TouchEvent> {
+        let state = hid::decode_report(&self.layout, data);
+        let contacts = read_contacts(&state, width, height);
+
+        // A non-zero contact count starts a new frame
+        let count = state
+            .axis(hid::USAGE_PAGE_DIGITIZER, hid::USAGE_CONTACT_COUNT)
+            .map(|axis| axis.value.max(0) as usize);
+        if self.pending == 0 || count.map_or(false, |count| count > 0) {
+            for tracked in self.contacts.iter_mut().flatten() {
+                tracked.seen = false;
+            }
+            self.pending = count.unwrap_or(contacts.len()).max(contacts.len());
+        }
+
+        let mut events = Vec::new();
+        for contact in &contacts {
+            self.pending = self.pending.saturating_sub(1);
+            self.update_contact(contact, &mut events);
+        }
+
+        // Fingers missing from a complete frame were lifted without saying so
+        if self.pending == 0 {
+            for slot in self.contacts.iter_mut() {
+                if let Some(tracked) = slot.filter(|tracked| !tracked.seen) {
+                    events.push(TouchEvent { phase: TouchPhase::Up, point: tracked.point, primary: tracked.primary });
+                    *slot = None;
+                }
+            }
+        }
+        events
+    }
+
+    fn update_contact(&mut self, contact: &Contact, events: &mut Vec<TouchEvent>) {
+        let point = TouchPoint { id: contact.id, x: contact.x, y: contact.y };
+        let index = self.contacts.iter().position(|slot| slot.map_or(false, |t| t.point.id == contact.id));
+
+        match (index, contact.touching) {
+            (Some(index), true) => {
+                let tracked = self.contacts[index].as_mut().unwrap();
+                tracked.seen = true;
+                if tracked.point != point {
+                    tracked.point = point;
+                    events.push(TouchEvent { phase: TouchPhase::Move, point, primary: tracked.primary });
+                }
+            }
+            (Some(index), false) => {
+                let tracked = self.contacts[index].take().unwrap();
+                events.push(TouchEvent { phase: TouchPhase::Up, point, primary: tracked.primary });
+            }
+            (None, true) => {
+                let primary = self.contacts.iter().all(|slot| slot.is_none());
+                if let Some(slot) = self.contacts.iter_mut().find(|slot| slot.is_none()) {
+                    *slot = Some(Tracked { point, primary, seen: true });
+                    events.push(TouchEvent { phase: TouchPhase::Down, point, primary });
+                }
+            }
+            // Lift of a contact we never tracked
+            (None, false) => {}
+        }
+    }
+}
+
+/// Whether a report layout describes a touch digitizer
+pub fn is_digitizer(layout: &ReportLayout) -> bool {
+    let has = |usage| {
+        layout
+            .fields
+            .iter()
+            .any(|field| field.usage_page == hid::USAGE_PAGE_DIGITIZER && field.usage == usage)
+    };
+    has(hid::USAGE_TIP_SWITCH)
+        && layout.fields.iter().any(|field| {
+            field.usage_page == hid::USAGE_PAGE_GENERIC_DESKTOP && field.usage == hid::USAGE_X && !field.relative
+        })
+}
+
+/// Contacts of a report, one per collection holding a tip switch
+fn read_contacts(state: &HidState, width: u32, height: u32) -> Vec<Contact> {
+    let mut collections: Vec<u16> = state
+        .axes
+        .iter()
+        .filter(|axis| axis.usage_page == hid::USAGE_PAGE_DIGITIZER && axis.usage == hid::USAGE_TIP_SWITCH)
+        .map(|axis| axis.collection)
+        .collect();
+    collections.dedup();
+
+    collections
+        .into_iter()
+        .filter_map(|collection| {
+            let axis = |page, usage| {
+                state
+                    .axes
+                    .iter()
+                    .find(|axis| axis.collection == collection && axis.usage_page == page && axis.usage == usage)
+            };
+            let x = axis(hid::USAGE_PAGE_GENERIC_DESKTOP, hid::USAGE_X)?;
+            let y = axis(hid::USAGE_PAGE_GENERIC_DESKTOP, hid::USAGE_Y)?;
+            let touching = axis(hid::USAGE_PAGE_DIGITIZER, hid::USAGE_TIP_SWITCH).map_or(false, |tip| tip.value != 0);
+            // Single-touch devices have no contact ID
+            let id = axis(hid::USAGE_PAGE_DIGITIZER, hid::USAGE_CONTACT_ID).map_or(0, |id| id.value.max(0) as u32);
+            Some(Contact {
+                id,
+                touching,
+                x: scale(x.value, x.logical_min, x.logical_max, width),
+                y: scale(y.value, y.logical_min, y.logical_max, height),
+            })
+        })
+        .collect()
+}
+
+/// Map a logical value onto 0..extent pixels
+fn scale(value: i32, min: i32, max: i32, extent: u32) -> f32 {
+    let range = max as i64 - min as i64;
+    if range <= 0 || extent == 0 {
+        return 0.0;
+    }
+    let value = (value as i64).clamp(min as i64, max as i64) - min as i64;
+    (value as f32) * (extent - 1) as f32 / range as f32
+}
+
+struct TouchState {
+    devices: Vec<Digitizer>,
+    events: Vec<TouchEvent>,
+    screen: (u32, u32),
+}
+
+/// Events past this many are dropped until the input layer catches up
+const EVENT_QUEUE_LIMIT: usize = 256;
+
+static TOUCH: Mutex<TouchState> = Mutex::new(TouchState {
+    devices: Vec::new(),
+    events: Vec::new(),
+    screen: (1024, 768),
+});
+
+/// Register a digitizer from its HID report descriptor, returning its index
+pub fn add_device(descriptor: &[u8]) -> Result<usize, &'static str> {
+    let digitizer = Digitizer::new(descriptor)?;
+    let mut touch = TOUCH.lock();
+    touch.devices.push(digitizer);
+    log::info!("Touch digitizer {} added", touch.devices.len() - 1);
+    Ok(touch.devices.len() - 1)
+}
+
+/// Feed an input report from digitizer `device`
+pub fn handle_report(device: usize, data: &[u8]) -> Result<(), &'static str> {
+    let mut touch = TOUCH.lock();
+    let (width, height) = touch.screen;
+    let events = touch
+        .devices
+        .get_mut(device)
+        .ok_or("Unknown touch device")?
+        .handle_report(data, width, height);
+    let room = EVENT_QUEUE_LIMIT.saturating_sub(touch.events.len());
+    touch.events.extend(events.into_iter().take(room));
+    Ok(())
+}
+
+/// Screen size positions are scaled to; call when the resolution changes
+pub fn set_screen_size(width: u32, height: u32) {
+    TOUCH.lock().screen = (width, height);
+}
+
+/// Take the queued events, or none if the queue is busy
+pub fn try_drain_events() -> Vec<TouchEvent> {
+    match TOUCH.try_lock() {
+        Some(mut touch) => core::mem::take(&mut touch.events),
+        None => Vec::new(),
+    }
+}
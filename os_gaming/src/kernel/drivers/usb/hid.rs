@@ -0,0 +1,457 @@
+//! HID report descriptor parsing
+//!
+//! Report-protocol devices (flight sticks, fight pads, most third-party
+//! gamepads) describe their input reports with a descriptor. Parsing it once
+//! gives a `ReportLayout` that `decode_report` uses to pull button and axis
+//! values out of each report. Only short items are interpreted; long items
+//! are skipped. Every field remembers the collection it was declared in, so
+//! multi-touch digitizers can tell their per-contact fields apart.
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+/// Usage pages the decoder cares about
+pub const USAGE_PAGE_GENERIC_DESKTOP: u16 = 0x01;
+pub const USAGE_PAGE_SIMULATION: u16 = 0x02;
+pub const USAGE_PAGE_BUTTON: u16 = 0x09;
+pub const USAGE_PAGE_DIGITIZER: u16 = 0x0D;
+
+/// Generic desktop usages
+pub const USAGE_X: u16 = 0x30;
+pub const USAGE_Y: u16 = 0x31;
+pub const USAGE_Z: u16 = 0x32;
+pub const USAGE_RX: u16 = 0x33;
+pub const USAGE_RY: u16 = 0x34;
+pub const USAGE_RZ: u16 = 0x35;
+pub const USAGE_HAT_SWITCH: u16 = 0x39;
+
+/// Digitizer usages
+pub const USAGE_TOUCH_SCREEN: u16 = 0x04;
+pub const USAGE_FINGER: u16 = 0x22;
+pub const USAGE_TIP_SWITCH: u16 = 0x42;
+pub const USAGE_CONTACT_ID: u16 = 0x51;
+pub const USAGE_CONTACT_COUNT: u16 = 0x54;
+
+// Main item tags
+const TAG_INPUT: u8 = 0x8;
+const TAG_OUTPUT: u8 = 0x9;
+const TAG_FEATURE: u8 = 0xB;
+const TAG_COLLECTION: u8 = 0xA;
+const TAG_END_COLLECTION: u8 = 0xC;
+
+// Global item tags
+const TAG_USAGE_PAGE: u8 = 0x0;
+const TAG_LOGICAL_MIN: u8 = 0x1;
+const TAG_LOGICAL_MAX: u8 = 0x2;
+const TAG_REPORT_SIZE: u8 = 0x7;
+const TAG_REPORT_ID: u8 = 0x8;
+const TAG_REPORT_COUNT: u8 = 0x9;
+const TAG_PUSH: u8 = 0xA;
+const TAG_POP: u8 = 0xB;
+
+// Local item tags
+const TAG_USAGE: u8 = 0x0;
+const TAG_USAGE_MIN: u8 = 0x1;
+const TAG_USAGE_MAX: u8 = 0x2;
+
+const ITEM_TYPE_MAIN: u8 = 0;
+const ITEM_TYPE_GLOBAL: u8 = 1;
+const ITEM_TYPE_LOCAL: u8 = 2;
+
+const LONG_ITEM_PREFIX: u8 = 0xFE;
+
+// Input item flags
+const FLAG_CONSTANT: u32 = 0x01;
+const FLAG_VARIABLE: u32 = 0x02;
+const FLAG_RELATIVE: u32 = 0x04;
+
+/// Global state that Push/Pop save and restore
+#[derive(Debug, Clone, Copy, Default)]
+struct GlobalState {
+    usage_page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    report_size: u32,
+    report_count: u32,
+    report_id: u8,
+}
+
+/// One input field of a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportField {
+    /// Report ID the field belongs to (0 if the device uses none)
+    pub report_id: u8,
+    /// Offset from the start of the report data, after any report ID byte
+    pub bit_offset: u32,
+    pub bit_size: u32,
+    pub usage_page: u16,
+    /// Usage for variable fields, first usage of the range for array fields
+    pub usage: u16,
+    pub logical_min: i32,
+    pub logical_max: i32,
+    /// Array field: the value selects a usage instead of being one
+    pub array: bool,
+    pub relative: bool,
+    /// Innermost collection, numbered in descriptor order from 1; 0 outside any
+    pub collection: u16,
+}
+
+/// Parsed input report format of a HID device
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportLayout {
+    pub fields: Vec<ReportField>,
+    /// Reports are prefixed with a report ID byte
+    pub uses_report_ids: bool,
+}
+
+impl ReportLayout {
+    /// Input report length in bytes for `report_id`, excluding the ID byte
+    pub fn report_len(&self, report_id: u8) -> usize {
+        let bits = self
+            .fields
+            .iter()
+            .filter(|field| field.report_id == report_id)
+            .map(|field| field.bit_offset + field.bit_size)
+            .max()
+            .unwrap_or(0);
+        ((bits + 7) / 8) as usize
+    }
+}
+
+/// An absolute or relative axis value from a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HidAxis {
+    pub usage_page: u16,
+    pub usage: u16,
+    pub value: i32,
+    pub logical_min: i32,
+    pub logical_max: i32,
+    /// Collection of the field the value came from
+    pub collection: u16,
+}
+
+impl HidAxis {
+    /// Scale the value to the full i16 range
+    pub fn normalized(&self) -> i16 {
+        let range = self.logical_max as i64 - self.logical_min as i64;
+        if range <= 0 {
+            return 0;
+        }
+        let value = (self.value as i64).clamp(self.logical_min as i64, self.logical_max as i64);
+        ((value - self.logical_min as i64) * 65535 / range - 32768) as i16
+    }
+
+    /// Scale the value to 0..=255, for triggers
+    pub fn normalized_u8(&self) -> u8 {
+        ((self.normalized() as i32 + 32768) >> 8) as u8
+    }
+}
+
+/// Values decoded from one input report
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HidState {
+    pub report_id: u8,
+    /// Bit n set when button usage n + 1 is pressed
+    pub buttons: u32,
+    pub axes: Vec<HidAxis>,
+    /// Hat switch direction, 0 = up going clockwise in 45 degree steps; None when centred
+    pub hat: Option<u8>,
+}
+
+impl HidState {
+    pub fn axis(&self, usage_page: u16, usage: u16) -> Option<&HidAxis> {
+        self.axes
+            .iter()
+            .find(|axis| axis.usage_page == usage_page && axis.usage == usage)
+    }
+}
+
+/// Walk a report descriptor and lay out every input field
+pub fn parse_report_descriptor(bytes: &[u8]) -> Result<ReportLayout, &'static str> {
+    let mut layout = ReportLayout::default();
+    let mut global = GlobalState::default();
+    let mut global_stack: Vec<GlobalState> = Vec::new();
+
+    // Local items, reset after every main item
+    let mut usages: Vec<(u16, u16)> = Vec::new();
+    let mut usage_min: Option<(u16, u16)> = None;
+    let mut usage_max: Option<u16> = None;
+
+    // Next free input bit for each report ID
+    let mut offsets: Vec<(u8, u32)> = Vec::new();
+
+    // Open collections, innermost last
+    let mut collections: Vec<u16> = Vec::new();
+    let mut collection_count: u16 = 0;
+
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let prefix = bytes[pos];
+        pos += 1;
+
+        if prefix == LONG_ITEM_PREFIX {
+            let size = *bytes.get(pos).ok_or("Truncated HID long item")? as usize;
+            pos += 2 + size;
+            if pos > bytes.len() {
+                return Err("Truncated HID long item");
+            }
+            continue;
+        }
+
+        let size = match prefix & 0x3 {
+            3 => 4,
+            n => n as usize,
+        };
+        let data = bytes.get(pos..pos + size).ok_or("Truncated HID item")?;
+        pos += size;
+
+        let unsigned = data
+            .iter()
+            .rev()
+            .fold(0u32, |acc, &byte| (acc << 8) | byte as u32);
+        let signed = match size {
+            1 => unsigned as u8 as i8 as i32,
+            2 => unsigned as u16 as i16 as i32,
+            _ => unsigned as i32,
+        };
+
+        let tag = prefix >> 4;
+        match (prefix >> 2) & 0x3 {
+            ITEM_TYPE_MAIN => {
+                match tag {
+                    TAG_INPUT => {
+                        let slot = match offsets.iter().position(|(id, _)| *id == global.report_id) {
+                            Some(slot) => slot,
+                            None => {
+                                offsets.push((global.report_id, 0));
+                                offsets.len() - 1
+                            }
+                        };
+                        add_input_fields(
+                            &mut layout,
+                            &global,
+                            unsigned,
+                            &usages,
+                            usage_min,
+                            usage_max,
+                            collections.last().copied().unwrap_or(0),
+                            &mut offsets[slot].1,
+                        )?;
+                    }
+                    TAG_COLLECTION => {
+                        collection_count = collection_count.checked_add(1).ok_or("Too many HID collections")?;
+                        collections.push(collection_count);
+                    }
+                    TAG_END_COLLECTION => {
+                        collections.pop().ok_or("HID end collection without collection")?;
+                    }
+                    // Output and feature reports are separate from input reports
+                    TAG_OUTPUT | TAG_FEATURE => {}
+                    _ => log::trace!("Skipping unknown HID main item {:#x}", tag),
+                }
+                usages.clear();
+                usage_min = None;
+                usage_max = None;
+            }
+            ITEM_TYPE_GLOBAL => match tag {
+                TAG_USAGE_PAGE => global.usage_page = unsigned as u16,
+                TAG_LOGICAL_MIN => global.logical_min = signed,
+                TAG_LOGICAL_MAX => {
+                    // A maximum only reads as negative when the minimum is too
+                    global.logical_max = if global.logical_min < 0 { signed } else { unsigned as i32 }
+                }
+                TAG_REPORT_SIZE => {
+                    if unsigned > 32 {
+                        return Err("HID report size over 32 bits");
+                    }
+                    global.report_size = unsigned;
+                }
+                TAG_REPORT_ID => {
+                    if unsigned == 0 {
+                        return Err("HID report ID 0 is reserved");
+                    }
+                    global.report_id = unsigned as u8;
+                    layout.uses_report_ids = true;
+                }
+                TAG_REPORT_COUNT => global.report_count = unsigned,
+                TAG_PUSH => global_stack.push(global),
+                TAG_POP => global = global_stack.pop().ok_or("HID pop without push")?,
+                _ => {}
+            },
+            ITEM_TYPE_LOCAL => {
+                // A 4-byte usage carries its own usage page in the high half
+                let usage = if size == 4 {
+                    ((unsigned >> 16) as u16, unsigned as u16)
+                } else {
+                    (global.usage_page, unsigned as u16)
+                };
+                match tag {
+                    TAG_USAGE => usages.push(usage),
+                    TAG_USAGE_MIN => usage_min = Some(usage),
+                    TAG_USAGE_MAX => usage_max = Some(usage.1),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(layout)
+}
+
+/// Append the fields described by one Input item
+fn add_input_fields(
+    layout: &mut ReportLayout,
+    global: &GlobalState,
+    flags: u32,
+    usages: &[(u16, u16)],
+    usage_min: Option<(u16, u16)>,
+    usage_max: Option<u16>,
+    collection: u16,
+    next_bit: &mut u32,
+) -> Result<(), &'static str> {
+    let start = *next_bit;
+    *next_bit = global
+        .report_count
+        .checked_mul(global.report_size)
+        .and_then(|bits| start.checked_add(bits))
+        .ok_or("HID report too large")?;
+
+    // Constant fields are padding
+    if flags & FLAG_CONSTANT != 0 || global.report_size == 0 {
+        return Ok(());
+    }
+
+    let usage_at = |index: u32| -> Option<(u16, u16)> {
+        if let Some(&usage) = usages.get(index as usize) {
+            return Some(usage);
+        }
+        if let (Some((page, min)), Some(max)) = (usage_min, usage_max) {
+            let usage = min as u32 + index;
+            return Some((page, usage.min(max as u32) as u16));
+        }
+        usages.last().copied()
+    };
+
+    let field = |index: u32, (usage_page, usage): (u16, u16), array: bool| ReportField {
+        report_id: global.report_id,
+        bit_offset: start + index * global.report_size,
+        bit_size: global.report_size,
+        usage_page,
+        usage,
+        logical_min: global.logical_min,
+        logical_max: global.logical_max,
+        array,
+        relative: flags & FLAG_RELATIVE != 0,
+        collection,
+    };
+
+    if flags & FLAG_VARIABLE != 0 {
+        for index in 0..global.report_count {
+            if let Some(usage) = usage_at(index) {
+                layout.fields.push(field(index, usage, false));
+            }
+        }
+    } else {
+        // Every slot of an array reports one usage out of the range
+        let base = usage_min.or_else(|| usages.first().copied());
+        if let Some(base) = base {
+            for index in 0..global.report_count {
+                layout.fields.push(field(index, base, true));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Read `size` bits starting at `offset`, little-endian as HID specifies
+fn extract_bits(data: &[u8], offset: u32, size: u32) -> Option<u32> {
+    if (offset + size) as usize > data.len() * 8 {
+        return None;
+    }
+    let mut value = 0u32;
+    for bit in 0..size {
+        let index = offset + bit;
+        if data[(index / 8) as usize] & (1 << (index % 8)) != 0 {
+            value |= 1 << bit;
+        }
+    }
+    Some(value)
+}
+
+/// Pull button, axis and hat values out of an input report.
+/// Fields that don't fit in `data` are left out.
+pub fn decode_report(layout: &ReportLayout, data: &[u8]) -> HidState {
+    let mut state = HidState::default();
+
+    let data = if layout.uses_report_ids {
+        match data.split_first() {
+            Some((&id, rest)) => {
+                state.report_id = id;
+                rest
+            }
+            None => return state,
+        }
+    } else {
+        data
+    };
+
+    let report_id = state.report_id;
+    for field in layout.fields.iter().filter(|field| field.report_id == report_id) {
+        let raw = match extract_bits(data, field.bit_offset, field.bit_size) {
+            Some(raw) => raw,
+            None => continue,
+        };
+        let value = if field.logical_min < 0 && field.bit_size < 32 {
+            // Sign-extend from the field width
+            let shift = 32 - field.bit_size;
+            ((raw << shift) as i32) >> shift
+        } else {
+            raw as i32
+        };
+
+        if field.array {
+            if value < field.logical_min || value > field.logical_max {
+                continue; // No usage selected in this slot
+            }
+            let usage = field.usage as i32 + (value - field.logical_min);
+            if field.usage_page == USAGE_PAGE_BUTTON {
+                set_button(&mut state, usage);
+            }
+            continue;
+        }
+
+        match (field.usage_page, field.usage) {
+            (USAGE_PAGE_BUTTON, usage) => {
+                if value != 0 {
+                    set_button(&mut state, usage as i32);
+                }
+            }
+            (USAGE_PAGE_GENERIC_DESKTOP, USAGE_HAT_SWITCH) => {
+                // Out-of-range values mean the hat is centred
+                if value >= field.logical_min && value <= field.logical_max {
+                    let positions = field.logical_max - field.logical_min + 1;
+                    state.hat = Some(((value - field.logical_min) * 8 / positions.max(1)) as u8);
+                }
+            }
+            (usage_page, usage) => state.axes.push(HidAxis {
+                usage_page,
+                usage,
+                value,
+                logical_min: field.logical_min,
+                logical_max: field.logical_max,
+                collection: field.collection,
+            }),
+        }
+    }
+
+    state
+}
+
+fn set_button(state: &mut HidState, usage: i32) {
+    // Button usage 0 means "no button"
+    if (1..=32).contains(&usage) {
+        state.buttons |= 1 << (usage - 1);
+    }
+}
@@ -6,6 +6,8 @@ use alloc::string::String;
 use core::sync::atomic::{AtomicBool, Ordering};
 use spin::Mutex;
 
+pub mod hid;
+
 /// USB controller types
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UsbControllerType {
@@ -83,7 +85,10 @@ pub struct UsbEndpoint {
     address: u8,
     attributes: u8,
     max_packet_size: u16,
+    /// bInterval from the descriptor: the fastest the device asks to be polled
     interval: u8,
+    /// Interval the endpoint is scheduled at, in the same encoding
+    poll_interval: u8,
 }
 
 /// USB controller information
@@ -184,6 +189,19 @@ impl UsbDevice {
         self.interfaces.push(interface);
     }
     
+    /// Set every HID interrupt IN endpoint to poll as close to `hz` as it
+    /// can. Returns the slowest achieved rate, or None without such endpoints.
+    pub fn set_hid_poll_rate(&mut self, hz: u32) -> Option<u32> {
+        let speed = self.speed;
+        self.interfaces
+            .iter_mut()
+            .filter(|interface| interface.class == UsbDeviceClass::Hid)
+            .flat_map(|interface| interface.endpoints.iter_mut())
+            .filter(|endpoint| endpoint.is_in() && endpoint.is_interrupt())
+            .map(|endpoint| endpoint.set_poll_rate(speed, hz))
+            .min()
+    }
+    
     /// Check if device is connected
     pub fn is_connected(&self) -> bool {
         self.connected.load(Ordering::SeqCst)
@@ -233,6 +251,7 @@ impl UsbEndpoint {
             attributes,
             max_packet_size,
             interval,
+            poll_interval: interval,
         }
     }
     
@@ -250,6 +269,47 @@ impl UsbEndpoint {
     pub fn get_type(&self) -> u8 {
         (self.attributes & 0x03)
     }
+    
+    /// Whether this is an interrupt endpoint
+    pub fn is_interrupt(&self) -> bool {
+        self.get_type() == 0x03
+    }
+    
+    /// Reports per second at the scheduled interval
+    pub fn poll_rate(&self, speed: UsbSpeed) -> u32 {
+        rate_for_interval(speed, self.poll_interval)
+    }
+    
+    /// Schedule the endpoint at the fastest rate not above `hz` that the
+    /// descriptor allows, and return that rate
+    pub fn set_poll_rate(&mut self, speed: UsbSpeed, hz: u32) -> u32 {
+        let hz = hz.max(1);
+        self.poll_interval = match speed {
+            // bInterval counts 1 ms frames
+            UsbSpeed::Low | UsbSpeed::Full => {
+                let frames = (1000 + hz - 1) / hz;
+                frames.clamp(self.interval.max(1) as u32, 255) as u8
+            }
+            // bInterval is an exponent: 2^(bInterval - 1) 125 us microframes
+            _ => {
+                let mut exponent = 1;
+                while exponent < 16 && rate_for_interval(speed, exponent) > hz {
+                    exponent += 1;
+                }
+                exponent.max(self.interval.clamp(1, 16))
+            }
+        };
+        self.poll_rate(speed)
+    }
+}
+
+/// Reports per second of an interrupt endpoint polled every `interval`
+/// (bInterval encoding for `speed`)
+pub fn rate_for_interval(speed: UsbSpeed, interval: u8) -> u32 {
+    match speed {
+        UsbSpeed::Low | UsbSpeed::Full => 1000 / interval.max(1) as u32,
+        _ => 8000 >> (interval.clamp(1, 16) - 1),
+    }
 }
 
 impl UsbController {
@@ -390,6 +450,16 @@ impl UsbManager {
         self.devices.iter()
             .find(|dev| dev.vendor_id == vendor_id && dev.product_id == product_id)
     }
+    
+    /// Apply `UsbDevice::set_hid_poll_rate` to every connected device.
+    /// Returns the slowest achieved rate, or None without HID devices.
+    pub fn set_hid_poll_rate(&mut self, hz: u32) -> Option<u32> {
+        self.devices
+            .iter_mut()
+            .filter(|dev| dev.is_connected())
+            .filter_map(|dev| dev.set_hid_poll_rate(hz))
+            .min()
+    }
 }
 
 // Add Clone implementation for device structures
@@ -433,6 +503,7 @@ impl Clone for UsbEndpoint {
             attributes: self.attributes,
             max_packet_size: self.max_packet_size,
             interval: self.interval,
+            poll_interval: self.poll_interval,
         }
     }
 }
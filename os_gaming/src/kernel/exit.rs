@@ -0,0 +1,95 @@
+//! Structured kernel exit
+//!
+//! Every way the kernel stops running goes through `exit` with an
+//! `ExitReason`, so a requested shutdown can be told apart from a crash. The
+//! reason is logged and, with the `qemu-exit` feature, written to QEMU's
+//! `isa-debug-exit` device (`-device isa-debug-exit,iobase=0xf4,iosize=0x04`)
+//! so a test harness sees a distinct exit status per reason. QEMU exits with
+//! `(code << 1) | 1`, e.g. 33 for `Shutdown`. On real hardware the port write
+//! is compiled out and the machine halts or reboots as usual.
+
+use core::fmt;
+
+use crate::kernel::drivers::power;
+
+#[cfg(feature = "qemu-exit")]
+use crate::kernel::io::WoPort;
+
+#[cfg(feature = "qemu-exit")]
+const QEMU_DEBUG_EXIT: WoPort<u32> = WoPort::new(0xf4);
+
+/// Why the kernel stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// A shutdown was requested
+    Shutdown,
+    /// A reboot was requested
+    Reboot,
+    /// A panic with the halt policy
+    PanicHalt,
+    /// A boot stage failed to initialize
+    InitFailed(&'static str),
+}
+
+impl ExitReason {
+    /// The value written to the debug-exit port. Never 0, whose exit status
+    /// (1) QEMU also uses for its own failures.
+    pub fn qemu_code(self) -> u32 {
+        match self {
+            ExitReason::Shutdown => 0x10,
+            ExitReason::Reboot => 0x11,
+            ExitReason::PanicHalt => 0x12,
+            ExitReason::InitFailed(_) => 0x13,
+        }
+    }
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitReason::Shutdown => write!(f, "shutdown"),
+            ExitReason::Reboot => write!(f, "reboot"),
+            ExitReason::PanicHalt => write!(f, "panic"),
+            ExitReason::InitFailed(stage) => write!(f, "{} initialization failed", stage),
+        }
+    }
+}
+
+/// Report `reason` to QEMU, when built for it. Safe to call from a panic: it
+/// neither locks nor allocates. Returns if the device is absent.
+pub fn report_to_qemu(reason: ExitReason) {
+    #[cfg(feature = "qemu-exit")]
+    // Safety: writing the debug-exit port either stops QEMU or is ignored
+    unsafe {
+        QEMU_DEBUG_EXIT.write(reason.qemu_code());
+    }
+    #[cfg(not(feature = "qemu-exit"))]
+    let _ = reason;
+}
+
+/// Stop the kernel for `reason`
+pub fn exit(reason: ExitReason) -> ! {
+    match reason {
+        ExitReason::Shutdown | ExitReason::Reboot => log::info!("Kernel exit: {}", reason),
+        _ => log::error!("Kernel exit: {}", reason),
+    }
+    report_to_qemu(reason);
+
+    match reason {
+        ExitReason::Shutdown => {
+            if let Err(e) = power::shutdown() {
+                log::error!("Power off failed: {}", e);
+            }
+        }
+        ExitReason::Reboot => power::emergency_reboot(),
+        _ => {}
+    }
+    halt()
+}
+
+fn halt() -> ! {
+    loop {
+        x86_64::instructions::interrupts::disable();
+        x86_64::instructions::hlt();
+    }
+}
@@ -7,6 +7,7 @@ use crate::kernel::drivers::sound;
 use crate::kernel::drivers::gamepad;
 use crate::kernel::drivers::network;
 use crate::kernel::drivers::timer as time;
+use crate::kernel::memory::{self, fault::{self, FaultAccess, FaultRegionKind}};
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0x20; // IST index for double fault stack
 
@@ -115,24 +116,41 @@ pub extern "x86-interrupt" fn page_fault_handler(
 ) {
     use x86_64::registers::control::Cr2;
 
+    // A non-canonical CR2 can't belong to any region; only a valid address
+    // is classified, the raw value is reported either way
     let accessed_address = Cr2::read();
-    let present = error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION);
-    let write = error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
-    let user = error_code.contains(PageFaultErrorCode::USER_MODE);
+    let access = FaultAccess::from_error_code(error_code.bits());
+
+    let reason = match accessed_address {
+        Ok(address) => match fault::region_kind(address) {
+            Some(FaultRegionKind::CopyOnWrite) if access.write && access.present => {
+                match fault::handle_copy_on_write_fault(address) {
+                    Ok(()) => return,
+                    Err(_) => "copy-on-write resolution failed",
+                }
+            }
+            Some(FaultRegionKind::GuardPage) => "stack overflow (guard page hit)",
+            _ => "invalid memory access",
+        },
+        Err(_) => "non-canonical address",
+    };
+
+    // Formatted without allocating, in case the heap is what faulted
+    let mapping = accessed_address.ok().map(memory::translate);
 
     panic!(
-        "PAGE FAULT\n\
-        Accessed Address: {:?}\n\
-        Error Code: {:?}\n\
-        Present: {}\n\
-        Write: {}\n\
-        User Access: {}\n\
+        "PAGE FAULT: {}\n\
+        Accessed Address (CR2): {:#x}\n\
+        Mapping: {:x?}\n\
+        Error Code: {:#x} ({})\n\
+        RIP: {:#x}\n\
         Stack Frame:\n{:#?}",
-        accessed_address,
-        error_code,
-        present,
-        write,
-        user,
+        reason,
+        Cr2::read_raw(),
+        mapping,
+        error_code.bits(),
+        access,
+        stack_frame.instruction_pointer.as_u64(),
         stack_frame
     );
 }
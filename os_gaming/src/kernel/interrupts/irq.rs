@@ -1,18 +1,22 @@
 pub mod pic {
-    use pic8259::ChainedPics;
+    use super::super::pic8259::ChainedPics;
+    pub use super::super::pic8259::Irq;
     use spin::Mutex;
+    use crate::kernel::interrupts::InterruptGuard;
 
     /// Primary PIC offset
     pub const PIC_1_OFFSET: u8 = 32;
     /// Secondary PIC offset
     pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
-    /// The programmable interrupt controllers
+    /// The programmable interrupt controllers. IRQ handlers take this lock
+    /// for end of interrupt, so hold it only with interrupts disabled.
     pub static PICS: Mutex<ChainedPics> =
         Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
     /// Initialize the PIC
     pub fn init() {
+        let _guard = InterruptGuard::new();
         unsafe {
             PICS.lock().initialize();
         }
@@ -22,6 +26,18 @@ pub mod pic {
     pub unsafe fn end_of_interrupt(interrupt_id: u8) {
         PICS.lock().notify_end_of_interrupt(interrupt_id);
     }
+
+    /// Stop `irq` from interrupting
+    pub fn mask(irq: Irq) {
+        let _guard = InterruptGuard::new();
+        unsafe { PICS.lock().mask(irq) }
+    }
+
+    /// Let `irq` interrupt once its handler is installed
+    pub fn unmask(irq: Irq) {
+        let _guard = InterruptGuard::new();
+        unsafe { PICS.lock().unmask(irq) }
+    }
 }
 
 /// Send end of interrupt signal to the appropriate controller
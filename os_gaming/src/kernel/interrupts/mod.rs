@@ -2,10 +2,12 @@ mod idt;
 mod handlers;
 mod apic;
 pub(crate) mod irq;
+pub(crate) mod pic8259;
 
 use lazy_static::lazy_static;
 use spin::Mutex;
 use core::arch::{asm};
+use core::marker::PhantomData;
 use x86_64::structures::idt::InterruptStackFrame;
 use x86_64::structures::idt::InterruptDescriptorTable;
 use crate::kernel;
@@ -14,7 +16,6 @@ pub use self::irq::*;
 // Add this to your existing interrupt constants
 pub const SOUND_INTERRUPT_INDEX: u8 = 15; // Choose an appropriate interrupt number
 pub const KEYBOARD_INTERRUPT_INDEX: u8 = 33; // Choose an appropriate interrupt number
-pub const PICS: pic8259::ChainedPics = unsafe { pic8259::ChainedPics::new(32, 40) }; // Primary and secondary PIC offsets
 
 lazy_static! {
     /// The global Interrupt Descriptor Table
@@ -57,12 +58,48 @@ pub fn without_interrupts<F, R>(f: F) -> R
 where
     F: FnOnce() -> R
 {
-    kernel::interrupts::without_interrupts(f)
+    let _guard = InterruptGuard::new();
+    f()
 }
 
 /// Check if interrupts are enabled
 pub fn are_enabled() -> bool {
-    kernel::interrupts::are_enabled()
+    x86_64::instructions::interrupts::are_enabled()
+}
+
+/// Keeps interrupts disabled for as long as it lives.
+///
+/// `new()` records whether interrupts were enabled and disables them; dropping
+/// the guard re-enables them only if they were enabled before. Nested guards
+/// therefore restore correctly: only the outermost drop turns interrupts back on.
+/// The guard can't be sent to another thread, since the saved state belongs to
+/// the CPU that created it.
+pub struct InterruptGuard {
+    was_enabled: bool,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl InterruptGuard {
+    pub fn new() -> Self {
+        let was_enabled = are_enabled();
+        if was_enabled {
+            x86_64::instructions::interrupts::disable();
+        }
+        Self { was_enabled, _not_send: PhantomData }
+    }
+
+    /// Whether interrupts will be re-enabled when this guard drops
+    pub fn restores_interrupts(&self) -> bool {
+        self.was_enabled
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if self.was_enabled {
+            x86_64::instructions::interrupts::enable();
+        }
+    }
 }
 
 /// Register a custom interrupt handler
@@ -0,0 +1,178 @@
+//! The two chained 8259 programmable interrupt controllers
+//!
+//! Each PIC has a command port and a data port. The initialization words
+//! after ICW1 and the interrupt masks go to the data port; ICW1 and
+//! end-of-interrupt go to the command port. The secondary PIC is wired to
+//! IRQ 2 of the primary, so lines 8-15 also need that line unmasked and
+//! acknowledged.
+use x86_64::instructions::port::Port;
+
+/// ICW1: start initialization, ICW4 follows
+const CMD_INIT: u8 = 0x11;
+/// Non-specific end of interrupt
+const CMD_END_OF_INTERRUPT: u8 = 0x20;
+/// ICW4: 8086 mode
+const MODE_8086: u8 = 0x01;
+
+/// An interrupt line, 0-7 on the primary PIC and 8-15 on the secondary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Irq(u8);
+
+impl Irq {
+    pub const TIMER: Irq = Irq(0);
+    pub const KEYBOARD: Irq = Irq(1);
+    /// Where the secondary PIC is chained in
+    pub const CASCADE: Irq = Irq(2);
+    pub const MOUSE: Irq = Irq(12);
+
+    pub const fn new(line: u8) -> Option<Irq> {
+        if line < 16 { Some(Irq(line)) } else { None }
+    }
+
+    pub fn line(self) -> u8 {
+        self.0
+    }
+
+    /// Which PIC the line is on and its bit in that PIC's mask
+    fn pic_and_bit(self) -> (usize, u8) {
+        if self.0 < 8 { (0, self.0) } else { (1, self.0 - 8) }
+    }
+}
+
+struct Pic {
+    /// First vector this PIC raises
+    offset: u8,
+    command: Port<u8>,
+    data: Port<u8>,
+}
+
+impl Pic {
+    /// Whether `vector` is one of the eight this PIC raises
+    fn handles_interrupt(&self, vector: u8) -> bool {
+        (self.offset..self.offset.wrapping_add(8)).contains(&vector)
+    }
+
+    unsafe fn write_command(&mut self, value: u8) {
+        self.command.write(value);
+    }
+
+    unsafe fn write_data(&mut self, value: u8) {
+        self.data.write(value);
+    }
+
+    unsafe fn read_mask(&mut self) -> u8 {
+        self.data.read()
+    }
+
+    unsafe fn set_masked(&mut self, bit: u8, masked: bool) {
+        let mask = self.read_mask();
+        let mask = if masked { mask | 1 << bit } else { mask & !(1 << bit) };
+        self.write_data(mask);
+    }
+
+    unsafe fn end_of_interrupt(&mut self) {
+        self.write_command(CMD_END_OF_INTERRUPT);
+    }
+}
+
+/// The primary and secondary PIC, remapped so their vectors don't collide
+/// with CPU exceptions
+pub struct ChainedPics {
+    pics: [Pic; 2],
+}
+
+impl ChainedPics {
+    /// # Safety
+    /// The offsets must not overlap each other or the CPU exception vectors.
+    pub const unsafe fn new(offset1: u8, offset2: u8) -> Self {
+        Self {
+            pics: [
+                Pic { offset: offset1, command: Port::new(0x20), data: Port::new(0x21) },
+                Pic { offset: offset2, command: Port::new(0xA0), data: Port::new(0xA1) },
+            ],
+        }
+    }
+
+    /// Program the vector offsets and cascade wiring, keeping the current
+    /// interrupt masks
+    ///
+    /// # Safety
+    /// Talks to the PIC hardware; call with interrupts disabled.
+    pub unsafe fn initialize(&mut self) {
+        // Port 0x80 is unused; writing it gives the PICs time between words
+        let mut wait_port: Port<u8> = Port::new(0x80);
+        let mut wait = || wait_port.write(0);
+
+        let saved_masks = [self.pics[0].read_mask(), self.pics[1].read_mask()];
+
+        // ICW1
+        self.pics[0].write_command(CMD_INIT);
+        wait();
+        self.pics[1].write_command(CMD_INIT);
+        wait();
+
+        // ICW2: vector offsets
+        let (offset1, offset2) = (self.pics[0].offset, self.pics[1].offset);
+        self.pics[0].write_data(offset1);
+        wait();
+        self.pics[1].write_data(offset2);
+        wait();
+
+        // ICW3: secondary on IRQ 2 of the primary, which is its cascade identity
+        self.pics[0].write_data(1 << 2);
+        wait();
+        self.pics[1].write_data(2);
+        wait();
+
+        // ICW4
+        self.pics[0].write_data(MODE_8086);
+        wait();
+        self.pics[1].write_data(MODE_8086);
+        wait();
+
+        self.pics[0].write_data(saved_masks[0]);
+        self.pics[1].write_data(saved_masks[1]);
+    }
+
+    /// Whether `vector` comes from either PIC
+    pub fn handles_interrupt(&self, vector: u8) -> bool {
+        self.pics.iter().any(|pic| pic.handles_interrupt(vector))
+    }
+
+    /// Acknowledge `vector` so the PICs raise further interrupts. A
+    /// secondary interrupt arrived through the cascade line, so both PICs
+    /// are acknowledged, the secondary first.
+    ///
+    /// # Safety
+    /// `vector` must be the interrupt being handled.
+    pub unsafe fn notify_end_of_interrupt(&mut self, vector: u8) {
+        if self.handles_interrupt(vector) {
+            if self.pics[1].handles_interrupt(vector) {
+                self.pics[1].end_of_interrupt();
+            }
+            self.pics[0].end_of_interrupt();
+        }
+    }
+
+    /// Stop `irq` from raising interrupts
+    ///
+    /// # Safety
+    /// Talks to the PIC hardware.
+    pub unsafe fn mask(&mut self, irq: Irq) {
+        let (pic, bit) = irq.pic_and_bit();
+        self.pics[pic].set_masked(bit, true);
+    }
+
+    /// Let `irq` raise interrupts. Lines on the secondary PIC also open the
+    /// cascade line, without which they never reach the CPU.
+    ///
+    /// # Safety
+    /// A handler must be installed for the line's vector.
+    pub unsafe fn unmask(&mut self, irq: Irq) {
+        let (pic, bit) = irq.pic_and_bit();
+        self.pics[pic].set_masked(bit, false);
+        if pic == 1 {
+            self.unmask(Irq::CASCADE);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Hardware inventory for support and debug dumps
+//!
+//! Collects what the detection code found into one report that can be pasted
+//! into a bug report. Anything that could not be probed is shown as "unknown".
+
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::cpu::identification::{self, CpuInfo};
+use super::drivers::{self, gpu, sound, storage};
+use super::memory::physical;
+
+/// GPU found on the PCI bus
+#[derive(Debug, Clone)]
+pub struct GpuEntry {
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub vendor_name: &'static str,
+    pub device_name: &'static str,
+}
+
+/// Storage device known to the storage driver
+#[derive(Debug, Clone)]
+pub struct StorageEntry {
+    pub name: String,
+    pub device_type: storage::StorageDeviceType,
+    pub capacity_bytes: u64,
+}
+
+/// Snapshot of the detected hardware. `None` means the probe was unavailable.
+#[derive(Debug, Clone, Default)]
+pub struct HardwareInventory {
+    pub cpu: Option<CpuInfo>,
+    pub gpus: Option<Vec<GpuEntry>>,
+    pub sound: Option<sound::SoundHardwareType>,
+    pub pci_devices: Option<Vec<gpu::PciFunction>>,
+    pub storage: Option<Vec<StorageEntry>>,
+    pub total_ram_bytes: Option<usize>,
+}
+
+impl HardwareInventory {
+    /// Probe every subsystem. This walks the PCI bus, so don't call it per frame.
+    pub fn collect() -> Self {
+        let gpus = gpu::enumerate_gpus().ok().map(|devices| {
+            devices
+                .iter()
+                .map(|device| GpuEntry {
+                    vendor_id: device.vendor_id,
+                    device_id: device.device_id,
+                    vendor_name: device.vendor_name,
+                    device_name: device.device_name,
+                })
+                .collect()
+        });
+
+        // A failed sound init leaves the global driver at its default, which would read as "none"
+        let sound_failed = drivers::driver_status()
+            .iter()
+            .any(|(name, status)| *name == "sound" && !status.is_ready());
+        let sound = if sound_failed {
+            None
+        } else {
+            Some(sound::get_sound_driver().get_hardware_type())
+        };
+
+        let storage = drivers::get_driver_manager().lock().as_ref().map(|manager| {
+            manager
+                .storage_manager
+                .get_devices()
+                .iter()
+                .map(|device| StorageEntry {
+                    name: String::from(device.get_name()),
+                    device_type: device.get_device_type(),
+                    capacity_bytes: device.get_size_bytes(),
+                })
+                .collect()
+        });
+
+        let total_ram = physical::get_physical_memory_manager().total_memory();
+
+        Self {
+            cpu: identification::get_cpu_info(),
+            gpus,
+            sound,
+            pci_devices: Some(gpu::enumerate_pci_functions()),
+            storage,
+            total_ram_bytes: if total_ram > 0 { Some(total_ram) } else { None },
+        }
+    }
+}
+
+const UNKNOWN: &str = "unknown";
+
+impl fmt::Display for HardwareInventory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "=== Hardware inventory ===")?;
+
+        match &self.cpu {
+            Some(cpu) => {
+                writeln!(f, "CPU vendor: {}", cpu.vendor_id)?;
+                writeln!(f, "CPU brand: {}", cpu.brand_string.trim())?;
+                writeln!(
+                    f,
+                    "CPU family/model/stepping: {}/{}/{}",
+                    cpu.family, cpu.model, cpu.stepping
+                )?;
+                let features = &cpu.features;
+                write!(f, "CPU features:")?;
+                for (name, present) in [
+                    ("sse", features.sse),
+                    ("sse2", features.sse2),
+                    ("avx", features.avx),
+                    ("avx2", features.avx2),
+                    ("hypervisor", features.hypervisor),
+                ] {
+                    if present {
+                        write!(f, " {}", name)?;
+                    }
+                }
+                writeln!(f)?;
+            }
+            None => writeln!(f, "CPU: {}", UNKNOWN)?,
+        }
+
+        match &self.gpus {
+            Some(gpus) if gpus.is_empty() => writeln!(f, "GPU: none detected")?,
+            Some(gpus) => {
+                for gpu in gpus {
+                    writeln!(
+                        f,
+                        "GPU: {:04x}:{:04x} {} {}",
+                        gpu.vendor_id, gpu.device_id, gpu.vendor_name, gpu.device_name
+                    )?;
+                }
+            }
+            None => writeln!(f, "GPU: {}", UNKNOWN)?,
+        }
+
+        match self.sound {
+            Some(sound) => writeln!(f, "Sound: {:?}", sound)?,
+            None => writeln!(f, "Sound: {}", UNKNOWN)?,
+        }
+
+        match &self.pci_devices {
+            Some(devices) => {
+                writeln!(f, "PCI devices: {}", devices.len())?;
+                for device in devices {
+                    writeln!(
+                        f,
+                        "  {:02x}:{:02x}.{} {:04x}:{:04x} class {:02x}{:02x}",
+                        device.bus,
+                        device.device,
+                        device.function,
+                        device.vendor_id,
+                        device.device_id,
+                        device.class,
+                        device.subclass
+                    )?;
+                }
+            }
+            None => writeln!(f, "PCI devices: {}", UNKNOWN)?,
+        }
+
+        match &self.storage {
+            Some(devices) if devices.is_empty() => writeln!(f, "Storage: none detected")?,
+            Some(devices) => {
+                for device in devices {
+                    writeln!(
+                        f,
+                        "Storage: {} ({:?}) {} MiB",
+                        device.name,
+                        device.device_type,
+                        device.capacity_bytes / (1024 * 1024)
+                    )?;
+                }
+            }
+            None => writeln!(f, "Storage: {}", UNKNOWN)?,
+        }
+
+        match self.total_ram_bytes {
+            Some(bytes) => writeln!(f, "RAM: {} MiB", bytes / (1024 * 1024)),
+            None => writeln!(f, "RAM: {}", UNKNOWN),
+        }
+    }
+}
+
+/// Gather the hardware inventory
+pub fn hardware_inventory() -> HardwareInventory {
+    HardwareInventory::collect()
+}
@@ -0,0 +1,241 @@
+//! Typed port-mapped I/O
+//!
+//! `RoPort`, `WoPort` and `RwPort` fix both the direction and the width of a
+//! port at the type level, so a write-only register can't be read by mistake
+//! and an 8-bit register can't be accessed with a 16-bit instruction.
+//! `PortRange` hands out typed ports relative to a driver's base address and,
+//! in debug builds, records ownership so two drivers claiming overlapping
+//! ranges are caught at claim time.
+
+use core::marker::PhantomData;
+use x86_64::instructions::port::{PortRead, PortWrite};
+
+#[cfg(debug_assertions)]
+use alloc::vec::Vec;
+#[cfg(debug_assertions)]
+use lazy_static::lazy_static;
+#[cfg(debug_assertions)]
+use spin::Mutex;
+
+/// Register widths an x86 `in`/`out` instruction can move
+pub trait PortWidth: PortRead + PortWrite + Copy {}
+
+impl PortWidth for u8 {}
+impl PortWidth for u16 {}
+impl PortWidth for u32 {}
+
+/// Read-only I/O port
+#[derive(Debug, Clone, Copy)]
+pub struct RoPort<T: PortWidth> {
+    port: u16,
+    _width: PhantomData<T>,
+}
+
+impl<T: PortWidth> RoPort<T> {
+    pub const fn new(port: u16) -> Self {
+        Self { port, _width: PhantomData }
+    }
+
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// # Safety
+    /// Reading a device register can have side effects (e.g. acknowledging an IRQ).
+    pub unsafe fn read(&self) -> T {
+        T::read_from_port(self.port)
+    }
+}
+
+/// Write-only I/O port
+///
+/// There is no `read`; reading one is a compile error:
+///
+/// ```compile_fail,E0599
+/// use fluxgridOs::kernel::io::WoPort;
+///
+/// let port = WoPort::<u8>::new(0x80);
+/// let _ = unsafe { port.read() };
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct WoPort<T: PortWidth> {
+    port: u16,
+    _width: PhantomData<T>,
+}
+
+impl<T: PortWidth> WoPort<T> {
+    pub const fn new(port: u16) -> Self {
+        Self { port, _width: PhantomData }
+    }
+
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// # Safety
+    /// Writing a device register can change hardware state in arbitrary ways.
+    pub unsafe fn write(&self, value: T) {
+        T::write_to_port(self.port, value)
+    }
+}
+
+/// Read-write I/O port
+#[derive(Debug, Clone, Copy)]
+pub struct RwPort<T: PortWidth> {
+    port: u16,
+    _width: PhantomData<T>,
+}
+
+impl<T: PortWidth> RwPort<T> {
+    pub const fn new(port: u16) -> Self {
+        Self { port, _width: PhantomData }
+    }
+
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// # Safety
+    /// See [`RoPort::read`].
+    pub unsafe fn read(&self) -> T {
+        T::read_from_port(self.port)
+    }
+
+    /// # Safety
+    /// See [`WoPort::write`].
+    pub unsafe fn write(&self, value: T) {
+        T::write_to_port(self.port, value)
+    }
+}
+
+/// A claimed, contiguous block of I/O ports owned by one driver.
+/// The claim is released when the range is dropped.
+#[derive(Debug)]
+pub struct PortRange {
+    base: u16,
+    len: u16,
+    owner: &'static str,
+}
+
+#[cfg(debug_assertions)]
+lazy_static! {
+    /// (first port, length, owner) of every live claim
+    static ref PORT_CLAIMS: Mutex<Vec<(u16, u16, &'static str)>> = Mutex::new(Vec::new());
+}
+
+impl PortRange {
+    /// Claim `len` ports starting at `base` for `owner`.
+    ///
+    /// In debug builds this fails if any port in the range is already claimed;
+    /// release builds skip the bookkeeping.
+    pub fn claim(owner: &'static str, base: u16, len: u16) -> Result<Self, &'static str> {
+        if len == 0 || base.checked_add(len - 1).is_none() {
+            return Err("Invalid I/O port range");
+        }
+
+        #[cfg(debug_assertions)]
+        {
+            let mut claims = PORT_CLAIMS.lock();
+            let end = base as u32 + len as u32;
+            if let Some(&(other_base, other_len, other_owner)) = claims.iter().find(|&&(b, l, _)| {
+                (b as u32) < end && (base as u32) < (b as u32 + l as u32)
+            }) {
+                log::error!(
+                    "{} tried to claim I/O ports 0x{:X}-0x{:X}, overlapping 0x{:X}-0x{:X} owned by {}",
+                    owner, base, end - 1, other_base, other_base as u32 + other_len as u32 - 1, other_owner
+                );
+                return Err("I/O port range already claimed");
+            }
+            claims.push((base, len, owner));
+        }
+
+        Ok(Self { base, len, owner })
+    }
+
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    pub fn len(&self) -> u16 {
+        self.len
+    }
+
+    pub fn owner(&self) -> &'static str {
+        self.owner
+    }
+
+    fn offset(&self, offset: u16, width: u16) -> u16 {
+        assert!(offset + width <= self.len, "I/O port offset outside claimed range");
+        self.base + offset
+    }
+
+    pub fn ro<T: PortWidth>(&self, offset: u16) -> RoPort<T> {
+        RoPort::new(self.offset(offset, core::mem::size_of::<T>() as u16))
+    }
+
+    pub fn wo<T: PortWidth>(&self, offset: u16) -> WoPort<T> {
+        WoPort::new(self.offset(offset, core::mem::size_of::<T>() as u16))
+    }
+
+    pub fn rw<T: PortWidth>(&self, offset: u16) -> RwPort<T> {
+        RwPort::new(self.offset(offset, core::mem::size_of::<T>() as u16))
+    }
+}
+
+impl Drop for PortRange {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        {
+            let mut claims = PORT_CLAIMS.lock();
+            if let Some(index) = claims.iter().position(|&(b, l, _)| b == self.base && l == self.len) {
+                claims.swap_remove(index);
+            }
+        }
+    }
+}
+
+/// Owner of a claimed port range, if any. Always `None` in release builds.
+pub fn port_owner(port: u16) -> Option<&'static str> {
+    #[cfg(debug_assertions)]
+    {
+        PORT_CLAIMS
+            .lock()
+            .iter()
+            .find(|&&(b, l, _)| port >= b && (port as u32) < b as u32 + l as u32)
+            .map(|&(_, _, owner)| owner)
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        let _ = port;
+        None
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+
+    // The claim registry is global; each test uses its own ports
+
+    #[test]
+    fn overlapping_claim_is_rejected() {
+        let first = PortRange::claim("first", 0xF000, 8).unwrap();
+        assert_eq!(PortRange::claim("second", 0xF004, 8).unwrap_err(), "I/O port range already claimed");
+        assert_eq!(PortRange::claim("second", 0xF000, 8).unwrap_err(), "I/O port range already claimed");
+        assert_eq!(port_owner(0xF007), Some("first"));
+
+        // Adjacent ranges don't overlap
+        let next = PortRange::claim("second", 0xF008, 8).unwrap();
+        assert_eq!(port_owner(0xF008), Some("second"));
+        drop(next);
+        drop(first);
+    }
+
+    #[test]
+    fn dropping_a_range_releases_it() {
+        let range = PortRange::claim("first", 0xF100, 4).unwrap();
+        drop(range);
+        assert_eq!(port_owner(0xF100), None);
+        assert!(PortRange::claim("second", 0xF100, 4).is_ok());
+    }
+}
@@ -1,5 +1,7 @@
 //! Kernel heap allocator
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
 #[cfg(not(feature = "std"))]
 use linked_list_allocator::LockedHeap;
 
@@ -27,10 +29,18 @@ pub const HEAP_START: usize = 0x_4444_4444_0000; // Example: 256TiB + 1MiB regio
 #[cfg(not(feature = "std"))]
 pub const HEAP_SIZE: usize = 256 * 1024; // 256 KiB (can be grown later if needed)
 
+// Host unit tests allocate through std's allocator
 #[cfg(not(feature = "std"))]
-#[global_allocator]
+#[cfg_attr(not(test), global_allocator)]
 static ALLOCATOR: LockedHeap = LockedHeap::empty();
 
+static HEAP_READY: AtomicBool = AtomicBool::new(false);
+
+/// Whether allocation works yet; code that also runs early in boot checks this
+pub fn heap_ready() -> bool {
+    HEAP_READY.load(Ordering::Acquire)
+}
+
 /// Initializes the kernel heap.
 /// Maps the virtual memory range for the heap and initializes `ALLOCATOR`.
 /// Called by `MemoryManager::init_services`.
@@ -93,6 +103,7 @@ pub fn init_heap() -> Result<(), MapToError<Size4KiB>> {
     unsafe {
         ALLOCATOR.lock().init(HEAP_START as *mut u8, HEAP_SIZE);
     }
+    HEAP_READY.store(true, Ordering::Release);
 
     log::info!("Kernel heap initialized. Usable range: {:#x} - {:#x}", HEAP_START, HEAP_START + HEAP_SIZE);
     Ok(())
@@ -101,5 +112,6 @@ pub fn init_heap() -> Result<(), MapToError<Size4KiB>> {
 #[cfg(feature = "std")]
 pub fn init_heap() -> Result<(), MapToError<Size4KiB>> {
     log::info!("Heap initialization skipped in std/test mode.");
+    HEAP_READY.store(true, Ordering::Release);
     Ok(())
 }
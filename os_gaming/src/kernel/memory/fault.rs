@@ -0,0 +1,112 @@
+//! Page-fault classification
+//!
+//! Keeps track of virtual ranges that are expected to fault (stack guard pages,
+//! copy-on-write mappings) so the page-fault handler can tell a stack overflow
+//! or a COW write apart from a genuinely bad access.
+
+extern crate alloc;
+use alloc::vec::Vec;
+use core::fmt;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+use super::memory_manager::{self, MemoryError};
+use super::PAGE_SIZE;
+
+/// What a registered fault region is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultRegionKind {
+    /// Unmapped page below a stack; touching it means the stack overflowed
+    GuardPage,
+    /// Read-only shared mapping that gets a private copy on first write
+    CopyOnWrite,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FaultRegion {
+    start: u64,
+    end: u64,
+    kind: FaultRegionKind,
+}
+
+lazy_static! {
+    static ref FAULT_REGIONS: Mutex<Vec<FaultRegion>> = Mutex::new(Vec::new());
+}
+
+/// Decoded page-fault error code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultAccess {
+    /// The page was present (protection violation) rather than not mapped
+    pub present: bool,
+    pub write: bool,
+    pub user: bool,
+    pub instruction_fetch: bool,
+}
+
+impl FaultAccess {
+    pub fn from_error_code(code: u64) -> Self {
+        Self {
+            present: code & 0x1 != 0,
+            write: code & 0x2 != 0,
+            user: code & 0x4 != 0,
+            instruction_fetch: code & 0x10 != 0,
+        }
+    }
+}
+
+impl fmt::Display for FaultAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = if self.instruction_fetch {
+            "instruction fetch"
+        } else if self.write {
+            "write"
+        } else {
+            "read"
+        };
+        write!(
+            f,
+            "{} {}, {} mode",
+            if self.present { "protection violation on" } else { "not-present" },
+            access,
+            if self.user { "user" } else { "supervisor" }
+        )
+    }
+}
+
+/// Register a guard page; `address` may be anywhere inside the page
+pub fn register_guard_page(address: VirtAddr) {
+    let start = address.align_down(PAGE_SIZE as u64).as_u64();
+    register(start, start + PAGE_SIZE as u64, FaultRegionKind::GuardPage);
+}
+
+/// Register a copy-on-write range
+pub fn register_copy_on_write(start: VirtAddr, size: usize) {
+    register(start.as_u64(), start.as_u64() + size as u64, FaultRegionKind::CopyOnWrite);
+}
+
+/// Forget the region starting at `start`
+pub fn unregister_region(start: VirtAddr) {
+    let start = start.as_u64();
+    FAULT_REGIONS.lock().retain(|region| region.start != start);
+}
+
+fn register(start: u64, end: u64, kind: FaultRegionKind) {
+    FAULT_REGIONS.lock().push(FaultRegion { start, end, kind });
+}
+
+/// Look up which registered region, if any, contains `address`.
+/// Returns None if the registry is locked, e.g. when faulting during registration.
+pub fn region_kind(address: VirtAddr) -> Option<FaultRegionKind> {
+    let address = address.as_u64();
+    FAULT_REGIONS
+        .try_lock()?
+        .iter()
+        .find(|region| address >= region.start && address < region.end)
+        .map(|region| region.kind)
+}
+
+/// Give the faulting COW page a private, writable copy
+pub fn handle_copy_on_write_fault(address: VirtAddr) -> Result<(), MemoryError> {
+    memory_manager::resolve_copy_on_write(address)
+}
@@ -325,6 +325,44 @@ impl MemoryManager {
         Ok(())
     }
 
+    /// Gives a copy-on-write page its own writable frame, copying the shared contents.
+    /// The shared frame is left alone; its other mappings still reference it.
+    pub fn resolve_copy_on_write_internal(&mut self, virtual_address: VirtAddr) -> Result<(), MemoryError> {
+        if !CORE_MM_INITIALIZED.load(Ordering::SeqCst) { return Err(MemoryError::InvalidState); }
+
+        let page = Page::<Size4KiB>::containing_address(virtual_address);
+        let (shared_frame, flags) = match self.mapper_mut().translate(page.start_address()) {
+            TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. } => (frame, flags),
+            TranslateResult::Mapped { .. } => return Err(MemoryError::InvalidMapping),
+            _ => return Err(MemoryError::NotMapped),
+        };
+
+        let pmm = physical::get_physical_memory_manager();
+        let private_frame = PhysFrame::<Size4KiB>::containing_address(
+            pmm.allocate_phys_addr().ok_or(MemoryError::OutOfMemory)?,
+        );
+
+        let offset = get_physical_memory_offset();
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                (offset + shared_frame.start_address().as_u64()).as_ptr::<u8>(),
+                (offset + private_frame.start_address().as_u64()).as_mut_ptr::<u8>(),
+                PAGE_SIZE,
+            );
+        }
+
+        let (_, flush) = self.mapper_mut().unmap(page).map_err(|_| MemoryError::InvalidMapping)?;
+        flush.flush();
+        match unsafe { self.mapper_mut().map_to(page, private_frame, flags | PageTableFlags::WRITABLE, pmm) } {
+            Ok(flush) => { flush.flush(); Ok(()) }
+            Err(e) => {
+                log::error!("COW remap of {:?} failed: {:?}", page, e);
+                pmm.free_phys_addr(private_frame.start_address());
+                Err(MemoryError::InvalidMapping)
+            }
+        }
+    }
+
     /// Helper to create the initial `OffsetPageTable`.
     unsafe fn create_page_tables(phys_mem_offset: VirtAddr) -> Result<OffsetPageTable<'static>, &'static str> {
         // `current_page_table` needs the offset directly, as PMM might not be fully usable yet for virt_to_phys.
@@ -365,6 +403,66 @@ pub fn unmap_region(virtual_address: VirtAddr, size: usize) -> Result<(), Memory
     MEMORY_MANAGER.lock().unmap_region_internal(virtual_address, size)
 }
 
+/// Resolve a write fault on a copy-on-write page.
+/// Uses `try_lock` since this runs from the page-fault handler and must not deadlock
+/// if the fault happened while the memory manager was held.
+pub fn resolve_copy_on_write(virtual_address: VirtAddr) -> Result<(), MemoryError> {
+    MEMORY_MANAGER
+        .try_lock()
+        .ok_or(MemoryError::InvalidState)?
+        .resolve_copy_on_write_internal(virtual_address)
+}
+
+/// Physical address backing `virt` and the effective flags of its mapping,
+/// or None if it is unmapped.
+///
+/// Walks the active page tables (CR3) directly, without taking the memory
+/// manager lock, so it is usable from fault handlers. 2 MiB and 1 GiB pages
+/// resolve to the page's physical base plus the offset into it. WRITABLE and
+/// USER_ACCESSIBLE are only reported if every level allows them, and
+/// NO_EXECUTE if any level sets it, matching what the CPU enforces.
+pub fn translate(virt: VirtAddr) -> Option<(PhysAddr, PageTableFlags)> {
+    if !CORE_MM_INITIALIZED.load(Ordering::SeqCst) {
+        return None;
+    }
+    let phys_offset = get_physical_memory_offset();
+    let indices = [virt.p4_index(), virt.p3_index(), virt.p2_index(), virt.p1_index()];
+    let inherited = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+
+    let (table_frame, _) = Cr3::read();
+    let mut table_addr = table_frame.start_address();
+    // Permissions every level so far grants, and whether any level forbids execution
+    let mut granted = inherited;
+    let mut no_execute = false;
+    for (level, index) in indices.iter().enumerate() {
+        // Safety: page tables live in physical memory, all of which is mapped at the offset
+        let table = unsafe { &*(phys_offset + table_addr.as_u64()).as_ptr::<PageTable>() };
+        let entry = &table[*index];
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        granted &= flags;
+        no_execute |= flags.contains(PageTableFlags::NO_EXECUTE);
+
+        // Level 0 is PML4, which cannot map a page itself
+        let page_size: u64 = match level {
+            1 if flags.contains(PageTableFlags::HUGE_PAGE) => 1 << 30,
+            2 if flags.contains(PageTableFlags::HUGE_PAGE) => 1 << 21,
+            3 => 1 << 12,
+            _ => {
+                table_addr = entry.addr();
+                continue;
+            }
+        };
+        let mut result = (flags & !inherited) | granted;
+        result.set(PageTableFlags::NO_EXECUTE, no_execute);
+        let base = entry.addr().align_down(page_size);
+        return Some((base + (virt.as_u64() & (page_size - 1)), result));
+    }
+    None
+}
+
 /// Provides access to the physical memory offset stored during core initialization.
 pub fn get_physical_memory_offset() -> VirtAddr {
     VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed))
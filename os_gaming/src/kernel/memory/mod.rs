@@ -5,18 +5,22 @@
 
 pub mod allocator;
 pub mod dma;
+pub mod fault;
 pub mod memory_manager;
 pub mod physical;
+pub mod selftest;
 pub mod r#virtual;
 
 // Re-export important types for convenience
 pub use memory_manager::{
     MemoryError, MemoryInitError, MemoryProtection, CacheType, MemoryType, MemoryInfo, MemoryProtectionFlags,
     map_page_for_kernel, // For direct use by allocator or other low-level kernel parts
+    translate,
     // Public mapping functions are also available directly from memory_manager:
     // memory_manager::map_physical_memory, memory_manager::unmap_region
 };
 pub use physical::PAGE_SIZE;
+pub use selftest::{selftest, MemTestReport, Pattern};
 
 use bootloader::BootInfo;
 use core::sync::atomic::{AtomicBool, Ordering};
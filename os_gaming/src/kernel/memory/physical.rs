@@ -166,6 +166,20 @@ impl FrameBitmap {
         }
     }
 
+    /// Mark every frame touching `range` as used. Returns how many were free.
+    pub fn reserve_range(&mut self, range: core::ops::Range<u64>) -> usize {
+        let start_frame = (range.start / PAGE_SIZE as u64) as usize;
+        let end_frame = ((range.end + PAGE_SIZE as u64 - 1) / PAGE_SIZE as u64) as usize;
+        let mut reserved = 0;
+        for frame_idx in start_frame..end_frame.min(self.bitmap.len() * 64) {
+            if !self.get_bit(frame_idx) {
+                self.set_frame(frame_idx, true);
+                reserved += 1;
+            }
+        }
+        reserved
+    }
+
     pub fn is_frame_used(&self, frame_idx: usize) -> bool {
         if frame_idx < self.total_frames {
             self.get_bit(frame_idx)
@@ -221,6 +235,12 @@ impl PhysicalMemoryManager {
         bitmap_guard.free_frames(start_frame_idx, count); // set_frame in FrameBitmap should update counts
     }
     
+    /// Keep the frame allocator away from `range`, e.g. RAM a later memory
+    /// map marks reserved. Returns how many free frames it took.
+    pub fn reserve_range(&self, range: core::ops::Range<u64>) -> usize {
+        self.frame_bitmap.lock().reserve_range(range)
+    }
+
     pub fn total_memory(&self) -> usize { self.total_memory.load(Ordering::SeqCst) }
     pub fn free_memory(&self) -> usize { self.frame_bitmap.lock().free_frames.load(Ordering::SeqCst) * PAGE_SIZE }
     pub fn used_memory(&self) -> usize { self.total_memory() - self.free_memory() }
@@ -347,3 +367,25 @@ pub fn allocate_contiguous_dma(size: usize, alignment: usize, limit_phys_addr_op
         PhysAddr::new(start_frame_idx as u64 * PAGE_SIZE as u64)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_range_is_never_allocated() {
+        let mut bitmap = FrameBitmap::new();
+        bitmap.init_frame_allocator(core::iter::once(0..16 * PAGE_SIZE as u64), PhysAddr::new(0), PhysAddr::new(0));
+
+        // Partial frames at either end are reserved whole
+        assert_eq!(bitmap.reserve_range(4 * PAGE_SIZE as u64 + 8..8 * PAGE_SIZE as u64 - 8), 4);
+        assert_eq!(bitmap.reserve_range(4 * PAGE_SIZE as u64..8 * PAGE_SIZE as u64), 0);
+
+        let mut allocated = Vec::new();
+        while let Some(frame) = bitmap.allocate_frame() {
+            allocated.push(frame);
+        }
+        assert_eq!(allocated.len(), 12);
+        assert!(allocated.iter().all(|frame| !(4..8).contains(frame)));
+    }
+}
@@ -0,0 +1,152 @@
+//! Optional power-on RAM test
+//!
+//! Only frames the physical allocator reports as free are tested. They are
+//! taken out of the allocator for the duration of the test, so nothing else
+//! can be using them, and handed back afterwards.
+
+use alloc::{vec, vec::Vec};
+use x86_64::PhysAddr;
+
+use super::memory_manager::get_physical_memory_offset;
+use super::physical::{get_physical_memory_manager, PAGE_SIZE};
+
+/// Upper bound on memory touched by one run (16 MiB)
+pub const MEMTEST_MAX_FRAMES: usize = 4096;
+/// Failing addresses kept in the report; further failures are only counted
+const MAX_RECORDED_FAILURES: usize = 64;
+
+const WORDS_PER_FRAME: usize = PAGE_SIZE / core::mem::size_of::<u64>();
+
+/// Data patterns the test can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// All bits clear
+    Zeros,
+    /// All bits set
+    Ones,
+    /// A single set bit that moves one position per word
+    WalkingOnes,
+    /// Each word holds its own physical address
+    AddressInAddress,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 4] = [
+        Pattern::Zeros,
+        Pattern::Ones,
+        Pattern::WalkingOnes,
+        Pattern::AddressInAddress,
+    ];
+
+    /// Value expected in word `index` at physical address `phys`
+    fn value(self, index: usize, phys: u64) -> u64 {
+        match self {
+            Pattern::Zeros => 0,
+            Pattern::Ones => u64::MAX,
+            Pattern::WalkingOnes => 1 << (index % 64),
+            Pattern::AddressInAddress => phys,
+        }
+    }
+}
+
+/// Outcome of a memory test run
+#[derive(Debug, Clone, Default)]
+pub struct MemTestReport {
+    pub frames_tested: usize,
+    pub bytes_tested: u64,
+    /// Number of words that failed at least one check
+    pub failure_count: usize,
+    /// First failing word addresses, up to `MAX_RECORDED_FAILURES`
+    pub failures: Vec<PhysAddr>,
+}
+
+impl MemTestReport {
+    pub fn passed(&self) -> bool {
+        self.failure_count == 0
+    }
+
+    fn record_failure(&mut self, addr: u64) {
+        self.failure_count += 1;
+        if self.failures.len() < MAX_RECORDED_FAILURES {
+            self.failures.push(PhysAddr::new(addr));
+        }
+    }
+}
+
+/// Test up to `MEMTEST_MAX_FRAMES` free physical frames with `patterns`.
+///
+/// Must run after the frame allocator is initialized. The frames are
+/// allocated for the duration of the test and freed afterwards, so in-use
+/// memory is never touched.
+pub fn selftest(patterns: &[Pattern]) -> MemTestReport {
+    let pmm = get_physical_memory_manager();
+    let mut frames = Vec::with_capacity(MEMTEST_MAX_FRAMES);
+    while frames.len() < MEMTEST_MAX_FRAMES {
+        match pmm.allocate_phys_addr() {
+            Some(frame) => frames.push(frame),
+            None => break,
+        }
+    }
+
+    log::info!("Memory self-test: testing {} free frames ({} KiB)", frames.len(), frames.len() * PAGE_SIZE / 1024);
+
+    let offset = get_physical_memory_offset();
+    let mut report = MemTestReport::default();
+    for frame in &frames {
+        let ptr = (offset + frame.as_u64()).as_mut_ptr::<u64>();
+        // Safety: the frame was just taken from the allocator, so it is ours
+        // alone, and the physical memory window maps it
+        unsafe { test_block(ptr, WORDS_PER_FRAME, frame.as_u64(), patterns, &mut report) };
+        report.frames_tested += 1;
+        report.bytes_tested += PAGE_SIZE as u64;
+    }
+
+    for frame in frames {
+        pmm.free_phys_addr(frame);
+    }
+
+    if report.passed() {
+        log::info!("Memory self-test passed ({} bytes)", report.bytes_tested);
+    } else {
+        log::error!(
+            "Memory self-test found {} bad words, first at {:?}",
+            report.failure_count,
+            report.failures.first()
+        );
+    }
+    report
+}
+
+/// Run a march over `words` u64s at `ptr`, whose first word lives at `phys_base`:
+/// write the pattern ascending, verify it and write its complement ascending,
+/// then verify the complement descending.
+///
+/// # Safety
+/// `ptr` must be valid for reads and writes of `words` u64s that nothing else uses.
+pub unsafe fn test_block(ptr: *mut u64, words: usize, phys_base: u64, patterns: &[Pattern], report: &mut MemTestReport) {
+    let phys = |i: usize| phys_base + (i * core::mem::size_of::<u64>()) as u64;
+    // Each word is checked several times; report it once
+    let mut failed = vec![false; words];
+
+    for &pattern in patterns {
+        for i in 0..words {
+            core::ptr::write_volatile(ptr.add(i), pattern.value(i, phys(i)));
+        }
+        for i in 0..words {
+            let expected = pattern.value(i, phys(i));
+            if core::ptr::read_volatile(ptr.add(i)) != expected {
+                failed[i] = true;
+            }
+            core::ptr::write_volatile(ptr.add(i), !expected);
+        }
+        for i in (0..words).rev() {
+            if core::ptr::read_volatile(ptr.add(i)) != !pattern.value(i, phys(i)) {
+                failed[i] = true;
+            }
+        }
+    }
+
+    for (i, _) in failed.iter().enumerate().filter(|(_, &bad)| bad) {
+        report.record_failure(phys(i));
+    }
+}
@@ -4,12 +4,20 @@ pub mod memory;
 pub mod interrupts;
 pub mod drivers;
 pub mod boot;
+pub mod multiboot2;
+pub mod inventory;
+pub mod sync;
+pub mod io;
+pub mod panic;
+pub mod exit;
+pub mod crash_log;
 
 use bootloader::BootInfo;
 // Re-export important items
 pub use cpu::init as cpu_init;
 pub use memory::init as memory_init;
 pub use interrupts::init as interrupts_init;
+pub use inventory::{hardware_inventory, HardwareInventory};
 use crate::println;
 
 // Kernel initialization function
@@ -24,7 +32,7 @@ pub fn init(boot_info: &'static BootInfo) -> Result<(), &'static str> {
     interrupts::init();
     
     // Initialize driver
-    drivers::init();
+    drivers::init()?;
     
     println!("Kernel initialized successfully!");
 
@@ -35,6 +43,5 @@ pub fn init(boot_info: &'static BootInfo) -> Result<(), &'static str> {
 // Kernel panic handler
 #[cfg(not(test))]
 pub fn panic(info: &core::panic::PanicInfo) -> ! {
-    println!("Kernel panic: {}", info);
-    loop {}
+    panic::handle_panic(info)
 }
\ No newline at end of file
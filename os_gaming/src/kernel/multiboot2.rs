@@ -0,0 +1,390 @@
+//! Multiboot2 boot information
+//!
+//! Parses the information structure a Multiboot2 loader (GRUB, or a UEFI
+//! loader speaking the protocol) hands the kernel. Only what the kernel uses
+//! is kept: the command line, the memory map, and the framebuffer the loader
+//! already set up, which gives a working display before any GPU driver loads.
+//!
+//! The structure is a u32 total size and a reserved u32, followed by tags
+//! aligned to 8 bytes, each starting with a u32 type and u32 size, ending
+//! with a tag of type 0.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use bootloader::bootinfo::MemoryRegionType;
+use spin::Mutex;
+
+const TAG_END: u32 = 0;
+const TAG_CMDLINE: u32 = 1;
+const TAG_MEMORY_MAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+
+/// Memory map tag: entry size and version, then the entries
+const MEMORY_MAP_ENTRIES: usize = 16;
+
+/// `MemoryAreaType` values of memory map entries
+const MEMORY_AVAILABLE: u32 = 1;
+const MEMORY_ACPI_AVAILABLE: u32 = 3;
+const MEMORY_RESERVED_HIBERNATE: u32 = 4;
+const MEMORY_DEFECTIVE: u32 = 5;
+
+/// Framebuffer tag: common fields end at offset 31, color info follows
+const FRAMEBUFFER_COLOR_INFO: usize = 32;
+
+const FRAMEBUFFER_TYPE_INDEXED: u8 = 0;
+const FRAMEBUFFER_TYPE_RGB: u8 = 1;
+const FRAMEBUFFER_TYPE_EGA_TEXT: u8 = 2;
+
+/// Where one color channel sits in a pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorField {
+    /// Bit position of the channel's least significant bit
+    pub position: u8,
+    /// Width of the channel in bits
+    pub size: u8,
+}
+
+impl ColorField {
+    /// Scale an 8-bit channel value into this field
+    pub fn encode(&self, value: u8) -> u32 {
+        if self.size == 0 {
+            return 0;
+        }
+        let scaled = if self.size >= 8 {
+            (value as u32) << (self.size - 8)
+        } else {
+            value as u32 >> (8 - self.size)
+        };
+        scaled << self.position
+    }
+}
+
+/// How pixel values map to colors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FramebufferKind {
+    /// Each pixel is an index into this palette of (r, g, b)
+    Indexed { palette: Vec<[u8; 3]> },
+    /// Direct color with these channel layouts
+    Rgb { red: ColorField, green: ColorField, blue: ColorField },
+    /// 80x25-style text mode: width and height are in characters
+    EgaText,
+}
+
+/// The linear framebuffer the bootloader set up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootFramebuffer {
+    /// Physical address of the first pixel
+    pub address: u64,
+    /// Bytes per row
+    pub pitch: u32,
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u8,
+    pub kind: FramebufferKind,
+}
+
+impl BootFramebuffer {
+    /// Bytes covered by the visible rows
+    pub fn size(&self) -> usize {
+        self.pitch as usize * self.height as usize
+    }
+
+    /// Pixel value for a 0xAARRGGBB color. Indexed framebuffers get the
+    /// closest palette entry.
+    pub fn encode_color(&self, argb: u32) -> u32 {
+        let (r, g, b) = ((argb >> 16) as u8, (argb >> 8) as u8, argb as u8);
+        match &self.kind {
+            FramebufferKind::Rgb { red, green, blue } => red.encode(r) | green.encode(g) | blue.encode(b),
+            FramebufferKind::Indexed { palette } => {
+                let distance = |entry: &[u8; 3]| {
+                    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+                    d(entry[0], r) + d(entry[1], g) + d(entry[2], b)
+                };
+                palette
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, entry)| distance(entry))
+                    .map_or(0, |(index, _)| index as u32)
+            }
+            FramebufferKind::EgaText => argb,
+        }
+    }
+}
+
+/// One entry of the loader's memory map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryArea {
+    pub start: u64,
+    pub length: u64,
+    pub region_type: MemoryRegionType,
+}
+
+/// Classify a memory map entry. Only `Available` RAM may be handed to the
+/// frame allocator; anything unknown is treated as reserved.
+pub fn region_type(area_type: u32) -> MemoryRegionType {
+    match area_type {
+        MEMORY_AVAILABLE => MemoryRegionType::Usable,
+        MEMORY_ACPI_AVAILABLE => MemoryRegionType::AcpiReclaimable,
+        MEMORY_RESERVED_HIBERNATE => MemoryRegionType::AcpiNvs,
+        MEMORY_DEFECTIVE => MemoryRegionType::BadMemory,
+        // Reserved (2) and types newer than this code
+        _ => MemoryRegionType::Reserved,
+    }
+}
+
+/// What the kernel keeps from the Multiboot2 information
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CustomBootInfo {
+    pub cmdline: Option<String>,
+    pub memory_map: Vec<MemoryArea>,
+    pub framebuffer: Option<BootFramebuffer>,
+}
+
+impl CustomBootInfo {
+    /// Bytes of RAM the memory map marks usable
+    pub fn usable_memory(&self) -> u64 {
+        self.memory_map
+            .iter()
+            .filter(|area| area.region_type == MemoryRegionType::Usable)
+            .map(|area| area.length)
+            .sum()
+    }
+
+    /// Physical ranges the memory map says the kernel must not allocate from
+    pub fn unusable_ranges(&self) -> impl Iterator<Item = core::ops::Range<u64>> + '_ {
+        self.memory_map
+            .iter()
+            .filter(|area| area.region_type != MemoryRegionType::Usable)
+            .map(|area| area.start..area.start.saturating_add(area.length))
+    }
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Option<u8> {
+    data.get(offset).copied()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(read_u32(data, offset)? as u64 | (read_u32(data, offset + 4)? as u64) << 32)
+}
+
+/// Parse a Multiboot2 information structure
+pub fn parse(info: &[u8]) -> Result<CustomBootInfo, &'static str> {
+    let total_size = read_u32(info, 0).ok_or("Multiboot2 info truncated")? as usize;
+    let info = info.get(..total_size).ok_or("Multiboot2 info truncated")?;
+
+    let mut boot_info = CustomBootInfo::default();
+    let mut offset = 8;
+    loop {
+        let tag_type = read_u32(info, offset).ok_or("Multiboot2 info has no end tag")?;
+        let size = read_u32(info, offset + 4).ok_or("Multiboot2 info has no end tag")? as usize;
+        if tag_type == TAG_END {
+            break;
+        }
+        if size < 8 {
+            return Err("Malformed Multiboot2 tag");
+        }
+        let tag = info.get(offset..offset + size).ok_or("Multiboot2 tag overruns the info")?;
+        match tag_type {
+            TAG_CMDLINE => {
+                let text = &tag[8..];
+                let text = &text[..text.iter().position(|&b| b == 0).unwrap_or(text.len())];
+                boot_info.cmdline = core::str::from_utf8(text).ok().map(String::from);
+            }
+            TAG_MEMORY_MAP => {
+                boot_info.memory_map = parse_memory_map(tag).ok_or("Malformed Multiboot2 memory map tag")?;
+            }
+            TAG_FRAMEBUFFER => {
+                boot_info.framebuffer = Some(parse_framebuffer(tag).ok_or("Malformed Multiboot2 framebuffer tag")?);
+            }
+            _ => {}
+        }
+        // Tags are 8-byte aligned
+        offset += (size + 7) & !7;
+    }
+    Ok(boot_info)
+}
+
+fn parse_memory_map(tag: &[u8]) -> Option<Vec<MemoryArea>> {
+    // Entries may grow in later versions; only the first 20 bytes are known
+    let entry_size = read_u32(tag, 8)? as usize;
+    if entry_size < 20 {
+        return None;
+    }
+    tag.get(MEMORY_MAP_ENTRIES..)?
+        .chunks_exact(entry_size)
+        .map(|entry| {
+            Some(MemoryArea {
+                start: read_u64(entry, 0)?,
+                length: read_u64(entry, 8)?,
+                region_type: region_type(read_u32(entry, 16)?),
+            })
+        })
+        .collect()
+}
+
+fn parse_framebuffer(tag: &[u8]) -> Option<BootFramebuffer> {
+    let kind = match read_u8(tag, 29)? {
+        FRAMEBUFFER_TYPE_INDEXED => {
+            let colors = read_u16(tag, FRAMEBUFFER_COLOR_INFO)? as usize;
+            let start = FRAMEBUFFER_COLOR_INFO + 2;
+            let palette = tag
+                .get(start..start + colors * 3)?
+                .chunks_exact(3)
+                .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+                .collect();
+            FramebufferKind::Indexed { palette }
+        }
+        FRAMEBUFFER_TYPE_RGB => {
+            let field = |i: usize| {
+                Some(ColorField {
+                    position: read_u8(tag, FRAMEBUFFER_COLOR_INFO + i * 2)?,
+                    size: read_u8(tag, FRAMEBUFFER_COLOR_INFO + i * 2 + 1)?,
+                })
+            };
+            FramebufferKind::Rgb { red: field(0)?, green: field(1)?, blue: field(2)? }
+        }
+        FRAMEBUFFER_TYPE_EGA_TEXT => FramebufferKind::EgaText,
+        _ => return None,
+    };
+    Some(BootFramebuffer {
+        address: read_u64(tag, 8)?,
+        pitch: read_u32(tag, 16)?,
+        width: read_u32(tag, 20)?,
+        height: read_u32(tag, 24)?,
+        bpp: read_u8(tag, 28)?,
+        kind,
+    })
+}
+
+/// Parse the structure the loader left at `address`
+///
+/// # Safety
+/// `address` must be the Multiboot2 information pointer passed in EBX, still
+/// mapped and not yet reused.
+pub unsafe fn parse_at(address: usize) -> Result<CustomBootInfo, &'static str> {
+    if address == 0 || address % 8 != 0 {
+        return Err("Invalid Multiboot2 info address");
+    }
+    let total_size = core::ptr::read_unaligned(address as *const u32) as usize;
+    parse(core::slice::from_raw_parts(address as *const u8, total_size))
+}
+
+static BOOT_INFO: Mutex<Option<CustomBootInfo>> = Mutex::new(None);
+
+/// Keep the parsed information for later subsystems
+pub fn set_boot_info(info: CustomBootInfo) {
+    *BOOT_INFO.lock() = Some(info);
+}
+
+pub fn boot_info() -> Option<CustomBootInfo> {
+    BOOT_INFO.lock().clone()
+}
+
+/// Whether `framebuffer` is a pixel surface rather than EGA text
+pub fn is_graphics(framebuffer: &BootFramebuffer) -> bool {
+    framebuffer.kind != FramebufferKind::EgaText
+}
+
+/// The loader's framebuffer, if it set one up in a graphics mode
+pub fn boot_framebuffer() -> Option<BootFramebuffer> {
+    BOOT_INFO
+        .lock()
+        .as_ref()
+        .and_then(|info| info.framebuffer.clone())
+        .filter(is_graphics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Information structure holding `tags`, each padded to 8 bytes, plus the end tag
+    fn info_with(tags: &[Vec<u8>]) -> Vec<u8> {
+        let mut info = vec![0u8; 8];
+        for tag in tags {
+            info.extend_from_slice(tag);
+            info.resize((info.len() + 7) & !7, 0);
+        }
+        info.extend_from_slice(&TAG_END.to_le_bytes());
+        info.extend_from_slice(&8u32.to_le_bytes());
+        let total = info.len() as u32;
+        info[0..4].copy_from_slice(&total.to_le_bytes());
+        info
+    }
+
+    fn memory_map_tag(entries: &[(u64, u64, u32)]) -> Vec<u8> {
+        let mut tag = Vec::new();
+        tag.extend_from_slice(&TAG_MEMORY_MAP.to_le_bytes());
+        tag.extend_from_slice(&((MEMORY_MAP_ENTRIES + entries.len() * 24) as u32).to_le_bytes());
+        tag.extend_from_slice(&24u32.to_le_bytes());
+        tag.extend_from_slice(&0u32.to_le_bytes());
+        for &(start, length, area_type) in entries {
+            tag.extend_from_slice(&start.to_le_bytes());
+            tag.extend_from_slice(&length.to_le_bytes());
+            tag.extend_from_slice(&area_type.to_le_bytes());
+            tag.extend_from_slice(&0u32.to_le_bytes());
+        }
+        tag
+    }
+
+    fn framebuffer_tag(framebuffer_type: u8, color_info: &[u8]) -> Vec<u8> {
+        let mut tag = Vec::new();
+        tag.extend_from_slice(&TAG_FRAMEBUFFER.to_le_bytes());
+        tag.extend_from_slice(&((FRAMEBUFFER_COLOR_INFO + color_info.len()) as u32).to_le_bytes());
+        tag.extend_from_slice(&0xFD00_0000u64.to_le_bytes());
+        tag.extend_from_slice(&(1024u32 * 4).to_le_bytes());
+        tag.extend_from_slice(&1024u32.to_le_bytes());
+        tag.extend_from_slice(&768u32.to_le_bytes());
+        tag.push(32);
+        tag.push(framebuffer_type);
+        tag.extend_from_slice(&[0, 0]);
+        tag.extend_from_slice(color_info);
+        tag
+    }
+
+    // One test: the parsed information is a global
+    #[test]
+    fn framebuffer_tag_is_used_and_text_mode_falls_back_to_vga() {
+        let info = info_with(&[framebuffer_tag(FRAMEBUFFER_TYPE_RGB, &[16, 8, 8, 8, 0, 8])]);
+        let boot_info = parse(&info).unwrap();
+        let framebuffer = boot_info.framebuffer.clone().unwrap();
+        assert_eq!((framebuffer.address, framebuffer.pitch), (0xFD00_0000, 4096));
+        assert_eq!((framebuffer.width, framebuffer.height, framebuffer.bpp), (1024, 768, 32));
+        assert_eq!(framebuffer.encode_color(0xFF12_3456), 0x12_3456);
+        set_boot_info(boot_info);
+        assert_eq!(boot_framebuffer(), Some(framebuffer));
+
+        // No framebuffer tag: nothing for the GPU layer, so the console stays in VGA text mode
+        let boot_info = parse(&info_with(&[])).unwrap();
+        assert_eq!(boot_info.framebuffer, None);
+        set_boot_info(boot_info);
+        assert_eq!(boot_framebuffer(), None);
+
+        // An EGA text framebuffer is not a pixel surface either
+        set_boot_info(parse(&info_with(&[framebuffer_tag(FRAMEBUFFER_TYPE_EGA_TEXT, &[])])).unwrap());
+        assert_eq!(boot_framebuffer(), None);
+    }
+
+    #[test]
+    fn reserved_hole_is_not_usable() {
+        let info = info_with(&[memory_map_tag(&[
+            (0x0, 0x9F000, MEMORY_AVAILABLE),
+            (0x9F000, 0x61000, 2),
+            (0x100000, 0x700000, MEMORY_AVAILABLE),
+        ])]);
+        let boot_info = parse(&info).unwrap();
+
+        let types: Vec<_> = boot_info.memory_map.iter().map(|area| area.region_type).collect();
+        assert_eq!(types, [MemoryRegionType::Usable, MemoryRegionType::Reserved, MemoryRegionType::Usable]);
+        assert_eq!(boot_info.usable_memory(), 0x9F000 + 0x700000);
+        assert!(boot_info.unusable_ranges().eq(core::iter::once(0x9F000..0x100000)));
+    }
+}
@@ -0,0 +1,336 @@
+//! Kernel panic handling
+//!
+//! What happens after a panic is chosen by a `PanicPolicy`: halt (the
+//! default), reboot after showing the message, or drop into a small serial
+//! debug console. The heap and most locks may be unusable by the time a
+//! panic is handled, so the policy is a plain atomic and everything here
+//! writes to the hardware directly without allocating.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::kernel::drivers::{power, timer, vga};
+use crate::kernel::crash_log;
+use crate::kernel::exit::{self, ExitReason};
+use crate::kernel::io::{RoPort, WoPort};
+
+/// How long the message stays on screen before a `Reboot` policy resets
+const REBOOT_DELAY_MS: u64 = 5000;
+/// Longest command line the debug console accepts
+const CONSOLE_LINE_MAX: usize = 80;
+/// Largest dump `mem` prints in one go
+const MEM_DUMP_MAX: usize = 256;
+
+// COM1, already initialized by the logger
+const COM1_DATA: WoPort<u8> = WoPort::new(0x3F8);
+const COM1_RECEIVE: RoPort<u8> = RoPort::new(0x3F8);
+const COM1_LINE_STATUS: RoPort<u8> = RoPort::new(0x3FD);
+const LSR_DATA_READY: u8 = 0x01;
+const LSR_TRANSMIT_EMPTY: u8 = 0x20;
+
+/// What the panic handler does once the message is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PanicPolicy {
+    /// Stop the CPU
+    Halt = 0,
+    /// Show the message for a few seconds, then reset the machine
+    Reboot = 1,
+    /// Open a debug console on the serial port
+    Console = 2,
+}
+
+impl PanicPolicy {
+    /// Parse the config/cmdline spelling: `halt`, `reboot` or `console`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "halt" => Some(PanicPolicy::Halt),
+            "reboot" => Some(PanicPolicy::Reboot),
+            "console" => Some(PanicPolicy::Console),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PanicPolicy::Halt => "halt",
+            PanicPolicy::Reboot => "reboot",
+            PanicPolicy::Console => "console",
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => PanicPolicy::Reboot,
+            2 => PanicPolicy::Console,
+            _ => PanicPolicy::Halt,
+        }
+    }
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(PanicPolicy::Halt as u8);
+/// Set once `panic=` was given on the command line, which then wins over the config file
+static POLICY_FROM_CMDLINE: AtomicBool = AtomicBool::new(false);
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+pub fn policy() -> PanicPolicy {
+    PanicPolicy::from_u8(POLICY.load(Ordering::SeqCst))
+}
+
+pub fn set_policy(policy: PanicPolicy) {
+    POLICY.store(policy as u8, Ordering::SeqCst);
+}
+
+/// Apply a `panic=<policy>` command line option
+pub fn set_policy_from_cmdline(policy: PanicPolicy) {
+    set_policy(policy);
+    POLICY_FROM_CMDLINE.store(true, Ordering::SeqCst);
+}
+
+/// Apply the policy named in the config file, unless the command line already chose one
+pub fn apply_config_policy(name: &str) {
+    if POLICY_FROM_CMDLINE.load(Ordering::SeqCst) {
+        return;
+    }
+    match PanicPolicy::from_name(name) {
+        Some(policy) => set_policy(policy),
+        None if name.is_empty() => {}
+        None => log::warn!("Unknown panic policy '{}' in config, keeping {}", name, policy().name()),
+    }
+}
+
+/// Control registers and stack state at the time of the panic
+#[derive(Debug, Clone, Copy)]
+struct PanicRegisters {
+    rsp: u64,
+    rbp: u64,
+    rflags: u64,
+    cr0: u64,
+    cr2: u64,
+    cr3: u64,
+    cr4: u64,
+}
+
+impl PanicRegisters {
+    fn capture() -> Self {
+        let (rsp, rbp): (u64, u64);
+        // Safety: reading the stack registers has no side effects
+        unsafe {
+            core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+        }
+        use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+        Self {
+            rsp,
+            rbp,
+            rflags: x86_64::registers::rflags::read_raw(),
+            cr0: Cr0::read_raw(),
+            cr2: Cr2::read_raw(),
+            cr3: Cr3::read_raw().0.start_address().as_u64(),
+            cr4: Cr4::read_raw(),
+        }
+    }
+}
+
+/// Writes straight to COM1, bypassing the logger's lock
+struct RawSerial;
+
+impl RawSerial {
+    fn write_byte(&mut self, byte: u8) {
+        // Safety: COM1 is only polled and written, never reconfigured here
+        unsafe {
+            while COM1_LINE_STATUS.read() & LSR_TRANSMIT_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            COM1_DATA.write(byte);
+        }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        // Safety: as above
+        unsafe {
+            while COM1_LINE_STATUS.read() & LSR_DATA_READY == 0 {
+                core::hint::spin_loop();
+            }
+            COM1_RECEIVE.read()
+        }
+    }
+}
+
+impl fmt::Write for RawSerial {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Report the panic and carry out the configured policy
+pub fn handle_panic(info: &PanicInfo) -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    // A panic while handling a panic: don't risk anything else
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        halt();
+    }
+
+    let registers = PanicRegisters::capture();
+    let policy = policy();
+    let mut serial = RawSerial;
+    let _ = writeln!(serial, "\nKernel panic: {}", info);
+    let _ = writeln!(serial, "Panic policy: {}", policy.name());
+
+    // The panicking code may have held the screen lock; nothing else will run now
+    if vga::WRITER.is_locked() {
+        // Safety: interrupts are off and this is the only code still running
+        unsafe { vga::WRITER.force_unlock() };
+    }
+    crate::println!("Kernel panic: {}", info);
+
+    match crash_log::save_panic(info, &registers) {
+        Ok(()) => {
+            let _ = writeln!(serial, "Crash record saved");
+        }
+        Err(e) => {
+            let _ = writeln!(serial, "Crash record not saved: {}", e);
+        }
+    }
+
+    match policy {
+        PanicPolicy::Halt => {
+            exit::report_to_qemu(ExitReason::PanicHalt);
+            halt()
+        }
+        PanicPolicy::Reboot => {
+            crate::println!("Rebooting in {} seconds...", REBOOT_DELAY_MS / 1000);
+            spin_delay_ms(REBOOT_DELAY_MS);
+            power::emergency_reboot()
+        }
+        PanicPolicy::Console => {
+            crate::println!("Debug console open on the serial port");
+            debug_console(&mut serial, &registers)
+        }
+    }
+}
+
+fn halt() -> ! {
+    loop {
+        x86_64::instructions::interrupts::disable();
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Busy-wait without relying on timer interrupts, which are off
+fn spin_delay_ms(ms: u64) {
+    let mhz = timer::get_cpu_mhz();
+    if mhz == 0 {
+        // Unknown TSC rate; a rough loop is good enough to keep the message up
+        for _ in 0..ms * 100_000 {
+            core::hint::spin_loop();
+        }
+        return;
+    }
+    // Safety: rdtsc has no side effects
+    let start = unsafe { core::arch::x86_64::_rdtsc() };
+    let cycles = ms * mhz * 1000;
+    while unsafe { core::arch::x86_64::_rdtsc() }.wrapping_sub(start) < cycles {
+        core::hint::spin_loop();
+    }
+}
+
+/// Minimal read-only monitor on COM1
+fn debug_console(serial: &mut RawSerial, registers: &PanicRegisters) -> ! {
+    let _ = writeln!(serial, "Debug console. Type 'help' for commands.");
+    let mut line = [0u8; CONSOLE_LINE_MAX];
+
+    loop {
+        let _ = write!(serial, "panic> ");
+        let len = read_line(serial, &mut line);
+        let command = core::str::from_utf8(&line[..len]).unwrap_or("");
+        let mut args = command.split_whitespace();
+
+        match args.next() {
+            None => {}
+            Some("help") => {
+                let _ = writeln!(serial, "  regs                 show registers captured at the panic");
+                let _ = writeln!(serial, "  mem <hex addr> [len] dump up to {} bytes of memory", MEM_DUMP_MAX);
+                let _ = writeln!(serial, "  continue             leave the console and idle with interrupts on");
+                let _ = writeln!(serial, "  reboot               reset the machine");
+                let _ = writeln!(serial, "  halt                 stop the CPU");
+            }
+            Some("regs") => {
+                let r = registers;
+                let _ = writeln!(serial, "  RSP={:#018x} RBP={:#018x} RFLAGS={:#018x}", r.rsp, r.rbp, r.rflags);
+                let _ = writeln!(serial, "  CR0={:#018x} CR2={:#018x}", r.cr0, r.cr2);
+                let _ = writeln!(serial, "  CR3={:#018x} CR4={:#018x}", r.cr3, r.cr4);
+            }
+            Some("mem") => dump_memory(serial, args.next(), args.next()),
+            Some("continue") => {
+                let _ = writeln!(serial, "Leaving console; the system stays stopped but interrupts are enabled");
+                loop {
+                    x86_64::instructions::interrupts::enable_and_hlt();
+                }
+            }
+            Some("reboot") => power::emergency_reboot(),
+            Some("halt") => halt(),
+            Some(other) => {
+                let _ = writeln!(serial, "Unknown command '{}'", other);
+            }
+        }
+    }
+}
+
+/// Read one line with basic backspace handling; returns its length
+fn read_line(serial: &mut RawSerial, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        match serial.read_byte() {
+            b'\r' | b'\n' => {
+                let _ = serial.write_str("\n");
+                return len;
+            }
+            0x08 | 0x7F if len > 0 => {
+                len -= 1;
+                let _ = serial.write_str("\x08 \x08");
+            }
+            byte @ 0x20..=0x7E if len < buf.len() => {
+                buf[len] = byte;
+                len += 1;
+                serial.write_byte(byte);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn dump_memory(serial: &mut RawSerial, addr: Option<&str>, len: Option<&str>) {
+    let parse_hex = |s: &str| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok();
+    let addr = match addr.and_then(parse_hex) {
+        Some(addr) => addr,
+        None => {
+            let _ = writeln!(serial, "usage: mem <hex addr> [len]");
+            return;
+        }
+    };
+    let len = len.and_then(|s| s.parse::<usize>().ok()).unwrap_or(64).min(MEM_DUMP_MAX);
+    if x86_64::VirtAddr::try_new(addr).is_err() || addr.checked_add(len as u64).is_none() {
+        let _ = writeln!(serial, "Address {:#x} is not canonical", addr);
+        return;
+    }
+
+    // An unmapped address faults here, and a fault now ends in `halt`
+    for row in (0..len).step_by(16) {
+        let _ = write!(serial, "{:#018x}:", addr + row as u64);
+        for offset in row..(row + 16).min(len) {
+            // Safety: the operator asked for this address; see above
+            let byte = unsafe { core::ptr::read_volatile((addr + offset as u64) as *const u8) };
+            let _ = write!(serial, " {:02x}", byte);
+        }
+        let _ = writeln!(serial);
+    }
+}
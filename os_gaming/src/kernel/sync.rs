@@ -0,0 +1,267 @@
+//! Locking primitives with debug-build deadlock detection, and a write-once
+//! cell for globals set up during boot
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU8, Ordering};
+use spin::{Mutex, MutexGuard};
+
+#[cfg(debug_assertions)]
+use core::panic::Location;
+#[cfg(debug_assertions)]
+use core::sync::atomic::{AtomicPtr, AtomicU32};
+
+#[cfg(debug_assertions)]
+const NO_OWNER: u32 = u32::MAX;
+
+/// A named `spin::Mutex` that, in debug builds, panics when the core that
+/// already holds it tries to lock it again instead of spinning forever.
+/// In release builds it is a plain `spin::Mutex`.
+pub struct DebugMutex<T> {
+    name: &'static str,
+    inner: Mutex<T>,
+    /// APIC id of the core holding the lock
+    #[cfg(debug_assertions)]
+    owner: AtomicU32,
+    /// Where the current holder acquired the lock
+    #[cfg(debug_assertions)]
+    acquired_at: AtomicPtr<Location<'static>>,
+}
+
+pub struct DebugMutexGuard<'a, T> {
+    guard: MutexGuard<'a, T>,
+    #[cfg(debug_assertions)]
+    owner: &'a AtomicU32,
+}
+
+impl<T> DebugMutex<T> {
+    pub const fn new(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            inner: Mutex::new(value),
+            #[cfg(debug_assertions)]
+            owner: AtomicU32::new(NO_OWNER),
+            #[cfg(debug_assertions)]
+            acquired_at: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> DebugMutexGuard<'_, T> {
+        #[cfg(debug_assertions)]
+        self.check_reentry(current_cpu_id());
+
+        let guard = self.inner.lock();
+        self.guard(guard)
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<DebugMutexGuard<'_, T>> {
+        let guard = self.inner.try_lock()?;
+        Some(self.guard(guard))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[track_caller]
+    fn guard<'a>(&'a self, guard: MutexGuard<'a, T>) -> DebugMutexGuard<'a, T> {
+        #[cfg(debug_assertions)]
+        {
+            self.owner.store(current_cpu_id(), Ordering::Release);
+            let location = Location::caller() as *const Location<'static> as *mut Location<'static>;
+            self.acquired_at.store(location, Ordering::Release);
+        }
+
+        DebugMutexGuard {
+            guard,
+            #[cfg(debug_assertions)]
+            owner: &self.owner,
+        }
+    }
+
+    /// Panic if `cpu` already holds this lock
+    #[cfg(debug_assertions)]
+    #[track_caller]
+    fn check_reentry(&self, cpu: u32) {
+        if self.owner.load(Ordering::Acquire) != cpu {
+            return;
+        }
+
+        let first = self.acquired_at.load(Ordering::Acquire);
+        match unsafe { first.as_ref() } {
+            Some(first) => panic!(
+                "reentrant lock: '{}' re-acquired on CPU {} at {}, already held since {}",
+                self.name,
+                cpu,
+                Location::caller(),
+                first
+            ),
+            None => panic!(
+                "reentrant lock: '{}' re-acquired on CPU {} at {}",
+                self.name,
+                cpu,
+                Location::caller()
+            ),
+        }
+    }
+}
+
+impl<T> Deref for DebugMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for DebugMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for DebugMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Cleared before the inner guard releases the lock
+        #[cfg(debug_assertions)]
+        self.owner.store(NO_OWNER, Ordering::Release);
+    }
+}
+
+/// Initial local APIC id of the executing core
+#[cfg(debug_assertions)]
+fn current_cpu_id() -> u32 {
+    raw_cpuid::CpuId::new()
+        .get_feature_info()
+        .map_or(0, |info| info.initial_local_apic_id() as u32)
+}
+
+const ONCE_EMPTY: u8 = 0;
+const ONCE_WRITING: u8 = 1;
+const ONCE_READY: u8 = 2;
+
+/// A value set at most once, then read from any core without locking.
+/// When several cores race in `get_or_init`, one runs the initializer and
+/// the others spin until it is done; an initializer that panics leaves them
+/// spinning.
+pub struct OnceCell<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// Readers on other cores get `&T`; the value may also be set from any core
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(ONCE_EMPTY),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// The value, once it has been completely written
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == ONCE_READY {
+            // Safety: READY is only stored after the value is written
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Store `value`, or hand it back if the cell is already set or being set
+    pub fn set(&self, value: T) -> Result<(), T> {
+        if !self.claim() {
+            return Err(value);
+        }
+        self.publish(value);
+        Ok(())
+    }
+
+    pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+        if self.claim() {
+            self.publish(init());
+        } else {
+            while self.state.load(Ordering::Acquire) != ONCE_READY {
+                core::hint::spin_loop();
+            }
+        }
+        // Safety: published above or by the core that claimed the cell
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    /// Take the right to write the value
+    fn claim(&self) -> bool {
+        self.state
+            .compare_exchange(ONCE_EMPTY, ONCE_WRITING, Ordering::Acquire, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Write the value after a successful `claim`
+    fn publish(&self, value: T) {
+        // Safety: the claim makes this the only writer, and no reader looks
+        // at the value before READY
+        unsafe { (*self.value.get()).write(value) };
+        self.state.store(ONCE_READY, Ordering::Release);
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == ONCE_READY {
+            // Safety: READY means the value was written and not yet dropped
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+
+    #[test]
+    fn second_set_is_refused_and_keeps_the_first_value() {
+        let cell = OnceCell::new();
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.set(1), Ok(()));
+        assert_eq!(cell.set(2), Err(2));
+        assert_eq!(cell.get(), Some(&1));
+    }
+
+    #[test]
+    fn get_or_init_skips_the_initializer_once_set() {
+        let cell = OnceCell::new();
+        assert_eq!(*cell.get_or_init(|| 1), 1);
+        assert_eq!(*cell.get_or_init(|| panic!("initializer ran twice")), 1);
+        assert_eq!(cell.set(3), Err(3));
+    }
+
+    #[test]
+    fn dropping_the_cell_drops_the_value_once() {
+        let value = Rc::new(());
+        let cell = OnceCell::new();
+        assert!(cell.set(Rc::clone(&value)).is_ok());
+        assert_eq!(Rc::strong_count(&value), 2);
+        drop(cell);
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        // An empty cell has nothing to drop
+        drop(OnceCell::<Rc<()>>::new());
+    }
+}
+
@@ -1,8 +1,7 @@
-#![cfg_attr(not(feature = "std"), no_std)] // Garder pour no_std
+// Unit tests build against the host's std so they can run with `cargo test --lib`
+#![cfg_attr(not(any(test, feature = "std")), no_std)] // Garder pour no_std
 // #![cfg_attr(not(feature = "std"), no_main)] // Supprimer, entry_point! s'en charge
 #![feature(abi_x86_interrupt)]
-#![no_std]
-#![cfg_attr(test, no_main)]
 #![allow(warnings)]
 
 // Imports conditionnels pour std
@@ -34,6 +33,8 @@ use log::{info, error}; // Garder les imports log
 
 // Déclaration des modules locaux (supprimer la version conditionnelle de gui si non nécessaire)
 pub mod config;
+pub mod events;
+pub mod runtime;
 pub mod kernel;
 pub mod gui;
 pub mod system;
@@ -89,19 +90,34 @@ impl Default for Config {
     }
 }
 
+/// Entry point used by the `bootloader` crate. That loader boots the kernel
+/// itself and never produces Multiboot2 information, so there is no pointer
+/// to pass on: boot falls back to VGA text mode until a GPU driver loads.
 #[cfg(not(feature = "std"))]
 pub fn kernel_main(boot_info: &'static BootInfo) -> ! {
-    // Initialize logger
-    logger::init().expect("Logger initialization failed");
+    kernel_entry_from_lib(boot_info, None)
+}
+
+/// Boot the kernel. `multiboot_info` is the physical address of the
+/// Multiboot2 information (EBX from GRUB) when the loader provided one; its
+/// framebuffer tag gives the GPU layer a surface before any driver loads.
+/// Entry stubs for Multiboot2 loaders call this with EBX instead of going
+/// through `kernel_main`.
+#[cfg(not(feature = "std"))]
+pub fn kernel_entry_from_lib(boot_info: &'static BootInfo, multiboot_info: Option<u64>) -> ! {
+    // Initialize logger; only fails if one is already installed, which keeps working
+    if let Err(e) = logger::init() {
+        crate::serial_println!("Logger already installed: {}", e);
+    }
     info!("Starting OS Gaming...");
 
     // Initialize kernel
     info!("Init Kernel...");
-    match init_kernel(boot_info) {
+    match init_kernel(boot_info, multiboot_info) {
         Ok(_) => info!("Kernel successfully initialized"),
         Err(e) => {
             error!("Error when Kernel initialize: {:?}", e);
-            hcf();
+            kernel::exit::exit(kernel::exit::ExitReason::InitFailed("kernel"));
         }
     }
 
@@ -111,18 +127,17 @@ pub fn kernel_main(boot_info: &'static BootInfo) -> ! {
         Ok(_) => info!("GUI successfully initialized"),
         Err(e) => {
             error!("Error when GUI initialize: {:?}", e);
-            hcf();
+            kernel::exit::exit(kernel::exit::ExitReason::InitFailed("gui"));
         }
     }
 
-    loop {
-        x86_64::instructions::hlt();
-    }
+    // The GUI only returns once the user quits
+    kernel::exit::exit(kernel::exit::ExitReason::Shutdown)
 }
 
 
-fn init_kernel(boot_info: &'static BootInfo) -> Result<(), &'static str> {
-    kernel::boot::init(boot_info)?;
+fn init_kernel(boot_info: &'static BootInfo, multiboot_info: Option<u64>) -> Result<(), &'static str> {
+    kernel::boot::init(boot_info, multiboot_info)?;
     Ok(())
 }
 
@@ -142,5 +157,5 @@ pub fn hcf() -> ! {
 
 
 
-#[cfg(not(feature = "bootloader-custom-config"))]
+#[cfg(not(any(test, feature = "bootloader-custom-config")))]
 entry_point!(kernel_main);
\ No newline at end of file
@@ -1,12 +1,20 @@
-use core::fmt::Write;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use uart_16550::SerialPort;
-use log::{Record, Level, Metadata, LevelFilter, SetLoggerError};
+use log::{Log, Record, Level, Metadata, LevelFilter, SetLoggerError};
+
+/// Base I/O port of COM1, the port QEMU's `-serial` option connects by default
+pub const COM1: u16 = 0x3F8;
 
 lazy_static! {
     pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
+        let mut serial_port = unsafe { SerialPort::new(COM1) };
         serial_port.init();
         Mutex::new(serial_port)
     };
@@ -33,25 +41,213 @@ macro_rules! serial_println {
         concat!($fmt, "\n"), $($arg)*));
 }
 
-pub struct SerialLogger;
+/// `fn() -> u64` giving microseconds since boot, null until the timer is up
+static TIMESTAMP_SOURCE: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Stamp every record with `source`, which must be lock-free since records
+/// are logged from interrupt context
+pub fn set_timestamp_source(source: fn() -> u64) {
+    TIMESTAMP_SOURCE.store(source as *mut (), Ordering::Release);
+}
+
+/// Microseconds since boot for a record being logged now, if a source is installed
+pub fn timestamp() -> Option<u64> {
+    let source = TIMESTAMP_SOURCE.load(Ordering::Acquire);
+    if source.is_null() {
+        return None;
+    }
+    // Safety: only `set_timestamp_source` stores here, always a `fn() -> u64`
+    let source: fn() -> u64 = unsafe { core::mem::transmute(source) };
+    Some(source())
+}
+
+/// Formats as `[seconds.micros] `, or nothing before the timer is up
+struct Timestamp(Option<u64>);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(us) => write!(f, "[{:>5}.{:06}] ", us / 1_000_000, us % 1_000_000),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes log records to a 16550 UART
+pub struct SerialLogger {
+    base: u16,
+    /// Opened on first use, unless `base` is COM1, which shares `SERIAL1`
+    port: Mutex<Option<SerialPort>>,
+    /// `LevelFilter` for targets without an override
+    level: AtomicUsize,
+    /// Set once `targets` has entries, so `enabled` skips the map otherwise
+    has_overrides: AtomicBool,
+    /// Per-target levels; a target also covers its `target::` submodules
+    targets: Mutex<BTreeMap<String, LevelFilter>>,
+}
+
+impl SerialLogger {
+    /// Logger for the UART at I/O port `base`, e.g. `COM1`
+    pub const fn new(base: u16) -> Self {
+        Self {
+            base,
+            port: Mutex::new(None),
+            level: AtomicUsize::new(LevelFilter::Info as usize),
+            has_overrides: AtomicBool::new(false),
+            targets: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Level for targets without an override
+    pub fn set_level(&self, level: LevelFilter) {
+        self.level.store(level as usize, Ordering::Relaxed);
+        self.update_max_level();
+    }
+
+    /// Log `target` and its submodules at `level` whatever the global level,
+    /// e.g. `os_gaming::kernel::drivers::gpu` at `Trace`
+    pub fn set_target_level(&self, target: &str, level: Level) {
+        self.targets.lock().insert(String::from(target), level.to_level_filter());
+        self.has_overrides.store(true, Ordering::Relaxed);
+        self.update_max_level();
+    }
+
+    fn global_level(&self) -> LevelFilter {
+        match self.level.load(Ordering::Relaxed) {
+            0 => LevelFilter::Off,
+            1 => LevelFilter::Error,
+            2 => LevelFilter::Warn,
+            3 => LevelFilter::Info,
+            4 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+
+    /// Level of the longest override matching `target`, else the global level
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let global = self.global_level();
+        if !self.has_overrides.load(Ordering::Relaxed) {
+            return global;
+        }
+        // Logging from an interrupt that cut into `set_target_level`
+        let targets = match self.targets.try_lock() {
+            Some(targets) => targets,
+            None => return global,
+        };
+        targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target.strip_prefix(prefix.as_str()).map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(global, |(_, &level)| level)
+    }
+
+    /// The `log` macros drop records above `log::max_level` before asking the
+    /// logger, so it has to cover the most verbose override
+    fn update_max_level(&self) {
+        let overrides = self.targets.lock().values().copied().max().unwrap_or(LevelFilter::Off);
+        log::set_max_level(self.global_level().max(overrides));
+    }
+
+    fn write(&self, args: fmt::Arguments) {
+        if self.base == COM1 {
+            let _ = SERIAL1.lock().write_fmt(args);
+            return;
+        }
+        let mut port = self.port.lock();
+        let port = port.get_or_insert_with(|| {
+            // Safety: the base was chosen as a 16550 UART by whoever built the logger
+            let mut port = unsafe { SerialPort::new(self.base) };
+            port.init();
+            port
+        });
+        let _ = port.write_fmt(args);
+    }
+}
 
 impl log::Log for SerialLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Info
+        metadata.level() <= self.level_for(metadata.target())
     }
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            serial_println!("[{}] {}", record.level(), record.args());
+            let time = Timestamp(timestamp());
+            self.write(format_args!("{}[{} {}] {}\r\n", time, record.level(), record.target(), record.args()));
+            crate::kernel::crash_log::record_log(format_args!("[{}] {}\n", record.level(), record.args()));
+            RECENT.log(record);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Keeps the last few formatted records in memory, e.g. for an on-screen console
+pub struct RingBufferLogger {
+    capacity: usize,
+    records: Mutex<VecDeque<String>>,
+}
+
+impl RingBufferLogger {
+    /// Logger keeping at most `capacity` records, evicting the oldest
+    pub const fn new(capacity: usize) -> Self {
+        Self { capacity, records: Mutex::new(VecDeque::new()) }
+    }
+
+    /// The kept records, oldest first
+    pub fn records(&self) -> Vec<String> {
+        self.records.lock().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().clear();
+    }
+}
+
+impl log::Log for RingBufferLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        // Records are formatted on the heap
+        self.capacity > 0 && crate::kernel::memory::allocator::heap_ready()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // Skipped rather than deadlocking when logging interrupts a reader
+        if let Some(mut records) = self.records.try_lock() {
+            if records.len() >= self.capacity {
+                records.pop_front();
+            }
+            let time = Timestamp(timestamp());
+            records.push_back(format!("{}[{} {}] {}", time, record.level(), record.target(), record.args()));
         }
     }
 
     fn flush(&self) {}
 }
 
-static LOGGER: SerialLogger = SerialLogger;
+/// Records shown by the GUI console
+const RECENT_CAPACITY: usize = 200;
+
+static RECENT: RingBufferLogger = RingBufferLogger::new(RECENT_CAPACITY);
+
+/// Recent kernel log records, oldest first
+pub fn recent_records() -> Vec<String> {
+    RECENT.records()
+}
 
+static LOGGER: SerialLogger = SerialLogger::new(COM1);
+
+/// Install `SerialLogger` as the global logger. `log::set_logger` keeps the
+/// reference and every `log` macro dispatches through it; a second call
+/// fails with `SetLoggerError` and leaves the first logger in place.
 pub fn init() -> Result<(), SetLoggerError> {
-    log::set_logger(&LOGGER)
-        .map(|()| log::set_max_level(LevelFilter::Info))
+    log::set_logger(&LOGGER).map(|()| LOGGER.set_level(LevelFilter::Info))
+}
+
+/// The installed logger, for changing levels at runtime
+pub fn logger() -> &'static SerialLogger {
+    &LOGGER
 }
\ No newline at end of file
@@ -0,0 +1,200 @@
+//! Cooperative background tasks
+//!
+//! There is no scheduler, so periodic subsystem work (autosave, retries,
+//! timeouts) registers a `Task` here and the main loop calls `poll`, which
+//! runs whatever is due. Tasks run on the main loop's stack with interrupts
+//! enabled, never in interrupt context, and may take locks, touch the
+//! filesystem or spawn and cancel tasks, including themselves.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::kernel::drivers::timer;
+
+/// What a task wants after running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskStatus {
+    /// Keep the task; periodic tasks run again after their interval
+    Continue,
+    /// Remove the task
+    Done,
+}
+
+/// Handle returned when spawning, used to cancel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskId(u32);
+
+pub type TaskFn = Box<dyn FnMut() -> TaskStatus + Send>;
+
+struct Task {
+    id: TaskId,
+    name: &'static str,
+    /// Uptime at which the task is due (ms)
+    next_run_ms: u64,
+    /// None for one-shot tasks
+    interval_ms: Option<u64>,
+    run: TaskFn,
+}
+
+/// A set of tasks and their deadlines, advanced by an explicit clock
+pub struct TaskRunner {
+    tasks: Vec<Task>,
+    /// Tasks taken out to run; cancelling one of these drops it when it returns
+    running: Vec<TaskId>,
+    cancelled: Vec<TaskId>,
+    next_id: u32,
+}
+
+impl TaskRunner {
+    pub const fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            running: Vec::new(),
+            cancelled: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    fn insert(&mut self, name: &'static str, next_run_ms: u64, interval_ms: Option<u64>, run: TaskFn) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.tasks.push(Task { id, name, next_run_ms, interval_ms, run });
+        id
+    }
+
+    /// Run `run` every `interval_ms`, first at `now_ms + interval_ms`
+    pub fn spawn_periodic(&mut self, name: &'static str, interval_ms: u64, now_ms: u64, run: TaskFn) -> TaskId {
+        let interval_ms = interval_ms.max(1);
+        self.insert(name, now_ms + interval_ms, Some(interval_ms), run)
+    }
+
+    /// Run `run` once at `now_ms + delay_ms`
+    pub fn spawn_once(&mut self, name: &'static str, delay_ms: u64, now_ms: u64, run: TaskFn) -> TaskId {
+        self.insert(name, now_ms + delay_ms, None, run)
+    }
+
+    /// Remove a task. Returns false if it already finished or was cancelled.
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        if let Some(index) = self.tasks.iter().position(|t| t.id == id) {
+            self.tasks.remove(index);
+            return true;
+        }
+        if self.running.contains(&id) && !self.cancelled.contains(&id) {
+            self.cancelled.push(id);
+            return true;
+        }
+        false
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len() + self.running.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Earliest deadline of any waiting task
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.tasks.iter().map(|t| t.next_run_ms).min()
+    }
+
+    /// Take the tasks due at `now_ms` out of the runner, earliest first
+    fn take_due(&mut self, now_ms: u64) -> Vec<Task> {
+        let mut due = Vec::new();
+        let mut i = 0;
+        while i < self.tasks.len() {
+            if self.tasks[i].next_run_ms <= now_ms {
+                let task = self.tasks.swap_remove(i);
+                self.running.push(task.id);
+                due.push(task);
+            } else {
+                i += 1;
+            }
+        }
+        due.sort_by_key(|t| t.next_run_ms);
+        due
+    }
+
+    /// Put a task back after it ran, unless it finished or was cancelled meanwhile
+    fn finish(&mut self, mut task: Task, status: TaskStatus, now_ms: u64) {
+        self.running.retain(|&id| id != task.id);
+        if let Some(index) = self.cancelled.iter().position(|&id| id == task.id) {
+            self.cancelled.swap_remove(index);
+            return;
+        }
+        let interval = match (status, task.interval_ms) {
+            (TaskStatus::Continue, Some(interval)) => interval,
+            _ => return,
+        };
+        // Keep the original cadence, but don't replay runs missed during a long stall
+        task.next_run_ms += interval;
+        if task.next_run_ms <= now_ms {
+            task.next_run_ms = now_ms + interval;
+        }
+        self.tasks.push(task);
+    }
+
+    /// Run every task due at `now_ms`. Returns how many ran.
+    pub fn run_due(&mut self, now_ms: u64) -> usize {
+        let due = self.take_due(now_ms);
+        let count = due.len();
+        for mut task in due {
+            let status = (task.run)();
+            self.finish(task, status, now_ms);
+        }
+        count
+    }
+}
+
+static RUNNER: Mutex<TaskRunner> = Mutex::new(TaskRunner::new());
+
+/// Register a task to run every `interval_ms` from the main loop
+pub fn spawn_periodic<F>(name: &'static str, interval_ms: u64, run: F) -> TaskId
+where
+    F: FnMut() -> TaskStatus + Send + 'static,
+{
+    RUNNER.lock().spawn_periodic(name, interval_ms, timer::uptime_ms(), Box::new(run))
+}
+
+/// Register a task to run once, `delay_ms` from now
+pub fn spawn_once<F>(name: &'static str, delay_ms: u64, run: F) -> TaskId
+where
+    F: FnOnce() + Send + 'static,
+{
+    let mut run = Some(run);
+    let task = move || {
+        if let Some(run) = run.take() {
+            run();
+        }
+        TaskStatus::Done
+    };
+    RUNNER.lock().spawn_once(name, delay_ms, timer::uptime_ms(), Box::new(task))
+}
+
+pub fn cancel(id: TaskId) -> bool {
+    RUNNER.lock().cancel(id)
+}
+
+/// Run the tasks that are due. Call from the main loop.
+///
+/// The runner is unlocked while tasks run so they can spawn and cancel.
+pub fn poll() {
+    let now = timer::uptime_ms();
+    let due = {
+        let mut runner = RUNNER.lock();
+        if runner.next_deadline().map_or(true, |deadline| deadline > now) {
+            return;
+        }
+        runner.take_due(now)
+    };
+
+    for mut task in due {
+        let status = (task.run)();
+        if status == TaskStatus::Done && task.interval_ms.is_some() {
+            log::debug!("Task '{}' finished", task.name);
+        }
+        RUNNER.lock().finish(task, status, now);
+    }
+}
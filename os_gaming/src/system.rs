@@ -10,11 +10,14 @@ use alloc::vec::Vec;
 use lazy_static::lazy_static;
 use spin::Mutex;
 
-use crate::config::{load_system_config, SystemConfig};
+use crate::config::{self, load_system_config, SystemConfig};
+use crate::events;
+use crate::runtime;
 use crate::gui::{self, FontManager, Renderer, Theme, WindowLayoutConfig, WindowManager};
 use crate::kernel::drivers::filesystem as fs;
 use crate::kernel::drivers::filesystem::{FilesystemManager};
 use crate::kernel::{self, drivers, interrupts, memory};
+use crate::kernel::sync::DebugMutex;
 use bincode::{Decode, Encode};
 use alloc::boxed::Box;
 use bootloader::BootInfo;
@@ -35,7 +38,7 @@ pub struct System {
     state: Mutex<SystemState>,
 
     /// System configuration
-    config: Mutex<SystemConfig>,
+    config: DebugMutex<SystemConfig>,
 
     /// Display resolution
     resolution: (u32, u32),
@@ -171,7 +174,7 @@ impl System {
     pub fn new() -> Self {
         Self {
             state: Mutex::new(SystemState::Initializing),
-            config: Mutex::new(SystemConfig::default()),
+            config: DebugMutex::new("system config", SystemConfig::default()),
             resolution: (1280, 720), // Default resolution
             uptime: Mutex::new(0),
             window_manager: None,
@@ -191,6 +194,8 @@ impl System {
             Ok(config) => *self.config.lock() = config,
             Err(e) => log::warn!("Failed to load system config: {}", e),
         }
+        // The global copy is what a hibernation image saves
+        *config::get_config().lock() = self.config.lock().clone();
 
         // Initialize kernel services
         log::info!("Initializing kernel services...");
@@ -209,11 +214,32 @@ impl System {
 
         // 3. Initialize drivers
         log::info!("Initializing device drivers...");
-        drivers::init();
+        drivers::init().map_err(|e| {
+            log::error!("Driver initialization failed: {}", e);
+            "Driver initialization failed"
+        })?;
+        for (name, status) in drivers::driver_status() {
+            if !status.is_ready() {
+                log::warn!("{}: {}", name, status);
+            }
+        }
         // 4. Initialize filesystem
         log::info!("Initializing filesystem...");
         FilesystemManager::init();
 
+        // Resume from a hibernation image if one was left behind
+        if let Err(e) = config::register_hibernation_state() {
+            log::warn!("Config won't survive hibernation: {}", e);
+        }
+        match drivers::power::resume_from_hibernation() {
+            Ok(true) => {
+                *self.config.lock() = config::get_config().lock().clone();
+                log::info!("System state restored from hibernation image");
+            }
+            Ok(false) => {}
+            Err(e) => log::warn!("Discarding hibernation image: {}", e),
+        }
+
         // Initialize GUI subsystems
         log::info!("Initializing GUI subsystems...");
 
@@ -251,6 +277,10 @@ impl System {
             log::info!("Initializing audio subsystem...");
             if drivers::sound::init().is_ok() {
                 self.audio_enabled = true;
+                let sample_rate = self.config.lock().audio.sample_rate;
+                if let Err(e) = drivers::sound::configure_pipeline(sample_rate) {
+                    log::warn!("Audio pipeline not configured: {}", e);
+                }
             } else {
                 log::warn!("Audio initialization failed, continuing without audio");
             }
@@ -269,6 +299,20 @@ impl System {
         // Register input handlers
         self.register_default_handlers();
 
+        {
+            let config = self.config.lock();
+            config::configure_autosave(config.storage.autosave_interval);
+            fs::set_sync_immediately(config.storage.sync_immediately);
+            drivers::mouse::configure(&config.input);
+            drivers::gamepad::configure(&config.input);
+            gui::input::configure_poll_rate(&config.input);
+            kernel::panic::apply_config_policy(&config.power.panic_policy);
+            drivers::acpi::configure(&config.power);
+            drivers::gpu::configure(&config.gpu);
+            drivers::io_scheduler::configure(&config.storage);
+            drivers::notification_sound::configure(&config.audio, &config.user_settings.notifications);
+        }
+
         // System is now running
         log::info!("System initialization complete!");
         *self.state.lock() = SystemState::Running;
@@ -276,6 +320,45 @@ impl System {
         Ok(())
     }
 
+    /// Apply the display group of the configuration to the running GUI
+    fn apply_display_config(&mut self) {
+        let (resolution, refresh_rate) = {
+            let config = self.config.lock();
+            drivers::gpu::set_linear_blending(config.display.linear_blending);
+            drivers::gpu::configure_refresh(&config.display);
+            if let Some(window_manager) = self.window_manager.as_ref() {
+                let mut window_manager = window_manager.lock();
+                window_manager.set_display_options(&config.display);
+                window_manager.set_notification_settings(&config.user_settings.notifications);
+                if let Some(layout) = &config.window_layout {
+                    window_manager.set_window_layout_options(layout);
+                }
+                if let Err(e) = gui::set_render_scale(&mut window_manager, config.display.render_scale) {
+                    log::warn!("Keeping the current render scale: {}", e);
+                }
+            }
+            (config.display.resolution, config.display.refresh_rate)
+        };
+        let (width, height) = match resolution {
+            Some(resolution) if resolution != self.resolution => resolution,
+            _ => return,
+        };
+        let window_manager = match self.window_manager.as_ref() {
+            Some(window_manager) => window_manager,
+            None => return,
+        };
+
+        match gui::set_resolution(&mut window_manager.lock(), width, height, refresh_rate) {
+            Ok(applied) => self.resolution = applied,
+            Err(e) => log::warn!(
+                "Keeping {}x{}: {}",
+                self.resolution.0,
+                self.resolution.1,
+                e
+            ),
+        }
+    }
+
     /// Run the system main loop
     pub fn run(&mut self) -> ! {
         log::info!("Entering system main loop");
@@ -286,12 +369,19 @@ impl System {
         loop {
             // Process pending events
             self.process_events();
+            events::dispatch_pending();
+            runtime::poll();
 
             // Update window manager
             if let Some(wm) = &self.window_manager {
                 wm.lock().update();
             }
 
+            if config::take_autosave_request() {
+                self.autosave();
+            }
+            fs::periodic_writeback();
+
             // Check system state
             match *self.state.lock() {
                 SystemState::Running => {
@@ -327,7 +417,7 @@ impl System {
             for event in events {
                 input_events.push(SystemEvent::Input(InputEvent::Key {
                     key: event.key_code,
-                    pressed: event.alt_pressed || event.ctrl_pressed || event.shift_pressed,
+                    pressed: event.scancode & 0x80 == 0,
                     modifiers: event.modifiers,
                 }));
             }
@@ -467,7 +557,8 @@ impl System {
     fn shutdown(&self) -> ! {
         log::info!("System shutting down...");
 
-        // Save any unsaved data
+        // Save any unsaved data, including where windows were dragged
+        self.autosave();
         self.save_system_state();
 
         // Close all open windows
@@ -492,13 +583,55 @@ impl System {
         // Perform platform-specific shutdown
         log::info!("Goodbye!");
 
-        // This function must not return, so it should call a diverging function
-        kernel::drivers::power::shutdown();
+        kernel::exit::exit(kernel::exit::ExitReason::Shutdown)
+    }
 
-        // In case shutdown returns (which it shouldn't)
-        loop {
-            // Halt the CPU
-            core::hint::spin_loop();
+    /// Write the config, including the current window layout, if anything changed.
+    /// The layout is left out when `remember_positions` is off.
+    fn autosave(&self) {
+        let remember_positions = self
+            .config
+            .lock()
+            .window_layout
+            .as_ref()
+            .map_or(true, |layout| layout.remember_positions);
+        if config::take_layout_dirty() && remember_positions {
+            if let Some(wm) = &self.window_manager {
+                let windows = wm
+                    .lock()
+                    .save_layout()
+                    .into_iter()
+                    // `z_order` counts from the front
+                    .rev()
+                    .enumerate()
+                    .map(|(z_order, (_, title, rect))| config::WindowPosition {
+                        id: title,
+                        position: (rect.x, rect.y),
+                        size: (rect.width, rect.height),
+                        minimized: false,
+                        maximized: false,
+                        z_order: z_order as u16,
+                    })
+                    .collect();
+
+                let mut config = self.config.lock();
+                config
+                    .window_layout
+                    .get_or_insert_with(config::WindowLayoutConfig::from_gui_layout)
+                    .windows = windows;
+                *config::get_config().lock() = config.clone();
+                config::mark_config_dirty();
+            }
+        }
+
+        if !config::has_unsaved_changes() {
+            return;
+        }
+
+        let config = self.config.lock().clone();
+        match config.save() {
+            Ok(()) => log::debug!("Autosaved configuration"),
+            Err(e) => log::warn!("Autosave failed: {}", e),
         }
     }
 
@@ -592,6 +725,7 @@ impl System {
             wm.lock().show_window(window_id);
         }
 
+        events::publish(events::Event::GameLaunched { window_id });
         Ok(window_id)
     }
 
@@ -695,10 +829,47 @@ pub fn get_system_info() -> SystemConfig {
 
 pub fn apply_profile(profile: SystemConfig) {
     let mut system = SYSTEM.lock();
-    system.config.lock().apply_profile(profile);
+    {
+        let mut config = system.config.lock();
+        config.apply_profile(profile);
+        apply_cpu_governor(&config);
+        config::configure_autosave(config.storage.autosave_interval);
+        fs::set_sync_immediately(config.storage.sync_immediately);
+        drivers::mouse::configure(&config.input);
+        drivers::gamepad::configure(&config.input);
+        gui::input::configure_poll_rate(&config.input);
+        kernel::panic::apply_config_policy(&config.power.panic_policy);
+        drivers::acpi::configure(&config.power);
+        drivers::gpu::configure(&config.gpu);
+        drivers::io_scheduler::configure(&config.storage);
+        drivers::notification_sound::configure(&config.audio, &config.user_settings.notifications);
+    }
+    system.apply_display_config();
 }
 
 pub fn optimize_performance() {
     let mut system = SYSTEM.lock();
-    system.config.lock().optimize_performance();
+    let mut config = system.config.lock();
+    config.optimize_performance();
+    apply_cpu_governor(&config);
+}
+
+pub fn optimize_power() {
+    let mut system = SYSTEM.lock();
+    let mut config = system.config.lock();
+    config.optimize_power();
+    apply_cpu_governor(&config);
+}
+
+/// Switch the CPU governor to the one selected in the configuration
+fn apply_cpu_governor(config: &SystemConfig) {
+    let governor = config.power.cpu_governor.as_str();
+    match kernel::cpu::power::governor_from_name(governor) {
+        Some(governor) => {
+            if let Err(e) = drivers::power::get_manager().lock().set_cpu_governor(governor) {
+                log::warn!("Failed to switch CPU governor: {}", e);
+            }
+        }
+        None => log::warn!("Unknown CPU governor '{}' in config", governor),
+    }
 }